@@ -6,10 +6,11 @@ use std::process::exit;
 use core_bluetooth::central::*;
 use core_bluetooth::central::peripheral::Peripheral;
 use core_bluetooth::*;
+use core_bluetooth::gatt::ValueView;
 use core_bluetooth::uuid::Uuid;
 
-const SERVICE: &str = "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6";
-const CHARACTERISTIC: &str = "ebe0ccc1-7a0a-4b0c-8a1a-6ff2997da3a6";
+const SERVICE: Uuid = uuid!("ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6");
+const CHARACTERISTIC: Uuid = uuid!("ebe0ccc1-7a0a-4b0c-8a1a-6ff2997da3a6");
 
 struct App {
     central: CentralManager,
@@ -50,7 +51,7 @@ impl App {
                     ManagerState::PoweredOn => {
                         info!("scanning for peripherals");
                         println!("Discovering Xiaomi sensors...");
-                        self.central.get_peripherals_with_services(&[SERVICE.parse().unwrap()]);
+                        self.central.get_peripherals_with_services(&[SERVICE]);
                         self.central.scan();
                     },
                     _ => {},
@@ -60,6 +61,7 @@ impl App {
                 peripheral,
                 advertisement_data,
                 rssi,
+                ..
             } => {
                 if advertisement_data.is_connectable() != Some(false) &&
                     self.connected_peripherals.insert(peripheral.clone())
@@ -77,8 +79,8 @@ impl App {
                     }
                 }
             }
-            CentralEvent::PeripheralConnected { peripheral } => {
-                peripheral.discover_services_with_uuids(&[SERVICE.parse().unwrap()]);
+            CentralEvent::PeripheralConnected { peripheral, .. } => {
+                peripheral.discover_services_with_uuids(&[SERVICE]);
             }
             CentralEvent::PeripheralDisconnected { peripheral, error: _, } => {
                 self.connected_peripherals.remove(&peripheral);
@@ -93,7 +95,7 @@ impl App {
             CentralEvent::ServicesDiscovered { peripheral, services, } => {
                 if let Ok(services) = services {
                     for service in services {
-                        peripheral.discover_characteristics_with_uuids(&service, &[CHARACTERISTIC.parse().unwrap()]);
+                        peripheral.discover_characteristics_with_uuids(&service, &[CHARACTERISTIC]);
                     }
                 }
             }
@@ -101,7 +103,7 @@ impl App {
                 if result.is_err() {
                     error!("couldn't subscribe to characteristic of {}", peripheral.id());
                 } else {
-                    println!("Subscribed to {} (#{})", peripheral.id(), self.shorten_uuid(peripheral.id()));
+                    println!("Subscribed to {} (#{})", peripheral.id(), self.shorten_uuid(peripheral.id().into()));
                 }
             }
             CentralEvent::CharacteristicsDiscovered { peripheral, service: _, characteristics } => {
@@ -113,14 +115,18 @@ impl App {
                     Err(err) => error!("couldn't discover characteristics of {}: {}", peripheral.id(), err),
                 }
             }
-            CentralEvent::CharacteristicValue { peripheral, characteristic: _, value } => {
+            CentralEvent::CharacteristicValue { peripheral, value, .. } => {
                 if let Ok(value) = value {
                     let now = chrono::Local::now().format("[%Y-%m-%d %H:%M:%S]");
 
-                    let t = i16::from_le_bytes([value[0], value[1]]) as f64 / 100.0;
-                    let rh = value[2];
-                    println!("{} #{}: t = {} C, rh = {}%",
-                        now, self.shorten_uuid(peripheral.id()), t, rh);
+                    let v = ValueView::new(&value);
+                    if let (Some(t), Some(rh)) = (v.get_i16_le(0), v.get_u8(2)) {
+                        let t = t as f64 / 100.0;
+                        println!("{} #{}: t = {} C, rh = {}%",
+                            now, self.shorten_uuid(peripheral.id().into()), t, rh);
+                    } else {
+                        warn!("malformed value from {}: {:?}", peripheral.id(), value);
+                    }
                 }
             }
             _ => {}
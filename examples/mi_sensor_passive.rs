@@ -13,8 +13,10 @@ use std::process::exit;
 
 use core_bluetooth::central::*;
 use core_bluetooth::*;
+use core_bluetooth::gatt::ValueView;
+use core_bluetooth::uuid::Uuid;
 
-const SERVICE: &str = "0000fe95-0000-1000-8000-00805f9b34fb";
+const SERVICE: Uuid = uuid!("fe95");
 
 struct App {
     central: CentralManager,
@@ -61,7 +63,7 @@ impl App {
                 advertisement_data,
                 ..
             } => {
-                if let Some(packet) = advertisement_data.service_data().get(SERVICE.parse().unwrap()) {
+                if let Some(packet) = advertisement_data.service_data().get(SERVICE) {
                     match Packet::parse(packet, |mac| self.encryption_keys.get(&mac).map(|v| &v[..])) {
                         Ok(packet) => {
                             if !packet.sensor_values.is_empty() {
@@ -223,51 +225,42 @@ impl Packet {
         debug!("payload: {}", hex::encode(payload));
         let mut r = Vec::new();
         while !payload.is_empty() {
-            if payload.len() < 3 {
-                warn!("truncated value");
-                break;
-            }
-            let kind = u16::from_be_bytes([payload[0], payload[1]]);
-            let len = payload[2] as usize;
-            payload = &payload[3..];
-            if len > payload.len() {
-                warn!("truncated value");
-                break;
-            }
-            let v = &payload[..len];
-            payload = &payload[len..];
-
-            let mut decoded = true;
-            match len {
-                1 => match kind {
-                    0xa10 => r.push(SensorValue::Battery(v[0])),
-                    0x810 => r.push(SensorValue::Moisture(v[0])),
-                    0x1210 => r.push(SensorValue::Switch(v[0])),
-                    0x1310 => r.push(SensorValue::Consumable(v[0])),
-                    _ => decoded = false,
-                }
-                3 => match kind {
-                    0x710 => r.push(SensorValue::Illuminance(u32::from_le_bytes([v[0], v[1], v[2], 0]))),
-                    _ => decoded = false,
-                }
-                2 => match kind {
-                    0x610 => r.push(SensorValue::Humidity(u16::from_le_bytes([v[0], v[1]]) as f32 / 10.0)),
-                    0x410 => r.push(SensorValue::Temperature(i16::from_le_bytes([v[0], v[1]]) as f32 / 10.0)),
-                    0x910 => r.push(SensorValue::Conductivity(u16::from_le_bytes([v[0], v[1]]) as u32)),
-                    0x1010 => r.push(SensorValue::Formaldehyde(u16::from_le_bytes([v[0], v[1]]) as f32 / 100.0)),
-                    _ => decoded = false,
-                }
-                4 => match kind {
-                    0xd10 => {
-                        r.push(SensorValue::Temperature(i16::from_le_bytes([v[0], v[1]]) as f32 / 10.0));
-                        r.push(SensorValue::Humidity(u16::from_le_bytes([v[2], v[3]]) as f32 / 10.0));
+            let header = ValueView::new(payload);
+            let (kind, v) = match (header.get_u16_be(0), header.get_u8(2)) {
+                (Some(kind), Some(len)) => match header.get(3, len as usize) {
+                    Some(v) => (kind, ValueView::new(v)),
+                    None => {
+                        warn!("truncated value");
+                        break;
                     }
-                    _ => decoded = false,
                 }
-                _ => decoded = false,
-            }
-            if !decoded {
-                warn!("couldn't decode sensor value: kind={:x} value={}", kind, hex::encode(v));
+                _ => {
+                    warn!("truncated value");
+                    break;
+                }
+            };
+            payload = &payload[3 + v.len()..];
+
+            let values = match (kind, v.len()) {
+                (0xa10, 1) => v.get_u8(0).map(|v| vec![SensorValue::Battery(v)]),
+                (0x810, 1) => v.get_u8(0).map(|v| vec![SensorValue::Moisture(v)]),
+                (0x1210, 1) => v.get_u8(0).map(|v| vec![SensorValue::Switch(v)]),
+                (0x1310, 1) => v.get_u8(0).map(|v| vec![SensorValue::Consumable(v)]),
+                (0x710, 3) => v.get_u24_le(0).map(|v| vec![SensorValue::Illuminance(v)]),
+                (0x610, 2) => v.get_u16_le(0).map(|v| vec![SensorValue::Humidity(v as f32 / 10.0)]),
+                (0x410, 2) => v.get_i16_le(0).map(|v| vec![SensorValue::Temperature(v as f32 / 10.0)]),
+                (0x910, 2) => v.get_u16_le(0).map(|v| vec![SensorValue::Conductivity(v as u32)]),
+                (0x1010, 2) => v.get_u16_le(0).map(|v| vec![SensorValue::Formaldehyde(v as f32 / 100.0)]),
+                (0xd10, 4) => v.get_i16_le(0).and_then(|t| Some(vec![
+                    SensorValue::Temperature(t as f32 / 10.0),
+                    SensorValue::Humidity(v.get_u16_le(2)? as f32 / 10.0),
+                ])),
+                _ => None,
+            };
+            if let Some(values) = values {
+                r.extend(values);
+            } else {
+                warn!("couldn't decode sensor value: kind={:x} value={}", kind, hex::encode(v.bytes()));
             }
         }
         Ok(Self {
@@ -1,19 +1,31 @@
 mod command;
+mod connect;
 mod delegate;
+#[cfg(feature = "serde")]
+mod serialize;
 pub mod characteristic;
 pub mod descriptor;
+pub mod diagnostics;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod metrics;
 pub mod peripheral;
 pub mod service;
+pub mod tree;
 
+use log::*;
 use objc::*;
 use objc::runtime::*;
 use static_assertions::*;
 use std::os::raw::*;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 use std::mem;
 use std::ptr;
 use std::ptr::NonNull;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::hash::Hash;
 
 use crate::*;
 use crate::error::Error;
@@ -21,9 +33,11 @@ use crate::platform::*;
 use crate::sync;
 use crate::uuid::*;
 
-use characteristic::Characteristic;
+use characteristic::{CccdState, Characteristic};
 use delegate::Delegate;
 use descriptor::Descriptor;
+use diagnostics::Diagnostics;
+use metrics::{EventOverflow, Metrics};
 use peripheral::*;
 use service::Service;
 
@@ -32,6 +46,41 @@ use service::Service;
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum CentralEvent {
+    /// Indicates that reading values of all descriptors of a characteristic completed.
+    ///
+    /// This event is triggered in response to the
+    /// [`read_all_descriptors`](peripheral/struct.Peripheral.html#method.read_all_descriptors)
+    /// method call, after all the corresponding
+    /// [`DescriptorValue`](enum.CentralEvent.html#variant.DescriptorValue) events.
+    AllDescriptorsRead {
+        /// The peripheral providing this information.
+        peripheral: Peripheral,
+
+        /// The characteristic whose descriptors were read.
+        characteristic: Characteristic,
+
+        /// Error if the descriptors discovery failed. Errors of individual reads are reported in
+        /// the `DescriptorValue` events.
+        result: Result<(), Error>,
+    },
+
+    /// Indicates that reading the Client Characteristic Configuration descriptor of a
+    /// characteristic completed.
+    ///
+    /// This event is triggered in response to the
+    /// [`read_cccd`](peripheral/struct.Peripheral.html#method.read_cccd) method call.
+    CccdRead {
+        /// The peripheral providing this information.
+        peripheral: Peripheral,
+
+        /// The characteristic whose descriptor was read.
+        characteristic: Characteristic,
+
+        /// The state of the notifications and indications, or `None` if the characteristic has no
+        /// Client Characteristic Configuration descriptor.
+        result: Result<Option<CccdState>, Error>,
+    },
+
     /// Indicates the peripheral discovered characteristics for a service.
     ///
     /// This event is triggered in response to the
@@ -48,6 +97,22 @@ pub enum CentralEvent {
         characteristics: Result<Vec<Characteristic>, Error>,
     },
 
+    /// Indicates that searching for characteristics in all services of a peripheral completed.
+    ///
+    /// This event is triggered in response to the
+    /// [`find_characteristics`](peripheral/struct.Peripheral.html#method.find_characteristics)
+    /// method call, after the corresponding
+    /// [`ServicesDiscovered`](enum.CentralEvent.html#variant.ServicesDiscovered) and
+    /// [`CharacteristicsDiscovered`](enum.CentralEvent.html#variant.CharacteristicsDiscovered)
+    /// events.
+    CharacteristicsFound {
+        /// The peripheral providing this information.
+        peripheral: Peripheral,
+
+        /// The found characteristics from all services or error if a discovery failed.
+        characteristics: Result<Vec<Characteristic>, Error>,
+    },
+
     /// Indicates that retrieving the specified characteristic’s value completed, or that the
     /// characteristic’s value changed.
     ///
@@ -65,6 +130,31 @@ pub enum CentralEvent {
 
         /// The value or error if the call failed.
         value: Result<Vec<u8>, Error>,
+
+        /// The number of value updates received for this characteristic so far, including this one.
+        ///
+        /// The counter starts at `1` and is reset when the peripheral disconnects. A gap between
+        /// sequence numbers of successive events means some updates were lost.
+        sequence: u64,
+
+        /// The time the value was received by the central manager on its dispatch queue.
+        ///
+        /// Comparing it with the time the event is taken from the receiver gives the latency of
+        /// the event delivery and processing in the application. It doesn't include the latency of
+        /// the radio link and the system Bluetooth stack.
+        queue_timestamp: Instant,
+    },
+
+    /// Indicates that a command was rejected without being issued to the peripheral.
+    ///
+    /// The crate rejects commands it knows would fail, to give a clear feedback instead of an
+    /// obscure error from the framework.
+    CommandRejected {
+        /// The peripheral the command was issued for.
+        peripheral: Peripheral,
+
+        /// The reason the command was rejected.
+        reason: CommandRejectReason,
     },
 
     /// Indicates the peripheral discovered descriptors for a characteristic.
@@ -148,6 +238,22 @@ pub enum CentralEvent {
         included_services: Result<Vec<Service>, Error>,
     },
 
+    /// Indicates that the RSSI of the peripheral stayed below the threshold, which often precedes
+    /// a disconnection.
+    ///
+    /// This event is triggered only if enabled with
+    /// [`CentralManagerBuilder::link_quality_warning`](struct.CentralManagerBuilder.html#method.link_quality_warning).
+    /// The RSSI is taken from
+    /// [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered) and
+    /// [`ReadRssiResult`](enum.CentralEvent.html#variant.ReadRssiResult) events.
+    LinkQualityWarning {
+        /// The peripheral with the weak signal.
+        peripheral: Peripheral,
+
+        /// The RSSI, in decibels, that triggered the warning.
+        rssi: i32,
+    },
+
     /// Indicates the central manager’s state updated.
     ///
     /// You handle this event to ensure that the central device supports Bluetooth low energy and
@@ -171,6 +277,26 @@ pub enum CentralEvent {
     PeripheralConnected {
         /// The now-connected peripheral.
         peripheral: Peripheral,
+
+        /// Hint that the peripheral was already connected to the system, for example by another
+        /// app, so no new link was established.
+        ///
+        /// This is inferred: it's `true` if the peripheral was returned in
+        /// [`GetPeripheralsWithServicesResult`](enum.CentralEvent.html#variant.GetPeripheralsWithServicesResult)
+        /// event and the connection completed within 20 ms after the
+        /// [`connect`](struct.CentralManager.html#method.connect) call was processed, which is
+        /// shorter than establishing a connection over the radio takes. It's always `false` for
+        /// connections made by other means, such as
+        /// [`force_rediscover`](peripheral/struct.Peripheral.html#method.force_rediscover).
+        was_already_connected: bool,
+
+        /// Time elapsed between processing the [`connect`](struct.CentralManager.html#method.connect)
+        /// call and receiving the connection callback.
+        ///
+        /// This is a coarse measurement that includes the dispatch queue latency, and can be used
+        /// as a rough link quality signal only. It's zero for connections made by other means, such
+        /// as [`force_rediscover`](peripheral/struct.Peripheral.html#method.force_rediscover).
+        connect_duration: Duration,
     },
 
     /// Indicates the central manager failed to create a connection with the peripheral.
@@ -218,6 +344,14 @@ pub enum CentralEvent {
         /// Use the RSSI data to determine the proximity of a discoverable peripheral device, and
         /// whether you want to connect to it automatically.
         rssi: i32,
+
+        /// Estimated advertising interval of the peripheral: time elapsed since the previous
+        /// discovery of the same peripheral in the current scan.
+        ///
+        /// Always `None` unless enabled with
+        /// [`estimate_interval`](struct.ScanOptions.html#method.estimate_interval) scan option.
+        /// Also `None` for the first discovery of a peripheral.
+        estimated_interval: Option<Duration>,
     },
 
     /// Indicates that a peripheral is again ready to send characteristic updates.
@@ -257,6 +391,35 @@ pub enum CentralEvent {
         rssi: Result<i32, Error>,
     },
 
+    /// Indicates that forced rediscovery of the peripheral completed.
+    ///
+    /// This event is triggered in response to the
+    /// [`force_rediscover`](peripheral/struct.Peripheral.html#method.force_rediscover)
+    /// method call, after the corresponding
+    /// [`ServicesDiscovered`](enum.CentralEvent.html#variant.ServicesDiscovered) event.
+    RediscoveryCompleted {
+        /// The peripheral providing this information.
+        peripheral: Peripheral,
+
+        /// The rediscovered services or error if reconnection or the discovery failed.
+        services: Result<Vec<Service>, Error>,
+    },
+
+    /// Indicates that services invalidated by a change of the peripheral's services were
+    /// rediscovered.
+    ///
+    /// This event is triggered only if enabled with
+    /// [`auto_rediscover_services`](struct.CentralManagerBuilder.html#method.auto_rediscover_services),
+    /// after the [`ServicesDiscovered`](enum.CentralEvent.html#variant.ServicesDiscovered) event
+    /// of the rediscovery.
+    ServicesAutoRediscovered {
+        /// The peripheral providing this information.
+        peripheral: Peripheral,
+
+        /// The rediscovered services or error if the discovery failed.
+        services: Result<Vec<Service>, Error>,
+    },
+
     /// Indicates that a peripheral’s services changed.
     ///
     /// This event is triggered whenever one or more services of a peripheral change. A peripheral’s
@@ -332,6 +495,22 @@ pub enum CentralEvent {
         result: Result<(), Error>,
     },
 
+    /// Indicates that a sequence of characteristic value writes completed.
+    ///
+    /// This event is triggered in response to the
+    /// [`write_sequence`](peripheral/struct.Peripheral.html#method.write_sequence)
+    /// method call.
+    WriteSequenceComplete {
+        /// The peripheral providing this information.
+        peripheral: Peripheral,
+
+        /// Results of the writes in the order they were requested. Results of
+        /// [`WithoutResponse`](characteristic/enum.WriteKind.html#variant.WithoutResponse) writes
+        /// only indicate whether the write was issued. If the peripheral disconnects, the
+        /// remaining writes fail.
+        results: Vec<Result<(), Error>>,
+    },
+
     /// Characteristic descriptor's value write completed.
     ///
     /// This event is triggered in response to the
@@ -352,12 +531,320 @@ pub enum CentralEvent {
 assert_impl_all!(CentralEvent: Send);
 assert_not_impl_any!(CentralEvent: Sync);
 
+impl CentralEvent {
+    /// Returns identifier of the peripheral this event is about. Returns `None` for events that
+    /// aren't about a single peripheral.
+    pub fn peripheral_id(&self) -> Option<PeripheralId> {
+        use CentralEvent::*;
+        match self {
+            | AllDescriptorsRead { peripheral, .. }
+            | CccdRead { peripheral, .. }
+            | CharacteristicsDiscovered { peripheral, .. }
+            | CharacteristicsFound { peripheral, .. }
+            | CharacteristicValue { peripheral, .. }
+            | CommandRejected { peripheral, .. }
+            | DescriptorsDiscovered { peripheral, .. }
+            | DescriptorValue { peripheral, .. }
+            | IncludedServicesDiscovered { peripheral, .. }
+            | LinkQualityWarning { peripheral, .. }
+            | PeripheralConnected { peripheral, .. }
+            | PeripheralConnectFailed { peripheral, .. }
+            | PeripheralDisconnected { peripheral, .. }
+            | PeripheralDiscovered { peripheral, .. }
+            | PeripheralIsReadyToWriteWithoutResponse { peripheral, .. }
+            | PeripheralNameChanged { peripheral, .. }
+            | ReadRssiResult { peripheral, .. }
+            | RediscoveryCompleted { peripheral, .. }
+            | ServicesAutoRediscovered { peripheral, .. }
+            | ServicesChanged { peripheral, .. }
+            | ServicesDiscovered { peripheral, .. }
+            | SubscriptionChangeResult { peripheral, .. }
+            | WriteCharacteristicResult { peripheral, .. }
+            | WriteSequenceComplete { peripheral, .. }
+            | WriteDescriptorResult { peripheral, .. }
+            => Some(peripheral.id()),
+            GetMaxWriteLenResult { .. }
+            | GetPeripheralsResult { .. }
+            | GetPeripheralsWithServicesResult { .. }
+            | ManagerStateChanged { .. }
+            => None,
+        }
+    }
+
+    /// Returns the error carried by this event, if any. This covers failed connections,
+    /// disconnections caused by an error, and failed discoveries, reads and writes. For
+    /// `WriteSequenceComplete` event this is the error of the first failed write.
+    pub fn error(&self) -> Option<&Error> {
+        use CentralEvent::*;
+        match self {
+            | AllDescriptorsRead { result: Err(e), .. }
+            | CccdRead { result: Err(e), .. }
+            | CharacteristicsDiscovered { characteristics: Err(e), .. }
+            | CharacteristicsFound { characteristics: Err(e), .. }
+            | CharacteristicValue { value: Err(e), .. }
+            | DescriptorsDiscovered { descriptors: Err(e), .. }
+            | DescriptorValue { value: Err(e), .. }
+            | IncludedServicesDiscovered { included_services: Err(e), .. }
+            | PeripheralConnectFailed { error: Some(e), .. }
+            | PeripheralDisconnected { error: Some(e), .. }
+            | ReadRssiResult { rssi: Err(e), .. }
+            | RediscoveryCompleted { services: Err(e), .. }
+            | ServicesAutoRediscovered { services: Err(e), .. }
+            | ServicesDiscovered { services: Err(e), .. }
+            | SubscriptionChangeResult { result: Err(e), .. }
+            | WriteCharacteristicResult { result: Err(e), .. }
+            | WriteDescriptorResult { result: Err(e), .. }
+            => Some(e),
+            WriteSequenceComplete { results, .. } => results.iter().find_map(|r| r.as_ref().err()),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        use CentralEvent::*;
+        match self {
+            AllDescriptorsRead { .. } => "AllDescriptorsRead",
+            CccdRead { .. } => "CccdRead",
+            CharacteristicsDiscovered { .. } => "CharacteristicsDiscovered",
+            CharacteristicsFound { .. } => "CharacteristicsFound",
+            CharacteristicValue { .. } => "CharacteristicValue",
+            CommandRejected { .. } => "CommandRejected",
+            DescriptorsDiscovered { .. } => "DescriptorsDiscovered",
+            DescriptorValue { .. } => "DescriptorValue",
+            GetMaxWriteLenResult { .. } => "GetMaxWriteLenResult",
+            GetPeripheralsResult { .. } => "GetPeripheralsResult",
+            GetPeripheralsWithServicesResult { .. } => "GetPeripheralsWithServicesResult",
+            IncludedServicesDiscovered { .. } => "IncludedServicesDiscovered",
+            LinkQualityWarning { .. } => "LinkQualityWarning",
+            ManagerStateChanged { .. } => "ManagerStateChanged",
+            PeripheralConnected { .. } => "PeripheralConnected",
+            PeripheralConnectFailed { .. } => "PeripheralConnectFailed",
+            PeripheralDisconnected { .. } => "PeripheralDisconnected",
+            PeripheralDiscovered { .. } => "PeripheralDiscovered",
+            PeripheralIsReadyToWriteWithoutResponse { .. } => "PeripheralIsReadyToWriteWithoutResponse",
+            PeripheralNameChanged { .. } => "PeripheralNameChanged",
+            ReadRssiResult { .. } => "ReadRssiResult",
+            RediscoveryCompleted { .. } => "RediscoveryCompleted",
+            ServicesAutoRediscovered { .. } => "ServicesAutoRediscovered",
+            ServicesChanged { .. } => "ServicesChanged",
+            ServicesDiscovered { .. } => "ServicesDiscovered",
+            SubscriptionChangeResult { .. } => "SubscriptionChangeResult",
+            WriteCharacteristicResult { .. } => "WriteCharacteristicResult",
+            WriteSequenceComplete { .. } => "WriteSequenceComplete",
+            WriteDescriptorResult { .. } => "WriteDescriptorResult",
+        }
+    }
+
+    /// Returns summary of this event for the event history. It has the event name, the peripheral
+    /// id and the error kind, but no values, advertisement data or names.
+    fn summary(&self) -> String {
+        let mut r = self.name().to_owned();
+        if let CentralEvent::ManagerStateChanged { new_state } = self {
+            r += &format!(" new_state={:?}", new_state);
+        }
+        if let Some(id) = self.peripheral_id() {
+            r += &format!(" peripheral={}", id);
+        }
+        if let Some(e) = self.error() {
+            r += &format!(" error={}", e.kind());
+        }
+        r
+    }
+}
+
+/// Routes events of a central manager to per-peripheral receivers. Created with
+/// [`route_by_peripheral`](fn.route_by_peripheral.html).
+pub struct PeripheralRouter {
+    routes: sync::Routes<PeripheralId, CentralEvent>,
+}
+
+impl PeripheralRouter {
+    /// Returns receiver of the events about the peripheral with the `id`, as reported by
+    /// [`CentralEvent::peripheral_id`](enum.CentralEvent.html#method.peripheral_id).
+    ///
+    /// Only events received after this call are routed to the returned receiver. Calling this
+    /// again for the same `id` replaces the previously returned receiver. Once the receiver is
+    /// dropped, the events of the peripheral go to the receiver returned by
+    /// [`route_by_peripheral`](fn.route_by_peripheral.html) again. The event being forwarded when
+    /// the drop is noticed is discarded.
+    pub fn stream_for(&self, id: PeripheralId) -> Receiver<CentralEvent> {
+        self.routes.add(id)
+    }
+}
+
+/// Splits the event `receiver` of a central manager into per-peripheral event streams, so events
+/// of each peripheral can be handled in a separate thread or task.
+///
+/// The streams are obtained with [`PeripheralRouter::stream_for`](struct.PeripheralRouter.html#method.stream_for).
+/// The returned receiver gets the events that aren't about a single peripheral, such as
+/// [`ManagerStateChanged`](enum.CentralEvent.html#variant.ManagerStateChanged), and the events of
+/// peripherals without a stream, such as
+/// [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered) of new
+/// peripherals.
+///
+/// A single background forwarder hands each event over to its stream and waits until the event
+/// is taken, so the backpressure of the manager channel is preserved. As a consequence a stream
+/// that is kept but not consumed holds up the events of all other streams, including the returned
+/// receiver. Drop the stream of a peripheral whose events aren't needed, its further events are
+/// then forwarded to the returned receiver.
+pub fn route_by_peripheral(receiver: Receiver<CentralEvent>)
+    -> (PeripheralRouter, Receiver<CentralEvent>)
+{
+    let (routes, rest) = sync::route(receiver, |event| event.peripheral_id());
+    (PeripheralRouter { routes }, rest)
+}
+
+/// Splits the event `receiver` of a central manager into two receivers: the first one gets the
+/// events carrying an error (as reported by [`CentralEvent::error`](enum.CentralEvent.html#method.error))
+/// along with a copy of the error, the second one gets all the other events.
+///
+/// The events are forwarded in the background one at a time, and the forwarding waits until
+/// the receiver of each event takes it, so the backpressure of the manager channel is preserved.
+/// This means a receiver that is kept but not consumed blocks the other one too; drop a receiver
+/// whose events aren't needed.
+pub fn errors_only(receiver: Receiver<CentralEvent>)
+    -> (Receiver<(CentralEvent, Error)>, Receiver<CentralEvent>)
+{
+    sync::split(receiver, |event| event.error().cloned())
+}
+
+/// Drops high-rate events of the `receiver` of a central manager that arrive sooner than
+/// `min_interval` after the last passed event of the same kind for the same peripheral, so a slow
+/// consumer such as UI isn't flooded. The throttled events are:
+///
+/// * [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered), which is
+///   repeated for every advertisement when scanning with duplicates allowed. The first discovery
+///   of each peripheral always passes.
+/// * Successful [`ReadRssiResult`](enum.CentralEvent.html#variant.ReadRssiResult).
+/// * Successful [`CharacteristicValue`](enum.CentralEvent.html#variant.CharacteristicValue),
+///   throttled separately for each characteristic UUID.
+///
+/// All other events, including connection and manager state changes and errors, always pass.
+///
+/// The throttling is leading-edge only: a dropped event is never delivered later, even if no
+/// other event of its kind follows. In particular the last event of a burst is lost, so the
+/// latest characteristic value or RSSI may never reach the returned receiver.
+///
+/// The background forwarder hands over the passed events one at a time and waits for each to be
+/// taken before reading the next one from `receiver`.
+pub fn throttle(receiver: Receiver<CentralEvent>, min_interval: Duration) -> Receiver<CentralEvent> {
+    let mut limiter = RateLimiter::new(min_interval);
+    sync::filter(receiver, move |event| limiter.pass(throttle_key(event), Instant::now()))
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum ThrottledKind {
+    Discovered,
+    Rssi,
+    Value(Uuid),
+}
+
+/// Returns the key to throttle the `event` by, or `None` if it must not be throttled.
+fn throttle_key(event: &CentralEvent) -> Option<(PeripheralId, ThrottledKind)> {
+    use CentralEvent::*;
+    let (peripheral, kind) = match event {
+        PeripheralDiscovered { peripheral, .. } => (peripheral, ThrottledKind::Discovered),
+        ReadRssiResult { peripheral, rssi: Ok(_) } => (peripheral, ThrottledKind::Rssi),
+        CharacteristicValue { peripheral, characteristic, value: Ok(_), .. } =>
+            (peripheral, ThrottledKind::Value(characteristic.id())),
+        _ => return None,
+    };
+    Some((peripheral.id(), kind))
+}
+
+/// Passes at most one item per key within the interval.
+struct RateLimiter<K> {
+    min_interval: Duration,
+
+    /// Time the last item passed, keyed by item key.
+    last_passed: HashMap<K, Instant>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_passed: HashMap::new(),
+        }
+    }
+
+    /// Returns `true` if the item with the `key` arriving at `now` should pass. Items without key
+    /// always pass.
+    fn pass(&mut self, key: Option<K>, now: Instant) -> bool {
+        let key = if let Some(v) = key {
+            v
+        } else {
+            return true;
+        };
+        match self.last_passed.get(&key) {
+            Some(&last) if now.saturating_duration_since(last) < self.min_interval => false,
+            _ => {
+                self.last_passed.insert(key, now);
+                true
+            }
+        }
+    }
+}
+
+/// The reason of command rejection reported in
+/// [`CommandRejected`](enum.CentralEvent.html#variant.CommandRejected) event.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum CommandRejectReason {
+    /// The command references a service or characteristic that was invalidated, as previously
+    /// reported by [`ServicesChanged`](enum.CentralEvent.html#variant.ServicesChanged) event.
+    /// The service must be discovered again.
+    StaleHandle,
+
+    /// The value passed to [`write_characteristic`](peripheral/struct.Peripheral.html#method.write_characteristic)
+    /// exceeds the maximum write length for the write kind. Reported only if enabled with
+    /// [`validate_write_len`](struct.CentralManagerBuilder.html#method.validate_write_len).
+    WriteTooLarge {
+        /// Length of the value.
+        len: usize,
+
+        /// Maximum write length for the write kind.
+        max: usize,
+    },
+
+    /// The characteristic requested in
+    /// [`discover_and_subscribe`](peripheral/struct.Peripheral.html#method.discover_and_subscribe)
+    /// wasn't found in the service, or the service or characteristic requested in
+    /// [`write_by_uuid`](peripheral/struct.Peripheral.html#method.write_by_uuid) wasn't discovered.
+    NotDiscovered {
+        /// UUID of the service or characteristic that wasn't found.
+        uuid: Uuid,
+    },
+
+    /// The characteristic requested in
+    /// [`discover_and_subscribe`](peripheral/struct.Peripheral.html#method.discover_and_subscribe),
+    /// [`subscribe_all`](peripheral/struct.Peripheral.html#method.subscribe_all) or
+    /// [`unsubscribe_all`](peripheral/struct.Peripheral.html#method.unsubscribe_all)
+    /// supports neither notifications nor indications.
+    NotSubscribable {
+        /// UUID of the characteristic.
+        uuid: Uuid,
+    },
+
+    /// The characteristic passed to
+    /// [`read_characteristic_checked`](peripheral/struct.Peripheral.html#method.read_characteristic_checked)
+    /// isn't readable.
+    NotReadable {
+        /// UUID of the characteristic.
+        uuid: Uuid,
+    },
+}
+
 /// Peripheral scanning options accepted by [`scan_with_options`](struct.CentralManager.html#method.scan_with_options).
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct ScanOptions {
     allow_duplicates: bool,
-    service_cbuuids: Option<StrongPtr<NSArray>>,
-    solicited_service_cbuuids: Option<StrongPtr<NSArray>>,
+    background_compatible: bool,
+    estimate_interval: bool,
+    prefer_coded_phy: bool,
+    show_power_alert: Option<bool>,
+    services: Option<Vec<Uuid>>,
+    solicited_services: Option<Vec<Uuid>>,
 }
 
 impl ScanOptions {
@@ -373,35 +860,150 @@ impl ScanOptions {
         self
     }
 
+    /// Specifies whether the scan should stay compatible with background execution.
+    ///
+    /// The system handles scans of apps running in background differently: duplicate filtering
+    /// can't be disabled and scans that don't specify any services UUIDs don't discover anything.
+    /// If `true`, [`allow_duplicates`](#method.allow_duplicates) is forced off and a warning is
+    /// logged for every part of the configuration the system would silently ignore. This is
+    /// useful for menu bar and daemon apps. The default is `false`.
+    pub fn background_compatible(mut self, v: bool) -> Self {
+        self.background_compatible = v;
+        self
+    }
+
+    /// Specifies whether [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered)
+    /// events should carry the estimated advertising interval of the peripheral.
+    ///
+    /// The estimate is the time elapsed since the previous discovery of the same peripheral, so
+    /// it's only meaningful with [`allow_duplicates`](#method.allow_duplicates) enabled. Tracking
+    /// is reset when the scan is restarted or cancelled. The default is `false`.
+    pub fn estimate_interval(mut self, v: bool) -> Self {
+        self.estimate_interval = v;
+        self
+    }
+
+    /// Specifies whether the scan should also discover peripherals advertising on the LE Coded PHY
+    /// (long range).
+    ///
+    /// Core Bluetooth doesn't allow selecting the PHY explicitly: on hardware that supports
+    /// extended scanning the system scans on the Coded PHY automatically. This option checks
+    /// the support at scan time and logs a warning if the Coded PHY is unavailable, in which case
+    /// the scan proceeds on the LE 1M PHY only. Extended scanning support can only be detected on
+    /// iOS 13 and later, on other platforms it's always considered unsupported.
+    /// The default is `false`.
+    pub fn prefer_coded_phy(mut self, v: bool) -> Self {
+        self.prefer_coded_phy = v;
+        self
+    }
+
+    /// Specifies whether the system should warn the user if Bluetooth is powered off while
+    /// scanning.
+    ///
+    /// Core Bluetooth has no per-scan power alert setting: `CBCentralManagerOptionShowPowerAlertKey`
+    /// is only honored when the central manager is created and
+    /// `scanForPeripheralsWithServices:options:` silently ignores it on all current OS versions.
+    /// For this reason the option is not passed to the system and a warning is logged if it's set.
+    /// Note the central manager is always created with the power alert disabled, so no alert is
+    /// shown during scans regardless of this option.
+    pub fn show_power_alert(mut self, v: bool) -> Self {
+        self.show_power_alert = Some(v);
+        self
+    }
+
     /// Specifies services UUIDs making the central manager return only peripherals that advertise
     /// these services.
     pub fn include_services(mut self, uuids: &[Uuid]) -> Self {
-        if self.service_cbuuids.is_none() {
-            self.service_cbuuids = Some(NSArray::with_capacity(uuids.len()).retain());
-        }
-        for &uuid in uuids {
-            self.service_cbuuids.as_ref().unwrap().push(CBUUID::from_uuid(uuid));
-        }
+        self.services.get_or_insert_with(Vec::new).extend_from_slice(uuids);
         self
     }
 
     /// Specifying this scan option causes the central manager to also scan for peripherals
     /// soliciting any of the services contained in the array.
     pub fn include_solicited_services(mut self, uuids: &[Uuid]) -> Self {
-        if self.solicited_service_cbuuids.is_none() {
-            self.solicited_service_cbuuids = Some(NSArray::with_capacity(uuids.len()).retain());
+        self.solicited_services.get_or_insert_with(Vec::new).extend_from_slice(uuids);
+        self
+    }
+
+    fn background_issues(&self) -> Vec<&'static str> {
+        let mut r = Vec::new();
+        if !self.background_compatible {
+            return r;
         }
-        for &uuid in uuids {
-            self.solicited_service_cbuuids.as_ref().unwrap().push(CBUUID::from_uuid(uuid));
+        if self.allow_duplicates {
+            r.push("duplicate filtering can't be disabled in background, allow_duplicates is ignored");
         }
-        self
+        if self.services.is_none() {
+            r.push("scans without service UUIDs don't discover peripherals in background");
+        }
+        r
+    }
+
+    /// Returns `true` if the Coded PHY was requested but can't be used.
+    fn coded_phy_unavailable(&self, extended_scan_supported: bool) -> bool {
+        self.prefer_coded_phy && !extended_scan_supported
+    }
+
+    /// Returns `true` if the power alert was configured. The system doesn't support it per scan.
+    fn power_alert_ignored(&self) -> bool {
+        self.show_power_alert.is_some()
+    }
+
+    /// Merges `options` of several scans into options of a single scan that discovers everything
+    /// any of them would: services are united and a scan without services makes the merged scan
+    /// discover all peripherals, flags that widen the scan are enabled if any scan enables them.
+    /// Returns `None` if there are no `options`.
+    fn merge<'a>(mut options: impl Iterator<Item=&'a ScanOptions>) -> Option<ScanOptions> {
+        fn union(mut a: Vec<Uuid>, b: &[Uuid]) -> Vec<Uuid> {
+            for uuid in b {
+                if !a.contains(uuid) {
+                    a.push(*uuid);
+                }
+            }
+            a
+        }
+
+        let mut r = options.next()?.clone();
+        for o in options {
+            r.allow_duplicates |= o.allow_duplicates;
+            r.background_compatible &= o.background_compatible;
+            r.estimate_interval |= o.estimate_interval;
+            r.prefer_coded_phy |= o.prefer_coded_phy;
+            r.show_power_alert = r.show_power_alert.or(o.show_power_alert);
+            r.services = match (r.services, &o.services) {
+                (Some(a), Some(b)) => Some(union(a, b)),
+                _ => None,
+            };
+            r.solicited_services = match (r.solicited_services, &o.solicited_services) {
+                (Some(a), Some(b)) => Some(union(a, b)),
+                (a, b) => a.or_else(|| b.clone()),
+            };
+        }
+        Some(r)
+    }
+
+    /// Returns all entries of the options dictionary passed to the system. The services UUIDs
+    /// aren't part of the dictionary, they're passed to the system separately.
+    fn options(&self) -> Vec<(ScanOptionKey, ScanOptionValue<'_>)> {
+        let mut r = vec![(ScanOptionKey::AllowDuplicates,
+            ScanOptionValue::Bool(self.allow_duplicates && !self.background_compatible))];
+        if let Some(uuids) = self.solicited_services.as_ref() {
+            r.push((ScanOptionKey::SolicitedServiceUuids, ScanOptionValue::Uuids(uuids)));
+        }
+        r
     }
 
     fn to_options_dict(&self) -> NSDictionary {
-        let dict = NSDictionary::with_capacity(2);
-        dict.insert(unsafe { CBCentralManagerScanOptionAllowDuplicatesKey }, NSNumber::new_bool(self.allow_duplicates));
-        if let Some(uuids) = self.solicited_service_cbuuids.as_ref() {
-            dict.insert(unsafe { CBCentralManagerScanOptionSolicitedServiceUUIDsKey }, uuids.as_ptr());
+        let options = self.options();
+        debug_assert!(options.iter().enumerate()
+            .all(|(i, (k, _))| options[..i].iter().all(|(pk, _)| pk != k)),
+            "duplicate scan option keys");
+        let dict = NSDictionary::with_capacity(options.len() as NSUInteger);
+        for (key, value) in options {
+            match value {
+                ScanOptionValue::Bool(v) => dict.insert(key.to_ns_string(), NSNumber::new_bool(v)),
+                ScanOptionValue::Uuids(v) => dict.insert(key.to_ns_string(), CBUUID::array_from_uuids(v)),
+            }
         }
         dict
     }
@@ -409,87 +1011,599 @@ impl ScanOptions {
 
 assert_impl_all!(ScanOptions: Send, Sync);
 
-struct Inner {
+/// Scan requested with [`CentralManager::begin_scan_session`](struct.CentralManager.html#method.begin_scan_session).
+///
+/// The session lasts until it's dropped.
+#[must_use = "scan session ends when dropped"]
+pub struct ScanSession {
     manager: StrongPtr<CBCentralManager>,
+    id: u64,
 }
 
-impl Drop for Inner {
+assert_impl_all!(ScanSession: Send, Sync);
+
+impl ScanSession {
+    /// Ends the session. This is the same as dropping it.
+    pub fn end(self) {}
+}
+
+impl Drop for ScanSession {
     fn drop(&mut self) {
-        command::Manager {
-            manager: self.manager.clone(),
-        }.drop_self();
+        objc::rc::autoreleasepool(|| {
+            command::EndScanSession {
+                manager: self.manager.clone(),
+                id: self.id,
+            }.dispatch();
+        });
     }
 }
 
-/// An object that scans for, discovers, connects to, and manages peripherals.
-///
-/// Before calling the `CentralManager` methods,
-/// [`ManagerStateChanged`](enum.CentralEvent.html#variant.ManagerStateChanged)
-/// event must be received indicating the [PoweredOn](../enum.ManagerState.html#variant.PoweredOn)
-/// state.
-#[derive(Clone)]
-// TODO The only reason why Arc is needed here is that we need to cleanup Delegate resources.
-// Unfortunately objc lib can't replace methods.
-pub struct CentralManager(Arc<Inner>);
+/// Key of the scan options dictionary entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ScanOptionKey {
+    AllowDuplicates,
+    SolicitedServiceUuids,
+}
 
-assert_impl_all!(CentralManager: Send, Sync);
+impl ScanOptionKey {
+    fn to_ns_string(self) -> NSString {
+        unsafe {
+            match self {
+                Self::AllowDuplicates => CBCentralManagerScanOptionAllowDuplicatesKey,
+                Self::SolicitedServiceUuids => CBCentralManagerScanOptionSolicitedServiceUUIDsKey,
+            }
+        }
+    }
+}
 
-impl CentralManager {
-    pub fn new() -> (Self, sync::Receiver<CentralEvent>) {
-        objc::rc::autoreleasepool(|| {
-            let (manager, recv) = CBCentralManager::new(false);
-            (Self(Arc::new(Inner {
-                manager,
-            })), recv)
-        })
+/// Value of the scan options dictionary entry.
+enum ScanOptionValue<'a> {
+    Bool(bool),
+    Uuids(&'a [Uuid]),
+}
+
+/// Peripheral connection options accepted by
+/// [`connect_with_options`](struct.CentralManager.html#method.connect_with_options).
+#[derive(Clone, Debug, Default)]
+pub struct ConnectOptions {
+    requires_ancs: bool,
+}
+
+impl ConnectOptions {
+    /// Specifies whether the system should require Apple Notification Center Service (ANCS)
+    /// authorization before reporting the connection.
+    ///
+    /// If `true`, the [`PeripheralConnected`](enum.CentralEvent.html#variant.PeripheralConnected)
+    /// event is triggered only after the user has authorized the peripheral to access ANCS,
+    /// so receiving the event implies ANCS is available to the peripheral. The default is `false`.
+    ///
+    /// The option exists only on iOS 13 and later. On macOS it isn't passed to the system and has
+    /// no effect.
+    pub fn requires_ancs(mut self, v: bool) -> Self {
+        self.requires_ancs = v;
+        self
     }
 
-    /// Returns a list of known peripherals by their identifiers. The result is returned as
-    /// [`GetPeripheralsWithServicesResult`](enum.CentralEvent.html#variant.GetPeripheralsWithServicesResult).
-    pub fn get_peripherals(&self, uuids: &[Uuid]) {
-        self.get_peripherals_tagged0(uuids, None);
+    /// Returns all entries of the options dictionary passed to the system. Options the platform
+    /// doesn't have are left out.
+    fn options(&self) -> Vec<(ConnectOptionKey, bool)> {
+        let mut r = Vec::new();
+        if cfg!(target_os = "ios") {
+            r.push((ConnectOptionKey::RequiresAncs, self.requires_ancs));
+        }
+        r
     }
 
-    /// Allows tagging an asynchronous [`get_peripherals`](struct.CentralManager.html#method.get_peripherals)
-    /// call with arbitrary `tag`.
-    pub fn get_peripherals_tagged(&self, uuids: &[Uuid], tag: Tag) {
-        self.get_peripherals_tagged0(uuids, Some(tag))
+    fn to_options_dict(&self) -> NSDictionary {
+        let options = self.options();
+        let dict = NSDictionary::with_capacity(options.len() as NSUInteger);
+        for (key, value) in options {
+            dict.insert(key.to_ns_string(), NSNumber::new_bool(value));
+        }
+        dict
     }
+}
 
-    /// Retrieves a list of the peripherals connected to the system whose services match
-    /// the specified `services_uuids`. The result is returned as
-    /// [`GetPeripheralsWithServicesResult`](enum.CentralEvent.html#variant.GetPeripheralsWithServicesResult).
-    pub fn get_peripherals_with_services(&self, services_uuids: &[Uuid]) {
-        self.get_peripherals_with_services_tagged0(services_uuids, None);
+assert_impl_all!(ConnectOptions: Send, Sync);
+
+/// Key of the connection options dictionary entry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ConnectOptionKey {
+    /// Exists only on iOS.
+    RequiresAncs,
+}
+
+impl ConnectOptionKey {
+    fn to_ns_string(self) -> NSString {
+        match self {
+            #[cfg(target_os = "ios")]
+            Self::RequiresAncs => unsafe { CBConnectPeripheralOptionRequiresANCS },
+            #[cfg(not(target_os = "ios"))]
+            Self::RequiresAncs => unreachable!("RequiresAncs option on a platform without it"),
+        }
     }
+}
 
-    /// Allows tagging an asynchronous [`get_peripherals_with_services`](struct.CentralManager.html#method.get_peripherals_with_services)
-    /// call with arbitrary `tag`.
-    pub fn get_peripherals_with_services_tagged(&self, services_uuids: &[Uuid], tag: Tag) {
-        self.get_peripherals_with_services_tagged0(services_uuids, Some(tag));
+/// Which services an app scans for and which peripherals it reconnects to, applied with
+/// [`apply_session`](struct.CentralManager.html#method.apply_session).
+///
+/// With the `serde` feature the descriptor is serializable and deserializable, so the app can
+/// persist its session and restore it on the next launch. Unlike the system state restoration,
+/// which is only available on iOS, this is fully controlled by the app.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SessionDescriptor {
+    /// UUIDs of services to scan for. If empty, all peripherals are discovered.
+    pub scan_services: Vec<Uuid>,
+
+    /// See [`ScanOptions::allow_duplicates`](struct.ScanOptions.html#method.allow_duplicates).
+    pub scan_allow_duplicates: bool,
+
+    /// Identifiers of peripherals to reconnect to, as returned by
+    /// [`Peripheral::id`](peripheral/struct.Peripheral.html#method.id).
+    pub reconnect_peripherals: Vec<Uuid>,
+}
+
+impl SessionDescriptor {
+    fn scan_options(&self) -> ScanOptions {
+        let options = ScanOptions::default().allow_duplicates(self.scan_allow_duplicates);
+        if self.scan_services.is_empty() {
+            options
+        } else {
+            options.include_services(&self.scan_services)
+        }
     }
+}
 
-    /// Scans for peripherals with default options.
-    /// See [`scan_with_options`](struct.CentralManager.html#method.scan_with_options).
-    pub fn scan(&self) {
-        self.scan_with_options(Default::default());
+/// Default value of [`CentralManagerBuilder::max_advertisement_bytes`](struct.CentralManagerBuilder.html#method.max_advertisement_bytes).
+pub const DEFAULT_MAX_ADVERTISEMENT_BYTES: usize = 4096;
+
+/// Function deciding whether an event should be sent to the receiver.
+pub(in crate) type EventFilter = Box<dyn FnMut(&CentralEvent) -> bool + Send>;
+
+/// Function called for each discovered peripheral, see
+/// [`CentralManager::on_discovered`](struct.CentralManager.html#method.on_discovered).
+pub(in crate) type DiscoveredCallback<P = Peripheral> =
+    Box<dyn FnMut(P, AdvertisementData, i32) + Send>;
+
+/// Builder of [`CentralManager`](struct.CentralManager.html) with non-default settings.
+pub struct CentralManagerBuilder {
+    validate_write_len: bool,
+    event_overflow: EventOverflow,
+    auto_rediscover_services: bool,
+    max_advertisement_bytes: usize,
+    event_filter: Option<EventFilter>,
+    event_history: usize,
+    link_quality_warning: Option<(i32, Duration)>,
+    keep_user_data_on_disconnect: bool,
+}
+
+impl Default for CentralManagerBuilder {
+    fn default() -> Self {
+        Self {
+            validate_write_len: false,
+            event_overflow: EventOverflow::default(),
+            auto_rediscover_services: false,
+            max_advertisement_bytes: DEFAULT_MAX_ADVERTISEMENT_BYTES,
+            event_filter: None,
+            event_history: 0,
+            link_quality_warning: None,
+            keep_user_data_on_disconnect: false,
+        }
     }
+}
 
-    /// Scans for peripherals that are advertising services with the specified `options`.
+impl CentralManagerBuilder {
+    /// Creates builder with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Specifies whether characteristic writes should be validated against the maximum write
+    /// length of the peripheral.
     ///
-    /// If the central manager is actively scanning with one set of parameters and it receives
-    /// another set to scan, the new parameters override the previous set. When the central manager
-    /// discovers a peripheral, it triggers
-    /// [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered) event.
-    pub fn scan_with_options(&self, options: ScanOptions) {
-        objc::rc::autoreleasepool(|| {
-            command::Scan {
-                manager: self.0.manager.clone(),
-                options,
+    /// If `true`, a write with value longer than the maximum write length for the write kind is
+    /// not issued and [`CommandRejected`](enum.CentralEvent.html#variant.CommandRejected) event
+    /// with [`WriteTooLarge`](enum.CommandRejectReason.html#variant.WriteTooLarge) reason is
+    /// triggered instead. The default is `false`.
+    pub fn validate_write_len(mut self, v: bool) -> Self {
+        self.validate_write_len = v;
+        self
+    }
+
+    /// Specifies what the central manager does when the event channel is full.
+    /// The default is [`EventOverflow::Block`](metrics/enum.EventOverflow.html#variant.Block).
+    pub fn event_overflow(mut self, v: EventOverflow) -> Self {
+        self.event_overflow = v;
+        self
+    }
+
+    /// Specifies whether services invalidated by a change of the peripheral's services should be
+    /// rediscovered automatically.
+    ///
+    /// If `true`, upon [`ServicesChanged`](enum.CentralEvent.html#variant.ServicesChanged) event
+    /// the services with UUIDs of the invalidated services are discovered again and
+    /// [`ServicesAutoRediscovered`](enum.CentralEvent.html#variant.ServicesAutoRediscovered) event
+    /// is triggered once done. Services newly added to the peripheral aren't discovered. The
+    /// default is `false`.
+    pub fn auto_rediscover_services(mut self, v: bool) -> Self {
+        self.auto_rediscover_services = v;
+        self
+    }
+
+    /// Specifies the maximum length of a single manufacturer data or service data entry of
+    /// advertisement data.
+    ///
+    /// Longer entries are dropped from [`AdvertisementData`](struct.AdvertisementData.html) with
+    /// a warning logged, so a misbehaving peripheral can't make the manager allocate arbitrary
+    /// amounts of memory. The default is
+    /// [`DEFAULT_MAX_ADVERTISEMENT_BYTES`](constant.DEFAULT_MAX_ADVERTISEMENT_BYTES.html), which
+    /// is well above the size of extended advertising data.
+    pub fn max_advertisement_bytes(mut self, v: usize) -> Self {
+        self.max_advertisement_bytes = v;
+        self
+    }
+
+    /// Specifies a function called for every event before it's sent to the receiver. If the
+    /// function returns `false`, the event is dropped. This allows filtering out unwanted events
+    /// or collecting statistics without forwarding events through another thread.
+    ///
+    /// The function is called on the dispatch queue of the central manager while it processes
+    /// the event, so it must be cheap and must not block. It must not call methods of the central
+    /// manager or its objects that wait for the manager's response. Dropped events aren't counted
+    /// in [`metrics`](struct.CentralManager.html#method.metrics).
+    pub fn event_filter(mut self, f: impl FnMut(&CentralEvent) -> bool + Send + 'static) -> Self {
+        self.event_filter = Some(Box::new(f));
+        self
+    }
+
+    /// Specifies how many of the most recent events are kept for
+    /// [`recent_events`](struct.CentralManager.html#method.recent_events). The default is `0`,
+    /// which disables the event history.
+    ///
+    /// Only event summaries are kept: the event name, the peripheral id and the error kind.
+    /// Values, advertisement data and names aren't retained. Events dropped by
+    /// [`event_filter`](struct.CentralManagerBuilder.html#method.event_filter) are kept too.
+    pub fn event_history(mut self, capacity: usize) -> Self {
+        self.event_history = capacity;
+        self
+    }
+
+    /// Enables [`LinkQualityWarning`](enum.CentralEvent.html#variant.LinkQualityWarning) event,
+    /// triggered when the RSSI of a peripheral stays below `threshold` decibels for at least
+    /// `window`. The event is triggered once until the RSSI recovers. Disabled by default.
+    ///
+    /// The RSSI is only known when the peripheral is discovered or its RSSI is read, so while
+    /// connected call [`read_rssi`](peripheral/struct.Peripheral.html#method.read_rssi)
+    /// periodically. A peripheral without RSSI readings for longer than `window` is considered
+    /// lost and its evaluation starts over with the next reading.
+    pub fn link_quality_warning(mut self, threshold: i32, window: Duration) -> Self {
+        self.link_quality_warning = Some((threshold, window));
+        self
+    }
+
+    /// Specifies whether data attached with
+    /// [`Peripheral::set_user_data`](peripheral/struct.Peripheral.html#method.set_user_data)
+    /// should be kept when the peripheral disconnects. The default is `false`.
+    pub fn keep_user_data_on_disconnect(mut self, v: bool) -> Self {
+        self.keep_user_data_on_disconnect = v;
+        self
+    }
+
+    /// Creates the central manager. Returns the manager and the receiving end of the channel
+    /// where the manager sends its events.
+    pub fn build(mut self) -> (CentralManager, sync::Receiver<CentralEvent>) {
+        objc::rc::autoreleasepool(|| {
+            let shared = Arc::new(Shared {
+                manager_state: AtomicU8::new(ManagerState::Unknown as u8),
+                metrics: Default::default(),
+                connects: Default::default(),
+                connected: Default::default(),
+                throttle: Default::default(),
+                history: Mutex::new(delegate::EventHistory::new(self.event_history)),
+                user_data: Default::default(),
+            });
+            let (manager, recv) = CBCentralManager::new(false, &mut self, shared.clone());
+            (CentralManager(Arc::new(Inner {
+                manager,
+                shared,
+                identifier: next_identifier(),
+            })), recv)
+        })
+    }
+}
+
+/// State shared between the central manager and its delegate.
+pub(in crate) struct Shared {
+    /// Last known manager state.
+    manager_state: AtomicU8,
+
+    metrics: metrics::Counters,
+
+    connects: Arc<connect::ConnectRegistry>,
+
+    /// Peripherals connected by this manager and not disconnected yet.
+    connected: Mutex<delegate::Connections<Peripheral>>,
+
+    /// Connection attempts started by `CentralManager::connect_throttled`.
+    throttle: Mutex<connect::ConnectThrottle<Peripheral>>,
+
+    /// Summaries of the most recent events, if enabled in `CentralManagerBuilder`.
+    history: Mutex<delegate::EventHistory>,
+
+    /// Data attached with `Peripheral::set_user_data`. It's accessed from the app threads
+    /// directly, without a round trip through the manager queue.
+    user_data: Mutex<delegate::UserDataMap>,
+}
+
+/// Returns a new process-unique central manager identifier. CoreBluetooth doesn't provide one.
+fn next_identifier() -> Uuid {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    let mut bytes = [0; 16];
+    bytes[..4].copy_from_slice(&std::process::id().to_be_bytes());
+    bytes[8..].copy_from_slice(&NEXT.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    Uuid::from_bytes(bytes)
+}
+
+struct Inner {
+    manager: StrongPtr<CBCentralManager>,
+    shared: Arc<Shared>,
+    identifier: Uuid,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        trace!("dropping central manager {:?}, retain count: {}", *self.manager,
+            unsafe { self.manager.retain_count() });
+        command::Manager {
+            manager: self.manager.clone(),
+        }.drop_self();
+    }
+}
+
+/// An object that scans for, discovers, connects to, and manages peripherals.
+///
+/// Before calling the `CentralManager` methods,
+/// [`ManagerStateChanged`](enum.CentralEvent.html#variant.ManagerStateChanged)
+/// event must be received indicating the [PoweredOn](../enum.ManagerState.html#variant.PoweredOn)
+/// state.
+#[derive(Clone)]
+// TODO The only reason why Arc is needed here is that we need to cleanup Delegate resources.
+// Unfortunately objc lib can't replace methods.
+pub struct CentralManager(Arc<Inner>);
+
+assert_impl_all!(CentralManager: Send, Sync);
+
+impl CentralManager {
+    /// Creates central manager with default settings.
+    /// See [`CentralManagerBuilder`](struct.CentralManagerBuilder.html).
+    pub fn new() -> (Self, sync::Receiver<CentralEvent>) {
+        CentralManagerBuilder::new().build()
+    }
+
+    fn state(&self) -> ManagerState {
+        ManagerState::from_u8(self.0.shared.manager_state.load(Ordering::SeqCst))
+            .unwrap_or(ManagerState::Unknown)
+    }
+
+    /// Returns the identifier of this central manager, useful to tell apart events and logs of
+    /// multiple managers.
+    ///
+    /// CoreBluetooth doesn't provide a stable manager identifier, so currently this is always
+    /// `Some` identifier assigned when the manager is built. It's unique within the process and
+    /// doesn't persist across process restarts. Clones of the manager share the identifier.
+    pub fn identifier(&self) -> Option<Uuid> {
+        Some(self.0.identifier)
+    }
+
+    /// Returns whether the device supports Bluetooth low energy central role, judging by the last
+    /// known state of the manager. See
+    /// [`ManagerState::is_le_supported`](../enum.ManagerState.html#method.is_le_supported).
+    ///
+    /// This doesn't wait for the manager to report its state, so it returns `None` right after
+    /// the manager is built. The definitive answer comes with the
+    /// [`ManagerStateChanged`](enum.CentralEvent.html#variant.ManagerStateChanged) event.
+    pub fn is_le_supported(&self) -> Option<bool> {
+        self.state().is_le_supported()
+    }
+
+    /// Returns the current event delivery metrics.
+    pub fn metrics(&self) -> Metrics {
+        self.0.shared.metrics.snapshot()
+    }
+
+    /// Returns summaries of the most recent events, oldest first. Returns an empty vector unless
+    /// enabled with
+    /// [`CentralManagerBuilder::event_history`](struct.CentralManagerBuilder.html#method.event_history).
+    ///
+    /// This is meant for diagnostics, for example dumping the history after an unexpected
+    /// disconnect. The format of the summaries isn't stable.
+    pub fn recent_events(&self) -> Vec<String> {
+        self.0.shared.history.lock().unwrap().to_vec()
+    }
+
+    /// Pauses event delivery. Until [`resume_events`](struct.CentralManager.html#method.resume_events)
+    /// is called, events are held in a buffer instead of being sent to the receiver.
+    ///
+    /// The buffer holds up to 1024 events, or up to the channel capacity with
+    /// [`EventOverflow::DropNewest`](metrics/enum.EventOverflow.html#variant.DropNewest) policy.
+    /// When the buffer is full, with the `DropNewest` policy new events are dropped. With the
+    /// [`Block`](metrics/enum.EventOverflow.html#variant.Block) and
+    /// [`BlockWithTimeout`](metrics/enum.EventOverflow.html#variant.BlockWithTimeout) policies
+    /// the buffered and new events are sent to the receiver as if the delivery wasn't paused.
+    pub fn pause_events(&self) {
+        objc::rc::autoreleasepool(|| {
+            command::Manager {
+                manager: self.0.manager.clone(),
+            }.pause_events();
+        })
+    }
+
+    /// Registers the function `f` to be called with the peripheral, its advertisement data and
+    /// RSSI for each discovered peripheral, replacing the previously registered function.
+    ///
+    /// This is an alternative to handling
+    /// [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered) events, which
+    /// are still sent to the receiver as usual.
+    ///
+    /// The function is called on the serial dispatch queue of the manager right before the event is
+    /// sent, so it should return quickly: no other events are processed while it runs.
+    pub fn on_discovered(&self, f: impl FnMut(Peripheral, AdvertisementData, i32) + Send + 'static) {
+        objc::rc::autoreleasepool(|| {
+            command::SetDiscoveredCallback {
+                manager: self.0.manager.clone(),
+                callback: Box::new(f),
+            }.dispatch();
+        })
+    }
+
+    /// Resumes event delivery paused with [`pause_events`](struct.CentralManager.html#method.pause_events),
+    /// sending the buffered events to the receiver first.
+    pub fn resume_events(&self) {
+        objc::rc::autoreleasepool(|| {
+            command::Manager {
+                manager: self.0.manager.clone(),
+            }.resume_events();
+        })
+    }
+
+    /// Scans for peripherals with the specified `options` for the specified `duration`, then stops
+    /// the scan and returns the discovered peripherals along with their advertisement data and RSSI.
+    ///
+    /// Each peripheral is reported once, with the data of the discovery with the strongest RSSI.
+    /// The `receiver` must be the one returned along with this manager. All other events received
+    /// while scanning are discarded.
+    ///
+    /// Returns error if the manager isn't in the [`PoweredOn`](../enum.ManagerState.html#variant.PoweredOn)
+    /// state, either initially or at some point during the scan.
+    ///
+    /// This method blocks the current thread and is meant for simple command line tools.
+    pub fn discover_for(&self, receiver: &sync::Receiver<CentralEvent>, duration: Duration,
+        options: ScanOptions) -> Result<Vec<(Peripheral, AdvertisementData, i32)>, Error>
+    {
+        let not_powered_on = || Error::new(crate::error::ErrorKind::ManagerNotPoweredOn,
+            "central manager must be in PoweredOn state to scan");
+
+        if self.state() != ManagerState::PoweredOn {
+            return Err(not_powered_on());
+        }
+
+        let deadline = Instant::now() + duration;
+        let mut discoveries = Discoveries::new();
+        self.scan_with_options(options);
+        let r = loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match sync::recv_timeout(receiver, timeout) {
+                Some(CentralEvent::PeripheralDiscovered { peripheral, advertisement_data, rssi, .. }) => {
+                    discoveries.insert(peripheral.id(), rssi, (peripheral, advertisement_data));
+                }
+                Some(CentralEvent::ManagerStateChanged { new_state }) if new_state != ManagerState::PoweredOn => {
+                    break Err(not_powered_on());
+                }
+                Some(_) => {}
+                None => {
+                    break Ok(discoveries.into_vec()
+                        .into_iter()
+                        .map(|((p, a), rssi)| (p, a, rssi))
+                        .collect());
+                }
+            }
+        };
+        self.cancel_scan();
+        r
+    }
+
+    /// Returns a list of known peripherals by their identifiers. The result is returned as
+    /// [`GetPeripheralsWithServicesResult`](enum.CentralEvent.html#variant.GetPeripheralsWithServicesResult).
+    pub fn get_peripherals(&self, uuids: &[Uuid]) {
+        self.get_peripherals_tagged0(uuids, None);
+    }
+
+    /// Allows tagging an asynchronous [`get_peripherals`](struct.CentralManager.html#method.get_peripherals)
+    /// call with arbitrary `tag`.
+    pub fn get_peripherals_tagged(&self, uuids: &[Uuid], tag: Tag) {
+        self.get_peripherals_tagged0(uuids, Some(tag))
+    }
+
+    /// Retrieves a list of the peripherals connected to the system whose services match
+    /// the specified `services_uuids`. The result is returned as
+    /// [`GetPeripheralsWithServicesResult`](enum.CentralEvent.html#variant.GetPeripheralsWithServicesResult).
+    pub fn get_peripherals_with_services(&self, services_uuids: &[Uuid]) {
+        self.get_peripherals_with_services_tagged0(services_uuids, None);
+    }
+
+    /// Allows tagging an asynchronous [`get_peripherals_with_services`](struct.CentralManager.html#method.get_peripherals_with_services)
+    /// call with arbitrary `tag`.
+    pub fn get_peripherals_with_services_tagged(&self, services_uuids: &[Uuid], tag: Tag) {
+        self.get_peripherals_with_services_tagged0(services_uuids, Some(tag));
+    }
+
+    /// Scans for peripherals with default options.
+    /// See [`scan_with_options`](struct.CentralManager.html#method.scan_with_options).
+    pub fn scan(&self) {
+        self.scan_with_options(Default::default());
+    }
+
+    /// Scans for peripherals that are advertising services with the specified `options`.
+    ///
+    /// If the central manager is actively scanning with one set of parameters and it receives
+    /// another set to scan, the new parameters override the previous set without stopping the scan
+    /// (see [`rescan`](struct.CentralManager.html#method.rescan)). When the central manager
+    /// discovers a peripheral, it triggers
+    /// [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered) event.
+    pub fn scan_with_options(&self, options: ScanOptions) {
+        objc::rc::autoreleasepool(|| {
+            command::Scan {
+                manager: self.0.manager.clone(),
+                options,
             }.dispatch()
         })
     }
 
+    /// Changes the options of the active scan, or starts scanning if not scanning already.
+    ///
+    /// Calling [`cancel_scan`](struct.CentralManager.html#method.cancel_scan) and then
+    /// [`scan_with_options`](struct.CentralManager.html#method.scan_with_options) leaves a window
+    /// in which the manager isn't scanning. There's no need for that since the framework replaces
+    /// parameters of the active scan when asked to scan again, so this is the same as calling
+    /// `scan_with_options` with the new `options`. The advertising interval estimation state is
+    /// reset as for a new scan.
+    pub fn rescan(&self, options: ScanOptions) {
+        self.scan_with_options(options);
+    }
+
+    /// Begins a scan session with the specified `options`. The session lasts until the returned
+    /// `ScanSession` is dropped.
+    ///
+    /// Core Bluetooth runs one scan at a time, so independent parts of an app can't scan with
+    /// different options by calling [`scan_with_options`](struct.CentralManager.html#method.scan_with_options)
+    /// without overriding each other. Instead the manager scans with the options of all active
+    /// sessions merged: peripherals advertising services of any session are discovered (a session
+    /// without services makes the scan discover all peripherals) and duplicate filtering is
+    /// disabled if any session disables it. The scan is updated whenever a session begins or ends
+    /// and is stopped when the last session ends.
+    ///
+    /// Note that since the scan is shared, each session may receive
+    /// [`PeripheralDiscovered`](enum.CentralEvent.html#variant.PeripheralDiscovered) events for
+    /// peripherals matching other sessions. Calling `scan_with_options`,
+    /// [`rescan`](struct.CentralManager.html#method.rescan) or
+    /// [`cancel_scan`](struct.CentralManager.html#method.cancel_scan) overrides the scan of
+    /// the sessions until a session begins or ends.
+    pub fn begin_scan_session(&self, options: ScanOptions) -> ScanSession {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        objc::rc::autoreleasepool(|| {
+            command::BeginScanSession {
+                manager: self.0.manager.clone(),
+                id,
+                options,
+            }.dispatch();
+        });
+        ScanSession {
+            manager: self.0.manager.clone(),
+            id,
+        }
+    }
+
     /// Asks the central manager to stop scanning for peripherals.
     pub fn cancel_scan(&self) {
         objc::rc::autoreleasepool(|| {
@@ -510,14 +1624,103 @@ impl CentralManager {
     /// [`cancel_connect`](struct.CentralManager.html#method.cancel_connect) method.
     /// Dropping the `Peripheral` also implicitly cancels connection.
     pub fn connect(&self, peripheral: &Peripheral) {
+        self.connect0(peripheral, None);
+    }
+
+    /// Establishes a local connection to the `peripheral` with the specified `options`.
+    ///
+    /// See [`connect`](struct.CentralManager.html#method.connect) method.
+    pub fn connect_with_options(&self, peripheral: &Peripheral, options: ConnectOptions) {
+        self.connect0(peripheral, Some(options));
+    }
+
+    fn connect0(&self, peripheral: &Peripheral, options: Option<ConnectOptions>) {
         objc::rc::autoreleasepool(|| {
             command::Connect {
                 manager: self.0.manager.clone(),
                 peripheral: peripheral.peripheral.clone(),
+                options,
             }.dispatch()
         })
     }
 
+    /// Starts scanning and reconnecting as specified by the `session`.
+    ///
+    /// The scan is started as with [`scan_with_options`](struct.CentralManager.html#method.scan_with_options).
+    /// Then the peripherals to reconnect to are retrieved by their identifiers and connected to as
+    /// with [`connect`](struct.CentralManager.html#method.connect). Peripherals the system doesn't
+    /// know anymore are skipped with a warning logged.
+    pub fn apply_session(&self, session: &SessionDescriptor) {
+        self.scan_with_options(session.scan_options());
+        if session.reconnect_peripherals.is_empty() {
+            return;
+        }
+        objc::rc::autoreleasepool(|| {
+            let uuids = NSArray::from_iter(session.reconnect_peripherals.iter().copied()
+                .map(NSUUID::from_uuid)).retain();
+            command::Reconnect {
+                manager: self.0.manager.clone(),
+                uuids,
+                count: session.reconnect_peripherals.len(),
+            }.dispatch()
+        })
+    }
+
+    /// Establishes a local connection to the `peripheral`, keeping at most `max_concurrent`
+    /// connection attempts started by this method in progress.
+    ///
+    /// Starting many connection attempts at once can exhaust the connection slots of the system
+    /// resulting in [`ConnectionLimitReached`](../error/enum.ErrorKind.html#variant.ConnectionLimitReached)
+    /// errors. With this method attempts over the limit are queued and started in order as each
+    /// in-progress attempt completes with
+    /// [`PeripheralConnected`](enum.CentralEvent.html#variant.PeripheralConnected),
+    /// [`PeripheralConnectFailed`](enum.CentralEvent.html#variant.PeripheralConnectFailed) or
+    /// [`PeripheralDisconnected`](enum.CentralEvent.html#variant.PeripheralDisconnected) event.
+    /// The most recently specified `max_concurrent` value applies to all queued attempts, zero is
+    /// treated as one. Connecting to a peripheral that is already queued or in progress does
+    /// nothing.
+    ///
+    /// [`cancel_connect`](struct.CentralManager.html#method.cancel_connect) removes a queued
+    /// attempt. Queued attempts are discarded without any events if the manager leaves the
+    /// [`PoweredOn`](../enum.ManagerState.html#variant.PoweredOn) state.
+    pub fn connect_throttled(&self, peripheral: &Peripheral, max_concurrent: usize) {
+        let start = self.0.shared.throttle.lock().unwrap()
+            .push(peripheral.id(), peripheral.clone(), max_concurrent);
+        for peripheral in start {
+            self.connect(&peripheral);
+        }
+    }
+
+    /// Establishes a local connection to the `peripheral` and returns future that resolves once
+    /// the connection is established or failed.
+    ///
+    /// The future resolves with error if the manager isn't in the
+    /// [`PoweredOn`](../enum.ManagerState.html#variant.PoweredOn) state or leaves it while
+    /// connecting. The corresponding [`PeripheralConnected`](enum.CentralEvent.html#variant.PeripheralConnected)
+    /// and [`PeripheralConnectFailed`](enum.CentralEvent.html#variant.PeripheralConnectFailed)
+    /// events are still triggered. Attempts to connect don't time out.
+    ///
+    /// Dropping the future before it resolves cancels the connection attempt as if
+    /// [`cancel_connect`](struct.CentralManager.html#method.cancel_connect) was called, unless
+    /// there are other pending futures connecting to the same peripheral.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn connect_async(&self, peripheral: &Peripheral)
+        -> impl std::future::Future<Output=Result<(), Error>>
+    {
+        let r = self.0.shared.connects.register(peripheral.id());
+        if self.state() == ManagerState::PoweredOn {
+            self.connect(peripheral);
+            let cancel = command::CancelConnect {
+                manager: self.0.manager.clone(),
+                peripheral: peripheral.peripheral.clone(),
+            };
+            r.on_cancel(move || objc::rc::autoreleasepool(|| cancel.cancel_connect()))
+        } else {
+            r.fail(Error::new(crate::error::ErrorKind::ManagerNotPoweredOn,
+                "central manager must be in PoweredOn state to connect"))
+        }
+    }
+
     /// Cancels an active or pending local connection to a peripheral.
     ///
     /// This method is nonblocking, and any other commands that are still pending to peripheral may
@@ -527,6 +1730,7 @@ impl CentralManager {
     /// disconnected, and the central manager object trigger
     /// [`PeripheralDisconnected`](enum.CentralEvent.html#variant.PeripheralDisconnected) event.
     pub fn cancel_connect(&self, peripheral: &Peripheral) {
+        self.0.shared.throttle.lock().unwrap().cancel(peripheral.id());
         objc::rc::autoreleasepool(|| {
             command::CancelConnect {
                 manager: self.0.manager.clone(),
@@ -535,6 +1739,116 @@ impl CentralManager {
         })
     }
 
+    /// Cancels local connections to all peripherals connected by this central manager, for
+    /// example on shutdown.
+    ///
+    /// This is the same as calling [`cancel_connect`](struct.CentralManager.html#method.cancel_connect)
+    /// for every peripheral the manager reported in
+    /// [`PeripheralConnected`](enum.CentralEvent.html#variant.PeripheralConnected) event and hasn't
+    /// reported in [`PeripheralDisconnected`](enum.CentralEvent.html#variant.PeripheralDisconnected)
+    /// event yet. Each of them triggers `PeripheralDisconnected` event. Pending connection attempts
+    /// are not canceled.
+    pub fn disconnect_all(&self) {
+        objc::rc::autoreleasepool(|| {
+            command::Manager {
+                manager: self.0.manager.clone(),
+            }.disconnect_all();
+        })
+    }
+
+    /// Stops all periodic and delayed activity of the central manager: polling started by
+    /// [`Peripheral::start_polling`](peripheral/struct.Peripheral.html#method.start_polling) and
+    /// pending retries of reads issued by
+    /// [`Peripheral::read_characteristic_with_retry`](peripheral/struct.Peripheral.html#method.read_characteristic_with_retry)
+    /// and subscriptions requested by
+    /// [`Peripheral::subscribe_with_retry`](peripheral/struct.Peripheral.html#method.subscribe_with_retry).
+    ///
+    /// A read or subscription waiting for a retry is abandoned without triggering
+    /// [`CharacteristicValue`](enum.CentralEvent.html#variant.CharacteristicValue) or
+    /// [`SubscriptionChangeResult`](enum.CentralEvent.html#variant.SubscriptionChangeResult) event.
+    /// Reads already issued complete as usual. To stop polling of a single characteristic use
+    /// [`Peripheral::stop_polling`](peripheral/struct.Peripheral.html#method.stop_polling).
+    pub fn cancel_all_timers(&self) {
+        objc::rc::autoreleasepool(|| {
+            command::Manager {
+                manager: self.0.manager.clone(),
+            }.cancel_timers();
+        })
+    }
+
+    /// Stops scanning, cancels local connections to all peripherals connected by this central
+    /// manager and tears the manager down, blocking until the teardown is complete.
+    ///
+    /// The teardown runs on the manager queue after all previously issued commands, so when this
+    /// method returns no more events will be sent and the event receiver is disconnected.
+    /// Pending [`connect_async`](struct.CentralManager.html#method.connect_async) futures fail
+    /// with [`OperationCancelled`](../error/enum.ErrorKind.html#variant.OperationCancelled) error.
+    /// Note that [`PeripheralDisconnected`](enum.CentralEvent.html#variant.PeripheralDisconnected)
+    /// events for the canceled connections are not delivered.
+    ///
+    /// Other clones of this central manager stay usable as objects but do nothing.
+    ///
+    /// Under the [`Block`](metrics/enum.EventOverflow.html#variant.Block) policy the teardown
+    /// can't run while the manager queue waits for the receiver to take an event, so calling this
+    /// from the thread consuming the events deadlocks as soon as an event is pending. Call it from
+    /// another thread and keep receiving until the receiver is disconnected.
+    pub fn shutdown_blocking(self) {
+        let (done, receiver) = sync::bounded_channel(1);
+        objc::rc::autoreleasepool(|| {
+            command::Shutdown {
+                manager: self.0.manager.clone(),
+                done,
+            }.dispatch();
+        });
+        // Disconnected if the manager has already been torn down, which is fine as well.
+        sync::recv_blocking(&receiver);
+    }
+
+    /// Returns a snapshot of the manager state for support tooling and bug reports: the manager
+    /// state, whether it's scanning, the peripherals it's connected to, pending polls and read
+    /// retries, and the event delivery [`metrics`](struct.CentralManager.html#method.metrics).
+    /// With the `serde` feature the snapshot is serializable.
+    ///
+    /// The snapshot is taken on the manager queue after all previously issued commands, so it's
+    /// consistent. This method blocks until the snapshot is taken and must not be called from
+    /// [`event_filter`](struct.CentralManagerBuilder.html#method.event_filter) or
+    /// [`on_discovered`](struct.CentralManager.html#method.on_discovered) callbacks. If the manager
+    /// has been shut down, only the manager state and metrics are reported.
+    ///
+    /// Under the [`Block`](metrics/enum.EventOverflow.html#variant.Block) policy the manager queue
+    /// waits for the receiver to take each event, so calling this from the thread consuming the
+    /// events deadlocks as soon as an event is pending. Call it from another thread, or use
+    /// [`metrics`](struct.CentralManager.html#method.metrics) which doesn't block.
+    pub fn diagnostics(&self) -> Diagnostics {
+        let (done, receiver) = sync::bounded_channel(1);
+        objc::rc::autoreleasepool(|| {
+            command::Diagnose {
+                manager: self.0.manager.clone(),
+                done,
+            }.dispatch();
+        });
+        // Disconnected if the manager has been torn down.
+        sync::recv_blocking(&receiver).unwrap_or_else(|| Diagnostics {
+            manager_state: self.state(),
+            is_scanning: false,
+            connected: Vec::new(),
+            polls: 0,
+            read_retries: 0,
+            metrics: self.metrics(),
+        })
+    }
+
+    /// Returns peripherals this manager connected to and hasn't disconnected from yet.
+    ///
+    /// These are the peripherals reported in
+    /// [`PeripheralConnected`](enum.CentralEvent.html#variant.PeripheralConnected) event and not
+    /// reported in [`PeripheralDisconnected`](enum.CentralEvent.html#variant.PeripheralDisconnected)
+    /// event since then. Unlike [`get_peripherals_with_services`](struct.CentralManager.html#method.get_peripherals_with_services)
+    /// this doesn't include peripherals connected to the system by other apps.
+    pub fn my_connected_peripherals(&self) -> Vec<Peripheral> {
+        self.0.shared.connected.lock().unwrap().iter().cloned().collect()
+    }
+
     fn get_peripherals_tagged0(&self, uuids: &[Uuid], tag: Option<Tag>) {
         objc::rc::autoreleasepool(|| {
             let uuids = NSArray::from_iter(uuids.iter().copied().map(NSUUID::from_uuid)).retain();
@@ -558,16 +1872,50 @@ impl CentralManager {
     }
 }
 
+/// Unique discoveries keyed by peripheral, in the order of first discovery, keeping the value with
+/// the strongest RSSI.
+struct Discoveries<K, V> {
+    index: HashMap<K, usize>,
+    items: Vec<(V, i32)>,
+}
+
+impl<K: Eq + Hash, V> Discoveries<K, V> {
+    fn new() -> Self {
+        Self {
+            index: HashMap::new(),
+            items: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, key: K, rssi: i32, value: V) {
+        if let Some(&i) = self.index.get(&key) {
+            if rssi > self.items[i].1 {
+                self.items[i] = (value, rssi);
+            }
+        } else {
+            self.index.insert(key, self.items.len());
+            self.items.push((value, rssi));
+        }
+    }
+
+    fn into_vec(self) -> Vec<(V, i32)> {
+        self.items
+    }
+}
+
 object_ptr_wrapper!(CBCentralManager);
 
 impl CBCentralManager {
-    pub fn new(show_power_alert: bool) -> (StrongPtr<Self>, sync::Receiver<CentralEvent>) {
-        let (sender, receiver) = sync::channel();
+    fn new(show_power_alert: bool, config: &mut CentralManagerBuilder, shared: Arc<Shared>)
+        -> (StrongPtr<Self>, sync::Receiver<CentralEvent>)
+    {
+        let (sender, receiver) = metrics::event_channel(config.event_overflow);
 
         unsafe {
             let queue = dispatch_queue_create(ptr::null(), DISPATCH_QUEUE_SERIAL);
 
-            let delegate = Delegate::new(sender, queue);
+            let delegate = Delegate::new(sender, queue, config, shared);
+            let mut delegate_ref = *delegate;
 
             let options = NSDictionary::with_capacity(1);
             options.insert(CBCentralManagerOptionShowPowerAlertKey, NSNumber::new_bool(show_power_alert));
@@ -575,6 +1923,7 @@ impl CBCentralManager {
             let mut r: *mut Object = msg_send![class!(CBCentralManager), alloc];
             r = msg_send![r.as_ptr(), initWithDelegate:delegate queue:queue options:options];
             let r = StrongPtr::wrap(Self::wrap(r));
+            delegate_ref.set_manager(*r);
 
             (r, receiver)
         }
@@ -599,11 +1948,42 @@ impl CBCentralManager {
         }
     }
 
+    fn is_scanning(&self) -> bool {
+        unsafe {
+            let r: BOOL = msg_send![self.as_ptr(), isScanning];
+            r != NO
+        }
+    }
+
     fn scan(&self, options: &ScanOptions) {
-        let services = options.service_cbuuids.as_ptr();
+        for issue in options.background_issues() {
+            warn!("background incompatible scan: {}", issue);
+        }
+        if options.coded_phy_unavailable(Self::supports_extended_scan()) {
+            warn!("extended scan is not supported, Coded PHY preference is ignored");
+        }
+        if options.power_alert_ignored() {
+            warn!("power alert can't be configured per scan, show_power_alert is ignored");
+        }
+        let services = options.services.as_ref().map(|v| CBUUID::array_from_uuids(v));
         let options = options.to_options_dict();
         unsafe {
-            let _: () = msg_send![self.as_ptr(), scanForPeripheralsWithServices:services options:options];
+            let _: () = msg_send![self.as_ptr(), scanForPeripheralsWithServices:services.as_ptr() options:options];
+        }
+    }
+
+    /// Returns `true` if the platform reports support of extended scan and connect feature
+    /// (`CBCentralManagerFeatureExtendedScanAndConnect`).
+    fn supports_extended_scan() -> bool {
+        const EXTENDED_SCAN_AND_CONNECT: NSUInteger = 1;
+        unsafe {
+            let cls = class!(CBCentralManager);
+            let responds: BOOL = msg_send![cls, respondsToSelector:sel!(supportsFeatures:)];
+            if responds == NO {
+                return false;
+            }
+            let r: BOOL = msg_send![cls, supportsFeatures:EXTENDED_SCAN_AND_CONNECT];
+            r != NO
         }
     }
 
@@ -613,9 +1993,9 @@ impl CBCentralManager {
         }
     }
 
-    fn connect(&self, peripheral: &CBPeripheral) {
+    fn connect(&self, peripheral: &CBPeripheral, options: Option<NSDictionary>) {
         unsafe {
-            let _: () = msg_send![self.as_ptr(), connectPeripheral:peripheral.as_ptr() options:nil];
+            let _: () = msg_send![self.as_ptr(), connectPeripheral:peripheral.as_ptr() options:options.as_ptr()];
         }
     }
 
@@ -659,20 +2039,22 @@ pub struct AdvertisementData {
     solicited_service_uuids: Vec<Uuid>,
     overflow_service_uuids: Vec<Uuid>,
     tx_power_level: Option<i32>,
+    unknown_keys: Vec<(String, Vec<u8>)>,
 }
 
 assert_impl_all!(AdvertisementData: Send, Sync);
 
 impl AdvertisementData {
-    pub(in crate) fn from_dict(dict: NSDictionary) -> Self {
+    pub(in crate) fn from_dict(dict: NSDictionary, max_bytes: usize) -> Self {
         let connectable = dict.get(unsafe { CBAdvertisementDataIsConnectable })
             .map(|r| unsafe { NSNumber::wrap(r) }.get_bool() );
         let local_name = dict.get(unsafe { CBAdvertisementDataLocalNameKey })
             .map(|r| unsafe { NSString::wrap(r) }.as_str().to_owned() );
         let manufacturer_data = dict.get(unsafe { CBAdvertisementDataManufacturerDataKey })
-            .map(|r| { unsafe { NSData::wrap(r) }; unsafe { NSData::wrap(r) }.as_bytes().to_owned() });
+            .and_then(|r| copy_advertisement_bytes(unsafe { NSData::wrap(r) }.as_bytes(), max_bytes,
+                "manufacturer data"));
         let service_data = dict.get(unsafe { CBAdvertisementDataServiceDataKey })
-            .map(|r| ServiceData::from_dict(unsafe { NSDictionary::wrap(r) } ))
+            .map(|r| ServiceData::from_dict(unsafe { NSDictionary::wrap(r) }, max_bytes))
             .unwrap_or(ServiceData::new());
         let get_uuids = |key| {
             dict.get(key)
@@ -689,6 +2071,20 @@ impl AdvertisementData {
         let solicited_service_uuids = get_uuids(unsafe { CBAdvertisementDataSolicitedServiceUUIDsKey });
         let tx_power_level = dict.get(unsafe { CBAdvertisementDataTxPowerLevelKey })
             .map(|r| unsafe { NSNumber::wrap(r) }.get_i32() );
+        let known_keys = unsafe {[
+            CBAdvertisementDataIsConnectable,
+            CBAdvertisementDataLocalNameKey,
+            CBAdvertisementDataManufacturerDataKey,
+            CBAdvertisementDataOverflowServiceUUIDsKey,
+            CBAdvertisementDataServiceDataKey,
+            CBAdvertisementDataServiceUUIDsKey,
+            CBAdvertisementDataSolicitedServiceUUIDsKey,
+            CBAdvertisementDataTxPowerLevelKey,
+        ]};
+        let known_keys: Vec<_> = known_keys.iter().map(|k| k.as_str()).collect();
+        let unknown_keys = unknown_advertisement_entries(dict.iter()
+            .map(|(k, v)| (unsafe { NSString::wrap(k) }.as_str().to_owned(), advertisement_value_bytes(v))),
+            &known_keys, max_bytes);
         Self {
             connectable,
             local_name,
@@ -698,6 +2094,7 @@ impl AdvertisementData {
             overflow_service_uuids,
             solicited_service_uuids,
             tx_power_level,
+            unknown_keys,
         }
     }
 
@@ -742,6 +2139,175 @@ impl AdvertisementData {
     pub fn tx_power_level(&self) -> Option<i32> {
         self.tx_power_level
     }
+
+    /// Entries of the advertisement data this crate doesn't decode, sorted by key.
+    ///
+    /// The values are decoded on a best-effort basis: binary data is returned as is, other
+    /// values (numbers, strings, arrays) as UTF-8 encoded textual description. This gives access
+    /// to advertisement data types added in future platform versions.
+    pub fn unknown_keys(&self) -> &[(String, Vec<u8>)] {
+        &self.unknown_keys
+    }
+
+    /// Returns the path loss in decibels, i.e. difference between the
+    /// [transmit power](struct.AdvertisementData.html#method.tx_power_level) and the `rssi`.
+    /// Returns `None` if the transmit power is not advertised.
+    pub fn path_loss(&self, rssi: i32) -> Option<i32> {
+        self.tx_power_level.map(|tx_power| tx_power - rssi)
+    }
+
+    /// Returns `true` if the peripheral advertises all of the `required` services, either in the
+    /// [service UUIDs](#method.service_uuids) or in the [overflow area](#method.overflow_service_uuids).
+    /// Returns `true` if `required` is empty.
+    ///
+    /// UUIDs are compared in their [shortest form](../uuid/struct.Uuid.html#method.shorten), so
+    /// a 16-bit UUID matches its 128-bit equivalent.
+    pub fn advertises_all(&self, required: &[Uuid]) -> bool {
+        required.iter().all(|uuid| self.advertises(uuid))
+    }
+
+    /// Returns `true` if the peripheral advertises at least one of the `any_of` services, either in
+    /// the [service UUIDs](#method.service_uuids) or in the [overflow area](#method.overflow_service_uuids).
+    /// Returns `false` if `any_of` is empty.
+    ///
+    /// UUIDs are compared in their [shortest form](../uuid/struct.Uuid.html#method.shorten), so
+    /// a 16-bit UUID matches its 128-bit equivalent.
+    pub fn advertises_any(&self, any_of: &[Uuid]) -> bool {
+        any_of.iter().any(|uuid| self.advertises(uuid))
+    }
+
+    /// Returns whether the peripheral requires pairing (bonding) before use, or `None` if it can't
+    /// be determined from the advertisement data.
+    ///
+    /// CoreBluetooth doesn't report the advertising flags, so this relies on services that
+    /// mandate bonding:
+    ///
+    /// * [Human Interface Device](../known/service/constant.HUMAN_INTERFACE_DEVICE.html)
+    ///   advertised by the peripheral, since HID over GATT requires bonding.
+    /// * Apple Notification Center Service solicited by the peripheral, since iOS shares
+    ///   notifications with bonded peripherals only.
+    ///
+    /// A peripheral may still require pairing to access some of its characteristics, in which
+    /// case the system prompts for pairing on the first access.
+    pub fn requires_pairing(&self) -> Option<bool> {
+        if self.advertises(&crate::known::service::HUMAN_INTERFACE_DEVICE) ||
+            self.solicited_service_uuids.contains(&ANCS_SERVICE)
+        {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    fn advertises(&self, uuid: &Uuid) -> bool {
+        self.service_uuids.iter()
+            .chain(&self.overflow_service_uuids)
+            .any(|v| v.shorten() == uuid.shorten())
+    }
+}
+
+/// UUID of the Apple Notification Center Service.
+const ANCS_SERVICE: Uuid = crate::uuid!("7905f431-b5ce-4e99-a40f-4b1e122d00d0");
+
+/// Coarse proximity of a peripheral returned by [`proximity`](fn.proximity.html).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Proximity {
+    /// The peripheral is very close, within about half a meter.
+    Immediate,
+
+    /// The peripheral is relatively close, within a few meters.
+    Near,
+
+    /// The peripheral is further away or the signal is heavily attenuated.
+    Far,
+
+    /// The proximity can't be determined from the RSSI.
+    Unknown,
+}
+
+/// RSSI value reported by Core Bluetooth when the RSSI is not available.
+const RSSI_UNAVAILABLE: i32 = 127;
+
+/// Typical path loss at 1 meter distance, in decibels.
+const PATH_LOSS_AT_1M: f64 = 41.0;
+
+/// Path loss exponent of the free space.
+const PATH_LOSS_EXPONENT: f64 = 2.0;
+
+/// Estimates proximity of a peripheral from its `rssi` and, if known, the advertised `tx_power`
+/// (see [`AdvertisementData::tx_power_level`](struct.AdvertisementData.html#method.tx_power_level)).
+///
+/// With `tx_power` the distance is estimated using the log-distance path loss model. Otherwise
+/// the RSSI is compared against fixed thresholds that assume a typical transmit power.
+///
+/// This is a rough heuristic: RSSI fluctuates considerably, and obstacles, reflections, antenna
+/// orientation and the human body can change it by tens of decibels. Average the RSSI over several
+/// readings for a more stable result and don't rely on the result for anything but UI hints.
+pub fn proximity(rssi: i32, tx_power: Option<i32>) -> Proximity {
+    if rssi >= 0 || rssi == RSSI_UNAVAILABLE {
+        return Proximity::Unknown;
+    }
+    if let Some(tx_power) = tx_power {
+        let path_loss = f64::from(tx_power - rssi);
+        let distance = 10f64.powf((path_loss - PATH_LOSS_AT_1M) / (10.0 * PATH_LOSS_EXPONENT));
+        if distance < 0.5 {
+            Proximity::Immediate
+        } else if distance < 4.0 {
+            Proximity::Near
+        } else {
+            Proximity::Far
+        }
+    } else if rssi >= -50 {
+        Proximity::Immediate
+    } else if rssi >= -75 {
+        Proximity::Near
+    } else {
+        Proximity::Far
+    }
+}
+
+/// Copies advertised `bytes` unless they're longer than `max_len`, in which case they're dropped
+/// with a warning. `what` is formatted only for the warning.
+fn copy_advertisement_bytes(bytes: &[u8], max_len: usize, what: impl fmt::Display)
+    -> Option<Vec<u8>>
+{
+    if bytes.len() > max_len {
+        warn!("dropping {} of {} bytes, the maximum is {}", what, bytes.len(), max_len);
+        None
+    } else {
+        Some(bytes.to_owned())
+    }
+}
+
+/// Returns the value of an advertisement data entry as bytes: `NSData` as is and anything else
+/// as its description.
+fn advertisement_value_bytes(value: NonNull<Object>) -> Vec<u8> {
+    unsafe {
+        let is_data: bool = msg_send![value.as_ptr(), isKindOfClass:class!(NSData)];
+        if is_data {
+            NSData::wrap(value).as_bytes().to_owned()
+        } else {
+            let r: *mut Object = msg_send![value.as_ptr(), description];
+            NSString::wrap(r).as_str().as_bytes().to_owned()
+        }
+    }
+}
+
+/// Returns the `entries` with keys not in `known_keys`, sorted by key. Values longer than
+/// `max_bytes` are dropped.
+fn unknown_advertisement_entries(entries: impl Iterator<Item=(String, Vec<u8>)>, known_keys: &[&str],
+    max_bytes: usize) -> Vec<(String, Vec<u8>)>
+{
+    let mut r: Vec<_> = entries
+        .filter(|(k, _)| !known_keys.contains(&k.as_str()))
+        .filter_map(|(k, v)| {
+            let v = copy_advertisement_bytes(&v, max_bytes,
+                format_args!("advertisement data `{}`", k))?;
+            Some((k, v))
+        })
+        .collect();
+    r.sort();
+    r
 }
 
 /// Service-specific advertisement data. The keys represent Service UUIDs.
@@ -755,11 +2321,12 @@ impl ServiceData {
         Self(Default::default())
     }
 
-    pub(in crate) fn from_dict(dict: NSDictionary) -> Self {
+    pub(in crate) fn from_dict(dict: NSDictionary, max_bytes: usize) -> Self {
         Self(dict.iter()
-            .map(|(k, v)| (
+            .filter_map(|(k, v)| Some((
                 unsafe { CBUUID::wrap(k) }.to_uuid(),
-                unsafe { NSData::wrap(v) }.as_bytes().to_owned()))
+                copy_advertisement_bytes(unsafe { NSData::wrap(v) }.as_bytes(), max_bytes,
+                    "service data")?)))
             .collect())
     }
 
@@ -779,3 +2346,457 @@ impl ServiceData {
         self.0.iter().map(|(k, v)| (*k, v.as_slice()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Issues commands from several threads while the last manager handle is dropped, so some of
+    /// them run on the queue after the delegate is gone. Needs Core Bluetooth, run on macOS with
+    /// `cargo test -- --ignored`, optionally under a sanitizer.
+    #[cfg(target_os = "macos")]
+    #[test]
+    #[ignore]
+    fn commands_racing_manager_drop() {
+        for _ in 0..20 {
+            let (manager, receiver) = CentralManager::new();
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    let manager = manager.clone();
+                    std::thread::spawn(move || {
+                        for _ in 0..100 {
+                            manager.scan();
+                            manager.get_peripherals(&[Uuid::from_u16(0x180d)]);
+                            manager.cancel_scan();
+                        }
+                    })
+                })
+                .collect();
+            drop(manager);
+            drop(receiver);
+            for thread in threads {
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    /// Shuts the manager down from another thread while this one keeps receiving events, which
+    /// must not deadlock with the `Block` policy. Needs the Objective-C runtime, run on macOS
+    /// with `cargo test -- --ignored`.
+    #[cfg(target_os = "macos")]
+    #[test]
+    #[ignore]
+    fn shutdown_blocking_off_event_thread() {
+        for _ in 0..20 {
+            let (manager, receiver) = CentralManager::new();
+            let shutdown = std::thread::spawn(move || manager.shutdown_blocking());
+            while sync::recv_blocking(&receiver).is_some() {}
+            shutdown.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn identifiers_are_distinct() {
+        let a = next_identifier();
+        let b = next_identifier();
+        assert_ne!(a, b);
+        assert_eq!(a.bytes()[..4], std::process::id().to_be_bytes());
+        assert_eq!(a.bytes()[..4], b.bytes()[..4]);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| (0..100).map(|_| next_identifier()).collect::<Vec<_>>()))
+            .collect();
+        let mut ids = std::collections::HashSet::new();
+        for thread in threads {
+            for id in thread.join().unwrap() {
+                assert!(ids.insert(id));
+            }
+        }
+        assert!(!ids.contains(&a) && !ids.contains(&b));
+    }
+
+    /// Needs the Objective-C runtime, run on macOS with `cargo test -- --ignored`.
+    #[cfg(target_os = "macos")]
+    #[test]
+    #[ignore]
+    fn manager_identifiers() {
+        let (a, _a_receiver) = CentralManager::new();
+        let (b, _b_receiver) = CentralManager::new();
+        assert!(a.identifier().is_some());
+        assert_ne!(a.identifier(), b.identifier());
+        assert_eq!(a.clone().identifier(), a.identifier());
+    }
+
+    #[test]
+    fn scan_options_background_issues() {
+        assert!(ScanOptions::default().allow_duplicates(true).background_issues().is_empty());
+
+        let opts = ScanOptions::default()
+            .allow_duplicates(true)
+            .background_compatible(true);
+        assert_eq!(opts.background_issues().len(), 2);
+
+        let opts = ScanOptions::default().background_compatible(true);
+        assert_eq!(opts.background_issues().len(), 1);
+    }
+
+    #[test]
+    fn scan_options_dict_entries() {
+        fn entries(opts: &ScanOptions) -> Vec<(ScanOptionKey, Option<bool>)> {
+            opts.options().into_iter()
+                .map(|(k, v)| (k, match v {
+                    ScanOptionValue::Bool(v) => Some(v),
+                    ScanOptionValue::Uuids(_) => None,
+                }))
+                .collect()
+        }
+
+        assert_eq!(entries(&ScanOptions::default()),
+            vec![(ScanOptionKey::AllowDuplicates, Some(false))]);
+        assert_eq!(entries(&ScanOptions::default().allow_duplicates(true)),
+            vec![(ScanOptionKey::AllowDuplicates, Some(true))]);
+
+        let opts = ScanOptions::default()
+            .allow_duplicates(true)
+            .background_compatible(true)
+            .estimate_interval(true)
+            .prefer_coded_phy(true)
+            .show_power_alert(true);
+        assert_eq!(entries(&opts), vec![(ScanOptionKey::AllowDuplicates, Some(false))]);
+    }
+
+    #[test]
+    fn connect_options_dict_entries() {
+        let opts = ConnectOptions::default().requires_ancs(true);
+        if cfg!(target_os = "ios") {
+            assert_eq!(ConnectOptions::default().options(),
+                vec![(ConnectOptionKey::RequiresAncs, false)]);
+            assert_eq!(opts.options(), vec![(ConnectOptionKey::RequiresAncs, true)]);
+        } else {
+            assert_eq!(opts.options(), vec![]);
+        }
+    }
+
+    #[test]
+    fn session_scan_options() {
+        let session = SessionDescriptor {
+            scan_allow_duplicates: true,
+            reconnect_peripherals: vec![Uuid::from_u16(1)],
+            ..Default::default()
+        };
+        let opts = session.scan_options();
+        assert!(opts.services.is_none());
+        assert_eq!(opts.options().len(), 1);
+        match opts.options()[0] {
+            (ScanOptionKey::AllowDuplicates, ScanOptionValue::Bool(v)) => assert!(v),
+            _ => panic!(),
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn session_serde() {
+        let session = SessionDescriptor {
+            scan_services: vec![Uuid::from_u16(0x180d)],
+            scan_allow_duplicates: false,
+            reconnect_peripherals: vec!["ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap()],
+        };
+        let json = serde_json::to_value(&session).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "scan_services": ["0000180d-0000-1000-8000-00805f9b34fb"],
+            "scan_allow_duplicates": false,
+            "reconnect_peripherals": ["ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6"],
+        }));
+        assert_eq!(serde_json::from_value::<SessionDescriptor>(json).unwrap(), session);
+
+        let restored: SessionDescriptor = serde_json::from_value(serde_json::json!({
+            "scan_services": [],
+            "scan_allow_duplicates": true,
+            "reconnect_peripherals": [],
+            "unknown": 1,
+        })).unwrap();
+        assert!(restored.scan_allow_duplicates);
+        assert!(serde_json::from_value::<SessionDescriptor>(serde_json::json!({
+            "scan_services": [],
+            "scan_allow_duplicates": true,
+        })).is_err());
+    }
+
+    #[test]
+    fn scan_options_coded_phy() {
+        let opts = ScanOptions::default();
+        assert!(!opts.coded_phy_unavailable(false));
+        assert!(!opts.coded_phy_unavailable(true));
+
+        let opts = ScanOptions::default().prefer_coded_phy(true);
+        assert!(opts.coded_phy_unavailable(false));
+        assert!(!opts.coded_phy_unavailable(true));
+    }
+
+    #[test]
+    fn scan_options_power_alert() {
+        assert!(!ScanOptions::default().power_alert_ignored());
+        assert!(ScanOptions::default().show_power_alert(false).power_alert_ignored());
+        assert!(ScanOptions::default().show_power_alert(true).power_alert_ignored());
+    }
+
+    #[test]
+    fn advertises_services() {
+        let adv = AdvertisementData {
+            connectable: None,
+            local_name: None,
+            manufacturer_data: None,
+            service_data: ServiceData(HashMap::new()),
+            service_uuids: vec![
+                "0000180d-0000-1000-8000-00805f9b34fb".parse().unwrap(),
+                "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap(),
+            ],
+            solicited_service_uuids: vec![Uuid::from_u16(0x1812)],
+            overflow_service_uuids: vec!["0000180f-0000-1000-8000-00805f9b34fb".parse().unwrap()],
+            tx_power_level: None,
+            unknown_keys: Vec::new(),
+        };
+        let hr = Uuid::from_slice(&[0x18, 0x0d]);
+        let battery = Uuid::from_u16(0x180f);
+        let hid = Uuid::from_u16(0x1812);
+
+        assert!(adv.advertises_all(&[]));
+        assert!(adv.advertises_all(&[hr, battery]));
+        assert!(!adv.advertises_all(&[hr, hid]));
+
+        assert!(!adv.advertises_any(&[]));
+        assert!(adv.advertises_any(&[hid, battery]));
+        assert!(!adv.advertises_any(&[hid, Uuid::from_u32(0x1234_180d)]));
+    }
+
+    #[test]
+    fn requires_pairing() {
+        let adv = |service_uuids, solicited_service_uuids| AdvertisementData {
+            connectable: Some(true),
+            local_name: None,
+            manufacturer_data: None,
+            service_data: ServiceData(HashMap::new()),
+            service_uuids,
+            solicited_service_uuids,
+            overflow_service_uuids: Vec::new(),
+            tx_power_level: None,
+            unknown_keys: Vec::new(),
+        };
+        let hid = "00001812-0000-1000-8000-00805f9b34fb".parse().unwrap();
+        let battery = Uuid::from_u16(0x180f);
+
+        assert_eq!(adv(vec![battery, hid], vec![]).requires_pairing(), Some(true));
+        assert_eq!(adv(vec![], vec![ANCS_SERVICE]).requires_pairing(), Some(true));
+        assert_eq!(adv(vec![battery], vec![hid]).requires_pairing(), None);
+        assert_eq!(adv(vec![], vec![]).requires_pairing(), None);
+    }
+
+    #[test]
+    fn rate_limiter() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(10);
+        let mut limiter = RateLimiter::new(interval);
+
+        // Burst of 100 items 1 ms apart for each of two keys.
+        let mut passed = HashMap::new();
+        for i in 0..100 {
+            let now = start + Duration::from_millis(i);
+            for &key in &[1, 2] {
+                if limiter.pass(Some(key), now) {
+                    passed.entry(key).or_insert_with(Vec::new).push(i);
+                }
+            }
+        }
+        let expected: Vec<_> = (0..10).map(|i| i * 10).collect();
+        assert_eq!(passed[&1], expected);
+        assert_eq!(passed[&2], expected);
+
+        assert!((0..100).all(|_| limiter.pass(None, start)));
+    }
+
+    #[test]
+    fn errors_only_events() {
+        let (sender, receiver) = sync::bounded_channel(8);
+        let (errors, rest) = errors_only(receiver);
+        // All events carrying an error need a peripheral, so only the passing ones are checked.
+        let events = vec![
+            CentralEvent::ManagerStateChanged { new_state: ManagerState::PoweredOn },
+            CentralEvent::GetPeripheralsResult { peripherals: Vec::new(), tag: None },
+            CentralEvent::ManagerStateChanged { new_state: ManagerState::PoweredOff },
+        ];
+        for event in events {
+            assert!(event.error().is_none());
+            assert!(sender.send_blocking(event));
+        }
+        drop(sender);
+
+        let rest = std::thread::spawn(move || {
+            let mut names = Vec::new();
+            while let Some(event) = sync::recv_timeout(&rest, Duration::from_secs(5)) {
+                names.push(event.name());
+            }
+            names
+        });
+        assert!(sync::recv_timeout(&errors, Duration::from_secs(5)).is_none());
+        assert_eq!(rest.join().unwrap(),
+            vec!["ManagerStateChanged", "GetPeripheralsResult", "ManagerStateChanged"]);
+    }
+
+    #[test]
+    fn split_events() {
+        let (sender, receiver) = sync::bounded_channel(8);
+        let (errors, rest) = sync::split(receiver, |v: &Result<i32, i32>| v.err());
+        for v in &[Ok(1), Err(2), Ok(3), Err(4)] {
+            assert!(sender.send_blocking(*v));
+        }
+        drop(sender);
+
+        fn collect<T>(r: Receiver<T>) -> Vec<T> {
+            let mut v = Vec::new();
+            while let Some(item) = sync::recv_timeout(&r, Duration::from_secs(5)) {
+                v.push(item);
+            }
+            v
+        }
+        let rest = std::thread::spawn(move || collect(rest));
+        assert_eq!(collect(errors), vec![(Err(2), 2), (Err(4), 4)]);
+        assert_eq!(rest.join().unwrap(), vec![Ok(1), Ok(3)]);
+    }
+
+    #[test]
+    fn split_events_dropped_receiver() {
+        let (sender, receiver) = sync::bounded_channel(8);
+        let (errors, rest) = sync::split(receiver, |v: &Result<i32, i32>| v.err());
+        drop(errors);
+        for v in &[Err(1), Err(2), Ok(3), Err(4), Ok(5)] {
+            assert!(sender.send_blocking(*v));
+        }
+        drop(sender);
+
+        let mut v = Vec::new();
+        while let Some(item) = sync::recv_timeout(&rest, Duration::from_secs(5)) {
+            v.push(item);
+        }
+        assert_eq!(v, vec![Ok(3), Ok(5)]);
+    }
+
+    #[test]
+    fn filter_dropped_receiver() {
+        let (sender, receiver) = sync::channel();
+        drop(sync::filter(receiver, |_: &i32| true));
+        // The forwarder stops once it notices the drop, which disconnects the source channel.
+        assert!((0..10).any(|i| !sender.send_blocking(i)));
+    }
+
+    #[test]
+    fn route_events() {
+        let (sender, receiver) = sync::bounded_channel(8);
+        let (routes, rest) = sync::route(receiver, |v: &(Option<u8>, i32)| v.0);
+        let a = routes.add(1);
+        let b = routes.add(2);
+        for v in &[(Some(1), 1), (Some(2), 2), (None, 3), (Some(3), 4), (Some(1), 5), (Some(2), 6)] {
+            assert!(sender.send_blocking(*v));
+        }
+        drop(sender);
+        drop(routes);
+
+        fn collect<T>(r: Receiver<T>) -> Vec<T> {
+            let mut v = Vec::new();
+            while let Some(item) = sync::recv_timeout(&r, Duration::from_secs(5)) {
+                v.push(item);
+            }
+            v
+        }
+        let a = std::thread::spawn(move || collect(a));
+        let b = std::thread::spawn(move || collect(b));
+        assert_eq!(collect(rest), vec![(None, 3), (Some(3), 4)]);
+        assert_eq!(a.join().unwrap(), vec![(Some(1), 1), (Some(1), 5)]);
+        assert_eq!(b.join().unwrap(), vec![(Some(2), 2), (Some(2), 6)]);
+    }
+
+    #[test]
+    fn route_events_dropped_stream() {
+        let (sender, receiver) = sync::bounded_channel(8);
+        let (routes, rest) = sync::route(receiver, |v: &(Option<u8>, i32)| v.0);
+        drop(routes.add(1));
+        let b = routes.add(2);
+        let events = [
+            (Some(1), 1), (Some(1), 2), (Some(2), 3), (Some(1), 4), (None, 5), (Some(2), 6),
+        ];
+        for v in &events {
+            assert!(sender.send_blocking(*v));
+        }
+        drop(sender);
+        drop(routes);
+
+        fn collect<T>(r: Receiver<T>) -> Vec<T> {
+            let mut v = Vec::new();
+            while let Some(item) = sync::recv_timeout(&r, Duration::from_secs(5)) {
+                v.push(item);
+            }
+            v
+        }
+        let b = std::thread::spawn(move || collect(b));
+        let rest = collect(rest);
+        // The events of the dropped stream are lost until the drop is noticed, which may take
+        // more than one event if the channel is buffered.
+        assert!(rest.ends_with(&[(Some(1), 4), (None, 5)]), "{:?}", rest);
+        assert_eq!(b.join().unwrap(), vec![(Some(2), 3), (Some(2), 6)]);
+    }
+
+    #[test]
+    fn proximity_buckets() {
+        assert_eq!(proximity(RSSI_UNAVAILABLE, None), Proximity::Unknown);
+        assert_eq!(proximity(RSSI_UNAVAILABLE, Some(0)), Proximity::Unknown);
+        assert_eq!(proximity(0, None), Proximity::Unknown);
+
+        assert_eq!(proximity(-40, None), Proximity::Immediate);
+        assert_eq!(proximity(-60, None), Proximity::Near);
+        assert_eq!(proximity(-90, None), Proximity::Far);
+
+        // ~0.3 m
+        assert_eq!(proximity(-30, Some(0)), Proximity::Immediate);
+        // ~2 m
+        assert_eq!(proximity(-47, Some(0)), Proximity::Near);
+        // ~11 m
+        assert_eq!(proximity(-62, Some(0)), Proximity::Far);
+        // Weak transmitter nearby.
+        assert_eq!(proximity(-60, Some(-20)), Proximity::Near);
+    }
+
+    #[test]
+    fn advertisement_bytes_cap() {
+        let data = vec![0xab; 100];
+        assert_eq!(copy_advertisement_bytes(&data, 100, "service data"), Some(data.clone()));
+        assert_eq!(copy_advertisement_bytes(&data, 99, "service data"), None);
+        assert_eq!(copy_advertisement_bytes(&[], 0, "service data"), Some(vec![]));
+        assert_eq!(CentralManagerBuilder::new().max_advertisement_bytes,
+            DEFAULT_MAX_ADVERTISEMENT_BYTES);
+    }
+
+    #[test]
+    fn unknown_advertisement_entries() {
+        let entries = vec![
+            ("kCBAdvDataLocalName".to_owned(), b"name".to_vec()),
+            ("kCBAdvDataRxPrimaryPHY".to_owned(), b"1".to_vec()),
+            ("kCBAdvDataFuture".to_owned(), vec![1, 2, 3]),
+            ("kCBAdvDataTooLong".to_owned(), vec![0; 5]),
+        ];
+        let known = &["kCBAdvDataLocalName", "kCBAdvDataTxPowerLevel"];
+        assert_eq!(super::unknown_advertisement_entries(entries.into_iter(), known, 4), vec![
+            ("kCBAdvDataFuture".to_owned(), vec![1, 2, 3]),
+            ("kCBAdvDataRxPrimaryPHY".to_owned(), b"1".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn discoveries_keep_strongest_rssi() {
+        let mut d = Discoveries::new();
+        d.insert(1, -70, "a1");
+        d.insert(2, -50, "b1");
+        d.insert(1, -40, "a2");
+        d.insert(2, -80, "b2");
+        d.insert(1, -60, "a3");
+        assert_eq!(d.into_vec(), vec![("a2", -40), ("b1", -50)]);
+    }
+}
@@ -3,6 +3,8 @@ use std::fmt;
 
 use super::*;
 use super::descriptor::Descriptor;
+use super::peripheral::PeripheralId;
+use super::service::CBService;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 #[non_exhaustive]
@@ -11,6 +13,42 @@ pub enum WriteKind {
     WithoutResponse = 1,
 }
 
+impl WriteKind {
+    /// Returns the write kind best suited for a characteristic with the specified `properties`:
+    /// `WithResponse` if the characteristic [`can_write`](struct.Properties.html#method.can_write),
+    /// otherwise `WithoutResponse` if it
+    /// [`can_write_without_response`](struct.Properties.html#method.can_write_without_response).
+    /// Returns `None` if the characteristic isn't writable.
+    pub fn best_for(properties: &Properties) -> Option<Self> {
+        if properties.can_write() {
+            Some(Self::WithResponse)
+        } else if properties.can_write_without_response() {
+            Some(Self::WithoutResponse)
+        } else {
+            None
+        }
+    }
+}
+
+/// Checks the characteristic with the `properties` and `uuid` can be read.
+pub(in crate) fn check_readable(properties: &Properties, uuid: Uuid) -> Result<(), CommandRejectReason> {
+    if properties.can_read() {
+        Ok(())
+    } else {
+        Err(CommandRejectReason::NotReadable { uuid })
+    }
+}
+
+/// Checks the characteristic with the `properties` and `uuid` supports notifications or
+/// indications.
+pub(in crate) fn check_subscribable(properties: &Properties, uuid: Uuid) -> Result<(), CommandRejectReason> {
+    if properties.can_notify() || properties.can_indicate() {
+        Ok(())
+    } else {
+        Err(CommandRejectReason::NotSubscribable { uuid })
+    }
+}
+
 #[derive(BitFlags, Copy, Clone, Debug, Eq, Hash, PartialEq)]
 #[repr(u32)]
 enum Property {
@@ -31,9 +69,39 @@ enum Property {
 pub struct Properties(BitFlags<Property>);
 
 impl Properties {
-    fn from_bits_truncate(bits: u32) -> Self {
+    pub(in crate) fn from_bits_truncate(bits: u32) -> Self {
         Self(BitFlags::from_bits_truncate(bits))
     }
+
+    /// Returns names of the properties, e.g. `["read", "notify"]`.
+    pub fn names(&self) -> Vec<&'static str> {
+        let names = [
+            (self.can_broadcast(), "broadcast"),
+            (self.can_read(), "read"),
+            (self.can_write_without_response(), "write without response"),
+            (self.can_write(), "write"),
+            (self.can_notify(), "notify"),
+            (self.can_indicate(), "indicate"),
+            (self.supports_authenticated_signed_writes(), "authenticated signed writes"),
+            (self.has_extended_properties(), "extended properties"),
+            (self.is_notify_encryption_required(), "notify encryption required"),
+            (self.is_indicate_encryption_required(), "indicate encryption required"),
+        ];
+        names.iter().filter(|&&(v, _)| v).map(|&(_, name)| name).collect()
+    }
+
+    /// Returns the Client Characteristic Configuration descriptor value that enables updates of
+    /// the characteristic's value: notifications if supported, otherwise indications. Returns
+    /// `None` if neither is supported.
+    pub fn preferred_cccd(&self) -> Option<[u8; 2]> {
+        if self.can_notify() {
+            Some([0x01, 0x00])
+        } else if self.can_indicate() {
+            Some([0x02, 0x00])
+        } else {
+            None
+        }
+    }
 }
 
 macro_rules! properties {
@@ -83,6 +151,37 @@ properties!(
 
 assert_impl_all!(Properties: Send, Sync);
 
+/// State of the notifications and indications of a characteristic as stored in its
+/// [Client Characteristic Configuration](../../known/descriptor/constant.CLIENT_CHARACTERISTIC_CONFIGURATION.html)
+/// descriptor.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CccdState {
+    /// Whether notifications are enabled.
+    pub notify: bool,
+
+    /// Whether indications are enabled.
+    pub indicate: bool,
+}
+
+impl CccdState {
+    /// Returns the state stored in the descriptor `value`.
+    pub fn from_value(value: u16) -> Self {
+        Self {
+            notify: value & 0x01 != 0,
+            indicate: value & 0x02 != 0,
+        }
+    }
+
+    /// Returns the state stored in the little-endian descriptor value `bytes`. Missing bytes are
+    /// treated as zeros.
+    pub(in crate) fn parse(bytes: &[u8]) -> Self {
+        Self::from_value(u16::from_le_bytes([
+            bytes.first().copied().unwrap_or(0),
+            bytes.get(1).copied().unwrap_or(0),
+        ]))
+    }
+}
+
 impl fmt::Debug for Properties {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_tuple("Properties")
@@ -91,6 +190,65 @@ impl fmt::Debug for Properties {
     }
 }
 
+/// Summary of what can be done with a characteristic, as returned by
+/// [`Characteristic::capabilities`](struct.Characteristic.html#method.capabilities).
+///
+/// The `Display` implementation lists the supported capabilities separated with commas, e.g.
+/// `read, notify`, or prints `none`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Capabilities {
+    /// See [`Properties::can_read`](struct.Properties.html#method.can_read).
+    pub read: bool,
+
+    /// See [`Properties::can_write`](struct.Properties.html#method.can_write).
+    pub write: bool,
+
+    /// See [`Properties::can_write_without_response`](struct.Properties.html#method.can_write_without_response).
+    pub write_without_response: bool,
+
+    /// See [`Properties::can_notify`](struct.Properties.html#method.can_notify).
+    pub notify: bool,
+
+    /// See [`Properties::can_indicate`](struct.Properties.html#method.can_indicate).
+    pub indicate: bool,
+}
+
+impl From<&Properties> for Capabilities {
+    fn from(v: &Properties) -> Self {
+        Self {
+            read: v.can_read(),
+            write: v.can_write(),
+            write_without_response: v.can_write_without_response(),
+            notify: v.can_notify(),
+            indicate: v.can_indicate(),
+        }
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [
+            (self.read, "read"),
+            (self.write, "write"),
+            (self.write_without_response, "write without response"),
+            (self.notify, "notify"),
+            (self.indicate, "indicate"),
+        ];
+        let mut empty = true;
+        for &(_, name) in names.iter().filter(|&&(v, _)| v) {
+            if !empty {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", name)?;
+            empty = false;
+        }
+        if empty {
+            write!(f, "none")?;
+        }
+        Ok(())
+    }
+}
+
 /// A characteristic of a remote peripheral’s service.
 ///
 /// Represents further information about a peripheral's service. A characteristic contains a single
@@ -109,6 +267,7 @@ impl fmt::Debug for Properties {
 pub struct Characteristic {
     id: Uuid,
     properties: Properties,
+    service_id: Option<Uuid>,
     pub(in crate) characteristic: StrongPtr<CBCharacteristic>,
 }
 
@@ -120,6 +279,7 @@ impl Characteristic {
         Self {
             id: characteristic.id(),
             properties: characteristic.properties(),
+            service_id: characteristic.service().map(|s| s.id()),
             characteristic,
         }
     }
@@ -131,6 +291,81 @@ impl Characteristic {
     pub fn properties(&self) -> &Properties {
         &self.properties
     }
+
+    /// Returns whether the characteristic has any descriptors, or `None` if its descriptors haven't
+    /// been discovered yet with
+    /// [`discover_descriptors`](../peripheral/struct.Peripheral.html#method.discover_descriptors).
+    ///
+    /// Unlike the other properties this reflects the current state of the characteristic rather
+    /// than the state at the time this object was created. The state is read on the manager queue
+    /// and this method blocks like
+    /// [`Peripheral::characteristic_ref`](../peripheral/struct.Peripheral.html#method.characteristic_ref).
+    /// Returns `None` if the characteristic is no longer attached to a peripheral.
+    pub fn has_descriptors(&self) -> Option<bool> {
+        let peripheral = objc::rc::autoreleasepool(|| {
+            Some(self.characteristic.service()?.peripheral()?.retain())
+        })?;
+        let characteristic = self.characteristic.clone();
+        super::peripheral::inspect(&peripheral, move |_| {
+            characteristic.descriptor_count().map(|n| n > 0)
+        })?
+    }
+
+    /// Returns summary of the read, write and subscription [`properties`](#method.properties).
+    pub fn capabilities(&self) -> Capabilities {
+        (&self.properties).into()
+    }
+
+    /// Returns UUID of the service to which this characteristic belongs, as returned by
+    /// [`Service::id`](../service/struct.Service.html#method.id), or `None` if the characteristic
+    /// was no longer attached to a service when this object was created.
+    pub fn service_id(&self) -> Option<Uuid> {
+        self.service_id
+    }
+}
+
+/// Identifies a characteristic independently of the characteristic object, so that it survives
+/// disconnects.
+///
+/// Characteristic objects become invalid when the peripheral disconnects. The reference can be
+/// created with [`Peripheral::characteristic_ref`](../peripheral/struct.Peripheral.html#method.characteristic_ref)
+/// and later resolved with [`Peripheral::resolve`](../peripheral/struct.Peripheral.html#method.resolve)
+/// after the peripheral is reconnected and its services and characteristics are rediscovered.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CharacteristicRef {
+    /// Identifier of the peripheral.
+    pub peripheral_id: PeripheralId,
+
+    /// UUID of the service to which the characteristic belongs.
+    pub service_uuid: Uuid,
+
+    /// UUID of the characteristic.
+    pub characteristic_uuid: Uuid,
+
+    /// Index of the characteristic among characteristics with the same `characteristic_uuid`
+    /// of services with the same `service_uuid`, in discovery order.
+    pub instance_index: usize,
+}
+
+/// Returns characteristics with the `characteristic_uuid` of services with the `service_uuid` in
+/// discovery order. `services` contains the discovered services with their characteristics, all
+/// paired with their UUIDs.
+pub(in crate) fn instances<C>(services: Vec<(Uuid, Vec<(Uuid, C)>)>, service_uuid: Uuid,
+    characteristic_uuid: Uuid) -> impl Iterator<Item=C>
+{
+    services.into_iter()
+        .filter(move |(id, _)| *id == service_uuid)
+        .flat_map(|(_, chars)| chars)
+        .filter(move |(id, _)| *id == characteristic_uuid)
+        .map(|(_, c)| c)
+}
+
+/// Returns `true` if `services` contain a characteristic with the `characteristic_uuid` in
+/// a service with the `service_uuid`. `services` are paired with their UUIDs as in `instances`.
+pub(in crate) fn supports<C>(services: Vec<(Uuid, Vec<(Uuid, C)>)>, service_uuid: Uuid,
+    characteristic_uuid: Uuid) -> bool
+{
+    instances(services, service_uuid, characteristic_uuid).next().is_some()
 }
 
 object_ptr_wrapper!(CBCharacteristic);
@@ -150,6 +385,20 @@ impl CBCharacteristic {
         }
     }
 
+    pub fn service(&self) -> Option<CBService> {
+        unsafe {
+            let r: *mut Object = msg_send![self.as_ptr(), service];
+            CBService::wrap_nullable(r)
+        }
+    }
+
+    pub fn is_notifying(&self) -> bool {
+        unsafe {
+            let r: bool = msg_send![self.as_ptr(), isNotifying];
+            r
+        }
+    }
+
     pub fn descriptors(&self) -> Option<Vec<Descriptor>> {
         let arr = unsafe {
             let r: *mut Object = msg_send![self.as_ptr(), descriptors];
@@ -160,6 +409,14 @@ impl CBCharacteristic {
             .collect())
     }
 
+    pub fn descriptor_count(&self) -> Option<usize> {
+        let arr = unsafe {
+            let r: *mut Object = msg_send![self.as_ptr(), descriptors];
+            NSArray::wrap_nullable(r)?
+        };
+        Some(arr.iter().count())
+    }
+
     pub fn value(&self) -> Option<Vec<u8>> {
         unsafe {
             let r: *mut Object = msg_send![self.as_ptr(), value];
@@ -167,4 +424,189 @@ impl CBCharacteristic {
             Some(r.as_bytes().into())
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::mem::ManuallyDrop;
+    use std::ptr::NonNull;
+
+    #[test]
+    fn service_id() {
+        // The object is never dereferenced nor released.
+        let characteristic = |service_id| ManuallyDrop::new(Characteristic {
+            id: Uuid::from_u16(0x2a37),
+            properties: Properties::from_bits_truncate(0),
+            service_id,
+            characteristic: unsafe { StrongPtr::wrap(CBCharacteristic(NonNull::dangling())) },
+        });
+        assert_eq!(characteristic(Some(Uuid::from_u16(0x180d))).service_id(),
+            Some(Uuid::from_u16(0x180d)));
+        assert_eq!(characteristic(None).service_id(), None);
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn descriptor_count() {
+        objc::rc::autoreleasepool(|| unsafe {
+            let uuid = CBUUID::from_uuid(Uuid::from_u16(0x2a37));
+            let mut r: *mut Object = msg_send![class!(CBMutableCharacteristic), alloc];
+            r = msg_send![r, initWithType:uuid.as_ptr() properties:Property::Read as NSUInteger
+                value:nil permissions:1 as NSUInteger];
+            let characteristic = StrongPtr::wrap(CBCharacteristic::wrap(r));
+
+            // Not discovered.
+            assert_eq!(characteristic.descriptor_count(), None);
+
+            let _: () = msg_send![r, setDescriptors:NSArray::with_capacity(0).as_ptr()];
+            assert_eq!(characteristic.descriptor_count(), Some(0));
+
+            let uuid = CBUUID::from_uuid(Uuid::from_u16(0x2901));
+            let mut d: *mut Object = msg_send![class!(CBMutableDescriptor), alloc];
+            d = msg_send![d, initWithType:uuid.as_ptr() value:NSString::from_str("Rate").as_ptr()];
+            let descriptor = StrongPtr::wrap(d);
+            let descriptors = NSArray::with_capacity(1);
+            descriptors.push(descriptor.as_ptr());
+            let _: () = msg_send![r, setDescriptors:descriptors.as_ptr()];
+            assert_eq!(characteristic.descriptor_count(), Some(1));
+        });
+    }
+
+    #[test]
+    fn write_kind_best_for() {
+        let data = &[
+            (0, None),
+            (Property::Read as u32 | Property::Notify as u32, None),
+            (Property::Write as u32, Some(WriteKind::WithResponse)),
+            (Property::WriteWithoutResponse as u32, Some(WriteKind::WithoutResponse)),
+            (Property::Write as u32 | Property::WriteWithoutResponse as u32, Some(WriteKind::WithResponse)),
+        ];
+        for &(bits, exp) in data {
+            assert_eq!(WriteKind::best_for(&Properties::from_bits_truncate(bits)), exp);
+        }
+    }
+
+    #[test]
+    fn preferred_cccd() {
+        let data = &[
+            (Property::Read as u32, None),
+            (Property::Notify as u32 | Property::Indicate as u32, Some([0x01, 0x00])),
+            (Property::Indicate as u32, Some([0x02, 0x00])),
+        ];
+        for &(bits, exp) in data {
+            assert_eq!(Properties::from_bits_truncate(bits).preferred_cccd(), exp);
+        }
+    }
+
+    #[test]
+    fn property_names() {
+        assert_eq!(Properties::from_bits_truncate(0).names(), Vec::<&str>::new());
+        let bits = Property::Read as u32 | Property::Notify as u32 | Property::WriteWithoutResponse as u32;
+        assert_eq!(Properties::from_bits_truncate(bits).names(),
+            vec!["read", "write without response", "notify"]);
+    }
+
+    #[test]
+    fn cccd_state() {
+        assert_eq!(CccdState::from_value(0), CccdState::default());
+        assert_eq!(CccdState::from_value(0x0001), CccdState { notify: true, indicate: false });
+        assert_eq!(CccdState::from_value(0x0002), CccdState { notify: false, indicate: true });
+        assert_eq!(CccdState::from_value(0x0003), CccdState { notify: true, indicate: true });
+
+        assert_eq!(CccdState::parse(&[]), CccdState::default());
+        assert_eq!(CccdState::parse(&[0x01]), CccdState { notify: true, indicate: false });
+
+        let indicate = Properties::from_bits_truncate(Property::Indicate as u32);
+        let cccd = indicate.preferred_cccd().unwrap();
+        assert_eq!(CccdState::parse(&cccd), CccdState { notify: false, indicate: true });
+    }
+
+    #[test]
+    fn check_readable() {
+        let uuid = Uuid::from_slice(&[0, 1]);
+        let notify_only = Properties::from_bits_truncate(Property::Notify as u32);
+        assert_eq!(super::check_readable(&notify_only, uuid),
+            Err(CommandRejectReason::NotReadable { uuid }));
+        let readable = Properties::from_bits_truncate(Property::Read as u32 | Property::Notify as u32);
+        assert_eq!(super::check_readable(&readable, uuid), Ok(()));
+    }
+
+    #[test]
+    fn check_subscribable() {
+        let uuid = Uuid::from_slice(&[0, 1]);
+        let data = &[
+            (Property::Notify as u32, true),
+            (Property::Indicate as u32, true),
+            (Property::Read as u32 | Property::Indicate as u32, true),
+            (Property::Read as u32, false),
+            (0, false),
+        ];
+        for &(bits, exp) in data {
+            let r = super::check_subscribable(&Properties::from_bits_truncate(bits), uuid);
+            assert_eq!(r, if exp { Ok(()) } else { Err(CommandRejectReason::NotSubscribable { uuid }) });
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let props = Properties::from_bits_truncate(Property::Read as u32 | Property::Notify as u32);
+        let caps = Capabilities::from(&props);
+        assert_eq!(caps, Capabilities {
+            read: true,
+            notify: true,
+            ..Default::default()
+        });
+        assert_eq!(caps.to_string(), "read, notify");
+
+        assert_eq!(Capabilities::default().to_string(), "none");
+    }
+
+    #[test]
+    fn instances() {
+        let s1 = Uuid::from_u16(1);
+        let s2 = Uuid::from_u16(2);
+        let c1 = Uuid::from_u16(11);
+        let c2 = Uuid::from_u16(12);
+        let tree = |gen| vec![
+            (s1, vec![(c1, (gen, "a")), (c2, (gen, "b")), (c1, (gen, "c"))]),
+            (s2, vec![(c1, (gen, "d"))]),
+            (s1, vec![(c1, (gen, "e"))]),
+        ];
+
+        let before = tree(1);
+        let index = super::instances(before, s1, c1).position(|c| c == (1, "c")).unwrap();
+        assert_eq!(index, 1);
+
+        // Reconnected and rediscovered.
+        let after = tree(2);
+        assert_eq!(super::instances(after.clone(), s1, c1).nth(index), Some((2, "c")));
+        assert_eq!(super::instances(after.clone(), s1, c1).collect::<Vec<_>>(),
+            vec![(2, "a"), (2, "c"), (2, "e")]);
+        assert_eq!(super::instances(after, s2, c2).next(), None);
+    }
+
+    #[test]
+    fn supports() {
+        let s1 = Uuid::from_u16(1);
+        let s2 = Uuid::from_u16(2);
+        let c1 = Uuid::from_u16(11);
+        let c2 = Uuid::from_u16(12);
+
+        // Services not discovered.
+        assert!(!super::supports(Vec::<(_, Vec<(_, ())>)>::new(), s1, c1));
+
+        // Characteristics not discovered.
+        let mut tree = vec![(s1, vec![]), (s2, vec![])];
+        assert!(!super::supports(tree.clone(), s1, c1));
+
+        tree[0].1 = vec![(c1, ())];
+        assert!(super::supports(tree.clone(), s1, c1));
+        assert!(!super::supports(tree.clone(), s1, c2));
+        assert!(!super::supports(tree.clone(), s2, c1));
+
+        tree[1].1 = vec![(c2, ()), (c1, ())];
+        assert!(super::supports(tree.clone(), s2, c1));
+        assert!(super::supports(tree, s2, c2));
+    }
+}
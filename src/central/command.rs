@@ -1,6 +1,9 @@
 use super::*;
 use super::characteristic::{CBCharacteristic, WriteKind};
+#[cfg(feature = "async_std_unstable")]
+use super::characteristic::CharacteristicRef;
 use super::descriptor::CBDescriptor;
+use super::delegate::ServiceFilter;
 use super::service::CBService;
 
 macro_rules! impl_via_manager {
@@ -11,6 +14,9 @@ macro_rules! impl_via_manager {
                 extern fn f(ctx: *mut c_void) {
                     unsafe {
                         let $ctx = $ctx_ty::from_ctx(ctx);
+                        if !$ctx.manager.delegate().is_valid() {
+                            return;
+                        }
                         $code;
                     }
                 }
@@ -32,7 +38,9 @@ macro_rules! impl_via_peripheral {
                 extern fn f(ctx: *mut c_void) {
                     unsafe {
                         let $ctx = $ctx_ty::from_ctx(ctx);
-                        $code;
+                        let valid = $ctx.peripheral.delegate().is_valid();
+                        let command = concat!(stringify!($ctx_ty), "::", stringify!($n));
+                        run_if_valid($ctx, valid, command, |$ctx| $code);
                     }
                 }
                 unsafe {
@@ -45,6 +53,17 @@ macro_rules! impl_via_peripheral {
     };
 }
 
+/// Runs `f` with the command context `ctx` if the delegate is `valid`. Otherwise logs the dropped
+/// `command` and drops `ctx`, which disconnects the result channels it holds so the waiters fail
+/// instead of blocking forever.
+fn run_if_valid<C>(ctx: C, valid: bool, command: &str, f: impl FnOnce(C)) {
+    if valid {
+        f(ctx);
+    } else {
+        warn!("{} command dropped: central manager of the peripheral has been dropped", command);
+    }
+}
+
 pub trait Command: 'static + Sized + Send  {
     fn into_ctx(self) -> *mut c_void {
         Box::into_raw(Box::new(self)) as *mut c_void
@@ -57,6 +76,11 @@ pub trait Command: 'static + Sized + Send  {
     unsafe fn dispatch(self, queue: *mut Object, f: dispatch_function_t) {
         dispatch_async_f(queue, self.into_ctx(), f);
     }
+
+    unsafe fn dispatch_after(self, delay: Duration, queue: *mut Object, f: dispatch_function_t) {
+        let delay = delay.as_nanos().min(i64::MAX as u128) as i64;
+        dispatch_after_f(dispatch_time(DISPATCH_TIME_NOW, delay), queue, self.into_ctx(), f);
+    }
 }
 
 #[repr(transparent)]
@@ -76,11 +100,72 @@ impl Command for Manager {
 
 impl_via_manager! { Manager =>
     cancel_scan(ctx) {
+        ctx.manager.delegate().set_interval_tracking(false);
         ctx.manager.cancel_scan();
     }
     drop_self(ctx) {
         ctx.manager.drop_self();
     }
+    disconnect_all(ctx) {
+        ctx.manager.delegate().disconnect_all(*ctx.manager);
+    }
+    cancel_timers(ctx) {
+        ctx.manager.delegate().cancel_timers();
+    }
+    pause_events(ctx) {
+        ctx.manager.delegate().pause_events();
+    }
+    resume_events(ctx) {
+        ctx.manager.delegate().resume_events();
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct Shutdown {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) done: crate::sync::Sender<()>,
+}
+
+impl Command for Shutdown {}
+
+impl_via_manager! { Shutdown =>
+    dispatch(ctx) {
+        ctx.manager.delegate().shutdown(*ctx.manager);
+        let _ = ctx.done.send_blocking(());
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct Diagnose {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) done: crate::sync::Sender<Diagnostics>,
+}
+
+impl Command for Diagnose {}
+
+impl_via_manager! { Diagnose =>
+    dispatch(ctx) {
+        if let Some(diagnostics) = ctx.manager.delegate().diagnostics(*ctx.manager) {
+            let _ = ctx.done.send_blocking(diagnostics);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct SetDiscoveredCallback {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) callback: DiscoveredCallback,
+}
+
+impl Command for SetDiscoveredCallback {}
+
+impl_via_manager! { SetDiscoveredCallback =>
+    dispatch(ctx) {
+        ctx.manager.delegate().set_discovered_callback(ctx.callback);
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////
@@ -103,6 +188,7 @@ impl_via_manager! { GetPeripherals =>
     }
     get_peripherals_with_services(ctx) {
         let peripherals = ctx.manager.get_peripherals_with_services(*ctx.uuids).unwrap_or_default();
+        ctx.manager.delegate().retrieved_connected(&peripherals);
         ctx.manager.delegate().send(CentralEvent::GetPeripheralsWithServicesResult {
             peripherals,
             tag: ctx.tag,
@@ -121,12 +207,28 @@ impl Command for CancelConnect {}
 
 impl_via_manager! { CancelConnect =>
     cancel_connect(ctx) {
+        ctx.manager.delegate().connect_cancelled(*ctx.peripheral);
         ctx.manager.cancel_connect(&ctx.peripheral);
     }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////
 
+pub struct ForceRediscover {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+}
+
+impl Command for ForceRediscover {}
+
+impl_via_manager! { ForceRediscover =>
+    dispatch(ctx) {
+        ctx.manager.delegate().start_rediscovery(*ctx.manager, *ctx.peripheral);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
 pub struct Scan {
     pub(in super) manager: StrongPtr<CBCentralManager>,
     pub(in super) options: ScanOptions,
@@ -136,22 +238,82 @@ impl Command for Scan {}
 
 impl_via_manager! { Scan =>
     dispatch(ctx) {
+        ctx.manager.delegate().set_interval_tracking(ctx.options.estimate_interval);
         ctx.manager.scan(&ctx.options);
     }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////
 
+pub struct BeginScanSession {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) id: u64,
+    pub(in super) options: ScanOptions,
+}
+
+impl Command for BeginScanSession {}
+
+impl_via_manager! { BeginScanSession =>
+    dispatch(ctx) {
+        ctx.manager.delegate().begin_scan_session(*ctx.manager, ctx.id, ctx.options);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct EndScanSession {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) id: u64,
+}
+
+impl Command for EndScanSession {}
+
+impl_via_manager! { EndScanSession =>
+    dispatch(ctx) {
+        ctx.manager.delegate().end_scan_session(*ctx.manager, ctx.id);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
 pub struct Connect {
     pub(in super) manager: StrongPtr<CBCentralManager>,
     pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) options: Option<ConnectOptions>,
 }
 
 impl Command for Connect {}
 
 impl_via_manager! { Connect =>
     dispatch(ctx) {
-        ctx.manager.connect(&ctx.peripheral);
+        ctx.manager.delegate().connect_started(*ctx.peripheral);
+        ctx.manager.connect(&ctx.peripheral, ctx.options.map(|o| o.to_options_dict()));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct Reconnect {
+    pub(in super) manager: StrongPtr<CBCentralManager>,
+    pub(in super) uuids: StrongPtr<NSArray>,
+
+    /// Number of the `uuids`.
+    pub(in super) count: usize,
+}
+
+impl Command for Reconnect {}
+
+impl_via_manager! { Reconnect =>
+    dispatch(ctx) {
+        let peripherals = ctx.manager.get_peripherals(*ctx.uuids).unwrap_or_default();
+        if peripherals.len() < ctx.count {
+            warn!("{} of {} session peripherals are unknown to the system",
+                ctx.count - peripherals.len(), ctx.count);
+        }
+        for peripheral in peripherals {
+            ctx.manager.delegate().connect_started(*peripheral.peripheral);
+            ctx.manager.connect(&peripheral.peripheral, None);
+        }
     }
 }
 
@@ -160,13 +322,171 @@ impl_via_manager! { Connect =>
 pub struct DiscoverServices {
     pub(in super) peripheral: StrongPtr<CBPeripheral>,
     pub(in super) uuids: Option<StrongPtr<NSArray>>,
+    pub(in super) filter: Option<ServiceFilter>,
 }
 
 impl Command for DiscoverServices {}
 
 impl_via_peripheral! { DiscoverServices =>
     dispatch(ctx) {
-        ctx.peripheral.discover_services(ctx.uuids.as_ref().map(|v| **v));
+        let uuids = ctx.uuids.as_ref().map(|v| **v);
+        match ctx.filter {
+            Some(filter) => ctx.peripheral.delegate()
+                .discover_services_filtered(*ctx.peripheral, uuids, filter),
+            None => ctx.peripheral.delegate().discover_services(*ctx.peripheral, uuids),
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct FindCharacteristics {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) uuids: Vec<Uuid>,
+}
+
+impl Command for FindCharacteristics {}
+
+impl_via_peripheral! { FindCharacteristics =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().find_characteristics(*ctx.peripheral, ctx.uuids);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "async_std_unstable")]
+pub struct DiscoverTree {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) sender: crate::sync::Sender<Result<tree::DeviceTree, Error>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Command for DiscoverTree {}
+
+#[cfg(feature = "async_std_unstable")]
+impl_via_peripheral! { DiscoverTree =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().start_tree_discovery(*ctx.peripheral, ctx.sender);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "async_std_unstable")]
+pub struct WaitForValue {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+    pub(in super) predicate: super::delegate::ValuePredicate,
+    pub(in super) sender: crate::sync::Sender<Result<Vec<u8>, Error>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Command for WaitForValue {}
+
+#[cfg(feature = "async_std_unstable")]
+impl_via_peripheral! { WaitForValue =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().add_value_waiter(*ctx.peripheral, *ctx.characteristic,
+            ctx.predicate, ctx.sender);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "async_std_unstable")]
+pub struct AddNotificationStream {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+    pub(in super) id: u64,
+    pub(in super) sender: crate::sync::Sender<Result<Vec<u8>, Error>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Command for AddNotificationStream {}
+
+#[cfg(feature = "async_std_unstable")]
+impl_via_peripheral! { AddNotificationStream =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().add_notification_stream(*ctx.peripheral, *ctx.characteristic,
+            ctx.id, ctx.sender);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "async_std_unstable")]
+pub struct RemoveNotificationStream {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+    pub(in super) id: u64,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Command for RemoveNotificationStream {}
+
+#[cfg(feature = "async_std_unstable")]
+impl_via_peripheral! { RemoveNotificationStream =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().remove_notification_stream(*ctx.peripheral, *ctx.characteristic,
+            ctx.id);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(feature = "async_std_unstable")]
+pub struct ReadSet {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristics: Vec<(CharacteristicRef, StrongPtr<CBCharacteristic>)>,
+    pub(in super) deadline: Duration,
+    pub(in super) sender: crate::sync::Sender<HashMap<CharacteristicRef, Result<Vec<u8>, Error>>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Command for ReadSet {}
+
+#[cfg(feature = "async_std_unstable")]
+impl_via_peripheral! { ReadSet =>
+    dispatch(ctx) {
+        let characteristics = ctx.characteristics.iter().map(|(k, c)| (*k, **c)).collect();
+        if let Some(id) = ctx.peripheral.delegate().start_read_set(*ctx.peripheral, characteristics,
+            ctx.sender)
+        {
+            ReadSetDeadline {
+                peripheral: ctx.peripheral,
+                id,
+            }.schedule(ctx.deadline);
+        }
+    }
+}
+
+/// Completes a read set with timeout errors for the reads not completed by the deadline.
+#[cfg(feature = "async_std_unstable")]
+pub struct ReadSetDeadline {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) id: u64,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Command for ReadSetDeadline {}
+
+#[cfg(feature = "async_std_unstable")]
+impl ReadSetDeadline {
+    pub fn schedule(self, deadline: Duration) {
+        extern fn f(ctx: *mut c_void) {
+            unsafe {
+                let ctx = ReadSetDeadline::from_ctx(ctx);
+                if !ctx.peripheral.delegate().is_valid() {
+                    return;
+                }
+                ctx.peripheral.delegate().expire_read_set(ctx.id);
+            }
+        }
+        unsafe {
+            let queue = self.peripheral.delegate().queue();
+            Command::dispatch_after(self, deadline, queue, f);
+        }
     }
 }
 
@@ -180,17 +500,51 @@ pub struct PeripheralServiceUuids {
 
 impl Command for PeripheralServiceUuids {}
 
+impl PeripheralServiceUuids {
+    fn is_stale(&self) -> bool {
+        self.peripheral.delegate().reject_stale(*self.peripheral, Some(*self.service))
+    }
+}
+
 impl_via_peripheral! { PeripheralServiceUuids =>
     discover_characteristics(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
         ctx.peripheral.discover_characteristics(*ctx.service, ctx.uuids.as_ref().map(|v| **v));
     }
     discover_included_services(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
         ctx.peripheral.discover_included_services(*ctx.service, ctx.uuids.as_ref().map(|v| **v));
     }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////
 
+pub struct DiscoverAndSubscribe {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) service: StrongPtr<CBService>,
+    pub(in super) uuids: StrongPtr<NSArray>,
+    pub(in super) uuid: Uuid,
+}
+
+impl Command for DiscoverAndSubscribe {}
+
+impl_via_peripheral! { DiscoverAndSubscribe =>
+    dispatch(ctx) {
+        let mut delegate = ctx.peripheral.delegate();
+        if delegate.reject_stale(*ctx.peripheral, Some(*ctx.service)) {
+            return;
+        }
+        delegate.subscribe_on_discovery(*ctx.peripheral, *ctx.service, ctx.uuid);
+        ctx.peripheral.discover_characteristics(*ctx.service, Some(*ctx.uuids));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
 pub struct Peripheral {
     pub(in super) peripheral: StrongPtr<CBPeripheral>,
 }
@@ -213,6 +567,24 @@ impl_via_peripheral! { Peripheral =>
 
 ///////////////////////////////////////////////////////////////////////////////////
 
+pub struct Reject {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) reason: CommandRejectReason,
+}
+
+impl Command for Reject {}
+
+impl_via_peripheral! { Reject =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().send(CentralEvent::CommandRejected {
+            peripheral: super::Peripheral::retain(*ctx.peripheral),
+            reason: ctx.reason,
+        });
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
 pub struct PeripheralTag {
     pub(in super) peripheral: StrongPtr<CBPeripheral>,
     pub(in super) tag: Option<Tag>,
@@ -244,36 +616,468 @@ pub struct Characteristic {
 
 impl Command for Characteristic {}
 
+impl Characteristic {
+    fn is_stale(&self) -> bool {
+        self.peripheral.delegate().reject_stale(*self.peripheral, self.characteristic.service())
+    }
+}
+
 impl_via_peripheral! { Characteristic =>
     discover_descriptors(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
         ctx.peripheral.discover_descriptors(*ctx.characteristic);
     }
     read(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
         ctx.peripheral.read_characteristic(*ctx.characteristic);
     }
     subscribe(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
         ctx.peripheral.set_notify_value(*ctx.characteristic, true);
     }
+    read_then_subscribe(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
+        ctx.peripheral.delegate().start_read_then_subscribe(*ctx.peripheral, *ctx.characteristic);
+        ctx.peripheral.read_characteristic(*ctx.characteristic);
+    }
+    read_all_descriptors(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
+        ctx.peripheral.delegate().read_all_descriptors(*ctx.peripheral, *ctx.characteristic);
+    }
+    read_cccd(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
+        ctx.peripheral.delegate().read_cccd(*ctx.peripheral, *ctx.characteristic);
+    }
+    ensure_subscribed(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
+        if !ctx.characteristic.is_notifying() {
+            ctx.peripheral.set_notify_value(*ctx.characteristic, true);
+        }
+    }
     unsubscribe(ctx) {
+        if ctx.is_stale() {
+            return;
+        }
         ctx.peripheral.set_notify_value(*ctx.characteristic, false);
     }
+    stop_polling(ctx) {
+        ctx.peripheral.delegate().stop_poll(*ctx.characteristic);
+    }
+    enable_value_dedup(ctx) {
+        ctx.peripheral.delegate().set_value_dedup(*ctx.peripheral, *ctx.characteristic, true);
+    }
+    disable_value_dedup(ctx) {
+        ctx.peripheral.delegate().set_value_dedup(*ctx.peripheral, *ctx.characteristic, false);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct StartPolling {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+    pub(in super) interval: Duration,
+}
+
+impl Command for StartPolling {}
+
+impl_via_peripheral! { StartPolling =>
+    dispatch(ctx) {
+        let generation = ctx.peripheral.delegate().start_poll(*ctx.peripheral, *ctx.characteristic);
+        PollTick {
+            peripheral: ctx.peripheral,
+            characteristic: ctx.characteristic,
+            interval: ctx.interval,
+            generation,
+        }.run();
+    }
+}
+
+/// Periodic read of a polled characteristic. Reschedules itself until the poll is stopped.
+pub struct PollTick {
+    peripheral: StrongPtr<CBPeripheral>,
+    characteristic: StrongPtr<CBCharacteristic>,
+    interval: Duration,
+    generation: u64,
+}
+
+impl Command for PollTick {}
+
+impl PollTick {
+    /// Must be called on the delegate queue.
+    fn run(self) {
+        if !self.peripheral.delegate().poll(*self.peripheral, *self.characteristic, self.generation) {
+            return;
+        }
+        extern fn f(ctx: *mut c_void) {
+            unsafe {
+                let ctx = PollTick::from_ctx(ctx);
+                if !ctx.peripheral.delegate().is_valid() {
+                    return;
+                }
+                ctx.run();
+            }
+        }
+        unsafe {
+            let queue = self.peripheral.delegate().queue();
+            let interval = self.interval;
+            Command::dispatch_after(self, interval, queue, f);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+/// Delay before re-issuing a read or subscription that failed with a transient error.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+pub struct ReadWithRetry {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+    pub(in super) max_retries: u32,
+}
+
+impl Command for ReadWithRetry {}
+
+impl_via_peripheral! { ReadWithRetry =>
+    dispatch(ctx) {
+        let mut delegate = ctx.peripheral.delegate();
+        if delegate.reject_stale(*ctx.peripheral, ctx.characteristic.service()) {
+            return;
+        }
+        delegate.start_read_retry(*ctx.peripheral, *ctx.characteristic, ctx.max_retries);
+        ctx.peripheral.read_characteristic(*ctx.characteristic);
+    }
+}
+
+/// Re-issues a read that failed with a transient error after `RETRY_DELAY`.
+pub struct RetryRead {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+}
+
+impl Command for RetryRead {}
+
+impl RetryRead {
+    pub fn schedule(self) {
+        extern fn f(ctx: *mut c_void) {
+            unsafe {
+                let ctx = RetryRead::from_ctx(ctx);
+                if !ctx.peripheral.delegate().is_valid() {
+                    return;
+                }
+                ctx.peripheral.delegate().retry_read(*ctx.peripheral, *ctx.characteristic);
+            }
+        }
+        unsafe {
+            let queue = self.peripheral.delegate().queue();
+            Command::dispatch_after(self, RETRY_DELAY, queue, f);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct SubscribeWithRetry {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+    pub(in super) max_retries: u32,
+}
+
+impl Command for SubscribeWithRetry {}
+
+impl_via_peripheral! { SubscribeWithRetry =>
+    dispatch(ctx) {
+        let mut delegate = ctx.peripheral.delegate();
+        if delegate.reject_stale(*ctx.peripheral, ctx.characteristic.service()) {
+            return;
+        }
+        delegate.start_subscribe_retry(*ctx.peripheral, *ctx.characteristic, ctx.max_retries);
+        ctx.peripheral.set_notify_value(*ctx.characteristic, true);
+    }
+}
+
+/// Re-issues a subscription that failed with a transient error after `RETRY_DELAY`.
+pub struct RetrySubscribe {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristic: StrongPtr<CBCharacteristic>,
+}
+
+impl Command for RetrySubscribe {}
+
+impl RetrySubscribe {
+    pub fn schedule(self) {
+        extern fn f(ctx: *mut c_void) {
+            unsafe {
+                let ctx = RetrySubscribe::from_ctx(ctx);
+                if !ctx.peripheral.delegate().is_valid() {
+                    return;
+                }
+                ctx.peripheral.delegate().retry_subscribe(*ctx.peripheral, *ctx.characteristic);
+            }
+        }
+        unsafe {
+            let queue = self.peripheral.delegate().queue();
+            Command::dispatch_after(self, RETRY_DELAY, queue, f);
+        }
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////////
 
+pub struct SetNotifyAll {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) characteristics: Vec<StrongPtr<CBCharacteristic>>,
+    pub(in super) rejected: Vec<CommandRejectReason>,
+    pub(in super) enabled: bool,
+}
+
+impl Command for SetNotifyAll {}
+
+impl_via_peripheral! { SetNotifyAll =>
+    dispatch(ctx) {
+        let delegate = ctx.peripheral.delegate();
+        for reason in ctx.rejected {
+            delegate.send(CentralEvent::CommandRejected {
+                peripheral: super::Peripheral::retain(*ctx.peripheral),
+                reason,
+            });
+        }
+        for characteristic in &ctx.characteristics {
+            if delegate.reject_stale(*ctx.peripheral, characteristic.service()) {
+                continue;
+            }
+            ctx.peripheral.set_notify_value(**characteristic, ctx.enabled);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+const INLINE_WRITE_VALUE_CAP: usize = 16;
+
+/// Characteristic value to write. Small values are stored inline so no `NSData` is created on the
+/// caller thread.
+pub enum WriteValue {
+    Inline {
+        buf: [u8; INLINE_WRITE_VALUE_CAP],
+        len: u8,
+    },
+    Data(StrongPtr<NSData>),
+}
+
+impl WriteValue {
+    pub fn new(value: &[u8]) -> Self {
+        if value.len() <= INLINE_WRITE_VALUE_CAP {
+            let mut buf = [0; INLINE_WRITE_VALUE_CAP];
+            buf[..value.len()].copy_from_slice(value);
+            Self::Inline {
+                buf,
+                len: value.len() as u8,
+            }
+        } else {
+            Self::Data(NSData::from_bytes(value).retain())
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Inline { buf, len } => &buf[..*len as usize],
+            Self::Data(data) => data.as_bytes(),
+        }
+    }
+
+    /// Returns the value as `NSData`. The inline value is copied since Core Bluetooth may retain
+    /// the data after the write call returns.
+    fn to_ns_data(&self) -> NSData {
+        match self {
+            Self::Inline { .. } => NSData::from_bytes(self.as_bytes()),
+            Self::Data(data) => **data,
+        }
+    }
+}
+
 pub struct WriteCharacteristic {
     pub(in super) peripheral: StrongPtr<CBPeripheral>,
     pub(in super) characteristic: StrongPtr<CBCharacteristic>,
-    pub(in super) value: StrongPtr<NSData>,
-    pub(in super) kind: WriteKind,
+    pub(in super) value: WriteValue,
+
+    /// `None` means the default write kind of the peripheral.
+    pub(in super) kind: Option<WriteKind>,
+
+    /// Receives the reason if the write is rejected.
+    pub(in super) rejected: Option<crate::sync::Sender<CommandRejectReason>>,
 }
 
 impl Command for WriteCharacteristic {}
 
 impl_via_peripheral! { WriteCharacteristic =>
     dispatch(ctx) {
-        ctx.peripheral.write_characteristic(*ctx.characteristic, *ctx.value, ctx.kind);
+        let kind = ctx.kind.unwrap_or_else(|| ctx.peripheral.delegate().default_write_kind(*ctx.peripheral));
+        let _ = write_characteristic(*ctx.peripheral, *ctx.characteristic, &ctx.value, kind,
+            ctx.rejected.as_ref());
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct SetDefaultWriteKind {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) kind: WriteKind,
+}
+
+impl Command for SetDefaultWriteKind {}
+
+impl_via_peripheral! { SetDefaultWriteKind =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().set_default_write_kind(*ctx.peripheral, ctx.kind);
+    }
+}
+
+pub struct Inspect {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) f: Box<dyn FnOnce(CBPeripheral) + Send>,
+}
+
+impl Command for Inspect {}
+
+impl_via_peripheral! { Inspect =>
+    dispatch(ctx) {
+        (ctx.f)(*ctx.peripheral);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+/// Outcome of `write_characteristic`.
+pub enum IssuedWrite {
+    /// The write was rejected and not issued.
+    Rejected,
+
+    /// The write without response was issued.
+    Sent,
+
+    /// The write with response was issued and awaits result, identified by the id.
+    Pending(u64),
+}
+
+/// Writes the characteristic value unless the write is rejected. The rejection reason is sent
+/// to `rejected` before the `CommandRejected` event, so the receiver of both can tell the
+/// rejection of this write from the others.
+pub fn write_characteristic(peripheral: CBPeripheral, characteristic: CBCharacteristic,
+    value: &WriteValue, kind: WriteKind,
+    rejected: Option<&crate::sync::Sender<CommandRejectReason>>) -> IssuedWrite
+{
+    let mut delegate = peripheral.delegate();
+    let reason = if delegate.is_stale(characteristic.service()) {
+        Some(CommandRejectReason::StaleHandle)
+    } else {
+        delegate.write_too_large(peripheral, value.as_bytes().len(), kind)
+    };
+    if let Some(reason) = reason {
+        if let Some(rejected) = rejected {
+            let _ = rejected.send_blocking(reason);
+        }
+        delegate.reject(peripheral, reason);
+        return IssuedWrite::Rejected;
+    }
+    objc::rc::autoreleasepool(|| {
+        peripheral.write_characteristic(characteristic, value.to_ns_data(), kind);
+    });
+    match kind {
+        WriteKind::WithResponse => delegate.write_issued(peripheral, characteristic)
+            .map(IssuedWrite::Pending)
+            .unwrap_or(IssuedWrite::Sent),
+        WriteKind::WithoutResponse => IssuedWrite::Sent,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct WriteSequence {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) writes: Vec<(StrongPtr<CBCharacteristic>, WriteValue, WriteKind)>,
+}
+
+impl Command for WriteSequence {}
+
+impl_via_peripheral! { WriteSequence =>
+    dispatch(ctx) {
+        ctx.peripheral.delegate().start_write_sequence(*ctx.peripheral, ctx.writes);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////////
+
+pub struct WriteByUuid {
+    pub(in super) peripheral: StrongPtr<CBPeripheral>,
+    pub(in super) service_uuid: Uuid,
+    pub(in super) characteristic_uuid: Uuid,
+    pub(in super) value: WriteValue,
+    pub(in super) kind: WriteKind,
+}
+
+impl Command for WriteByUuid {}
+
+impl_via_peripheral! { WriteByUuid =>
+    dispatch(ctx) {
+        let services: Vec<_> = ctx.peripheral.services().unwrap_or_default().into_iter()
+            .map(|s| {
+                let chars = s.service.characteristics().unwrap_or_default();
+                (s, chars)
+            })
+            .collect();
+        let found = find_characteristic(ctx.service_uuid, ctx.characteristic_uuid,
+            services.iter().map(|(s, chars)| (s.id(), chars.iter().map(|c| c.id()).collect())));
+        match found {
+            Ok((si, ci)) => {
+                let characteristic = *services[si].1[ci].characteristic;
+                let _ = write_characteristic(*ctx.peripheral, characteristic, &ctx.value, ctx.kind,
+                    None);
+            }
+            Err(reason) => {
+                ctx.peripheral.delegate().send(CentralEvent::CommandRejected {
+                    peripheral: super::Peripheral::retain(*ctx.peripheral),
+                    reason,
+                });
+            }
+        }
+    }
+}
+
+/// Returns indices of the service with `service_uuid` and of its characteristic with
+/// `characteristic_uuid` in `services`, which lists UUIDs of services and their characteristics.
+fn find_characteristic(service_uuid: Uuid, characteristic_uuid: Uuid,
+    services: impl IntoIterator<Item=(Uuid, Vec<Uuid>)>)
+    -> Result<(usize, usize), CommandRejectReason>
+{
+    let mut service_found = false;
+    for (si, (id, chars)) in services.into_iter().enumerate() {
+        if id != service_uuid {
+            continue;
+        }
+        service_found = true;
+        if let Some(ci) = chars.iter().position(|&c| c == characteristic_uuid) {
+            return Ok((si, ci));
+        }
     }
+    Err(CommandRejectReason::NotDiscovered {
+        uuid: if service_found { characteristic_uuid } else { service_uuid },
+    })
 }
 
 ///////////////////////////////////////////////////////////////////////////////////
@@ -305,4 +1109,89 @@ impl_via_peripheral! { WriteDescriptor =>
     dispatch(ctx) {
         ctx.peripheral.write_descriptor(*ctx.descriptor, *ctx.value);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_if_valid() {
+        struct Ctx {
+            done: crate::sync::Sender<u32>,
+        }
+
+        let (done, receiver) = crate::sync::bounded_channel(1);
+        super::run_if_valid(Ctx { done }, true, "Ctx::dispatch", |ctx| {
+            let _ = ctx.done.send_blocking(1);
+        });
+        assert_eq!(crate::sync::recv_blocking(&receiver), Some(1));
+        assert_eq!(crate::sync::recv_blocking(&receiver), None);
+
+        let (done, receiver) = crate::sync::bounded_channel(1);
+        super::run_if_valid(Ctx { done }, false, "Ctx::dispatch", |_| unreachable!());
+        assert_eq!(crate::sync::recv_blocking(&receiver), None);
+    }
+
+    #[test]
+    fn inline_write_value() {
+        for len in 0..=INLINE_WRITE_VALUE_CAP {
+            let value: Vec<u8> = (0..len as u8).collect();
+            let v = WriteValue::new(&value);
+            match v {
+                WriteValue::Inline { .. } => {}
+                WriteValue::Data(_) => panic!("value of len {} is not inline", len),
+            }
+            assert_eq!(v.as_bytes(), &value[..]);
+        }
+    }
+
+    /// Compares the cost of passing a small write value to the queue with and without the inline
+    /// buffer, i.e. the work done on the calling thread of a write-heavy control loop. Needs
+    /// the Objective-C runtime, run on macOS with `cargo test --release -- --ignored --nocapture`.
+    #[cfg(target_os = "macos")]
+    #[test]
+    #[ignore]
+    fn bench_small_write_value() {
+        use std::time::Instant;
+
+        const N: u32 = 1_000_000;
+        let value = [0x55; 8];
+
+        let start = Instant::now();
+        for _ in 0..N {
+            objc::rc::autoreleasepool(|| {
+                std::hint::black_box(NSData::from_bytes(&value).retain());
+            });
+        }
+        let data = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..N {
+            objc::rc::autoreleasepool(|| {
+                std::hint::black_box(WriteValue::new(&value));
+            });
+        }
+        let inline = start.elapsed();
+
+        println!("{} writes of {} bytes: NSData {:?}, inline {:?}", N, value.len(), data, inline);
+    }
+
+    #[test]
+    fn find_characteristic() {
+        let s1 = Uuid::from_slice(&[1, 0]);
+        let s2 = Uuid::from_slice(&[2, 0]);
+        let s3 = Uuid::from_slice(&[3, 0]);
+        let a = Uuid::from_slice(&[0, 1]);
+        let b = Uuid::from_slice(&[0, 2]);
+        let c = Uuid::from_slice(&[0, 3]);
+        let services = vec![(s1, vec![a]), (s2, vec![a, b]), (s2, vec![c])];
+
+        assert_eq!(super::find_characteristic(s2, b, services.clone()), Ok((1, 1)));
+        assert_eq!(super::find_characteristic(s2, c, services.clone()), Ok((2, 0)));
+        assert_eq!(super::find_characteristic(s1, b, services.clone()),
+            Err(CommandRejectReason::NotDiscovered { uuid: b }));
+        assert_eq!(super::find_characteristic(s3, a, services),
+            Err(CommandRejectReason::NotDiscovered { uuid: s3 }));
+    }
+}
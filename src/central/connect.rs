@@ -0,0 +1,316 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+#[cfg(feature = "async_std_unstable")]
+use std::future::Future;
+#[cfg(feature = "async_std_unstable")]
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "async_std_unstable")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "async_std_unstable")]
+use std::task::{Context, Poll};
+use std::task::Waker;
+
+use crate::central::peripheral::PeripheralId;
+use crate::error::Error;
+
+type ConnectResult = Result<(), Error>;
+
+#[derive(Default)]
+struct Slot {
+    result: Option<ConnectResult>,
+    waker: Option<Waker>,
+}
+
+impl Slot {
+    fn complete(&mut self, result: ConnectResult) {
+        if self.result.is_none() {
+            self.result = Some(result);
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Connection attempts to a peripheral: registration tokens and the corresponding slots.
+type PendingSlots = Vec<(u64, Arc<Mutex<Slot>>)>;
+
+/// Pending connection attempts awaited by futures returned from `CentralManager::connect_async`.
+/// The delegate resolves them as connection events arrive.
+#[derive(Default)]
+pub(in crate) struct ConnectRegistry {
+    #[cfg(feature = "async_std_unstable")]
+    next_token: AtomicU64,
+
+    /// Keyed by peripheral id.
+    pending: Mutex<HashMap<PeripheralId, PendingSlots>>,
+}
+
+impl ConnectRegistry {
+    /// Registers a connection attempt to the peripheral `id` and returns the future resolving
+    /// when the attempt completes. Dropping the future deregisters the attempt.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn register(self: &Arc<Self>, id: PeripheralId) -> ConnectFuture {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        self.pending.lock().unwrap()
+            .entry(id)
+            .or_default()
+            .push((token, slot.clone()));
+        ConnectFuture {
+            registry: self.clone(),
+            id,
+            token,
+            slot,
+            on_cancel: None,
+        }
+    }
+
+    /// Completes all pending connection attempts to the peripheral `id` with the `result`.
+    pub fn resolve(&self, id: PeripheralId, result: ConnectResult) {
+        let slots = self.pending.lock().unwrap().remove(&id);
+        for (_, slot) in slots.into_iter().flatten() {
+            slot.lock().unwrap().complete(result.clone());
+        }
+    }
+
+    /// Completes all pending connection attempts with the `error`.
+    pub fn resolve_all(&self, error: Error) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        for (_, slot) in pending.into_values().flatten() {
+            slot.lock().unwrap().complete(Err(error.clone()));
+        }
+    }
+
+    /// Deregisters the attempt. Returns `true` if it was still pending and no other attempts to
+    /// the peripheral `id` are pending.
+    #[cfg(feature = "async_std_unstable")]
+    fn deregister(&self, id: PeripheralId, token: u64) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(slots) = pending.get_mut(&id) {
+            let len = slots.len();
+            slots.retain(|(t, _)| *t != token);
+            if slots.is_empty() {
+                pending.remove(&id);
+                return len > 0;
+            }
+        }
+        false
+    }
+}
+
+/// Connection attempts started by `CentralManager::connect_throttled`. Keeps at most
+/// `max_concurrent` attempts in flight and queues the rest.
+pub(in crate) struct ConnectThrottle<P> {
+    max_concurrent: usize,
+    in_flight: HashSet<PeripheralId>,
+    queued: VecDeque<(PeripheralId, P)>,
+}
+
+impl<P> Default for ConnectThrottle<P> {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 1,
+            in_flight: HashSet::new(),
+            queued: VecDeque::new(),
+        }
+    }
+}
+
+impl<P> ConnectThrottle<P> {
+    /// Queues connection attempt to the peripheral `id` unless it's already queued or in flight,
+    /// and updates the concurrency limit. Returns peripherals to start connecting to now.
+    pub fn push(&mut self, id: PeripheralId, peripheral: P, max_concurrent: usize) -> Vec<P> {
+        self.max_concurrent = max_concurrent.max(1);
+        if !self.in_flight.contains(&id) && self.queued.iter().all(|(i, _)| *i != id) {
+            self.queued.push_back((id, peripheral));
+        }
+        self.start_next()
+    }
+
+    /// Marks the attempt to the peripheral `id` as complete. Returns peripherals to start
+    /// connecting to now.
+    pub fn complete(&mut self, id: PeripheralId) -> Vec<P> {
+        if self.in_flight.remove(&id) {
+            self.start_next()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Removes the attempt to the peripheral `id` if it's still queued.
+    pub fn cancel(&mut self, id: PeripheralId) {
+        self.queued.retain(|(i, _)| *i != id);
+    }
+
+    /// Returns the number of attempts in flight or queued.
+    pub fn len(&self) -> usize {
+        self.in_flight.len() + self.queued.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.in_flight.clear();
+        self.queued.clear();
+    }
+
+    fn start_next(&mut self) -> Vec<P> {
+        let mut r = Vec::new();
+        while self.in_flight.len() < self.max_concurrent {
+            if let Some((id, peripheral)) = self.queued.pop_front() {
+                self.in_flight.insert(id);
+                r.push(peripheral);
+            } else {
+                break;
+            }
+        }
+        r
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+pub(in crate) struct ConnectFuture {
+    registry: Arc<ConnectRegistry>,
+    id: PeripheralId,
+    token: u64,
+    slot: Arc<Mutex<Slot>>,
+
+    /// Called when the future is dropped before the attempt completes and no other attempts to
+    /// the same peripheral are pending.
+    on_cancel: Option<Box<dyn FnOnce() + Send>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl ConnectFuture {
+    /// Completes the future immediately with the `error`.
+    pub fn fail(self, error: Error) -> Self {
+        self.slot.lock().unwrap().complete(Err(error));
+        self
+    }
+
+    pub fn on_cancel(mut self, f: impl FnOnce() + Send + 'static) -> Self {
+        self.on_cancel = Some(Box::new(f));
+        self
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Future for ConnectFuture {
+    type Output = ConnectResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().unwrap();
+        if let Some(result) = slot.result.take() {
+            Poll::Ready(result)
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Drop for ConnectFuture {
+    fn drop(&mut self) {
+        if self.registry.deregister(self.id, self.token) {
+            if let Some(f) = self.on_cancel.take() {
+                f();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "async_std_unstable")]
+    use crate::error::ErrorKind;
+
+    fn id(v: u8) -> PeripheralId {
+        crate::uuid::Uuid::from_slice(&[0, v]).into()
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn resolve() {
+        let registry = Arc::new(ConnectRegistry::default());
+        let ok = registry.register(id(1));
+        let failed = registry.register(id(2));
+        let other = registry.register(id(3));
+
+        registry.resolve(id(1), Ok(()));
+        registry.resolve(id(2), Err(Error::new(ErrorKind::ConnectionFailed, "failed")));
+
+        assert!(async_std::task::block_on(ok).is_ok());
+        assert_eq!(async_std::task::block_on(failed).unwrap_err().kind(), ErrorKind::ConnectionFailed);
+
+        registry.resolve_all(Error::new(ErrorKind::ManagerNotPoweredOn, "powered off"));
+        assert_eq!(async_std::task::block_on(other).unwrap_err().kind(), ErrorKind::ManagerNotPoweredOn);
+        assert!(registry.pending.lock().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn fail() {
+        let registry = Arc::new(ConnectRegistry::default());
+        let f = registry.register(id(1)).fail(Error::new(ErrorKind::ManagerNotPoweredOn, ""));
+        assert_eq!(async_std::task::block_on(f).unwrap_err().kind(), ErrorKind::ManagerNotPoweredOn);
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn drop_deregisters() {
+        let registry = Arc::new(ConnectRegistry::default());
+        let f1 = registry.register(id(1));
+        let f2 = registry.register(id(1));
+        drop(f1);
+        assert_eq!(registry.pending.lock().unwrap()[&id(1)].len(), 1);
+        drop(f2);
+        assert!(registry.pending.lock().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn drop_cancels() {
+        let cancels = Arc::new(AtomicU64::new(0));
+        let registry = Arc::new(ConnectRegistry::default());
+        let register = |id| {
+            let cancels = cancels.clone();
+            registry.register(id).on_cancel(move || { cancels.fetch_add(1, Ordering::SeqCst); })
+        };
+
+        let f1 = register(id(1));
+        let f2 = register(id(1));
+        drop(f1);
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+        drop(f2);
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+
+        let f = register(id(1));
+        registry.resolve(id(1), Ok(()));
+        drop(f);
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn throttle() {
+        let mut throttle = ConnectThrottle::<u8>::default();
+        assert_eq!(throttle.push(id(1), 1, 2), vec![1]);
+        assert_eq!(throttle.push(id(2), 2, 2), vec![2]);
+        assert_eq!(throttle.push(id(3), 3, 2), Vec::<u8>::new());
+        assert_eq!(throttle.push(id(4), 4, 2), Vec::<u8>::new());
+        assert_eq!(throttle.push(id(1), 1, 2), Vec::<u8>::new());
+        assert_eq!(throttle.push(id(5), 5, 2), Vec::<u8>::new());
+
+        assert_eq!(throttle.complete(id(3)), Vec::<u8>::new());
+        assert_eq!(throttle.complete(id(1)), vec![3]);
+        throttle.cancel(id(4));
+        assert_eq!(throttle.complete(id(2)), vec![5]);
+        assert_eq!(throttle.complete(id(2)), Vec::<u8>::new());
+        assert_eq!(throttle.in_flight.len(), 2);
+        assert_eq!(throttle.len(), 2);
+
+        assert_eq!(throttle.push(id(6), 6, 3), vec![6]);
+        throttle.clear();
+        assert_eq!(throttle.push(id(7), 7, 1), vec![7]);
+    }
+}
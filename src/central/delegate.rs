@@ -3,73 +3,2631 @@ use log::*;
 use objc::*;
 use objc::declare::ClassDecl;
 use objc::runtime::*;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::os::raw::*;
 use std::ptr;
 use std::ptr::NonNull;
+use std::sync::{Arc, Weak};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
 use super::*;
-use crate::central::peripheral::Peripheral;
+use super::command::{self, IssuedWrite, WriteValue};
+use crate::central::peripheral::{CBPeripheral, Peripheral};
+use crate::central::characteristic::{CBCharacteristic, WriteKind};
+#[cfg(feature = "async_std_unstable")]
+use crate::central::characteristic::CharacteristicRef;
+use crate::central::descriptor::CBDescriptor;
+use crate::central::metrics::{Counters, EventOverflow};
+use crate::central::service::CBService;
+use crate::central::tree::{CharacteristicNode, DeviceTree, ServiceNode};
 use crate::error::*;
 use crate::platform::*;
 
+const MANAGER_IVAR: &'static str = "__manager";
 const QUEUE_IVAR: &'static str = "__queue";
-const SENDER_IVAR: &'static str = "__sender";
+const STATE_IVAR: &'static str = "__state";
+const SHARED_IVAR: &'static str = "__shared";
 
 type Sender = crate::sync::Sender<CentralEvent>;
+pub(in super) type UserData = Arc<dyn std::any::Any + Send + Sync>;
+type TreeSender = crate::sync::Sender<Result<DeviceTree, Error>>;
+type PendingTree = (TreeDiscovery<Service, Characteristic, Descriptor>, Vec<TreeSender>);
+#[cfg(feature = "async_std_unstable")]
+type ValueSender = crate::sync::Sender<Result<Vec<u8>, Error>>;
+#[cfg(feature = "async_std_unstable")]
+pub(in super) type ValuePredicate = Box<dyn Fn(&[u8]) -> bool + Send>;
+#[cfg(feature = "async_std_unstable")]
+type ReadSetResults<K> = HashMap<K, Result<Vec<u8>, Error>>;
+#[cfg(feature = "async_std_unstable")]
+type ReadSetSender = crate::sync::Sender<ReadSetResults<CharacteristicRef>>;
+
+/// Delegate state. Only accessed from the delegate queue.
+struct State {
+    sender: Sender,
+
+    shared: Arc<Shared>,
+    validate_write_len: bool,
+    event_overflow: EventOverflow,
+    auto_rediscover_services: bool,
+    max_advertisement_bytes: usize,
+
+    event_filter: RefCell<Option<EventFilter>>,
+
+    on_discovered: RefCell<OnDiscovered<Peripheral>>,
+
+    /// Events held back while delivery is paused.
+    paused: RefCell<Option<PausedEvents<CentralEvent>>>,
+
+    /// Services invalidated by `peripheral:didModifyServices:` keyed by their address.
+    /// Values are the owning peripheral address and the retained service, so the address can't be
+    /// reused while it's tracked.
+    stale_services: HashMap<usize, (usize, StrongPtr<CBService>)>,
+
+    /// Advertising interval tracking for the current scan, if enabled in `ScanOptions`.
+    intervals: Option<IntervalTracker>,
+
+    scan_sessions: ScanSessions,
+
+    /// Link quality warnings, if enabled in `CentralManagerBuilder`.
+    link_quality: Option<LinkQuality>,
+
+    value_sequences: ValueSequences,
+
+    value_dedup: ValueDedup,
+
+    default_write_kinds: DefaultWriteKinds,
+
+    keep_user_data_on_disconnect: bool,
+
+    read_retries: Retries,
+
+    subscribe_retries: Retries,
+
+    read_subscriptions: ReadSubscriptions,
+
+    #[cfg(feature = "async_std_unstable")]
+    value_waiters: ValueWaiters<ValueSender>,
+
+    #[cfg(feature = "async_std_unstable")]
+    read_sets: ReadSets<CharacteristicRef, ReadSetSender>,
+
+    #[cfg(feature = "async_std_unstable")]
+    notification_streams: NotificationStreams<ValueSender>,
+
+    descriptor_reads: DescriptorReads,
+
+    cccd_reads: CccdReads,
+
+    characteristic_searches: CharacteristicSearches<Characteristic>,
+
+    /// Characteristics to subscribe to once discovered, keyed by service address. Values are the
+    /// owning peripheral address and the characteristic UUIDs.
+    pending_subscriptions: HashMap<usize, (usize, Vec<Uuid>)>,
+
+    rediscoveries: Rediscoveries,
+
+    auto_rediscoveries: AutoRediscoveries,
+
+    service_discoveries: ServiceDiscoveries,
+
+    write_sequences: WriteSequences<(StrongPtr<CBCharacteristic>, WriteValue)>,
+
+    pending_writes: PendingWrites,
+
+    polls: Polls,
+
+    system_connected: SystemConnections<StrongPtr<CBPeripheral>>,
+
+    /// Filters to apply to the next discovered services, keyed by peripheral address.
+
+    /// Keyed by peripheral address. Values are the discovery and the senders of its result.
+    tree_discoveries: HashMap<usize, PendingTree>,
+}
+
+impl State {
+    fn new(sender: Sender, config: &mut CentralManagerBuilder, shared: Arc<Shared>) -> Self {
+        Self {
+            sender,
+            shared,
+            validate_write_len: config.validate_write_len,
+            event_overflow: config.event_overflow,
+            auto_rediscover_services: config.auto_rediscover_services,
+            max_advertisement_bytes: config.max_advertisement_bytes,
+            event_filter: RefCell::new(config.event_filter.take()),
+            on_discovered: Default::default(),
+            paused: RefCell::new(None),
+            stale_services: HashMap::new(),
+            intervals: None,
+            scan_sessions: ScanSessions::default(),
+            link_quality: config.link_quality_warning
+                .map(|(threshold, window)| LinkQuality::new(threshold, window)),
+            value_sequences: ValueSequences::default(),
+            value_dedup: ValueDedup::default(),
+            default_write_kinds: DefaultWriteKinds::default(),
+            keep_user_data_on_disconnect: config.keep_user_data_on_disconnect,
+            read_retries: Retries::default(),
+            subscribe_retries: Retries::default(),
+            read_subscriptions: ReadSubscriptions::default(),
+            #[cfg(feature = "async_std_unstable")]
+            value_waiters: ValueWaiters::default(),
+            #[cfg(feature = "async_std_unstable")]
+            read_sets: ReadSets::default(),
+            #[cfg(feature = "async_std_unstable")]
+            notification_streams: NotificationStreams::default(),
+            descriptor_reads: DescriptorReads::default(),
+            cccd_reads: CccdReads::default(),
+            characteristic_searches: CharacteristicSearches::default(),
+            pending_subscriptions: HashMap::new(),
+            rediscoveries: Rediscoveries::default(),
+            auto_rediscoveries: AutoRediscoveries::default(),
+            service_discoveries: ServiceDiscoveries::default(),
+            write_sequences: WriteSequences::default(),
+            pending_writes: PendingWrites::default(),
+            polls: Polls::default(),
+            system_connected: SystemConnections::default(),
+            tree_discoveries: HashMap::new(),
+        }
+    }
+
+    fn send(&self, event: CentralEvent) {
+        {
+            let mut history = self.shared.history.lock().unwrap();
+            if history.is_enabled() {
+                history.push(event.summary());
+            }
+        }
+        if let Some(filter) = self.event_filter.borrow_mut().as_mut() {
+            if !filter(&event) {
+                return;
+            }
+        }
+        if let Some(paused) = self.paused.borrow_mut().as_mut() {
+            paused.send(&self.shared.metrics, &self.sender, self.event_overflow, event);
+            return;
+        }
+        self.shared.metrics.send(&self.sender, self.event_overflow, event);
+    }
+
+    /// Logs what's still tracked when the delegate is dropped. Does nothing unless trace logging
+    /// is enabled.
+    fn trace_teardown(&self) {
+        if !log_enabled!(Level::Trace) {
+            return;
+        }
+        let summary = self.teardown_summary(&self.shared.connected.lock().unwrap());
+        trace!("tearing down delegate state: {}", summary);
+    }
+
+    /// Counts what's still tracked, with the peripherals `connected` by the manager.
+    fn teardown_summary<P>(&self, connected: &Connections<P>) -> TeardownSummary {
+        #[cfg(feature = "async_std_unstable")]
+        let read_sets = self.read_sets.sets.len();
+        #[cfg(not(feature = "async_std_unstable"))]
+        let read_sets = 0;
+        TeardownSummary {
+            connected: connected.iter().count(),
+            throttled: self.shared.throttle.lock().unwrap().len(),
+            polls: self.polls.polls.len(),
+            read_retries: self.read_retries.retries.len(),
+            read_sets,
+            tree_discoveries: self.tree_discoveries.len(),
+        }
+    }
+
+    /// Numbers the `value` update of the `characteristic` received on the queue at `received`.
+    /// Returns `None` if the value duplicates the previous one and must be dropped.
+    fn value_received(&mut self, peripheral: usize, characteristic: usize,
+        value: &Result<Vec<u8>, Error>, received: Instant) -> Option<ValueStamp>
+    {
+        let duplicate = match value {
+            Ok(value) => self.value_dedup.is_duplicate(characteristic, value),
+            Err(_) => false,
+        };
+        if duplicate {
+            None
+        } else {
+            Some(ValueStamp {
+                sequence: self.value_sequences.next(peripheral, characteristic),
+                queue_timestamp: received,
+            })
+        }
+    }
+
+    /// Returns the snapshot of the state. `is_scanning` is reported as is.
+    fn diagnostics(&self, is_scanning: bool) -> Diagnostics {
+        let mut connected: Vec<_> = self.shared.connected.lock().unwrap().iter()
+            .map(|p| (p.id(), p.peripheral.state()))
+            .collect();
+        connected.sort_by_key(|(id, _)| *id);
+        Diagnostics {
+            manager_state: ManagerState::from_u8(self.shared.manager_state.load(Ordering::SeqCst))
+                .unwrap_or(ManagerState::Unknown),
+            is_scanning,
+            connected,
+            polls: self.polls.polls.len(),
+            read_retries: self.read_retries.retries.len(),
+            metrics: self.shared.metrics.snapshot(),
+        }
+    }
+}
+
+/// Sequence number and receive time of a `CharacteristicValue` event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ValueStamp {
+    sequence: u64,
+    queue_timestamp: Instant,
+}
+
+/// What's still tracked when the delegate is dropped.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct TeardownSummary {
+    connected: usize,
+    throttled: usize,
+    polls: usize,
+    read_retries: usize,
+    read_sets: usize,
+    tree_discoveries: usize,
+}
+
+impl fmt::Display for TeardownSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} connected peripherals, {} throttled connects, {} polls, {} read retries, \
+            {} read sets, {} tree discoveries",
+            self.connected, self.throttled, self.polls, self.read_retries, self.read_sets,
+            self.tree_discoveries)
+    }
+}
+
+const PAUSED_EVENTS_CAPACITY: usize = 1024;
+
+/// Buffer of events sent while delivery is paused.
+struct PausedEvents<T> {
+    events: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> PausedEvents<T> {
+    fn new(overflow: EventOverflow) -> Self {
+        let capacity = match overflow {
+            EventOverflow::Block | EventOverflow::BlockWithTimeout { .. } => PAUSED_EVENTS_CAPACITY,
+            EventOverflow::DropNewest { capacity } => capacity,
+        };
+        Self {
+            events: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Buffers `item`. If the buffer is full, drops the `item` or, with `Block` and
+    /// `BlockWithTimeout` policies, sends the buffered items and the `item` to the channel.
+    fn send(&mut self, counters: &Counters, sender: &crate::sync::Sender<T>, overflow: EventOverflow,
+        item: T)
+    {
+        if self.events.len() < self.capacity {
+            self.events.push_back(item);
+        } else {
+            match overflow {
+                EventOverflow::Block | EventOverflow::BlockWithTimeout { .. } => {
+                    self.flush(counters, sender, overflow);
+                    counters.send(sender, overflow, item);
+                }
+                EventOverflow::DropNewest { .. } => counters.record_dropped(),
+            }
+        }
+    }
+
+    /// Sends all buffered items to the channel.
+    fn flush(&mut self, counters: &Counters, sender: &crate::sync::Sender<T>, overflow: EventOverflow) {
+        for item in self.events.drain(..) {
+            counters.send(sender, overflow, item);
+        }
+    }
+}
+
+/// Estimates advertising intervals from timestamps of successive discoveries of the same
+/// peripheral.
+#[derive(Default)]
+struct IntervalTracker {
+    last_seen: HashMap<usize, Instant>,
+}
+
+impl IntervalTracker {
+    /// Records discovery of peripheral `id` at `now` and returns the time elapsed since its
+    /// previous discovery.
+    fn observe(&mut self, id: usize, now: Instant) -> Option<Duration> {
+        self.last_seen.insert(id, now)
+            .map(|prev| now.saturating_duration_since(prev))
+    }
+}
+
+/// Detects peripherals whose RSSI stays below the threshold for the window, see
+/// `CentralManagerBuilder::link_quality_warning`.
+struct LinkQuality {
+    threshold: i32,
+    window: Duration,
+
+    /// Keyed by peripheral address. Values are the time of the first reading below the threshold,
+    /// the time of the last reading and whether the warning was issued since.
+    weak_since: HashMap<usize, (Instant, Instant, bool)>,
+}
+
+impl LinkQuality {
+    fn new(threshold: i32, window: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            weak_since: HashMap::new(),
+        }
+    }
+
+    /// Records the `rssi` of the `peripheral` read at `now`. Returns `true` if the warning should
+    /// be issued. The warning is issued once until a reading at or above the threshold.
+    /// Non-negative readings mean the RSSI is unavailable and only show the peripheral is still
+    /// around.
+    ///
+    /// Peripherals without readings for longer than the window are considered lost and
+    /// forgotten, so scanning many peripherals doesn't accumulate them.
+    fn observe(&mut self, peripheral: usize, rssi: i32, now: Instant) -> bool {
+        let window = self.window;
+        self.weak_since.retain(|_, (_, last, _)| now.saturating_duration_since(*last) <= window);
+        if rssi >= 0 {
+            if let Some((_, last, _)) = self.weak_since.get_mut(&peripheral) {
+                *last = now;
+            }
+            return false;
+        }
+        if rssi >= self.threshold {
+            self.weak_since.remove(&peripheral);
+            return false;
+        }
+        let (since, last, warned) = self.weak_since.entry(peripheral).or_insert((now, now, false));
+        *last = now;
+        if !*warned && now.saturating_duration_since(*since) >= self.window {
+            *warned = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.weak_since.remove(&peripheral);
+    }
+}
+
+/// Per-characteristic counters of value updates.
+#[derive(Default)]
+struct ValueSequences {
+    /// Keyed by characteristic address. Values are the owning peripheral address and the number of
+    /// updates received.
+    counters: HashMap<usize, (usize, u64)>,
+}
+
+impl ValueSequences {
+    /// Counts a value update of the `characteristic` and returns its sequence number.
+    fn next(&mut self, peripheral: usize, characteristic: usize) -> u64 {
+        let counter = &mut self.counters.entry(characteristic).or_insert((peripheral, 0)).1;
+        *counter += 1;
+        *counter
+    }
+
+    /// Resets counters of all characteristics of the `peripheral`.
+    fn reset(&mut self, peripheral: usize) {
+        self.counters.retain(|_, (p, _)| *p != peripheral);
+    }
+}
+
+/// Write kinds used by `Peripheral::write` when no kind is specified.
+#[derive(Default)]
+struct DefaultWriteKinds {
+    /// Keyed by peripheral address.
+    kinds: HashMap<usize, WriteKind>,
+}
+
+impl DefaultWriteKinds {
+    fn set(&mut self, peripheral: usize, kind: WriteKind) {
+        self.kinds.insert(peripheral, kind);
+    }
+
+    /// Returns the write kind set for the `peripheral`, or `WithResponse` if none is set.
+    fn get(&self, peripheral: usize) -> WriteKind {
+        self.kinds.get(&peripheral).copied().unwrap_or(WriteKind::WithResponse)
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.kinds.remove(&peripheral);
+    }
+}
+
+/// App data attached to peripherals with `Peripheral::set_user_data`.
+#[derive(Default)]
+pub(in super) struct UserDataMap {
+    data: HashMap<PeripheralId, UserData>,
+}
+
+impl UserDataMap {
+    pub(in super) fn set(&mut self, peripheral: PeripheralId, data: Option<UserData>) {
+        if let Some(data) = data {
+            self.data.insert(peripheral, data);
+        } else {
+            self.data.remove(&peripheral);
+        }
+    }
+
+    pub(in super) fn get(&self, peripheral: PeripheralId) -> Option<UserData> {
+        self.data.get(&peripheral).cloned()
+    }
+
+    fn reset(&mut self, peripheral: PeripheralId) {
+        self.data.remove(&peripheral);
+    }
+}
+
+/// Last values of characteristics with deduplication of identical consecutive values enabled.
+#[derive(Default)]
+struct ValueDedup {
+    /// Keyed by characteristic address. Values are the owning peripheral address and the last
+    /// value received.
+    values: HashMap<usize, (usize, Option<Vec<u8>>)>,
+}
+
+impl ValueDedup {
+    fn set_enabled(&mut self, peripheral: usize, characteristic: usize, enabled: bool) {
+        if enabled {
+            self.values.entry(characteristic).or_insert((peripheral, None));
+        } else {
+            self.values.remove(&characteristic);
+        }
+    }
+
+    /// Returns `true` if deduplication is enabled for the `characteristic` and the `value` is
+    /// identical to the previous one. Otherwise remembers the `value` and returns `false`.
+    fn is_duplicate(&mut self, characteristic: usize, value: &[u8]) -> bool {
+        match self.values.get_mut(&characteristic) {
+            Some((_, last)) if last.as_deref() == Some(value) => true,
+            Some((_, last)) => {
+                *last = Some(value.into());
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Disables deduplication for all characteristics of the `peripheral`.
+    fn reset(&mut self, peripheral: usize) {
+        self.values.retain(|_, (p, _)| *p != peripheral);
+    }
+}
+
+/// Retries left for characteristic reads issued with `Peripheral::read_characteristic_with_retry`
+/// or subscriptions requested with `Peripheral::subscribe_with_retry`.
+#[derive(Default)]
+struct Retries {
+    /// Keyed by characteristic address. Values are the owning peripheral address and the number
+    /// of retries left.
+    retries: HashMap<usize, (usize, u32)>,
+}
+
+impl Retries {
+    fn start(&mut self, peripheral: usize, characteristic: usize, max_retries: u32) {
+        self.retries.insert(characteristic, (peripheral, max_retries));
+    }
+
+    /// Handles the result of a read or subscription of the `characteristic`. Returns `true` if it
+    /// should be retried.
+    fn complete(&mut self, characteristic: usize, transient_error: bool) -> bool {
+        match self.retries.get_mut(&characteristic) {
+            Some((_, left)) if transient_error && *left > 0 => {
+                *left -= 1;
+                true
+            }
+            Some(_) => {
+                self.retries.remove(&characteristic);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn is_pending(&self, characteristic: usize) -> bool {
+        self.retries.contains_key(&characteristic)
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.retries.retain(|_, (p, _)| *p != peripheral);
+    }
+
+    fn clear(&mut self) {
+        self.retries.clear();
+    }
+}
+
+/// Characteristics to subscribe to once their value is read, as requested with
+/// `Peripheral::read_then_subscribe`.
+#[derive(Default)]
+struct ReadSubscriptions {
+    /// Keyed by characteristic address. Values are the owning peripheral address.
+    pending: HashMap<usize, usize>,
+}
+
+impl ReadSubscriptions {
+    fn start(&mut self, peripheral: usize, characteristic: usize) {
+        self.pending.insert(characteristic, peripheral);
+    }
+
+    /// Handles the result of a read of the `characteristic`. Returns `true` if the characteristic
+    /// should be subscribed to now.
+    fn complete(&mut self, characteristic: usize) -> bool {
+        self.pending.remove(&characteristic).is_some()
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.pending.retain(|_, p| *p != peripheral);
+    }
+}
+
+/// Waiters for a characteristic value matching a predicate, as requested with
+/// `Peripheral::next_value_where`.
+#[cfg(feature = "async_std_unstable")]
+struct ValueWaiters<T> {
+    /// Keyed by characteristic address. Values are the owning peripheral address, the predicate
+    /// and the waiter.
+    waiters: HashMap<usize, Vec<(usize, ValuePredicate, T)>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl<T> Default for ValueWaiters<T> {
+    fn default() -> Self {
+        Self {
+            waiters: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl<T> ValueWaiters<T> {
+    fn add(&mut self, peripheral: usize, characteristic: usize, predicate: ValuePredicate, waiter: T) {
+        self.waiters.entry(characteristic).or_default().push((peripheral, predicate, waiter));
+    }
+
+    /// Removes and returns waiters of the `characteristic` whose predicate matches the `value`.
+    fn matched(&mut self, characteristic: usize, value: &[u8]) -> Vec<T> {
+        let waiters = match self.waiters.get_mut(&characteristic) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        let mut r = Vec::new();
+        let mut i = 0;
+        while i < waiters.len() {
+            if (waiters[i].1)(value) {
+                r.push(waiters.remove(i).2);
+            } else {
+                i += 1;
+            }
+        }
+        if waiters.is_empty() {
+            self.waiters.remove(&characteristic);
+        }
+        r
+    }
+
+    /// Removes and returns waiters of the `peripheral`.
+    fn reset(&mut self, peripheral: usize) -> Vec<T> {
+        let mut r = Vec::new();
+        for waiters in self.waiters.values_mut() {
+            let mut i = 0;
+            while i < waiters.len() {
+                if waiters[i].0 == peripheral {
+                    r.push(waiters.remove(i).2);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.waiters.retain(|_, v| !v.is_empty());
+        r
+    }
+
+    fn clear(&mut self) -> Vec<T> {
+        self.waiters.drain().flat_map(|(_, v)| v).map(|(_, _, w)| w).collect()
+    }
+}
+
+/// Scan sessions started with `CentralManager::begin_scan_session`, in the order of starting.
+#[derive(Default)]
+struct ScanSessions {
+    sessions: Vec<(u64, ScanOptions)>,
+}
+
+impl ScanSessions {
+    fn begin(&mut self, id: u64, options: ScanOptions) {
+        self.sessions.push((id, options));
+    }
+
+    /// Returns `true` if the session was active.
+    fn end(&mut self, id: u64) -> bool {
+        let len = self.sessions.len();
+        self.sessions.retain(|(i, _)| *i != id);
+        self.sessions.len() < len
+    }
+
+    /// Returns options of the scan covering all active sessions, or `None` if there are none.
+    fn merged(&self) -> Option<ScanOptions> {
+        ScanOptions::merge(self.sessions.iter().map(|(_, o)| o))
+    }
+
+    /// Returns options of the scan to issue when the manager transitions to the `new_state`.
+    /// Scans are only issued in `PoweredOn` state, so the sessions begun before that or alive
+    /// across a power cycle are applied on entering it.
+    fn state_changed(&self, new_state: ManagerState) -> Option<ScanOptions> {
+        if new_state == ManagerState::PoweredOn {
+            self.merged()
+        } else {
+            None
+        }
+    }
+}
+
+/// Streams of characteristic values requested with `Peripheral::notifications`.
+#[cfg(feature = "async_std_unstable")]
+struct NotificationStreams<T> {
+    /// Keyed by characteristic address.
+    streams: HashMap<usize, CharacteristicStreams<T>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+struct CharacteristicStreams<T> {
+    /// The owning peripheral address, the stream id and the sender.
+    streams: Vec<(usize, u64, T)>,
+
+    /// Whether the streams subscribed to the characteristic, as opposed to relying on
+    /// a subscription made by the app.
+    subscribed: bool,
+
+    /// Whether the result of the subscription made by the streams is awaited.
+    subscribing: bool,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl<T> Default for NotificationStreams<T> {
+    fn default() -> Self {
+        Self {
+            streams: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl<T> NotificationStreams<T> {
+    /// Adds the stream `id` of the `characteristic`. Returns `true` if the streams should
+    /// subscribe to the characteristic, which is when it's not `notifying` and the streams haven't
+    /// subscribed yet.
+    fn add(&mut self, peripheral: usize, characteristic: usize, id: u64, sender: T,
+        notifying: bool) -> bool
+    {
+        let streams = self.streams.entry(characteristic).or_insert_with(|| CharacteristicStreams {
+            streams: Vec::new(),
+            subscribed: false,
+            subscribing: false,
+        });
+        streams.streams.push((peripheral, id, sender));
+        let subscribe = !notifying && !streams.subscribed;
+        if subscribe {
+            streams.subscribed = true;
+            streams.subscribing = true;
+        }
+        subscribe
+    }
+
+    fn senders<'a>(&'a self, characteristic: usize) -> impl Iterator<Item=&'a T> + 'a {
+        self.streams.get(&characteristic).into_iter()
+            .flat_map(|v| &v.streams)
+            .map(|(_, _, s)| s)
+    }
+
+    /// Completes the subscription to the `characteristic` made by the streams, if any. If it
+    /// failed, removes and returns senders of the streams of the characteristic.
+    fn subscribe_completed(&mut self, characteristic: usize, ok: bool) -> Vec<T> {
+        let streams = match self.streams.get_mut(&characteristic) {
+            Some(v) if v.subscribing => v,
+            _ => return Vec::new(),
+        };
+        streams.subscribing = false;
+        if ok {
+            return Vec::new();
+        }
+        self.streams.remove(&characteristic).into_iter()
+            .flat_map(|v| v.streams)
+            .map(|(_, _, s)| s)
+            .collect()
+    }
+
+    /// Removes the stream `id` of the `characteristic`. Returns `true` if it was the last stream
+    /// of the characteristic and the streams subscribed to it, so they should unsubscribe.
+    fn remove(&mut self, characteristic: usize, id: u64) -> bool {
+        let streams = match self.streams.get_mut(&characteristic) {
+            Some(v) => v,
+            None => return false,
+        };
+        let len = streams.streams.len();
+        streams.streams.retain(|(_, i, _)| *i != id);
+        if streams.streams.len() < len && streams.streams.is_empty() {
+            self.streams.remove(&characteristic).map(|v| v.subscribed).unwrap_or(false)
+        } else {
+            false
+        }
+    }
+
+    /// Removes and returns senders of streams of the `peripheral`.
+    fn reset(&mut self, peripheral: usize) -> Vec<T> {
+        let mut r = Vec::new();
+        for streams in self.streams.values_mut() {
+            let streams = &mut streams.streams;
+            let mut i = 0;
+            while i < streams.len() {
+                if streams[i].0 == peripheral {
+                    r.push(streams.remove(i).2);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+        self.streams.retain(|_, v| !v.streams.is_empty());
+        r
+    }
+
+    fn clear(&mut self) -> Vec<T> {
+        self.streams.drain().flat_map(|(_, v)| v.streams).map(|(_, _, s)| s).collect()
+    }
+}
+
+/// Sets of characteristic reads requested with `Peripheral::read_set` that complete together.
+#[cfg(feature = "async_std_unstable")]
+struct ReadSets<K, T> {
+    next_id: u64,
+
+    /// Keyed by set id.
+    sets: HashMap<u64, ReadSet<K, T>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+struct ReadSet<K, T> {
+    peripheral: usize,
+
+    /// Reads not completed yet keyed by characteristic address.
+    pending: HashMap<usize, K>,
+
+    results: ReadSetResults<K>,
+    waiter: T,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl<K, T> Default for ReadSets<K, T> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            sets: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl<K: Eq + Hash, T> ReadSets<K, T> {
+    /// Starts a set of `reads` of characteristics identified by their address. Returns id of
+    /// the set.
+    fn start(&mut self, peripheral: usize, reads: Vec<(usize, K)>, waiter: T) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sets.insert(id, ReadSet {
+            peripheral,
+            pending: reads.into_iter().collect(),
+            results: HashMap::new(),
+            waiter,
+        });
+        id
+    }
+
+    /// Records the result of a read of the `characteristic`. Returns the sets completed by it.
+    fn complete(&mut self, characteristic: usize, result: &Result<Vec<u8>, Error>)
+        -> Vec<(T, ReadSetResults<K>)>
+    {
+        let mut done = Vec::new();
+        for (&id, set) in &mut self.sets {
+            if let Some(key) = set.pending.remove(&characteristic) {
+                set.results.insert(key, result.clone());
+                if set.pending.is_empty() {
+                    done.push(id);
+                }
+            }
+        }
+        done.into_iter()
+            .filter_map(|id| self.sets.remove(&id))
+            .map(|set| (set.waiter, set.results))
+            .collect()
+    }
+
+    /// Completes the set `id` with the `error` as the result of the reads not completed yet.
+    /// Returns `None` if the set has already completed.
+    fn expire(&mut self, id: u64, error: &Error) -> Option<(T, ReadSetResults<K>)> {
+        let mut set = self.sets.remove(&id)?;
+        for (_, key) in set.pending.drain() {
+            set.results.insert(key, Err(error.clone()));
+        }
+        Some((set.waiter, set.results))
+    }
+
+    /// Completes sets of the `peripheral`, or of all peripherals if `None`, with the `error` as
+    /// the result of the reads not completed yet.
+    fn expire_all(&mut self, peripheral: Option<usize>, error: &Error) -> Vec<(T, ReadSetResults<K>)> {
+        let ids: Vec<_> = self.sets.iter()
+            .filter(|(_, s)| peripheral.map(|p| p == s.peripheral).unwrap_or(true))
+            .map(|(&id, _)| id)
+            .collect();
+        ids.into_iter().filter_map(|id| self.expire(id, error)).collect()
+    }
+}
+
+/// Tracks progress of bulk reads of characteristic descriptors.
+#[derive(Default)]
+struct DescriptorReads {
+    /// Keyed by characteristic address. Values are the owning peripheral address and addresses of
+    /// descriptors with pending reads, or `None` while the descriptors are being discovered.
+    reads: HashMap<usize, (usize, Option<HashSet<usize>>)>,
+}
+
+impl DescriptorReads {
+    /// Starts tracking bulk read of the `characteristic` descriptors, awaiting their discovery.
+    /// Any bulk read of the same characteristic in progress is restarted.
+    fn start(&mut self, peripheral: usize, characteristic: usize) {
+        self.reads.insert(characteristic, (peripheral, None));
+    }
+
+    /// Returns `true` if the bulk read of the `characteristic` awaits descriptors discovery.
+    fn is_discovering(&self, characteristic: usize) -> bool {
+        matches!(self.reads.get(&characteristic), Some((_, None)))
+    }
+
+    /// Records the discovered `descriptors` as pending reads. Returns `true` if there's nothing to
+    /// read and thus the bulk read is complete.
+    fn discovered(&mut self, characteristic: usize, descriptors: impl IntoIterator<Item=usize>) -> bool {
+        let pending: HashSet<_> = descriptors.into_iter().collect();
+        if pending.is_empty() {
+            self.reads.remove(&characteristic);
+            true
+        } else {
+            if let Some(read) = self.reads.get_mut(&characteristic) {
+                read.1 = Some(pending);
+            }
+            false
+        }
+    }
+
+    /// Stops tracking the bulk read of the `characteristic`.
+    fn cancel(&mut self, characteristic: usize) {
+        self.reads.remove(&characteristic);
+    }
+
+    /// Records completed read of the `descriptor`. Returns `true` if it was the last pending read
+    /// of the bulk read.
+    fn read(&mut self, characteristic: usize, descriptor: usize) -> bool {
+        let done = match self.reads.get_mut(&characteristic) {
+            Some((_, Some(pending))) => pending.remove(&descriptor) && pending.is_empty(),
+            _ => false,
+        };
+        if done {
+            self.reads.remove(&characteristic);
+        }
+        done
+    }
+
+    /// Stops tracking all bulk reads of the `peripheral`.
+    fn reset(&mut self, peripheral: usize) {
+        self.reads.retain(|_, (p, _)| *p != peripheral);
+    }
+}
+
+/// Reads of Client Characteristic Configuration descriptors issued by `Peripheral::read_cccd`.
+#[derive(Default)]
+struct CccdReads {
+    /// Keyed by characteristic address. Values are the owning peripheral address and whether the
+    /// descriptor read was issued, as opposed to awaiting the descriptors discovery.
+    reads: HashMap<usize, (usize, bool)>,
+}
+
+impl CccdReads {
+    /// Starts tracking the read of the `characteristic` descriptor, awaiting the descriptors
+    /// discovery.
+    fn start(&mut self, peripheral: usize, characteristic: usize) {
+        self.reads.insert(characteristic, (peripheral, false));
+    }
+
+    /// Returns `true` if the read of the `characteristic` descriptor awaits descriptors discovery.
+    fn is_discovering(&self, characteristic: usize) -> bool {
+        match self.reads.get(&characteristic) {
+            Some((_, reading)) => !reading,
+            None => false,
+        }
+    }
+
+    /// Records that the descriptor read of the `characteristic` was issued.
+    fn reading(&mut self, characteristic: usize) {
+        if let Some((_, reading)) = self.reads.get_mut(&characteristic) {
+            *reading = true;
+        }
+    }
+
+    /// Stops tracking the read of the `characteristic` descriptor. Returns `true` if the read was
+    /// issued.
+    fn complete(&mut self, characteristic: usize) -> bool {
+        match self.reads.get(&characteristic) {
+            Some(&(_, true)) => {
+                self.reads.remove(&characteristic);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn cancel(&mut self, characteristic: usize) {
+        self.reads.remove(&characteristic);
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.reads.retain(|_, (p, _)| *p != peripheral);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum RediscoveryStep {
+    Disconnect,
+    Connect,
+    DiscoverServices,
+}
+
+/// Tracks progress of forced rediscoveries of peripherals.
+#[derive(Default)]
+struct Rediscoveries {
+    /// Keyed by peripheral address. Values are the steps in progress.
+    steps: HashMap<usize, RediscoveryStep>,
+}
+
+impl Rediscoveries {
+    /// Starts rediscovery of the `peripheral` and returns the first step to perform.
+    /// Any rediscovery of the same peripheral in progress is restarted.
+    fn start(&mut self, peripheral: usize, disconnected: bool) -> RediscoveryStep {
+        let step = if disconnected {
+            RediscoveryStep::Connect
+        } else {
+            RediscoveryStep::Disconnect
+        };
+        self.steps.insert(peripheral, step);
+        step
+    }
+
+    fn is_at(&self, peripheral: usize, step: RediscoveryStep) -> bool {
+        self.steps.get(&peripheral) == Some(&step)
+    }
+
+    /// Moves rediscovery of the `peripheral` past the `completed` step. Returns `false` if
+    /// the peripheral isn't being rediscovered or is at a different step.
+    fn advance(&mut self, peripheral: usize, completed: RediscoveryStep) -> bool {
+        if !self.is_at(peripheral, completed) {
+            return false;
+        }
+        match completed {
+            RediscoveryStep::Disconnect => {
+                self.steps.insert(peripheral, RediscoveryStep::Connect);
+            }
+            RediscoveryStep::Connect => {
+                self.steps.insert(peripheral, RediscoveryStep::DiscoverServices);
+            }
+            RediscoveryStep::DiscoverServices => {
+                self.steps.remove(&peripheral);
+            }
+        }
+        true
+    }
+
+    fn cancel(&mut self, peripheral: usize) {
+        self.steps.remove(&peripheral);
+    }
+}
+
+/// Tracks automatic rediscoveries of services invalidated by `peripheral:didModifyServices:`.
+#[derive(Default)]
+struct AutoRediscoveries {
+    /// Keyed by peripheral address. Values are UUIDs of the services being rediscovered and
+    /// the number of services discoveries started for them.
+    pending: HashMap<usize, (Vec<Uuid>, usize)>,
+}
+
+impl AutoRediscoveries {
+    /// Starts rediscovery of the `services` of the `peripheral`. Returns UUIDs of the services
+    /// to discover, including those of a rediscovery in progress, or `None` if there's nothing to
+    /// discover.
+    fn start(&mut self, peripheral: usize, services: impl IntoIterator<Item=Uuid>) -> Option<Vec<Uuid>> {
+        let (pending, started) = self.pending.entry(peripheral).or_default();
+        for uuid in services {
+            if !pending.contains(&uuid) {
+                pending.push(uuid);
+            }
+        }
+        if pending.is_empty() {
+            self.pending.remove(&peripheral);
+            None
+        } else {
+            *started += 1;
+            Some(pending.clone())
+        }
+    }
+
+    /// Records completion of a services discovery started for the rediscovery of
+    /// the `peripheral`. Returns `true` if it was the last one, i.e. the rediscovery is complete.
+    fn complete(&mut self, peripheral: usize) -> bool {
+        let started = match self.pending.get_mut(&peripheral) {
+            Some((_, started)) => started,
+            None => return false,
+        };
+        *started -= 1;
+        if *started == 0 {
+            self.pending.remove(&peripheral);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.pending.remove(&peripheral);
+    }
+}
+
+/// Origin of a services discovery.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum DiscoveryOrigin {
+    /// Rediscovery of services invalidated by `peripheral:didModifyServices:`.
+    AutoRediscovery,
+
+    /// Discovery of a search started by `Peripheral::find_characteristics`.
+    Search,
+
+    /// Discovery whose result is filtered by kind of services.
+    Filtered(ServiceFilter),
+
+    /// Any other discovery.
+    Other,
+}
+
+/// Services discoveries awaiting result. Core Bluetooth reports results of services discoveries
+/// of a peripheral in the order they were started, so a result belongs to the oldest discovery.
+#[derive(Default)]
+struct ServiceDiscoveries {
+    /// Keyed by peripheral address.
+    pending: HashMap<usize, VecDeque<DiscoveryOrigin>>,
+}
+
+impl ServiceDiscoveries {
+    fn started(&mut self, peripheral: usize, origin: DiscoveryOrigin) {
+        self.pending.entry(peripheral).or_default().push_back(origin);
+    }
+
+    /// Returns origin of the discovery of the `peripheral` the received result belongs to.
+    /// Results of untracked discoveries are reported as `Other`.
+    fn completed(&mut self, peripheral: usize) -> DiscoveryOrigin {
+        let pending = match self.pending.get_mut(&peripheral) {
+            Some(v) => v,
+            None => return DiscoveryOrigin::Other,
+        };
+        let r = pending.pop_front().unwrap_or(DiscoveryOrigin::Other);
+        if pending.is_empty() {
+            self.pending.remove(&peripheral);
+        }
+        r
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.pending.remove(&peripheral);
+    }
+}
+
+/// Peripherals connected by the central manager.
+pub(in super) struct Connections<P> {
+    /// Keyed by peripheral address.
+    peripherals: HashMap<usize, P>,
+}
+
+impl<P> Default for Connections<P> {
+    fn default() -> Self {
+        Self {
+            peripherals: HashMap::new(),
+        }
+    }
+}
+
+impl<P> Connections<P> {
+    fn connected(&mut self, id: usize, peripheral: P) {
+        self.peripherals.insert(id, peripheral);
+    }
+
+    fn disconnected(&mut self, id: usize) {
+        self.peripherals.remove(&id);
+    }
+
+    /// Forgets all peripherals, for example when the manager leaves `PoweredOn` state and
+    /// the connections are lost.
+    fn clear(&mut self) {
+        self.peripherals.clear();
+    }
+
+    pub(in super) fn iter(&self) -> impl Iterator<Item=&P> {
+        self.peripherals.values()
+    }
+}
+
+/// Function registered with `CentralManager::on_discovered`.
+struct OnDiscovered<P> {
+    f: Option<DiscoveredCallback<P>>,
+}
+
+impl<P> Default for OnDiscovered<P> {
+    fn default() -> Self {
+        Self {
+            f: None,
+        }
+    }
+}
+
+impl<P: Clone> OnDiscovered<P> {
+    /// Replaces the registered function with `f`.
+    fn set(&mut self, f: DiscoveredCallback<P>) {
+        self.f = Some(f);
+    }
+
+    /// Calls the registered function, if any, for the discovered `peripheral`.
+    fn discovered(&mut self, peripheral: &P, advertisement_data: &AdvertisementData, rssi: i32) {
+        if let Some(f) = self.f.as_mut() {
+            f(peripheral.clone(), advertisement_data.clone(), rssi);
+        }
+    }
+}
+
+/// Ring buffer of event summaries.
+#[derive(Default)]
+pub(in super) struct EventHistory {
+    events: VecDeque<String>,
+    capacity: usize,
+}
+
+impl EventHistory {
+    pub(in super) fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Adds the `event`, dropping the oldest one if the history is full.
+    fn push(&mut self, event: String) {
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    pub(in super) fn to_vec(&self) -> Vec<String> {
+        self.events.iter().cloned().collect()
+    }
+}
+
+/// Kind of services reported in `ServicesDiscovered` event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(in crate) enum ServiceFilter {
+    Primary,
+    Secondary,
+}
+
+impl ServiceFilter {
+    fn matches(self, primary: bool) -> bool {
+        primary == (self == Self::Primary)
+    }
+}
+
+/// Connection latency below which a connection to a peripheral already connected to the system
+/// is assumed to have been made without a radio round trip.
+const ALREADY_CONNECTED_MAX_LATENCY: Duration = Duration::from_millis(20);
+
+/// Infers whether peripherals were already connected to the system when connected locally.
+struct SystemConnections<P> {
+    /// Peripherals returned by `retrieveConnectedPeripheralsWithServices:` keyed by their address.
+    retrieved: HashMap<usize, P>,
+
+    /// Start times of connection attempts keyed by peripheral address.
+    connects: HashMap<usize, Instant>,
+}
+
+impl<P> Default for SystemConnections<P> {
+    fn default() -> Self {
+        Self {
+            retrieved: HashMap::new(),
+            connects: HashMap::new(),
+        }
+    }
+}
+
+impl<P> SystemConnections<P> {
+    /// Records the `peripherals` retrieved as connected to the system. Peripherals of
+    /// the previous retrievals are forgotten unless there's a connection attempt to them.
+    fn retrieved(&mut self, peripherals: impl IntoIterator<Item=(usize, P)>) {
+        let connects = &self.connects;
+        self.retrieved.retain(|id, _| connects.contains_key(id));
+        self.retrieved.extend(peripherals);
+    }
+
+    fn connect_started(&mut self, id: usize, now: Instant) {
+        self.connects.entry(id).or_insert(now);
+    }
+
+    /// Completes the connection attempt to the peripheral `id`. Returns the time elapsed since
+    /// the attempt started (zero if it wasn't started with `connect_started`) and whether the
+    /// peripheral was retrieved as connected to the system and the connection completed quicker
+    /// than a radio round trip. The peripheral is forgotten as retrieved.
+    fn connected(&mut self, id: usize, now: Instant) -> (Duration, bool) {
+        let retrieved = self.retrieved.remove(&id).is_some();
+        match self.connects.remove(&id) {
+            Some(started) => {
+                let duration = now.saturating_duration_since(started);
+                (duration, retrieved && duration < ALREADY_CONNECTED_MAX_LATENCY)
+            }
+            None => (Duration::from_secs(0), false),
+        }
+    }
+
+    /// Forgets the connection attempt to the peripheral `id` that was cancelled or failed.
+    fn connect_cancelled(&mut self, id: usize) {
+        self.connects.remove(&id);
+    }
+
+    fn reset(&mut self, id: usize) {
+        self.retrieved.remove(&id);
+        self.connects.remove(&id);
+    }
+
+    fn clear(&mut self) {
+        self.retrieved.clear();
+        self.connects.clear();
+    }
+}
+
+/// Services with their characteristics and descriptors.
+type Tree<S, C, D> = Vec<(S, Vec<(C, Vec<D>)>)>;
+
+/// Services keyed by their address, with characteristics that are `None` until discovered.
+type PendingServices<S, C, D> = Vec<(usize, S, Option<PendingCharacteristics<C, D>>)>;
+
+/// Characteristics keyed by their address, with descriptors that are `None` until discovered.
+type PendingCharacteristics<C, D> = Vec<(usize, C, Option<Vec<D>>)>;
+
+/// Searches for characteristics in all services started by `Peripheral::find_characteristics`.
+struct CharacteristicSearches<C> {
+    /// Keyed by peripheral address.
+    searches: HashMap<usize, CharacteristicSearch<C>>,
+}
+
+struct CharacteristicSearch<C> {
+    uuids: Vec<Uuid>,
+
+    /// Addresses of services with characteristics discovery in progress, or `None` while
+    /// the services are being discovered.
+    pending: Option<HashSet<usize>>,
+
+    found: Vec<C>,
+}
+
+impl<C> Default for CharacteristicSearches<C> {
+    fn default() -> Self {
+        Self {
+            searches: HashMap::new(),
+        }
+    }
+}
+
+impl<C> CharacteristicSearches<C> {
+    /// Starts the search for characteristics with the `uuids` in the `peripheral`, awaiting its
+    /// services discovery. Any search in the same peripheral in progress is restarted.
+    fn start(&mut self, peripheral: usize, uuids: Vec<Uuid>) {
+        self.searches.insert(peripheral, CharacteristicSearch {
+            uuids,
+            pending: None,
+            found: Vec::new(),
+        });
+    }
+
+    /// Returns `true` if the search in the `peripheral` awaits its services discovery.
+    fn is_discovering_services(&self, peripheral: usize) -> bool {
+        match self.searches.get(&peripheral) {
+            Some(search) => search.pending.is_none(),
+            None => false,
+        }
+    }
+
+    /// Records the discovered `services` as awaiting characteristics discovery. Returns the UUIDs
+    /// of the characteristics to discover.
+    fn services_discovered(&mut self, peripheral: usize, services: impl IntoIterator<Item=usize>)
+        -> Vec<Uuid>
+    {
+        match self.searches.get_mut(&peripheral) {
+            Some(search) => {
+                search.pending = Some(services.into_iter().collect());
+                search.uuids.clone()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns `true` if the search in the `peripheral` awaits characteristics discovery of
+    /// the `service`.
+    fn awaits(&self, peripheral: usize, service: usize) -> bool {
+        match self.searches.get(&peripheral).and_then(|s| s.pending.as_ref()) {
+            Some(pending) => pending.contains(&service),
+            None => false,
+        }
+    }
+
+    /// Records the discovered `characteristics` of the `service`, keeping those with the searched
+    /// UUIDs.
+    fn characteristics_discovered(&mut self, peripheral: usize, service: usize,
+        characteristics: impl IntoIterator<Item=(Uuid, C)>)
+    {
+        if let Some(search) = self.searches.get_mut(&peripheral) {
+            if search.pending.as_mut().map(|p| p.remove(&service)).unwrap_or(false) {
+                let uuids = &search.uuids;
+                search.found.extend(characteristics.into_iter()
+                    .filter(|(uuid, _)| uuids.contains(uuid))
+                    .map(|(_, c)| c));
+            }
+        }
+    }
+
+    /// Returns the found characteristics if the search in the `peripheral` is complete.
+    fn finish(&mut self, peripheral: usize) -> Option<Vec<C>> {
+        match self.searches.get(&peripheral).and_then(|s| s.pending.as_ref()) {
+            Some(pending) if pending.is_empty() => {
+                self.searches.remove(&peripheral).map(|s| s.found)
+            }
+            _ => None,
+        }
+    }
+
+    /// Stops the search in the `peripheral`. Returns `false` if there was no search.
+    fn cancel(&mut self, peripheral: usize) -> bool {
+        self.searches.remove(&peripheral).is_some()
+    }
+}
+
+/// Discovery of the whole attribute tree of a peripheral. Services and characteristics are
+/// identified by their addresses.
+struct TreeDiscovery<S, C, D> {
+    /// `None` until services are discovered.
+    services: Option<PendingServices<S, C, D>>,
+}
+
+impl<S, C, D> Default for TreeDiscovery<S, C, D> {
+    fn default() -> Self {
+        Self {
+            services: None,
+        }
+    }
+}
+
+impl<S, C, D> TreeDiscovery<S, C, D> {
+    fn services_discovered(&mut self, services: Vec<(usize, S)>) {
+        self.services = Some(services.into_iter().map(|(id, s)| (id, s, None)).collect());
+    }
+
+    /// Returns `false` if characteristics of the `service` weren't awaited.
+    fn characteristics_discovered(&mut self, service: usize, characteristics: Vec<(usize, C)>) -> bool {
+        let chars = self.services.iter_mut().flatten()
+            .find(|(id, _, chars)| *id == service && chars.is_none());
+        if let Some((_, _, chars)) = chars {
+            *chars = Some(characteristics.into_iter().map(|(id, c)| (id, c, None)).collect());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns `false` if descriptors of the `characteristic` weren't awaited.
+    fn descriptors_discovered(&mut self, characteristic: usize, descriptors: Vec<D>) -> bool {
+        let descs = self.services.iter_mut().flatten()
+            .flat_map(|(_, _, chars)| chars.iter_mut().flatten())
+            .find(|(id, _, descs)| *id == characteristic && descs.is_none());
+        if let Some((_, _, descs)) = descs {
+            *descs = Some(descriptors);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the tree if everything is discovered.
+    fn finish(&mut self) -> Option<Tree<S, C, D>> {
+        let complete = self.services.as_ref()?.iter()
+            .all(|(_, _, chars)| chars.as_ref()
+                .map(|chars| chars.iter().all(|(_, _, descs)| descs.is_some()))
+                .unwrap_or(false));
+        if !complete {
+            return None;
+        }
+        Some(self.services.take()?.into_iter()
+            .map(|(_, s, chars)| (s, chars.unwrap_or_default().into_iter()
+                .map(|(_, c, descs)| (c, descs.unwrap_or_default()))
+                .collect()))
+            .collect())
+    }
+}
+
+/// Tracks characteristics polled by `Peripheral::start_polling`.
+#[derive(Default)]
+struct Polls {
+    /// Keyed by characteristic address.
+    polls: HashMap<usize, ActivePoll>,
+    next_generation: u64,
+}
+
+struct ActivePoll {
+    peripheral: usize,
+
+    /// Identifies ticks of this poll so ticks of a replaced poll stop.
+    generation: u64,
+
+    /// Whether a read was issued and its value hasn't arrived yet.
+    reading: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PollTick {
+    Read,
+
+    /// The previous read hasn't completed yet.
+    Skip,
+
+    /// The poll was stopped or replaced.
+    Stop,
+}
+
+impl Polls {
+    /// Starts polling the `characteristic`, replacing the existing poll of it if any. Returns the
+    /// generation of the new poll.
+    fn start(&mut self, peripheral: usize, characteristic: usize) -> u64 {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        self.polls.insert(characteristic, ActivePoll {
+            peripheral,
+            generation,
+            reading: false,
+        });
+        generation
+    }
+
+    /// Returns what to do on the tick of the `generation` poll of the `characteristic`.
+    fn tick(&mut self, characteristic: usize, generation: u64) -> PollTick {
+        match self.polls.get_mut(&characteristic) {
+            Some(poll) if poll.generation == generation => if poll.reading {
+                PollTick::Skip
+            } else {
+                poll.reading = true;
+                PollTick::Read
+            }
+            _ => PollTick::Stop,
+        }
+    }
+
+    /// Marks the read of the `characteristic` as completed.
+    fn complete(&mut self, characteristic: usize) {
+        if let Some(poll) = self.polls.get_mut(&characteristic) {
+            poll.reading = false;
+        }
+    }
+
+    fn stop(&mut self, characteristic: usize) {
+        self.polls.remove(&characteristic);
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.polls.retain(|_, p| p.peripheral != peripheral);
+    }
+
+    fn clear(&mut self) {
+        self.polls.clear();
+    }
+}
+
+type WriteResults = Vec<Result<(), Error>>;
+
+struct WriteSequence<P> {
+    /// Writes yet to be issued: characteristic address, payload and write kind.
+    writes: VecDeque<(usize, P, WriteKind)>,
+    results: WriteResults,
+
+    /// Address of the characteristic whose write awaits result and id of the write, `None` until
+    /// the write with response is issued.
+    in_flight: Option<(usize, Option<u64>)>,
+}
+
+enum WriteStep<P> {
+    Write(P, WriteKind),
+    Complete(WriteResults),
+}
+
+/// Tracks progress of characteristic write sequences. Each peripheral runs at most one sequence
+/// at a time, the others are queued.
+struct WriteSequences<P> {
+    /// Keyed by peripheral address.
+    sequences: HashMap<usize, VecDeque<WriteSequence<P>>>,
+}
+
+impl<P> Default for WriteSequences<P> {
+    fn default() -> Self {
+        Self {
+            sequences: HashMap::new(),
+        }
+    }
+}
+
+impl<P> WriteSequences<P> {
+    /// Queues the sequence of `writes` to the `peripheral`, given as characteristic address, payload
+    /// and write kind.
+    fn start(&mut self, peripheral: usize, writes: Vec<(usize, P, WriteKind)>) {
+        self.sequences.entry(peripheral).or_default()
+            .push_back(WriteSequence {
+                writes: writes.into(),
+                results: Vec::new(),
+                in_flight: None,
+            });
+    }
+
+    /// Returns the next step of the current sequence of the `peripheral`. Returns `None` if
+    /// there's no sequence or it waits for the result of the write in flight, or if the next write
+    /// is without response and the peripheral can't send it now.
+    ///
+    /// The returned write becomes in flight until its result is recorded with `complete`. If it's
+    /// a write with response, its id must be recorded with `issued`.
+    fn next(&mut self, peripheral: usize, can_send_without_response: bool) -> Option<WriteStep<P>> {
+        let queue = self.sequences.get_mut(&peripheral)?;
+        let seq = queue.front_mut()?;
+        if seq.in_flight.is_some() {
+            return None;
+        }
+        match seq.writes.front() {
+            None => {
+                let results = queue.pop_front().unwrap().results;
+                if queue.is_empty() {
+                    self.sequences.remove(&peripheral);
+                }
+                Some(WriteStep::Complete(results))
+            }
+            Some((_, _, WriteKind::WithoutResponse)) if !can_send_without_response => None,
+            Some(_) => {
+                let (characteristic, payload, kind) = seq.writes.pop_front().unwrap();
+                seq.in_flight = Some((characteristic, None));
+                Some(WriteStep::Write(payload, kind))
+            }
+        }
+    }
+
+    /// Records `id` of the write in flight of the `peripheral` issued with response.
+    fn issued(&mut self, peripheral: usize, id: u64) {
+        if let Some((_, in_flight_id)) = self.sequences.get_mut(&peripheral)
+            .and_then(|q| q.front_mut())
+            .and_then(|seq| seq.in_flight.as_mut())
+        {
+            *in_flight_id = Some(id);
+        }
+    }
+
+    /// Records `result` of the write in flight of the `peripheral`. Returns `false` if the write
+    /// in flight isn't the write to the `characteristic` with the `id`, i.e. the result belongs
+    /// to another write.
+    fn complete(&mut self, peripheral: usize, characteristic: usize, id: Option<u64>,
+        result: Result<(), Error>) -> bool
+    {
+        match self.sequences.get_mut(&peripheral).and_then(|q| q.front_mut()) {
+            Some(seq) if seq.in_flight == Some((characteristic, id)) => {
+                seq.in_flight = None;
+                seq.results.push(result);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Stops all sequences of the `peripheral`, failing the remaining writes with `error`.
+    /// Returns the results of the stopped sequences.
+    fn cancel(&mut self, peripheral: usize, error: &Error) -> Vec<WriteResults> {
+        self.sequences.remove(&peripheral).into_iter().flatten()
+            .map(|mut seq| {
+                let remaining = seq.writes.len() + seq.in_flight.map(|_| 1).unwrap_or(0);
+                seq.results.extend((0..remaining).map(|_| Err(error.clone())));
+                seq.results
+            })
+            .collect()
+    }
+}
+
+/// Characteristic writes with response awaiting result. Core Bluetooth reports results of writes
+/// to a characteristic in the order they were issued, so a result belongs to the oldest write.
+#[derive(Default)]
+struct PendingWrites {
+    next_id: u64,
+
+    /// Keyed by characteristic address. Values are the owning peripheral address and ids of
+    /// the writes in the order they were issued.
+    writes: HashMap<usize, (usize, VecDeque<u64>)>,
+}
+
+impl PendingWrites {
+    /// Records the write to the `characteristic` as awaiting result. Returns id of the write.
+    fn issued(&mut self, peripheral: usize, characteristic: usize) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.writes.entry(characteristic)
+            .or_insert_with(|| (peripheral, VecDeque::new()))
+            .1.push_back(id);
+        id
+    }
+
+    /// Returns id of the write to the `characteristic` the received result belongs to, or `None`
+    /// if no write is tracked.
+    fn completed(&mut self, characteristic: usize) -> Option<u64> {
+        let ids = &mut self.writes.get_mut(&characteristic)?.1;
+        let r = ids.pop_front();
+        if ids.is_empty() {
+            self.writes.remove(&characteristic);
+        }
+        r
+    }
+
+    fn reset(&mut self, peripheral: usize) {
+        self.writes.retain(|_, (p, _)| *p != peripheral);
+    }
+}
+
+/// Finds the characteristic with the `uuid` among `characteristics` given as pairs of UUID and
+/// whether the characteristic supports notifications or indications. Returns index of the found
+/// characteristic.
+fn find_subscribable(uuid: Uuid, characteristics: impl IntoIterator<Item=(Uuid, bool)>)
+    -> Result<usize, CommandRejectReason>
+{
+    let (i, subscribable) = characteristics.into_iter()
+        .enumerate()
+        .find(|(_, (id, _))| *id == uuid)
+        .map(|(i, (_, subscribable))| (i, subscribable))
+        .ok_or(CommandRejectReason::NotDiscovered { uuid })?;
+    if subscribable {
+        Ok(i)
+    } else {
+        Err(CommandRejectReason::NotSubscribable { uuid })
+    }
+}
+
+fn write_too_large(len: usize, max: usize) -> Option<CommandRejectReason> {
+    if len > max {
+        Some(CommandRejectReason::WriteTooLarge { len, max })
+    } else {
+        None
+    }
+}
 
 object_ptr_wrapper!(Delegate);
 
-impl Delegate {
-    pub fn new(sender: Sender, queue: *mut Object) -> StrongPtr<Self> {
-        let mut r = unsafe {
-            let r: *mut Object = msg_send![*DELEGATE_CLASS, alloc];
-            Self::wrap(r)
-        };
-        r.set_sender(sender);
-        r.set_queue(queue);
-        unsafe { StrongPtr::wrap(r) }
+impl Delegate {
+    pub fn new(sender: Sender, queue: *mut Object, config: &mut CentralManagerBuilder,
+        shared: Arc<Shared>) -> StrongPtr<Self>
+    {
+        let mut r = unsafe {
+            let r: *mut Object = msg_send![*DELEGATE_CLASS, alloc];
+            Self::wrap(r)
+        };
+        r.set_shared(&shared);
+        r.set_state(State::new(sender, config, shared));
+        r.set_queue(queue);
+        unsafe { StrongPtr::wrap(r) }
+    }
+
+    pub fn drop_self(&mut self) {
+        trace!("dropping delegate {:?}", self.0);
+        if let Some(state) = self.state() {
+            state.trace_teardown();
+        }
+        self.drop_state();
+        self.set_manager(nil);
+    }
+
+    /// Stops scanning, cancels connections of the `manager`, fails pending connection futures
+    /// and drops the delegate.
+    pub fn shutdown(&mut self, manager: CBCentralManager) {
+        if manager.state() == ManagerState::PoweredOn {
+            manager.cancel_scan();
+        }
+        self.disconnect_all(manager);
+        if let Some(state) = self.state() {
+            state.shared.connected.lock().unwrap().clear();
+            state.shared.throttle.lock().unwrap().clear();
+            state.shared.connects.resolve_all(Error::new(ErrorKind::OperationCancelled,
+                "central manager shut down"));
+        }
+        self.drop_self();
+    }
+
+    /// Returns the snapshot of the manager state, or `None` if the manager has been torn down.
+    pub fn diagnostics(&self, manager: CBCentralManager) -> Option<Diagnostics> {
+        let is_scanning = manager.state() == ManagerState::PoweredOn && manager.is_scanning();
+        self.state().map(|s| s.diagnostics(is_scanning))
+    }
+
+    /// Returns the manager this delegate belongs to. The manager is not retained by the delegate
+    /// so this returns `None` once the delegate has been dropped.
+    pub fn manager(&self) -> Option<CBCentralManager> {
+        unsafe {
+            CBCentralManager::wrap_nullable(self.ivar(MANAGER_IVAR) as *mut Object)
+        }
+    }
+
+    pub fn set_manager(&mut self, manager: impl ObjectPtr) {
+        unsafe {
+            *self.ivar_mut(MANAGER_IVAR) = manager.as_ptr() as *mut c_void;
+        }
+    }
+
+    pub fn queue(&self) -> *mut Object {
+        unsafe {
+            self.ivar(QUEUE_IVAR) as *mut Object
+        }
+    }
+
+    fn set_queue(&mut self, queue: *mut Object) {
+        unsafe {
+            *self.ivar_mut(QUEUE_IVAR) = queue as *mut c_void;
+        }
+    }
+
+    /// Returns the state shared with the manager, or `None` if the manager has been dropped.
+    ///
+    /// Unlike the delegate state, this can be called from any thread: the reference is set once
+    /// when the delegate is created and lives as long as the delegate object.
+    pub fn shared(&self) -> Option<Arc<Shared>> {
+        unsafe {
+            (self.ivar(SHARED_IVAR) as *const Weak<Shared>).as_ref()?.upgrade()
+        }
+    }
+
+    fn set_shared(&mut self, shared: &Arc<Shared>) {
+        unsafe {
+            let shared = Box::new(Arc::downgrade(shared));
+            *self.ivar_mut(SHARED_IVAR) = Box::into_raw(shared) as *mut c_void;
+        }
+    }
+
+    fn state(&self) -> Option<&State> {
+        unsafe {
+            (self.ivar(STATE_IVAR) as *mut State).as_ref()
+        }
+    }
+
+    fn state_mut(&mut self) -> Option<&mut State> {
+        unsafe {
+            (*self.ivar_mut(STATE_IVAR) as *mut State).as_mut()
+        }
+    }
+
+    fn set_state(&mut self, state: State) {
+        unsafe {
+            *self.ivar_mut(STATE_IVAR) = Box::into_raw(Box::new(state)) as *mut c_void;
+        }
+    }
+
+    fn drop_state(&mut self) {
+        unsafe {
+            let p = self.ivar_mut(STATE_IVAR);
+            if let Some(state) = NonNull::new(*p) {
+                let _ = Box::<State>::from_raw(state.as_ptr() as *mut State);
+                *p = ptr::null_mut();
+            }
+        }
+    }
+
+    /// Returns `false` if the delegate has been dropped. Commands dispatched to the delegate queue
+    /// after that must do nothing.
+    pub fn is_valid(&self) -> bool {
+        self.state().is_some()
+    }
+
+    /// Enables or disables advertising interval tracking. Any previously collected tracking data
+    /// is discarded.
+    pub fn set_interval_tracking(&mut self, enabled: bool) {
+        if let Some(state) = self.state_mut() {
+            state.intervals = if enabled {
+                Some(IntervalTracker::default())
+            } else {
+                None
+            };
+        }
+    }
+
+    /// Adds the scan session `id` and updates the scan to cover all active sessions.
+    pub fn begin_scan_session(&mut self, manager: CBCentralManager, id: u64, options: ScanOptions) {
+        if let Some(state) = self.state_mut() {
+            state.scan_sessions.begin(id, options);
+            self.update_scan_sessions(manager);
+        }
+    }
+
+    /// Removes the scan session `id` and updates the scan to cover the remaining sessions,
+    /// stopping it if there are none.
+    pub fn end_scan_session(&mut self, manager: CBCentralManager, id: u64) {
+        let ended = self.state_mut()
+            .map(|s| s.scan_sessions.end(id))
+            .unwrap_or(false);
+        if ended {
+            self.update_scan_sessions(manager);
+        }
+    }
+
+    fn update_scan_sessions(&mut self, manager: CBCentralManager) {
+        let (options, powered_on) = match self.state() {
+            Some(s) => (s.scan_sessions.merged(),
+                s.shared.manager_state.load(Ordering::SeqCst) == ManagerState::PoweredOn as u8),
+            None => return,
+        };
+        self.set_interval_tracking(options.as_ref().map(|o| o.estimate_interval).unwrap_or(false));
+        if !powered_on {
+            // Applied when the manager transitions to PoweredOn.
+            return;
+        }
+        if let Some(options) = options {
+            manager.scan(&options);
+        } else {
+            manager.cancel_scan();
+        }
+    }
+
+    /// Returns `WriteTooLarge` reason if write length validation is enabled and `len` exceeds the
+    /// maximum write length of the `kind`.
+    pub fn write_too_large(&self, peripheral: CBPeripheral, len: usize, kind: WriteKind)
+        -> Option<CommandRejectReason>
+    {
+        match self.state() {
+            Some(state) if state.validate_write_len =>
+                write_too_large(len, peripheral.max_write_len(kind)),
+            _ => None,
+        }
+    }
+
+    /// Sends `CommandRejected` event with the `reason`.
+    pub fn reject(&self, peripheral: CBPeripheral, reason: CommandRejectReason) {
+        self.send(CentralEvent::CommandRejected {
+            peripheral: unsafe { Peripheral::retain(peripheral) },
+            reason,
+        });
+    }
+
+    /// Makes the characteristic with `uuid` subscribed once it's discovered in the `service`.
+    pub fn subscribe_on_discovery(&mut self, peripheral: CBPeripheral, service: CBService, uuid: Uuid) {
+        if let Some(state) = self.state_mut() {
+            state.pending_subscriptions.entry(service.as_ptr() as usize)
+                .or_insert_with(|| (peripheral.as_ptr() as usize, Vec::new()))
+                .1.push(uuid);
+        }
+    }
+
+    /// Starts forced rediscovery of the `peripheral`: disconnecting, connecting and discovering
+    /// services, as tracked in the callbacks.
+    pub fn start_rediscovery(&mut self, manager: CBCentralManager, peripheral: CBPeripheral) {
+        let step = if let Some(state) = self.state_mut() {
+            state.rediscoveries.start(peripheral.as_ptr() as usize, peripheral.is_disconnected())
+        } else {
+            return;
+        };
+        match step {
+            RediscoveryStep::Disconnect => manager.cancel_connect(&peripheral),
+            RediscoveryStep::Connect => manager.connect(&peripheral, None),
+            RediscoveryStep::DiscoverServices => unreachable!(),
+        }
+    }
+
+    /// Reads values of all descriptors of the `characteristic`, discovering them first if needed.
+    pub fn read_all_descriptors(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic) {
+        if let Some(state) = self.state_mut() {
+            state.descriptor_reads.start(peripheral.as_ptr() as usize, characteristic.as_ptr() as usize);
+        } else {
+            return;
+        }
+        match characteristic.descriptors() {
+            Some(descriptors) => self.read_descriptors(peripheral, characteristic, &descriptors),
+            None => peripheral.discover_descriptors(characteristic),
+        }
+    }
+
+    /// Reads the Client Characteristic Configuration descriptor of the `characteristic`,
+    /// discovering the descriptors first if needed.
+    pub fn read_cccd(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic) {
+        if let Some(state) = self.state_mut() {
+            state.cccd_reads.start(peripheral.as_ptr() as usize, characteristic.as_ptr() as usize);
+        } else {
+            return;
+        }
+        match characteristic.descriptors() {
+            Some(descriptors) => self.read_cccd_of(peripheral, characteristic, &descriptors),
+            None => peripheral.discover_descriptors(characteristic),
+        }
+    }
+
+    fn read_cccd_of(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic,
+        descriptors: &[Descriptor])
+    {
+        let cccd = descriptors.iter()
+            .find(|d| d.id() == known::descriptor::CLIENT_CHARACTERISTIC_CONFIGURATION);
+        let state = if let Some(state) = self.state_mut() {
+            state
+        } else {
+            return;
+        };
+        if let Some(cccd) = cccd {
+            state.cccd_reads.reading(characteristic.as_ptr() as usize);
+            peripheral.read_descriptor(*cccd.descriptor);
+        } else {
+            state.cccd_reads.cancel(characteristic.as_ptr() as usize);
+            self.send(CentralEvent::CccdRead {
+                peripheral: unsafe { Peripheral::retain(peripheral) },
+                characteristic: unsafe { Characteristic::retain(characteristic) },
+                result: Ok(None),
+            });
+        }
+    }
+
+    fn read_descriptors(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic,
+        descriptors: &[Descriptor])
+    {
+        let done = if let Some(state) = self.state_mut() {
+            state.descriptor_reads.discovered(characteristic.as_ptr() as usize,
+                descriptors.iter().map(|d| d.descriptor.as_ptr() as usize))
+        } else {
+            return;
+        };
+        if done {
+            self.send(CentralEvent::AllDescriptorsRead {
+                peripheral: unsafe { Peripheral::retain(peripheral) },
+                characteristic: unsafe { Characteristic::retain(characteristic) },
+                result: Ok(()),
+            });
+        } else {
+            for descriptor in descriptors {
+                peripheral.read_descriptor(*descriptor.descriptor);
+            }
+        }
+    }
+
+    /// Queues the sequence of `writes` to the `peripheral` and starts it unless another sequence is
+    /// in progress.
+    pub fn start_write_sequence(&mut self, peripheral: CBPeripheral,
+        writes: Vec<(StrongPtr<CBCharacteristic>, WriteValue, WriteKind)>)
+    {
+        if let Some(state) = self.state_mut() {
+            state.write_sequences.start(peripheral.as_ptr() as usize, writes.into_iter()
+                .map(|(c, value, kind)| (c.as_ptr() as usize, (c, value), kind))
+                .collect());
+        } else {
+            return;
+        }
+        self.advance_write_sequence(peripheral);
+    }
+
+    /// Issues writes of the current write sequence of the `peripheral` as far as possible and
+    /// sends `WriteSequenceComplete` events for the completed sequences.
+    fn advance_write_sequence(&mut self, peripheral: CBPeripheral) {
+        let p = peripheral.as_ptr() as usize;
+        loop {
+            let can_send = peripheral.can_send_write_without_response();
+            let step = match self.state_mut().and_then(|s| s.write_sequences.next(p, can_send)) {
+                Some(step) => step,
+                None => return,
+            };
+            match step {
+                WriteStep::Write((characteristic, value), kind) => {
+                    let result = match command::write_characteristic(peripheral, *characteristic,
+                        &value, kind, None)
+                    {
+                        IssuedWrite::Rejected =>
+                            Err(Error::new(ErrorKind::InvalidParameters, "write was rejected")),
+                        IssuedWrite::Sent => Ok(()),
+                        IssuedWrite::Pending(id) => {
+                            if let Some(state) = self.state_mut() {
+                                state.write_sequences.issued(p, id);
+                            }
+                            continue;
+                        }
+                    };
+                    if let Some(state) = self.state_mut() {
+                        state.write_sequences.complete(p, characteristic.as_ptr() as usize, None,
+                            result);
+                    }
+                }
+                WriteStep::Complete(results) => {
+                    self.send(CentralEvent::WriteSequenceComplete {
+                        peripheral: unsafe { Peripheral::retain(peripheral) },
+                        results,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Records the write with response to the `characteristic` as awaiting result. Returns id of
+    /// the write.
+    pub fn write_issued(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic)
+        -> Option<u64>
+    {
+        self.state_mut().map(|s| s.pending_writes.issued(peripheral.as_ptr() as usize,
+            characteristic.as_ptr() as usize))
+    }
+
+    /// Records the `peripherals` retrieved as connected to the system.
+    pub fn retrieved_connected(&mut self, peripherals: &[Peripheral]) {
+        if let Some(state) = self.state_mut() {
+            state.system_connected.retrieved(peripherals.iter()
+                .map(|p| (p.peripheral.as_ptr() as usize, p.peripheral.clone())));
+        }
+    }
+
+    /// Records the start of a connection attempt to the `peripheral`.
+    pub fn connect_started(&mut self, peripheral: CBPeripheral) {
+        if let Some(state) = self.state_mut() {
+            state.system_connected.connect_started(peripheral.as_ptr() as usize, Instant::now());
+        }
+    }
+
+    /// Records the cancellation of a connection attempt to the `peripheral`.
+    pub fn connect_cancelled(&mut self, peripheral: CBPeripheral) {
+        if let Some(state) = self.state_mut() {
+            state.system_connected.connect_cancelled(peripheral.as_ptr() as usize);
+        }
+    }
+
+    /// Completes the throttled connection attempt to the `peripheral`, if any, and starts
+    /// the queued attempts it makes room for.
+    fn connect_throttled_next(&mut self, peripheral: &Peripheral) {
+        let start = self.state()
+            .map(|s| s.shared.throttle.lock().unwrap().complete(peripheral.id()))
+            .unwrap_or_default();
+        if start.is_empty() {
+            return;
+        }
+        if let Some(manager) = self.manager() {
+            for p in start {
+                self.connect_started(*p.peripheral);
+                manager.connect(&p.peripheral, None);
+            }
+        }
+    }
+
+    /// Starts discovery of all services, characteristics and descriptors of the `peripheral` unless
+    /// it's in progress. The resulting tree is sent to `sender`.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn start_tree_discovery(&mut self, peripheral: CBPeripheral, sender: TreeSender) {
+        let state = if let Some(state) = self.state_mut() {
+            state
+        } else {
+            return;
+        };
+        let p = peripheral.as_ptr() as usize;
+        if let Some((_, senders)) = state.tree_discoveries.get_mut(&p) {
+            senders.push(sender);
+            return;
+        }
+        state.tree_discoveries.insert(p, (TreeDiscovery::default(), vec![sender]));
+        self.discover_services(peripheral, None);
+    }
+
+    fn tree_services_discovered(&mut self, peripheral: CBPeripheral,
+        services: &Result<Vec<Service>, Error>)
+    {
+        let p = peripheral.as_ptr() as usize;
+        let discovery = match self.state_mut().and_then(|s| s.tree_discoveries.get_mut(&p)) {
+            Some((discovery, _)) => discovery,
+            None => return,
+        };
+        match services {
+            Ok(services) => {
+                discovery.services_discovered(services.iter()
+                    .map(|s| (s.service.as_ptr() as usize, s.clone()))
+                    .collect());
+                for service in services {
+                    peripheral.discover_characteristics(*service.service, None);
+                }
+                self.finish_tree_discovery(p);
+            }
+            Err(e) => self.fail_tree_discovery(p, e.clone()),
+        }
+    }
+
+    fn tree_characteristics_discovered(&mut self, peripheral: CBPeripheral, service: CBService,
+        characteristics: &Result<Vec<Characteristic>, Error>)
+    {
+        let p = peripheral.as_ptr() as usize;
+        let discovery = match self.state_mut().and_then(|s| s.tree_discoveries.get_mut(&p)) {
+            Some((discovery, _)) => discovery,
+            None => return,
+        };
+        match characteristics {
+            Ok(chars) => {
+                let awaited = discovery.characteristics_discovered(service.as_ptr() as usize,
+                    chars.iter()
+                        .map(|c| (c.characteristic.as_ptr() as usize, c.clone()))
+                        .collect());
+                if awaited {
+                    for characteristic in chars {
+                        peripheral.discover_descriptors(*characteristic.characteristic);
+                    }
+                    self.finish_tree_discovery(p);
+                }
+            }
+            Err(e) => self.fail_tree_discovery(p, e.clone()),
+        }
+    }
+
+    fn tree_descriptors_discovered(&mut self, peripheral: CBPeripheral,
+        characteristic: CBCharacteristic, descriptors: &Result<Vec<Descriptor>, Error>)
+    {
+        let p = peripheral.as_ptr() as usize;
+        let discovery = match self.state_mut().and_then(|s| s.tree_discoveries.get_mut(&p)) {
+            Some((discovery, _)) => discovery,
+            None => return,
+        };
+        match descriptors {
+            Ok(descs) => {
+                if discovery.descriptors_discovered(characteristic.as_ptr() as usize, descs.clone()) {
+                    self.finish_tree_discovery(p);
+                }
+            }
+            Err(e) => self.fail_tree_discovery(p, e.clone()),
+        }
+    }
+
+    /// Starts searching for characteristics with the `uuids` in all services of the `peripheral`.
+    pub fn find_characteristics(&mut self, peripheral: CBPeripheral, uuids: Vec<Uuid>) {
+        if let Some(state) = self.state_mut() {
+            state.characteristic_searches.start(peripheral.as_ptr() as usize, uuids);
+            self.discover_services_from(peripheral, None, DiscoveryOrigin::Search);
+        }
+    }
+
+    /// Starts discovery of the services with the `uuids` of the `peripheral`.
+    pub fn discover_services(&mut self, peripheral: CBPeripheral, uuids: Option<NSArray>) {
+        self.discover_services_from(peripheral, uuids, DiscoveryOrigin::Other);
+    }
+
+    /// Starts discovery of the services with the `uuids` of the `peripheral`. The `filter` is
+    /// applied to the `ServicesDiscovered` event of this discovery only.
+    pub fn discover_services_filtered(&mut self, peripheral: CBPeripheral, uuids: Option<NSArray>,
+        filter: ServiceFilter)
+    {
+        self.discover_services_from(peripheral, uuids, DiscoveryOrigin::Filtered(filter));
+    }
+
+    fn discover_services_from(&mut self, peripheral: CBPeripheral, uuids: Option<NSArray>,
+        origin: DiscoveryOrigin)
+    {
+        if let Some(state) = self.state_mut() {
+            state.service_discoveries.started(peripheral.as_ptr() as usize, origin);
+        }
+        peripheral.discover_services(uuids);
+    }
+
+    fn search_services_discovered(&mut self, peripheral: CBPeripheral,
+        services: Result<Vec<Service>, Error>)
+    {
+        let p = peripheral.as_ptr() as usize;
+        let state = if let Some(state) = self.state_mut() {
+            state
+        } else {
+            return;
+        };
+        match services {
+            Ok(services) => {
+                let uuids = state.characteristic_searches.services_discovered(p,
+                    services.iter().map(|s| s.service.as_ptr() as usize));
+                if !services.is_empty() {
+                    objc::rc::autoreleasepool(|| {
+                        let uuids = CBUUID::array_from_uuids(&uuids);
+                        for service in &services {
+                            peripheral.discover_characteristics(*service.service, Some(uuids));
+                        }
+                    });
+                }
+                self.finish_search(peripheral);
+            }
+            Err(e) => self.fail_search(peripheral, e),
+        }
+    }
+
+    fn search_characteristics_discovered(&mut self, peripheral: CBPeripheral, service: CBService,
+        characteristics: Result<Vec<Characteristic>, Error>)
+    {
+        match characteristics {
+            Ok(chars) => {
+                if let Some(state) = self.state_mut() {
+                    state.characteristic_searches.characteristics_discovered(
+                        peripheral.as_ptr() as usize, service.as_ptr() as usize,
+                        chars.into_iter().map(|c| (c.id(), c)));
+                }
+                self.finish_search(peripheral);
+            }
+            Err(e) => self.fail_search(peripheral, e),
+        }
+    }
+
+    /// Sends the characteristics found in the `peripheral` if the search is complete.
+    fn finish_search(&mut self, peripheral: CBPeripheral) {
+        let found = self.state_mut()
+            .and_then(|s| s.characteristic_searches.finish(peripheral.as_ptr() as usize));
+        if let Some(found) = found {
+            self.send(CentralEvent::CharacteristicsFound {
+                peripheral: unsafe { Peripheral::retain(peripheral) },
+                characteristics: Ok(found),
+            });
+        }
+    }
+
+    fn fail_search(&mut self, peripheral: CBPeripheral, error: Error) {
+        let cancelled = self.state_mut()
+            .map(|s| s.characteristic_searches.cancel(peripheral.as_ptr() as usize))
+            .unwrap_or(false);
+        if cancelled {
+            self.send(CentralEvent::CharacteristicsFound {
+                peripheral: unsafe { Peripheral::retain(peripheral) },
+                characteristics: Err(error),
+            });
+        }
+    }
+
+    /// Sends `LinkQualityWarning` event if the RSSI of the `peripheral` stayed below the threshold
+    /// for the window.
+    fn check_link_quality(&mut self, peripheral: Peripheral, rssi: i32) {
+        let warn = self.state_mut()
+            .and_then(|s| s.link_quality.as_mut())
+            .map(|l| l.observe(peripheral.peripheral.as_ptr() as usize, rssi, Instant::now()))
+            .unwrap_or(false);
+        if warn {
+            self.send(CentralEvent::LinkQualityWarning {
+                peripheral,
+                rssi,
+            });
+        }
+    }
+
+    /// Sends the tree of the `peripheral` if its discovery is complete.
+    fn finish_tree_discovery(&mut self, peripheral: usize) {
+        let state = if let Some(state) = self.state_mut() {
+            state
+        } else {
+            return;
+        };
+        let services = match state.tree_discoveries.get_mut(&peripheral).and_then(|(d, _)| d.finish()) {
+            Some(services) => services,
+            None => return,
+        };
+        let tree = DeviceTree {
+            services: services.into_iter()
+                .map(|(service, chars)| ServiceNode {
+                    service,
+                    characteristics: chars.into_iter()
+                        .map(|(characteristic, descriptors)| CharacteristicNode {
+                            characteristic,
+                            descriptors,
+                        })
+                        .collect(),
+                })
+                .collect(),
+        };
+        if let Some((_, senders)) = state.tree_discoveries.remove(&peripheral) {
+            for sender in senders {
+                let _ = sender.try_send(Ok(tree.clone()));
+            }
+        }
+    }
+
+    fn fail_tree_discovery(&mut self, peripheral: usize, error: Error) {
+        if let Some((_, senders)) = self.state_mut().and_then(|s| s.tree_discoveries.remove(&peripheral)) {
+            for sender in senders {
+                let _ = sender.try_send(Err(error.clone()));
+            }
+        }
+    }
+
+    /// Cancels connections to all connected peripherals.
+    pub fn disconnect_all(&self, manager: CBCentralManager) {
+        if let Some(state) = self.state() {
+            for peripheral in state.shared.connected.lock().unwrap().iter() {
+                manager.cancel_connect(&peripheral.peripheral);
+            }
+        }
+    }
+
+    /// Starts polling the `characteristic` and returns the generation of the poll.
+    pub fn start_poll(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic) -> u64 {
+        self.state_mut()
+            .map(|s| s.polls.start(peripheral.as_ptr() as usize, characteristic.as_ptr() as usize))
+            .unwrap_or(0)
+    }
+
+    pub fn set_value_dedup(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic,
+        enabled: bool)
+    {
+        if let Some(state) = self.state_mut() {
+            state.value_dedup.set_enabled(peripheral.as_ptr() as usize,
+                characteristic.as_ptr() as usize, enabled);
+        }
+    }
+
+    pub fn set_default_write_kind(&mut self, peripheral: CBPeripheral, kind: WriteKind) {
+        if let Some(state) = self.state_mut() {
+            state.default_write_kinds.set(peripheral.as_ptr() as usize, kind);
+        }
+    }
+
+    pub fn default_write_kind(&self, peripheral: CBPeripheral) -> WriteKind {
+        self.state()
+            .map(|s| s.default_write_kinds.get(peripheral.as_ptr() as usize))
+            .unwrap_or(WriteKind::WithResponse)
+    }
+
+    pub fn start_read_retry(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic,
+        max_retries: u32)
+    {
+        if let Some(state) = self.state_mut() {
+            state.read_retries.start(peripheral.as_ptr() as usize, characteristic.as_ptr() as usize,
+                max_retries);
+        }
+    }
+
+    /// Re-issues the read of the `characteristic` unless it has completed in the meantime.
+    pub fn retry_read(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic) {
+        let pending = self.state()
+            .map(|s| s.read_retries.is_pending(characteristic.as_ptr() as usize))
+            .unwrap_or(false);
+        if !pending || self.reject_stale(peripheral, characteristic.service()) {
+            return;
+        }
+        objc::rc::autoreleasepool(|| {
+            peripheral.read_characteristic(characteristic);
+        });
+    }
+
+    pub fn start_subscribe_retry(&mut self, peripheral: CBPeripheral,
+        characteristic: CBCharacteristic, max_retries: u32)
+    {
+        if let Some(state) = self.state_mut() {
+            state.subscribe_retries.start(peripheral.as_ptr() as usize,
+                characteristic.as_ptr() as usize, max_retries);
+        }
+    }
+
+    /// Re-issues the subscription to the `characteristic` unless it has completed in the meantime.
+    pub fn retry_subscribe(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic) {
+        let pending = self.state()
+            .map(|s| s.subscribe_retries.is_pending(characteristic.as_ptr() as usize))
+            .unwrap_or(false);
+        if !pending || self.reject_stale(peripheral, characteristic.service()) {
+            return;
+        }
+        objc::rc::autoreleasepool(|| {
+            peripheral.set_notify_value(characteristic, true);
+        });
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    pub fn add_value_waiter(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic,
+        predicate: ValuePredicate, sender: ValueSender)
+    {
+        if let Some(state) = self.state_mut() {
+            state.value_waiters.add(peripheral.as_ptr() as usize, characteristic.as_ptr() as usize,
+                predicate, sender);
+        }
+    }
+
+    /// Issues reads of the `characteristics` as a set and returns id of the set, or `None` if
+    /// there's nothing to wait for.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn start_read_set(&mut self, peripheral: CBPeripheral,
+        characteristics: Vec<(CharacteristicRef, CBCharacteristic)>, sender: ReadSetSender) -> Option<u64>
+    {
+        if characteristics.is_empty() {
+            let _ = sender.try_send(HashMap::new());
+            return None;
+        }
+        let reads = characteristics.iter().map(|(k, c)| (c.as_ptr() as usize, *k)).collect();
+        let id = self.state_mut()?.read_sets.start(peripheral.as_ptr() as usize, reads, sender);
+        for (_, characteristic) in characteristics {
+            peripheral.read_characteristic(characteristic);
+        }
+        Some(id)
+    }
+
+    /// Completes the read set `id` if it hasn't completed yet, failing the pending reads with
+    /// timeout error.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn expire_read_set(&mut self, id: u64) {
+        let done = self.state_mut()
+            .and_then(|s| s.read_sets.expire(id, &Error::new(ErrorKind::Timeout, "read deadline elapsed")));
+        if let Some((sender, results)) = done {
+            let _ = sender.try_send(results);
+        }
+    }
+
+    /// Adds the notification stream `id` of the `characteristic` and subscribes to
+    /// the characteristic unless it's already notifying or subscribed by other streams.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn add_notification_stream(&mut self, peripheral: CBPeripheral,
+        characteristic: CBCharacteristic, id: u64, sender: ValueSender)
+    {
+        if self.reject_stale(peripheral, characteristic.service()) {
+            let _ = sender.try_send(Err(Error::new(ErrorKind::InvalidHandle, "stale characteristic")));
+            return;
+        }
+        if let Some(state) = self.state_mut() {
+            let subscribe = state.notification_streams.add(peripheral.as_ptr() as usize,
+                characteristic.as_ptr() as usize, id, sender, characteristic.is_notifying());
+            if subscribe {
+                peripheral.set_notify_value(characteristic, true);
+            }
+        }
+    }
+
+    /// Removes the notification stream `id` of the `characteristic` and unsubscribes from
+    /// the characteristic if it was the last stream of it and the streams subscribed to it.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn remove_notification_stream(&mut self, peripheral: CBPeripheral,
+        characteristic: CBCharacteristic, id: u64)
+    {
+        let last = self.state_mut()
+            .map(|s| s.notification_streams.remove(characteristic.as_ptr() as usize, id))
+            .unwrap_or(false);
+        if last && !peripheral.is_disconnected() {
+            peripheral.set_notify_value(characteristic, false);
+        }
+    }
+
+    /// Fails value waiters, read sets and notification streams of the `peripheral`, or of all
+    /// peripherals if `None`, with the `error`.
+    #[cfg(feature = "async_std_unstable")]
+    fn fail_waiters(&mut self, peripheral: Option<usize>, error: Error) {
+        let state = match self.state_mut() {
+            Some(s) => s,
+            None => return,
+        };
+        let waiters = match peripheral {
+            Some(p) => state.value_waiters.reset(p),
+            None => state.value_waiters.clear(),
+        };
+        for sender in waiters {
+            let _ = sender.try_send(Err(error.clone()));
+        }
+        for (sender, results) in state.read_sets.expire_all(peripheral, &error) {
+            let _ = sender.try_send(results);
+        }
+        let streams = match peripheral {
+            Some(p) => state.notification_streams.reset(p),
+            None => state.notification_streams.clear(),
+        };
+        for sender in streams {
+            let _ = sender.try_send(Err(error.clone()));
+        }
+    }
+
+    pub fn start_read_then_subscribe(&mut self, peripheral: CBPeripheral,
+        characteristic: CBCharacteristic)
+    {
+        if let Some(state) = self.state_mut() {
+            state.read_subscriptions.start(peripheral.as_ptr() as usize, characteristic.as_ptr() as usize);
+        }
+    }
+
+    /// Stops all polls and abandons all pending read and subscription retries.
+    pub fn cancel_timers(&mut self) {
+        if let Some(state) = self.state_mut() {
+            state.polls.clear();
+            state.read_retries.clear();
+            state.subscribe_retries.clear();
+        }
+    }
+
+    pub fn stop_poll(&mut self, characteristic: CBCharacteristic) {
+        if let Some(state) = self.state_mut() {
+            state.polls.stop(characteristic.as_ptr() as usize);
+        }
     }
 
-    pub fn drop_self(&mut self) {
-        trace!("dropping delegate {:?}", self.0);
-        self.drop_sender();
+    /// Handles the tick of the `generation` poll of the `characteristic`, reading its value unless
+    /// the previous read is still in flight. Returns `false` if the poll has stopped.
+    pub fn poll(&mut self, peripheral: CBPeripheral, characteristic: CBCharacteristic,
+        generation: u64) -> bool
+    {
+        let tick = match self.state_mut() {
+            Some(state) => state.polls.tick(characteristic.as_ptr() as usize, generation),
+            None => return false,
+        };
+        match tick {
+            PollTick::Read => {
+                if self.reject_stale(peripheral, characteristic.service()) {
+                    self.stop_poll(characteristic);
+                    return false;
+                }
+                objc::rc::autoreleasepool(|| {
+                    peripheral.read_characteristic(characteristic);
+                });
+                true
+            }
+            PollTick::Skip => true,
+            PollTick::Stop => false,
+        }
     }
 
-    pub fn queue(&self) -> *mut Object {
-        unsafe {
-            self.ivar(QUEUE_IVAR) as *mut Object
+    /// Sends `event` to the channel, or buffers it while delivery is paused. Does nothing if
+    /// the delegate has been dropped.
+    pub fn send(&self, event: CentralEvent) {
+        if let Some(state) = self.state() {
+            state.send(event);
         }
     }
 
-    fn set_queue(&mut self, queue: *mut Object) {
-        unsafe {
-            *self.ivar_mut(QUEUE_IVAR) = queue as *mut c_void;
+    pub fn set_discovered_callback(&self, callback: DiscoveredCallback) {
+        if let Some(state) = self.state() {
+            state.on_discovered.borrow_mut().set(callback);
         }
     }
 
-    fn sender(&self) -> Option<&Sender> {
-        unsafe {
-            (self.ivar(SENDER_IVAR) as *mut Sender).as_ref()
+    /// Starts buffering events instead of sending them. Does nothing if already paused.
+    pub fn pause_events(&self) {
+        if let Some(state) = self.state() {
+            let mut paused = state.paused.borrow_mut();
+            if paused.is_none() {
+                *paused = Some(PausedEvents::new(state.event_overflow));
+            }
         }
     }
 
-    fn set_sender(&mut self, sender: Sender) {
-        unsafe {
-            *self.ivar_mut(SENDER_IVAR) = Box::into_raw(Box::new(sender)) as *mut c_void;
+    /// Sends the buffered events and stops buffering.
+    pub fn resume_events(&self) {
+        if let Some(state) = self.state() {
+            let paused = state.paused.borrow_mut().take();
+            if let Some(mut paused) = paused {
+                paused.flush(&state.shared.metrics, &state.sender, state.event_overflow);
+            }
         }
     }
 
-    fn drop_sender(&mut self) {
-        unsafe {
-            let p = self.ivar_mut(SENDER_IVAR);
-            let _ = Box::<Sender>::from_raw(NonNull::new(*p).unwrap().as_ptr() as *mut Sender);
-            *p = ptr::null_mut();
+    /// Sends `CommandRejected` event with `StaleHandle` reason if the `service` is known to be
+    /// invalidated. Returns `true` if the command was rejected.
+    pub fn reject_stale(&self, peripheral: CBPeripheral, service: Option<CBService>) -> bool {
+        let stale = self.is_stale(service);
+        if stale {
+            self.reject(peripheral, CommandRejectReason::StaleHandle);
         }
+        stale
     }
 
-    pub fn send(&self, event: CentralEvent) {
-        if let Some(sender) = self.sender() {
-            let _ = sender.send_blocking(event);
+    /// Returns `true` if the `service` is known to be invalidated.
+    pub fn is_stale(&self, service: Option<CBService>) -> bool {
+        match (self.state(), service) {
+            (Some(state), Some(service)) =>
+                state.stale_services.contains_key(&(service.as_ptr() as usize)),
+            _ => false,
         }
     }
 
@@ -81,12 +2639,32 @@ impl Delegate {
         peripheral: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
+            let mut connect_duration = Duration::from_secs(0);
+            let mut was_already_connected = false;
+            if let Some(state) = this.state_mut() {
+                let p = peripheral.peripheral.as_ptr() as usize;
+                state.shared.connects.resolve(peripheral.id(), Ok(()));
+                state.shared.connected.lock().unwrap().connected(p, peripheral.clone());
+                let (duration, already) = state.system_connected.connected(p, Instant::now());
+                connect_duration = duration;
+                was_already_connected = already;
+            }
+            let rediscover = this.state_mut()
+                .map(|s| s.rediscoveries.advance(peripheral.peripheral.as_ptr() as usize,
+                    RediscoveryStep::Connect))
+                .unwrap_or(false);
 
             this.send(CentralEvent::PeripheralConnected {
-                peripheral,
+                peripheral: peripheral.clone(),
+                was_already_connected,
+                connect_duration,
             });
+            if rediscover {
+                this.discover_services(*peripheral.peripheral, None);
+            }
+            this.connect_throttled_next(&peripheral);
         }
     }
 
@@ -99,13 +2677,63 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
+            let mut write_sequences = Vec::new();
+            if let Some(state) = this.state_mut() {
+                let p = peripheral.peripheral.as_ptr() as usize;
+                state.stale_services.retain(|_, (sp, _)| *sp != p);
+                state.value_sequences.reset(p);
+                state.value_dedup.reset(p);
+                state.default_write_kinds.reset(p);
+                if !state.keep_user_data_on_disconnect {
+                    state.shared.user_data.lock().unwrap().reset(peripheral.id());
+                }
+                if let Some(link_quality) = state.link_quality.as_mut() {
+                    link_quality.reset(p);
+                }
+                state.read_retries.reset(p);
+                state.subscribe_retries.reset(p);
+                state.read_subscriptions.reset(p);
+                state.descriptor_reads.reset(p);
+                state.cccd_reads.reset(p);
+                state.characteristic_searches.cancel(p);
+                state.pending_subscriptions.retain(|_, (sp, _)| *sp != p);
+                state.auto_rediscoveries.reset(p);
+                state.service_discoveries.reset(p);
+                state.polls.reset(p);
+                state.shared.connected.lock().unwrap().disconnected(p);
+                state.system_connected.reset(p);
+                state.pending_writes.reset(p);
+                write_sequences = state.write_sequences.cancel(p,
+                    &Error::new(ErrorKind::PeripheralDisconnected, "peripheral disconnected"));
+            }
+            this.fail_tree_discovery(peripheral.peripheral.as_ptr() as usize,
+                Error::new(ErrorKind::PeripheralDisconnected, "peripheral disconnected"));
+            #[cfg(feature = "async_std_unstable")]
+            this.fail_waiters(Some(peripheral.peripheral.as_ptr() as usize),
+                Error::new(ErrorKind::PeripheralDisconnected, "peripheral disconnected"));
+            for results in write_sequences {
+                this.send(CentralEvent::WriteSequenceComplete {
+                    peripheral: peripheral.clone(),
+                    results,
+                });
+            }
+            let reconnect = this.state_mut()
+                .map(|s| s.rediscoveries.advance(peripheral.peripheral.as_ptr() as usize,
+                    RediscoveryStep::Disconnect))
+                .unwrap_or(false);
             let error = NSError::wrap_nullable(error).map(Error::from_ns_error);
             this.send(CentralEvent::PeripheralDisconnected {
-                peripheral,
+                peripheral: peripheral.clone(),
                 error,
             });
+            if reconnect {
+                if let Some(manager) = this.manager() {
+                    manager.connect(&peripheral.peripheral, None);
+                }
+            }
+            this.connect_throttled_next(&peripheral);
         }
     }
 
@@ -118,13 +2746,32 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
             let error = NSError::wrap_nullable(error).map(Error::from_ns_error);
+            let failure = error.clone()
+                .unwrap_or_else(|| Error::new(ErrorKind::ConnectionFailed, "connection failed"));
+            let mut rediscover = false;
+            if let Some(state) = this.state_mut() {
+                state.shared.connects.resolve(peripheral.id(), Err(failure.clone()));
+                let p = peripheral.peripheral.as_ptr() as usize;
+                state.system_connected.connect_cancelled(p);
+                rediscover = state.rediscoveries.is_at(p, RediscoveryStep::Connect);
+                if rediscover {
+                    state.rediscoveries.cancel(p);
+                }
+            }
             this.send(CentralEvent::PeripheralConnectFailed {
-                peripheral,
+                peripheral: peripheral.clone(),
                 error,
             });
+            this.connect_throttled_next(&peripheral);
+            if rediscover {
+                this.send(CentralEvent::RediscoveryCompleted {
+                    peripheral,
+                    services: Err(failure),
+                });
+            }
         }
     }
 
@@ -138,26 +2785,64 @@ impl Delegate {
         rssi: *mut Object)
     {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
+            let estimated_interval = this.state_mut()
+                .and_then(|s| s.intervals.as_mut())
+                .and_then(|t| t.observe(peripheral as usize, Instant::now()));
             let peripheral = Peripheral::retain(peripheral);
-            let advertisement_data = AdvertisementData::from_dict(NSDictionary::wrap(advertisement_data));
+            let max_bytes = this.state()
+                .map(|s| s.max_advertisement_bytes)
+                .unwrap_or(DEFAULT_MAX_ADVERTISEMENT_BYTES);
+            let advertisement_data = AdvertisementData::from_dict(NSDictionary::wrap(advertisement_data),
+                max_bytes);
             let rssi = NSNumber::wrap(rssi).get_i32();
 
             peripheral.peripheral.set_delegate(this);
 
+            if let Some(state) = this.state() {
+                state.on_discovered.borrow_mut().discovered(&peripheral, &advertisement_data, rssi);
+            }
+
             this.send(CentralEvent::PeripheralDiscovered {
-                peripheral,
+                peripheral: peripheral.clone(),
                 advertisement_data,
                 rssi,
+                estimated_interval,
             });
+            this.check_link_quality(peripheral, rssi);
         }
     }
 
     #[allow(non_snake_case)]
     extern fn centralManagerDidUpdateState(this: &mut Object, _: Sel, manager: *mut Object) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let new_state = CBCentralManager::wrap(manager).state();
+            if let Some(state) = this.state_mut() {
+                state.shared.manager_state.store(new_state as u8, Ordering::SeqCst);
+                if new_state != ManagerState::PoweredOn {
+                    state.shared.connected.lock().unwrap().clear();
+                    state.shared.throttle.lock().unwrap().clear();
+                    state.system_connected.clear();
+                    state.shared.connects.resolve_all(Error::new(ErrorKind::ManagerNotPoweredOn,
+                        "central manager left PoweredOn state"));
+                }
+                if let Some(options) = state.scan_sessions.state_changed(new_state) {
+                    CBCentralManager::wrap(manager).scan(&options);
+                }
+            }
+            if new_state != ManagerState::PoweredOn {
+                let peripherals: Vec<_> = this.state()
+                    .map(|s| s.tree_discoveries.keys().copied().collect())
+                    .unwrap_or_default();
+                for p in peripherals {
+                    this.fail_tree_discovery(p, Error::new(ErrorKind::ManagerNotPoweredOn,
+                        "central manager left PoweredOn state"));
+                }
+                #[cfg(feature = "async_std_unstable")]
+                this.fail_waiters(None, Error::new(ErrorKind::ManagerNotPoweredOn,
+                    "central manager left PoweredOn state"));
+            }
 
             this.send(CentralEvent::ManagerStateChanged { new_state });
         }
@@ -180,14 +2865,71 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
             let services = result(
                 NSError::wrap_nullable(error), || peripheral.peripheral.services().unwrap());
+            if let (Some(state), Ok(services)) = (this.state_mut(), services.as_ref()) {
+                for service in services {
+                    state.stale_services.remove(&(service.service.as_ptr() as usize));
+                }
+            }
+            let rediscovered = this.state_mut()
+                .map(|s| s.rediscoveries.advance(peripheral.peripheral.as_ptr() as usize,
+                    RediscoveryStep::DiscoverServices))
+                .unwrap_or(false);
+            let rediscovered = if rediscovered {
+                Some(services.clone())
+            } else {
+                None
+            };
+            let origin = this.state_mut()
+                .map(|s| s.service_discoveries.completed(peripheral.peripheral.as_ptr() as usize))
+                .unwrap_or(DiscoveryOrigin::Other);
+            let auto_rediscovered = origin == DiscoveryOrigin::AutoRediscovery && this.state_mut()
+                .map(|s| s.auto_rediscoveries.complete(peripheral.peripheral.as_ptr() as usize))
+                .unwrap_or(false);
+            let auto_rediscovered = if auto_rediscovered {
+                Some(services.clone())
+            } else {
+                None
+            };
+            this.tree_services_discovered(*peripheral.peripheral, &services);
+            let searched = origin == DiscoveryOrigin::Search && this.state()
+                .map(|s| s.characteristic_searches.is_discovering_services(
+                    peripheral.peripheral.as_ptr() as usize))
+                .unwrap_or(false);
+            let searched = if searched {
+                Some(services.clone())
+            } else {
+                None
+            };
+            let cb_peripheral = *peripheral.peripheral;
+            let services = match (services, origin) {
+                (Ok(services), DiscoveryOrigin::Filtered(filter)) => Ok(services.into_iter()
+                    .filter(|s| filter.matches(s.is_primary()))
+                    .collect()),
+                (services, _) => services,
+            };
             this.send(CentralEvent::ServicesDiscovered {
-                peripheral,
+                peripheral: peripheral.clone(),
                 services,
             });
+            if let Some(services) = auto_rediscovered {
+                this.send(CentralEvent::ServicesAutoRediscovered {
+                    peripheral: peripheral.clone(),
+                    services,
+                });
+            }
+            if let Some(services) = rediscovered {
+                this.send(CentralEvent::RediscoveryCompleted {
+                    peripheral,
+                    services,
+                });
+            }
+            if let Some(services) = searched {
+                this.search_services_discovered(cb_peripheral, services);
+            }
         }
     }
 
@@ -222,16 +2964,51 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
+            let pending_subscriptions = this.state_mut()
+                .and_then(|s| s.pending_subscriptions.remove(&(service as usize)))
+                .map(|(_, uuids)| uuids)
+                .unwrap_or_default();
             let peripheral = Peripheral::retain(peripheral);
             let service = Service::retain(service);
             let characteristics = result(
                 NSError::wrap_nullable(error), || service.service.characteristics().unwrap());
+            let subscribe_to = match &characteristics {
+                Ok(chars) if !pending_subscriptions.is_empty() => Some(chars.clone()),
+                _ => None,
+            };
+            this.tree_characteristics_discovered(*peripheral.peripheral, *service.service,
+                &characteristics);
+            let searched = this.state()
+                .map(|s| s.characteristic_searches.awaits(peripheral.peripheral.as_ptr() as usize,
+                    service.service.as_ptr() as usize))
+                .unwrap_or(false);
+            let searched = if searched {
+                Some((*peripheral.peripheral, *service.service, characteristics.clone()))
+            } else {
+                None
+            };
             this.send(CentralEvent::CharacteristicsDiscovered {
-                peripheral,
+                peripheral: peripheral.clone(),
                 service,
                 characteristics,
             });
+            if let Some(chars) = subscribe_to {
+                for uuid in pending_subscriptions {
+                    let found = find_subscribable(uuid, chars.iter()
+                        .map(|c| (c.id(), c.properties().can_notify() || c.properties().can_indicate())));
+                    match found {
+                        Ok(i) => peripheral.peripheral.set_notify_value(*chars[i].characteristic, true),
+                        Err(reason) => this.send(CentralEvent::CommandRejected {
+                            peripheral: peripheral.clone(),
+                            reason,
+                        }),
+                    }
+                }
+            }
+            if let Some((peripheral, service, characteristics)) = searched {
+                this.search_characteristics_discovered(peripheral, service, characteristics);
+            }
         }
     }
 
@@ -244,16 +3021,68 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
             let characteristic = Characteristic::retain(characteristic);
             let descriptors = result(
                 NSError::wrap_nullable(error), || characteristic.characteristic.descriptors().unwrap());
+            let bulk_read = this.state()
+                .map(|s| s.descriptor_reads.is_discovering(characteristic.characteristic.as_ptr() as usize))
+                .unwrap_or(false);
+            let bulk_read = if bulk_read {
+                Some((*peripheral.peripheral, *characteristic.characteristic,
+                    descriptors.clone()))
+            } else {
+                None
+            };
+            let cccd_read = this.state()
+                .map(|s| s.cccd_reads.is_discovering(characteristic.characteristic.as_ptr() as usize))
+                .unwrap_or(false);
+            let cccd_read = if cccd_read {
+                Some((*peripheral.peripheral, *characteristic.characteristic,
+                    descriptors.clone()))
+            } else {
+                None
+            };
+            this.tree_descriptors_discovered(*peripheral.peripheral, *characteristic.characteristic,
+                &descriptors);
             this.send(CentralEvent::DescriptorsDiscovered {
                 peripheral,
                 characteristic,
                 descriptors,
             });
+            match bulk_read {
+                Some((peripheral, characteristic, Ok(descriptors))) => {
+                    this.read_descriptors(peripheral, characteristic, &descriptors);
+                }
+                Some((peripheral, characteristic, Err(error))) => {
+                    if let Some(state) = this.state_mut() {
+                        state.descriptor_reads.cancel(characteristic.as_ptr() as usize);
+                    }
+                    this.send(CentralEvent::AllDescriptorsRead {
+                        peripheral: Peripheral::retain(peripheral),
+                        characteristic: Characteristic::retain(characteristic),
+                        result: Err(error),
+                    });
+                }
+                None => {}
+            }
+            match cccd_read {
+                Some((peripheral, characteristic, Ok(descriptors))) => {
+                    this.read_cccd_of(peripheral, characteristic, &descriptors);
+                }
+                Some((peripheral, characteristic, Err(error))) => {
+                    if let Some(state) = this.state_mut() {
+                        state.cccd_reads.cancel(characteristic.as_ptr() as usize);
+                    }
+                    this.send(CentralEvent::CccdRead {
+                        peripheral: Peripheral::retain(peripheral),
+                        characteristic: Characteristic::retain(characteristic),
+                        result: Err(error),
+                    });
+                }
+                None => {}
+            }
         }
     }
 
@@ -266,16 +3095,65 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
-            let peripheral = Peripheral::retain(peripheral);
-            let characteristic = Characteristic::retain(characteristic);
+            let queue_timestamp = Instant::now();
+            let mut this = Delegate::wrap(this);
             let value = result(NSError::wrap_nullable(error),
-                || characteristic.characteristic.value().unwrap());
-            this.send(CentralEvent::CharacteristicValue {
-                peripheral,
-                characteristic,
-                value,
-            });
+                || CBCharacteristic::wrap(characteristic).value().unwrap());
+            let mut subscribe = false;
+            let stamp = match this.state_mut() {
+                Some(s) => {
+                    s.polls.complete(characteristic as usize);
+                    let transient_error = match &value {
+                        Err(e) => match e.kind() {
+                            ErrorKind::Att(kind) => kind.is_transient(),
+                            _ => false,
+                        },
+                        Ok(_) => false,
+                    };
+                    if s.read_retries.complete(characteristic as usize, transient_error) {
+                        command::RetryRead {
+                            peripheral: CBPeripheral::wrap(peripheral).retain(),
+                            characteristic: CBCharacteristic::wrap(characteristic).retain(),
+                        }.schedule();
+                        return;
+                    }
+                    subscribe = s.read_subscriptions.complete(characteristic as usize);
+                    #[cfg(feature = "async_std_unstable")]
+                    {
+                        if let Ok(value) = &value {
+                            for sender in s.value_waiters.matched(characteristic as usize, value) {
+                                let _ = sender.try_send(Ok(value.clone()));
+                            }
+                        }
+                        for (sender, results) in s.read_sets.complete(characteristic as usize, &value) {
+                            let _ = sender.try_send(results);
+                        }
+                        for sender in s.notification_streams.senders(characteristic as usize) {
+                            if let Err(crate::sync::TrySendError::Full) = sender.try_send(value.clone()) {
+                                warn!("notification stream is full, value dropped");
+                            }
+                        }
+                    }
+                    s.value_received(peripheral as usize, characteristic as usize, &value,
+                        queue_timestamp)
+                }
+                None => Some(ValueStamp {
+                    sequence: 0,
+                    queue_timestamp,
+                }),
+            };
+            if let Some(stamp) = stamp {
+                this.send(CentralEvent::CharacteristicValue {
+                    peripheral: Peripheral::retain(peripheral),
+                    characteristic: Characteristic::retain(characteristic),
+                    value,
+                    sequence: stamp.sequence,
+                    queue_timestamp: stamp.queue_timestamp,
+                });
+            }
+            if subscribe {
+                CBPeripheral::wrap(peripheral).set_notify_value(CBCharacteristic::wrap(characteristic), true);
+            }
         }
     }
 
@@ -288,16 +3166,48 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
+            let characteristic = CBDescriptor::wrap(descriptor).characteristic();
+            let bulk_read_done = match (this.state_mut(), characteristic) {
+                (Some(state), Some(characteristic)) =>
+                    state.descriptor_reads.read(characteristic.as_ptr() as usize, descriptor as usize),
+                _ => false,
+            };
+            let is_cccd = CBDescriptor::wrap(descriptor).id()
+                == known::descriptor::CLIENT_CHARACTERISTIC_CONFIGURATION;
+            let cccd_read = match (this.state_mut(), characteristic) {
+                (Some(state), Some(characteristic)) if is_cccd =>
+                    state.cccd_reads.complete(characteristic.as_ptr() as usize),
+                _ => false,
+            };
             let peripheral = Peripheral::retain(peripheral);
             let descriptor = Descriptor::retain(descriptor);
             let value = result(NSError::wrap_nullable(error),
                 || descriptor.descriptor.value().unwrap());
+            let cccd_state = if cccd_read {
+                Some(value.clone().map(|v| Some(CccdState::parse(&v))))
+            } else {
+                None
+            };
             this.send(CentralEvent::DescriptorValue {
-                peripheral,
+                peripheral: peripheral.clone(),
                 descriptor,
                 value,
             });
+            if let Some(result) = cccd_state {
+                this.send(CentralEvent::CccdRead {
+                    peripheral: peripheral.clone(),
+                    characteristic: Characteristic::retain(characteristic.unwrap()),
+                    result,
+                });
+            }
+            if bulk_read_done {
+                this.send(CentralEvent::AllDescriptorsRead {
+                    peripheral,
+                    characteristic: Characteristic::retain(characteristic.unwrap()),
+                    result: Ok(()),
+                });
+            }
         }
     }
 
@@ -310,15 +3220,27 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
             let characteristic = Characteristic::retain(characteristic);
             let result = result(NSError::wrap_nullable(error), || {});
+            let in_sequence = this.state_mut()
+                .map(|s| {
+                    let c = characteristic.characteristic.as_ptr() as usize;
+                    let id = s.pending_writes.completed(c);
+                    s.write_sequences.complete(peripheral.peripheral.as_ptr() as usize, c, id,
+                        result.clone())
+                })
+                .unwrap_or(false);
+            let cb_peripheral = *peripheral.peripheral;
             this.send(CentralEvent::WriteCharacteristicResult {
                 peripheral,
                 characteristic,
                 result,
             });
+            if in_sequence {
+                this.advance_write_sequence(cb_peripheral);
+            }
         }
     }
 
@@ -350,11 +3272,13 @@ impl Delegate {
         peripheral: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
+            let cb_peripheral = *peripheral.peripheral;
             this.send(CentralEvent::PeripheralIsReadyToWriteWithoutResponse {
                 peripheral,
             });
+            this.advance_write_sequence(cb_peripheral);
         }
     }
 
@@ -367,10 +3291,33 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
+            let result = result(NSError::wrap_nullable(error), || {});
+            if let Some(s) = this.state_mut() {
+                let transient_error = match &result {
+                    Err(e) => e.kind().is_transient(),
+                    Ok(()) => false,
+                };
+                if s.subscribe_retries.complete(characteristic as usize, transient_error) {
+                    command::RetrySubscribe {
+                        peripheral: CBPeripheral::wrap(peripheral).retain(),
+                        characteristic: CBCharacteristic::wrap(characteristic).retain(),
+                    }.schedule();
+                    return;
+                }
+                #[cfg(feature = "async_std_unstable")]
+                {
+                    let failed = s.notification_streams.subscribe_completed(characteristic as usize,
+                        result.is_ok());
+                    if let Err(e) = &result {
+                        for sender in failed {
+                            let _ = sender.try_send(Err(e.clone()));
+                        }
+                    }
+                }
+            }
             let peripheral = Peripheral::retain(peripheral);
             let characteristic = Characteristic::retain(characteristic);
-            let result = result(NSError::wrap_nullable(error), || {});
             this.send(CentralEvent::SubscriptionChangeResult {
                 peripheral,
                 characteristic,
@@ -388,13 +3335,17 @@ impl Delegate {
         error: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
             let rssi = result(NSError::wrap_nullable(error), || NSNumber::wrap(rssi).get_i32());
+            let value = rssi.as_ref().ok().copied();
             this.send(CentralEvent::ReadRssiResult {
-                peripheral,
+                peripheral: peripheral.clone(),
                 rssi,
             });
+            if let Some(rssi) = value {
+                this.check_link_quality(peripheral, rssi);
+            }
         }
     }
 
@@ -423,18 +3374,37 @@ impl Delegate {
         invalidated_services: *mut Object,
     ) {
         unsafe {
-            let this = Delegate::wrap(this);
+            let mut this = Delegate::wrap(this);
             let peripheral = Peripheral::retain(peripheral);
             let services = peripheral.peripheral.services().unwrap();
-            let invalidated_services = NSArray::wrap(invalidated_services)
+            let invalidated_services: Vec<_> = NSArray::wrap(invalidated_services)
                 .iter()
                 .map(|s| Service::retain(s))
                 .collect();
+            let mut rediscover = None;
+            if let Some(state) = this.state_mut() {
+                let p = peripheral.peripheral.as_ptr() as usize;
+                for service in &invalidated_services {
+                    state.stale_services.insert(service.service.as_ptr() as usize,
+                        (p, service.service.clone()));
+                }
+                if state.auto_rediscover_services {
+                    rediscover = state.auto_rediscoveries.start(p,
+                        invalidated_services.iter().map(|s| s.id()));
+                }
+            }
+            let cb_peripheral = *peripheral.peripheral;
             this.send(CentralEvent::ServicesChanged {
                 peripheral,
                 services,
                 invalidated_services,
             });
+            if let Some(uuids) = rediscover {
+                objc::rc::autoreleasepool(|| {
+                    this.discover_services_from(cb_peripheral,
+                        Some(CBUUID::array_from_uuids(&uuids)), DiscoveryOrigin::AutoRediscovery);
+                });
+            }
         }
     }
 
@@ -455,8 +3425,10 @@ lazy_static! {
         decl.add_protocol(Protocol::get("CBCentralManagerDelegate").unwrap());
         decl.add_protocol(Protocol::get("CBPeripheralDelegate").unwrap());
 
+        decl.add_ivar::<*mut c_void>(MANAGER_IVAR);
         decl.add_ivar::<*mut c_void>(QUEUE_IVAR);
-        decl.add_ivar::<*mut c_void>(SENDER_IVAR);
+        decl.add_ivar::<*mut c_void>(STATE_IVAR);
+        decl.add_ivar::<*mut c_void>(SHARED_IVAR);
 
         unsafe {
             type D = Delegate;
@@ -529,3 +3501,1018 @@ lazy_static! {
         decl.register()
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shared(manager_state: ManagerState, event_history: usize) -> Arc<Shared> {
+        Arc::new(Shared {
+            manager_state: AtomicU8::new(manager_state as u8),
+            metrics: Default::default(),
+            connects: Default::default(),
+            connected: Default::default(),
+            throttle: Default::default(),
+            history: Mutex::new(EventHistory::new(event_history)),
+            user_data: Default::default(),
+        })
+    }
+
+    #[test]
+    fn interval_tracker() {
+        let mut t = IntervalTracker::default();
+        let now = Instant::now();
+        assert_eq!(t.observe(1, now), None);
+        assert_eq!(t.observe(2, now), None);
+        assert_eq!(t.observe(1, now + Duration::from_millis(100)), Some(Duration::from_millis(100)));
+        assert_eq!(t.observe(1, now + Duration::from_millis(250)), Some(Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn link_quality() {
+        let mut l = LinkQuality::new(-80, Duration::from_secs(5));
+        let now = Instant::now();
+        let at = |secs| now + Duration::from_secs(secs);
+
+        assert!(!l.observe(1, -70, at(0)));
+        assert!(!l.observe(1, -82, at(1)));
+        assert!(!l.observe(1, -85, at(3)));
+        assert!(!l.observe(2, -90, at(5)));
+        assert!(l.observe(1, -88, at(6)));
+        assert!(!l.observe(1, -91, at(8)));
+
+        // Unavailable readings don't interrupt the window.
+        assert!(!l.observe(2, 127, at(8)));
+        assert!(l.observe(2, -90, at(10)));
+
+        assert!(!l.observe(1, -75, at(9)));
+        assert!(!l.observe(1, -85, at(10)));
+        assert!(l.observe(1, -86, at(15)));
+
+        l.reset(1);
+        assert!(!l.observe(1, -86, at(20)));
+
+        // Lost peripherals are forgotten.
+        assert!(!l.observe(3, -90, at(20)));
+        assert!(!l.observe(1, -86, at(26)));
+        assert_eq!(l.weak_since.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert!(!l.observe(3, -90, at(27)));
+        assert!(l.observe(3, -90, at(32)));
+    }
+
+    #[test]
+    fn default_write_kinds() {
+        let mut k = DefaultWriteKinds::default();
+        assert_eq!(k.get(1), WriteKind::WithResponse);
+
+        k.set(1, WriteKind::WithoutResponse);
+        assert_eq!(k.get(1), WriteKind::WithoutResponse);
+        assert_eq!(k.get(2), WriteKind::WithResponse);
+
+        k.set(1, WriteKind::WithResponse);
+        assert_eq!(k.get(1), WriteKind::WithResponse);
+
+        k.set(1, WriteKind::WithoutResponse);
+        k.reset(1);
+        assert_eq!(k.get(1), WriteKind::WithResponse);
+    }
+
+    #[test]
+    fn scan_sessions() {
+        let mut s = ScanSessions::default();
+        assert!(s.merged().is_none());
+
+        s.begin(1, ScanOptions::default().include_services(&[Uuid::from_u16(0x180d)]));
+        s.begin(2, ScanOptions::default()
+            .include_services(&[Uuid::from_u16(0x180f), Uuid::from_u16(0x180d)])
+            .allow_duplicates(true));
+        let merged = s.merged().unwrap();
+        assert_eq!(merged.services, Some(vec![Uuid::from_u16(0x180d), Uuid::from_u16(0x180f)]));
+        assert!(merged.allow_duplicates);
+
+        assert!(s.end(2));
+        assert!(!s.end(2));
+        let merged = s.merged().unwrap();
+        assert_eq!(merged.services, Some(vec![Uuid::from_u16(0x180d)]));
+        assert!(!merged.allow_duplicates);
+
+        s.begin(3, ScanOptions::default());
+        assert_eq!(s.merged().unwrap().services, None);
+
+        assert!(s.end(1));
+        assert!(s.end(3));
+        assert!(s.merged().is_none());
+    }
+
+    #[test]
+    fn scan_sessions_state_changed() {
+        let mut s = ScanSessions::default();
+        assert!(s.state_changed(ManagerState::PoweredOn).is_none());
+
+        // Begun before PoweredOn.
+        s.begin(1, ScanOptions::default().include_services(&[Uuid::from_u16(0x180d)]));
+        assert!(s.state_changed(ManagerState::Unknown).is_none());
+        assert_eq!(s.state_changed(ManagerState::PoweredOn).unwrap().services,
+            Some(vec![Uuid::from_u16(0x180d)]));
+
+        // Alive across a power cycle.
+        assert!(s.state_changed(ManagerState::PoweredOff).is_none());
+        s.begin(2, ScanOptions::default().include_services(&[Uuid::from_u16(0x180f)]));
+        assert_eq!(s.state_changed(ManagerState::PoweredOn).unwrap().services,
+            Some(vec![Uuid::from_u16(0x180d), Uuid::from_u16(0x180f)]));
+
+        assert!(s.end(1));
+        assert!(s.end(2));
+        assert!(s.state_changed(ManagerState::PoweredOn).is_none());
+    }
+
+    #[test]
+    fn user_data() {
+        let id = |v| PeripheralId::from(Uuid::from_slice(&[0, v]));
+        let mut m = UserDataMap::default();
+        assert!(m.get(id(1)).is_none());
+
+        m.set(id(1), Some(Arc::new(42u32)));
+        m.set(id(2), Some(Arc::new(String::from("sensor"))));
+        assert_eq!(*m.get(id(1)).unwrap().downcast::<u32>().unwrap(), 42);
+        assert!(m.get(id(1)).unwrap().downcast::<String>().is_err());
+        assert_eq!(*m.get(id(2)).unwrap().downcast::<String>().unwrap(), "sensor");
+
+        m.set(id(1), Some(Arc::new(43u32)));
+        assert_eq!(*m.get(id(1)).unwrap().downcast::<u32>().unwrap(), 43);
+
+        m.reset(id(1));
+        assert!(m.get(id(1)).is_none());
+        assert!(m.get(id(2)).is_some());
+
+        m.set(id(2), None);
+        assert!(m.get(id(2)).is_none());
+    }
+
+    #[test]
+    fn user_data_while_event_pending() {
+        let shared = shared(ManagerState::PoweredOn, 0);
+        let (sender, receiver) = crate::central::metrics::event_channel(EventOverflow::Block);
+        let event_thread = std::thread::spawn({
+            let shared = shared.clone();
+            // The channel may have a buffer slot, so the second event blocks if the first doesn't.
+            move || for event in 1..=2 {
+                shared.metrics.send(&sender, EventOverflow::Block, event);
+            }
+        });
+        while shared.metrics.snapshot().pending() == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // The event thread is blocked on the undelivered event, the data must still be
+        // accessible.
+        let id = PeripheralId::from(Uuid::from_slice(&[0, 1]));
+        shared.user_data.lock().unwrap().set(id, Some(Arc::new(42u32)));
+        let data = shared.user_data.lock().unwrap().get(id).unwrap();
+        assert_eq!(*data.downcast::<u32>().unwrap(), 42);
+        assert_eq!(shared.metrics.snapshot().pending(), 1);
+
+        assert_eq!(crate::sync::recv_blocking(&receiver), Some(1));
+        assert_eq!(crate::sync::recv_blocking(&receiver), Some(2));
+        event_thread.join().unwrap();
+    }
+
+    #[test]
+    fn value_sequences() {
+        let mut s = ValueSequences::default();
+        assert_eq!(s.next(1, 10), 1);
+        assert_eq!(s.next(1, 10), 2);
+        assert_eq!(s.next(1, 11), 1);
+        assert_eq!(s.next(2, 20), 1);
+        assert_eq!(s.next(1, 10), 3);
+
+        s.reset(1);
+        assert_eq!(s.next(1, 10), 1);
+        assert_eq!(s.next(1, 11), 1);
+        assert_eq!(s.next(2, 20), 2);
+    }
+
+    #[test]
+    fn value_dedup() {
+        let mut d = ValueDedup::default();
+        assert!(!d.is_duplicate(10, &[1]));
+        assert!(!d.is_duplicate(10, &[1]));
+
+        d.set_enabled(1, 10, true);
+        assert!(!d.is_duplicate(10, &[1]));
+        assert!(d.is_duplicate(10, &[1]));
+        assert!(!d.is_duplicate(10, &[2]));
+        assert!(!d.is_duplicate(10, &[1]));
+        assert!(d.is_duplicate(10, &[1]));
+
+        // Enabling again doesn't forget the last value.
+        d.set_enabled(1, 10, true);
+        assert!(d.is_duplicate(10, &[1]));
+
+        d.set_enabled(1, 10, false);
+        assert!(!d.is_duplicate(10, &[1]));
+
+        d.set_enabled(1, 10, true);
+        assert!(!d.is_duplicate(10, &[1]));
+        d.reset(1);
+        assert!(!d.is_duplicate(10, &[1]));
+    }
+
+    #[test]
+    fn read_retries() {
+        let mut r = Retries::default();
+        assert!(!r.complete(10, true));
+
+        r.start(1, 10, 2);
+        assert!(r.complete(10, true));
+        assert!(r.is_pending(10));
+        assert!(!r.complete(10, false));
+        assert!(!r.is_pending(10));
+
+        r.start(1, 10, 2);
+        assert!(r.complete(10, true));
+        assert!(r.complete(10, true));
+        assert!(!r.complete(10, true));
+        assert!(!r.is_pending(10));
+
+        r.start(1, 10, 1);
+        r.reset(1);
+        assert!(!r.complete(10, true));
+
+        r.start(1, 10, 1);
+        r.start(2, 20, 1);
+        assert!(r.complete(10, true));
+        r.clear();
+        assert!(!r.is_pending(10));
+        assert!(!r.complete(20, true));
+    }
+
+    #[test]
+    fn subscribe_retry() {
+        let busy = Error::new(ErrorKind::Att(AttErrorKind::UnlikelyError), "busy");
+        let denied = Error::new(ErrorKind::Att(AttErrorKind::InsufficientAuthentication), "denied");
+        let mut r = Retries::default();
+
+        // The first attempt fails with a transient error, the retry succeeds.
+        r.start(1, 10, 3);
+        assert!(r.complete(10, busy.kind().is_transient()));
+        assert!(!r.complete(10, false));
+        assert!(!r.is_pending(10));
+
+        // Permanent errors aren't retried.
+        r.start(1, 10, 3);
+        assert!(!r.complete(10, denied.kind().is_transient()));
+        assert!(!r.is_pending(10));
+    }
+
+    #[test]
+    fn read_subscriptions() {
+        let mut r = ReadSubscriptions::default();
+        assert!(!r.complete(10));
+
+        r.start(1, 10);
+        r.start(2, 20);
+        assert!(r.complete(10));
+
+        // Subsequent values don't subscribe again.
+        assert!(!r.complete(10));
+
+        r.reset(2);
+        assert!(!r.complete(20));
+    }
+
+    #[test]
+    fn descriptor_reads() {
+        let mut r = DescriptorReads::default();
+        assert!(!r.is_discovering(10));
+
+        r.start(1, 10);
+        assert!(r.is_discovering(10));
+        assert!(!r.read(10, 100));
+        assert!(!r.discovered(10, vec![100, 101]));
+        assert!(!r.is_discovering(10));
+        assert!(!r.read(10, 100));
+        assert!(!r.read(10, 100));
+        assert!(r.read(10, 101));
+        assert!(!r.read(10, 101));
+
+        r.start(1, 11);
+        assert!(r.discovered(11, vec![]));
+        assert!(!r.is_discovering(11));
+
+        r.start(1, 12);
+        r.start(2, 20);
+        r.cancel(12);
+        assert!(!r.is_discovering(12));
+        r.reset(2);
+        assert!(!r.is_discovering(20));
+    }
+
+    #[test]
+    fn cccd_reads() {
+        let mut r = CccdReads::default();
+        assert!(!r.is_discovering(10));
+        assert!(!r.complete(10));
+
+        r.start(1, 10);
+        assert!(r.is_discovering(10));
+        assert!(!r.complete(10));
+        r.reading(10);
+        assert!(!r.is_discovering(10));
+        assert!(r.complete(10));
+        assert!(!r.complete(10));
+
+        r.start(1, 11);
+        r.cancel(11);
+        assert!(!r.is_discovering(11));
+
+        r.start(2, 20);
+        r.reading(20);
+        r.reset(2);
+        assert!(!r.complete(20));
+    }
+
+    #[test]
+    fn characteristic_searches() {
+        let a = Uuid::from_slice(&[0, 1]);
+        let b = Uuid::from_slice(&[0, 2]);
+        let other = Uuid::from_slice(&[0, 3]);
+
+        let mut s = CharacteristicSearches::default();
+        assert!(!s.is_discovering_services(1));
+        s.start(1, vec![a, b]);
+        assert!(s.is_discovering_services(1));
+        assert!(!s.awaits(1, 10));
+        assert_eq!(s.finish(1), None);
+
+        assert_eq!(s.services_discovered(1, vec![10, 11, 12]), vec![a, b]);
+        assert!(!s.is_discovering_services(1));
+        assert!(s.awaits(1, 10));
+        s.characteristics_discovered(1, 10, vec![(a, 100), (other, 101)]);
+        assert!(!s.awaits(1, 10));
+        s.characteristics_discovered(1, 10, vec![(a, 100)]);
+        s.characteristics_discovered(1, 11, vec![(other, 110)]);
+        assert_eq!(s.finish(1), None);
+        s.characteristics_discovered(1, 12, vec![(b, 120), (a, 121)]);
+        assert_eq!(s.finish(1), Some(vec![100, 120, 121]));
+        assert_eq!(s.finish(1), None);
+
+        s.start(2, vec![a]);
+        assert_eq!(s.services_discovered(2, vec![]), vec![a]);
+        assert_eq!(s.finish(2), Some(vec![]));
+
+        s.start(3, vec![a]);
+        assert!(s.cancel(3));
+        assert!(!s.cancel(3));
+        assert!(!s.is_discovering_services(3));
+    }
+
+    #[test]
+    fn write_too_large() {
+        assert_eq!(super::write_too_large(20, 20), None);
+        assert_eq!(super::write_too_large(0, 20), None);
+        assert_eq!(super::write_too_large(21, 20), Some(CommandRejectReason::WriteTooLarge { len: 21, max: 20 }));
+    }
+
+    #[test]
+    fn find_subscribable() {
+        let a = Uuid::from_slice(&[0, 1]);
+        let b = Uuid::from_slice(&[0, 2]);
+        let c = Uuid::from_slice(&[0, 3]);
+        let chars = vec![(a, false), (b, true)];
+
+        assert_eq!(super::find_subscribable(b, chars.clone()), Ok(1));
+        assert_eq!(super::find_subscribable(a, chars.clone()),
+            Err(CommandRejectReason::NotSubscribable { uuid: a }));
+        assert_eq!(super::find_subscribable(c, chars),
+            Err(CommandRejectReason::NotDiscovered { uuid: c }));
+        assert_eq!(super::find_subscribable(c, vec![]),
+            Err(CommandRejectReason::NotDiscovered { uuid: c }));
+    }
+
+    #[test]
+    fn rediscoveries() {
+        use RediscoveryStep::*;
+
+        let mut r = Rediscoveries::default();
+        assert!(!r.advance(1, Disconnect));
+
+        assert_eq!(r.start(1, false), Disconnect);
+        assert!(!r.advance(1, Connect));
+        assert!(r.advance(1, Disconnect));
+        assert!(r.is_at(1, Connect));
+        assert!(r.advance(1, Connect));
+        assert!(r.is_at(1, DiscoverServices));
+        assert!(r.advance(1, DiscoverServices));
+        assert!(!r.advance(1, DiscoverServices));
+
+        assert_eq!(r.start(2, true), Connect);
+        assert!(r.is_at(2, Connect));
+        r.cancel(2);
+        assert!(!r.advance(2, Connect));
+    }
+
+    #[test]
+    fn write_sequences() {
+        use WriteKind::*;
+
+        fn write(step: Option<WriteStep<&'static str>>) -> Option<(&'static str, WriteKind)> {
+            match step {
+                Some(WriteStep::Write(payload, kind)) => Some((payload, kind)),
+                Some(WriteStep::Complete(_)) => panic!("unexpected completion"),
+                None => None,
+            }
+        }
+
+        fn complete(step: Option<WriteStep<&'static str>>) -> Vec<bool> {
+            match step {
+                Some(WriteStep::Complete(results)) => results.iter().map(|r| r.is_ok()).collect(),
+                _ => panic!("sequence not completed"),
+            }
+        }
+
+        let mut s = WriteSequences::default();
+        s.start(1, vec![(10, "a", WithoutResponse), (11, "b", WithoutResponse), (12, "c", WithResponse),
+            (10, "d", WithResponse)]);
+        s.start(1, vec![(10, "e", WithoutResponse)]);
+
+        assert_eq!(write(s.next(1, true)), Some(("a", WithoutResponse)));
+        assert_eq!(write(s.next(1, true)), None);
+        assert!(s.complete(1, 10, None, Ok(())));
+
+        // Paced until the peripheral is ready.
+        assert_eq!(write(s.next(1, false)), None);
+        assert_eq!(write(s.next(1, true)), Some(("b", WithoutResponse)));
+        assert!(s.complete(1, 11, None, Ok(())));
+
+        // Serialized until the response is received.
+        assert_eq!(write(s.next(1, true)), Some(("c", WithResponse)));
+        s.issued(1, 7);
+        assert_eq!(write(s.next(1, true)), None);
+        assert!(!s.complete(1, 10, Some(7), Ok(())));
+        assert!(!s.complete(2, 12, Some(7), Ok(())));
+        // Result of another write to the same characteristic issued earlier.
+        assert!(!s.complete(1, 12, Some(6), Ok(())));
+        assert!(!s.complete(1, 12, None, Ok(())));
+        assert!(s.complete(1, 12, Some(7), Err(Error::new(ErrorKind::Unknown, ""))));
+        assert_eq!(write(s.next(1, false)), Some(("d", WithResponse)));
+        s.issued(1, 8);
+        assert!(s.complete(1, 10, Some(8), Ok(())));
+
+        assert_eq!(complete(s.next(1, true)), vec![true, true, false, true]);
+
+        // The queued sequence.
+        assert_eq!(write(s.next(1, true)), Some(("e", WithoutResponse)));
+        assert!(s.complete(1, 10, None, Ok(())));
+        assert_eq!(complete(s.next(1, true)), vec![true]);
+        assert!(s.next(1, true).is_none());
+        assert!(s.sequences.is_empty());
+    }
+
+    #[test]
+    fn pending_writes() {
+        let mut w = PendingWrites::default();
+        let a = w.issued(1, 10);
+        let b = w.issued(1, 10);
+        let c = w.issued(1, 11);
+        let d = w.issued(2, 20);
+        assert_eq!(w.completed(10), Some(a));
+        assert_eq!(w.completed(11), Some(c));
+        assert_eq!(w.completed(11), None);
+        assert_eq!(w.completed(10), Some(b));
+
+        let e = w.issued(1, 10);
+        w.reset(1);
+        assert_ne!(e, d);
+        assert_eq!(w.completed(10), None);
+        assert_eq!(w.completed(20), Some(d));
+        assert!(w.writes.is_empty());
+    }
+
+    #[test]
+    fn write_sequences_cancel() {
+        let mut s = WriteSequences::default();
+        s.start(1, vec![(10, (), WriteKind::WithResponse), (11, (), WriteKind::WithResponse)]);
+        s.start(1, vec![(10, (), WriteKind::WithResponse)]);
+        s.start(2, vec![(10, (), WriteKind::WithResponse)]);
+        assert!(s.next(1, true).is_some());
+        s.issued(1, 0);
+        assert!(s.complete(1, 10, Some(0), Ok(())));
+        assert!(s.next(1, true).is_some());
+
+        let error = Error::new(ErrorKind::PeripheralDisconnected, "");
+        let results: Vec<Vec<_>> = s.cancel(1, &error).into_iter()
+            .map(|r| r.into_iter().map(|r| r.map_err(|e| e.kind())).collect())
+            .collect();
+        assert_eq!(results, vec![
+            vec![Ok(()), Err(ErrorKind::PeripheralDisconnected)],
+            vec![Err(ErrorKind::PeripheralDisconnected)],
+        ]);
+        assert!(s.next(1, true).is_none());
+        assert!(s.next(2, true).is_some());
+    }
+
+    #[test]
+    fn paused_events() {
+        let (sender, receiver) = crate::sync::bounded_channel(16);
+        let counters = Counters::default();
+        let recv_all = || {
+            let mut v = Vec::new();
+            while let Some(item) = crate::sync::recv_timeout(&receiver, Duration::from_millis(10)) {
+                v.push(item);
+            }
+            v
+        };
+
+        let overflow = EventOverflow::DropNewest { capacity: 2 };
+        let mut p = PausedEvents::new(overflow);
+        for i in 0..3 {
+            p.send(&counters, &sender, overflow, i);
+        }
+        assert!(recv_all().is_empty());
+        p.flush(&counters, &sender, overflow);
+        assert_eq!(recv_all(), vec![0, 1]);
+        assert_eq!(counters.snapshot().dropped(), 1);
+
+        let overflow = EventOverflow::Block;
+        let mut p = PausedEvents::new(overflow);
+        p.capacity = 2;
+        for i in 0..2 {
+            p.send(&counters, &sender, overflow, i);
+        }
+        assert!(recv_all().is_empty());
+        p.send(&counters, &sender, overflow, 2);
+        assert_eq!(recv_all(), vec![0, 1, 2]);
+        p.send(&counters, &sender, overflow, 3);
+        p.flush(&counters, &sender, overflow);
+        assert_eq!(recv_all(), vec![3]);
+    }
+
+    #[test]
+    fn auto_rediscoveries() {
+        let a = Uuid::from_slice(&[0, 1]);
+        let b = Uuid::from_slice(&[0, 2]);
+
+        let mut r = AutoRediscoveries::default();
+        assert_eq!(r.start(1, vec![]), None);
+        assert!(!r.complete(1));
+
+        assert_eq!(r.start(1, vec![a, a]), Some(vec![a]));
+        assert_eq!(r.start(1, vec![b, a]), Some(vec![a, b]));
+        assert_eq!(r.start(2, vec![b]), Some(vec![b]));
+        // Complete once both started discoveries complete.
+        assert!(!r.complete(1));
+        assert!(r.complete(1));
+        assert!(!r.complete(1));
+        assert_eq!(r.start(1, vec![]), None);
+        assert_eq!(r.start(1, vec![a]), Some(vec![a]));
+        r.reset(1);
+        assert!(!r.complete(1));
+        assert!(r.complete(2));
+    }
+
+    #[test]
+    fn service_discoveries() {
+        use DiscoveryOrigin::*;
+
+        let mut d = ServiceDiscoveries::default();
+        assert_eq!(d.completed(1), Other);
+
+        // A discovery started by the user before the rediscovery completes first.
+        d.started(1, Other);
+        d.started(1, AutoRediscovery);
+        d.started(2, AutoRediscovery);
+        assert_eq!(d.completed(1), Other);
+        assert_eq!(d.completed(1), AutoRediscovery);
+        assert_eq!(d.completed(1), Other);
+
+        // A search doesn't take the result of a discovery started before it.
+        d.started(1, Other);
+        d.started(1, Search);
+        assert_eq!(d.completed(1), Other);
+        assert_eq!(d.completed(1), Search);
+
+        // The filter applies only to the result of its own discovery.
+        d.started(1, AutoRediscovery);
+        d.started(1, Filtered(ServiceFilter::Primary));
+        assert_eq!(d.completed(1), AutoRediscovery);
+        assert_eq!(d.completed(1), Filtered(ServiceFilter::Primary));
+        assert_eq!(d.completed(1), Other);
+
+        d.started(2, Other);
+        d.reset(2);
+        assert_eq!(d.completed(2), Other);
+        assert!(d.pending.is_empty());
+    }
+
+    #[test]
+    fn polls() {
+        let mut p = Polls::default();
+        assert_eq!(p.tick(10, 0), PollTick::Stop);
+
+        let g = p.start(1, 10);
+        assert_eq!(p.tick(10, g), PollTick::Read);
+        assert_eq!(p.tick(10, g), PollTick::Skip);
+        assert_eq!(p.tick(10, g), PollTick::Skip);
+        p.complete(10);
+        assert_eq!(p.tick(10, g), PollTick::Read);
+        p.complete(10);
+
+        let g2 = p.start(1, 10);
+        assert_eq!(p.tick(10, g), PollTick::Stop);
+        assert_eq!(p.tick(10, g2), PollTick::Read);
+
+        let g3 = p.start(2, 20);
+        p.stop(10);
+        assert_eq!(p.tick(10, g2), PollTick::Stop);
+        assert_eq!(p.tick(20, g3), PollTick::Read);
+        p.reset(2);
+        assert_eq!(p.tick(20, g3), PollTick::Stop);
+
+        let g4 = p.start(1, 10);
+        let g5 = p.start(2, 20);
+        p.clear();
+        assert_eq!(p.tick(10, g4), PollTick::Stop);
+        assert_eq!(p.tick(20, g5), PollTick::Stop);
+    }
+
+    #[test]
+    fn connections() {
+        let mut c = Connections::default();
+        c.connected(1, "a");
+        c.connected(2, "b");
+        c.connected(3, "c");
+        c.connected(1, "a");
+        c.disconnected(2);
+        c.disconnected(4);
+        let mut disconnect: Vec<_> = c.iter().copied().collect();
+        disconnect.sort();
+        assert_eq!(disconnect, vec!["a", "c"]);
+
+        // Reconnected.
+        c.connected(2, "b");
+        assert_eq!(c.iter().count(), 3);
+
+        c.clear();
+        assert_eq!(c.iter().count(), 0);
+    }
+
+    #[test]
+    fn system_connections() {
+        let now = Instant::now();
+        let fast = now + ALREADY_CONNECTED_MAX_LATENCY / 2;
+        let slow = now + ALREADY_CONNECTED_MAX_LATENCY * 2;
+
+        let mut c = SystemConnections::default();
+        c.retrieved(vec![(1, ())]);
+        c.connect_started(1, now);
+        c.connect_started(2, now);
+        assert_eq!(c.connected(1, fast), (fast - now, true));
+        assert_eq!(c.connected(2, fast), (fast - now, false));
+
+        // Not connecting.
+        assert_eq!(c.connected(1, fast), (Duration::from_secs(0), false));
+
+        c.retrieved(vec![(1, ())]);
+        c.connect_started(1, now);
+        assert_eq!(c.connected(1, slow), (slow - now, false));
+
+        c.retrieved(vec![(1, ())]);
+        c.reset(1);
+        c.connect_started(1, now);
+        assert_eq!(c.connected(1, fast), (fast - now, false));
+
+        // The clock went backwards.
+        c.connect_started(1, fast);
+        assert_eq!(c.connected(1, now), (Duration::from_secs(0), false));
+
+        // A cancelled attempt doesn't leave its start time to the next one.
+        c.connect_started(2, now);
+        c.connect_cancelled(2);
+        c.connect_started(2, slow);
+        assert_eq!(c.connected(2, slow), (Duration::from_secs(0), false));
+        assert!(c.retrieved.is_empty());
+        assert!(c.connects.is_empty());
+
+        // Retrieved peripherals are forgotten by the next retrieval unless connecting.
+        c.retrieved(vec![(1, ()), (2, ())]);
+        c.connect_started(2, now);
+        c.retrieved(vec![(3, ())]);
+        let mut retrieved: Vec<_> = c.retrieved.keys().copied().collect();
+        retrieved.sort_unstable();
+        assert_eq!(retrieved, vec![2, 3]);
+        assert_eq!(c.connected(2, fast), (fast - now, true));
+        assert_eq!(c.retrieved.keys().copied().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn notification_streams() {
+        use async_std::task::block_on;
+
+        let mut s = NotificationStreams::<ValueSender>::default();
+        let (s1, r1) = crate::sync::bounded_channel(4);
+        let (s2, r2) = crate::sync::bounded_channel(4);
+        let (s3, r3) = crate::sync::bounded_channel(4);
+        assert!(s.add(1, 10, 1, s1, false));
+        assert!(!s.add(1, 10, 2, s2, false));
+        assert!(!s.add(2, 20, 3, s3, true));
+        assert!(s.subscribe_completed(10, true).is_empty());
+        assert!(s.subscribe_completed(10, false).is_empty());
+
+        for sender in s.senders(10) {
+            assert!(sender.try_send(Ok(vec![1, 2])).is_ok());
+        }
+        assert_eq!(block_on(r1.recv()).unwrap().unwrap(), vec![1, 2]);
+        assert_eq!(block_on(r2.recv()).unwrap().unwrap(), vec![1, 2]);
+        assert_eq!(s.senders(11).count(), 0);
+
+        // Unsubscribe only when the last stream is dropped.
+        assert!(!s.remove(10, 1));
+        assert!(!s.remove(10, 1));
+        assert!(block_on(r1.recv()).is_none());
+        assert!(s.remove(10, 2));
+        assert!(block_on(r2.recv()).is_none());
+        assert_eq!(s.senders(10).count(), 0);
+
+        // Don't unsubscribe from the subscription made by the app.
+        let (s4, r4) = crate::sync::bounded_channel(4);
+        assert!(!s.add(2, 40, 4, s4, true));
+        assert!(!s.remove(40, 4));
+        assert!(block_on(r4.recv()).is_none());
+
+        let failed = s.reset(2);
+        assert_eq!(failed.len(), 1);
+        assert!(!s.remove(20, 3));
+        drop(failed);
+        assert!(block_on(r3.recv()).is_none());
+
+        // Failed subscription ends the streams.
+        let (s5, r5) = crate::sync::bounded_channel(4);
+        let (s6, r6) = crate::sync::bounded_channel(4);
+        assert!(s.add(1, 30, 5, s5, false));
+        assert!(!s.add(1, 30, 6, s6, false));
+        let failed = s.subscribe_completed(30, false);
+        assert_eq!(failed.len(), 2);
+        drop(failed);
+        assert!(block_on(r5.recv()).is_none());
+        assert!(block_on(r6.recv()).is_none());
+        assert!(!s.remove(30, 5));
+        assert_eq!(s.senders(30).count(), 0);
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn value_waiters() {
+        let mut w = ValueWaiters::default();
+        assert!(w.matched(10, &[1]).is_empty());
+
+        w.add(1, 10, Box::new(|v| v.first() == Some(&0x81)), "reply");
+        w.add(1, 10, Box::new(|_| true), "any");
+        w.add(2, 20, Box::new(|_| true), "other");
+        assert_eq!(w.matched(10, &[0x01, 0xff]), vec!["any"]);
+        assert!(w.matched(10, &[0x02]).is_empty());
+        assert_eq!(w.matched(10, &[0x81, 0x00]), vec!["reply"]);
+        assert!(w.matched(10, &[0x81]).is_empty());
+
+        w.add(1, 10, Box::new(|_| false), "never");
+        assert_eq!(w.reset(1), vec!["never"]);
+        assert_eq!(w.clear(), vec!["other"]);
+        assert!(w.waiters.is_empty());
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn read_sets() {
+        let value = |v: u8| Ok(vec![v]);
+        let timeout = Error::new(ErrorKind::Timeout, "");
+        let mut s = ReadSets::default();
+
+        let fast = s.start(1, vec![(10, "a"), (11, "b")], "fast");
+        let slow = s.start(1, vec![(11, "b"), (12, "c")], "slow");
+        assert!(s.complete(10, &value(1)).is_empty());
+        let done = s.complete(11, &value(2));
+        assert_eq!(done.len(), 1);
+        let (waiter, results) = &done[0];
+        assert_eq!(*waiter, "fast");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results["a"].as_ref().unwrap(), &[1]);
+        assert_eq!(results["b"].as_ref().unwrap(), &[2]);
+        assert!(s.expire(fast, &timeout).is_none());
+
+        let (waiter, results) = s.expire(slow, &timeout).unwrap();
+        assert_eq!(waiter, "slow");
+        assert_eq!(results["b"].as_ref().unwrap(), &[2]);
+        assert_eq!(results["c"].as_ref().unwrap_err().kind(), ErrorKind::Timeout);
+        assert!(s.complete(12, &value(3)).is_empty());
+
+        s.start(1, vec![(10, "a")], "p1");
+        s.start(2, vec![(20, "a")], "p2");
+        let disconnected = Error::new(ErrorKind::PeripheralDisconnected, "");
+        let done = s.expire_all(Some(1), &disconnected);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].0, "p1");
+        assert_eq!(done[0].1["a"].as_ref().unwrap_err().kind(), ErrorKind::PeripheralDisconnected);
+        assert_eq!(s.expire_all(None, &disconnected).len(), 1);
+        assert!(s.sets.is_empty());
+    }
+
+    #[test]
+    fn tree_discovery() {
+        let mut t = TreeDiscovery::default();
+        assert!(t.finish().is_none());
+        assert!(!t.characteristics_discovered(1, vec![(11, "c11")]));
+
+        t.services_discovered(vec![(1, "s1"), (2, "s2"), (3, "s3")]);
+        assert!(t.characteristics_discovered(1, vec![(11, "c11"), (12, "c12")]));
+        assert!(!t.characteristics_discovered(1, vec![]));
+        assert!(t.characteristics_discovered(3, vec![]));
+        assert!(t.descriptors_discovered(11, vec!["d11a", "d11b"]));
+        assert!(!t.descriptors_discovered(11, vec![]));
+        assert!(!t.descriptors_discovered(21, vec!["d21"]));
+        assert!(t.descriptors_discovered(12, vec![]));
+        assert!(t.finish().is_none());
+
+        assert!(t.characteristics_discovered(2, vec![(21, "c21")]));
+        assert!(t.finish().is_none());
+        assert!(t.descriptors_discovered(21, vec!["d21"]));
+        assert_eq!(t.finish(), Some(vec![
+            ("s1", vec![("c11", vec!["d11a", "d11b"]), ("c12", vec![])]),
+            ("s2", vec![("c21", vec!["d21"])]),
+            ("s3", vec![]),
+        ]));
+    }
+
+    #[test]
+    fn service_filter() {
+        let services = [("a", true), ("b", false), ("c", true)];
+        let filter = |f: ServiceFilter| services.iter()
+            .filter(|(_, primary)| f.matches(*primary))
+            .map(|(s, _)| *s)
+            .collect::<Vec<_>>();
+        assert_eq!(filter(ServiceFilter::Primary), vec!["a", "c"]);
+        assert_eq!(filter(ServiceFilter::Secondary), vec!["b"]);
+    }
+
+    #[test]
+    fn event_filter() {
+        let (sender, receiver) = crate::sync::bounded_channel(8);
+        let mut config = CentralManagerBuilder::new()
+            .event_overflow(EventOverflow::DropNewest { capacity: 8 })
+            .event_filter(|e| !matches!(e,
+                CentralEvent::ManagerStateChanged { new_state: ManagerState::PoweredOff }));
+        let shared = shared(ManagerState::Unknown, 0);
+        let state = State::new(sender, &mut config, shared.clone());
+        for &new_state in &[ManagerState::PoweredOff, ManagerState::PoweredOn, ManagerState::PoweredOff] {
+            state.send(CentralEvent::ManagerStateChanged { new_state });
+        }
+
+        let recv = || crate::sync::recv_timeout(&receiver, Duration::from_millis(10));
+        match recv() {
+            Some(CentralEvent::ManagerStateChanged { new_state: ManagerState::PoweredOn }) => {}
+            e => panic!("{:?}", e),
+        }
+        assert!(recv().is_none());
+        assert_eq!(shared.metrics.snapshot().sent(), 1);
+    }
+
+    #[test]
+    fn on_discovered() {
+        let (sender, _receiver) = crate::sync::bounded_channel(1);
+        let state = State::new(sender, &mut CentralManagerBuilder::new(),
+            shared(ManagerState::PoweredOn, 0));
+        assert!(state.on_discovered.borrow().f.is_none());
+
+        let adv = |name: &str| AdvertisementData {
+            connectable: None,
+            local_name: Some(name.into()),
+            manufacturer_data: None,
+            service_data: ServiceData(HashMap::new()),
+            service_uuids: Vec::new(),
+            solicited_service_uuids: Vec::new(),
+            overflow_service_uuids: Vec::new(),
+            tx_power_level: None,
+            unknown_keys: Vec::new(),
+        };
+        let recorder = || {
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let c = calls.clone();
+            let f: DiscoveredCallback<u32> = Box::new(move |p, adv, rssi| {
+                c.lock().unwrap().push((p, adv.local_name().unwrap().to_owned(), rssi));
+            });
+            (f, calls)
+        };
+
+        let mut on_discovered = OnDiscovered::default();
+        on_discovered.discovered(&1, &adv("a"), -50);
+
+        let (f, first) = recorder();
+        on_discovered.set(f);
+        on_discovered.discovered(&1, &adv("a"), -50);
+        on_discovered.discovered(&2, &adv("b"), -60);
+        on_discovered.discovered(&1, &adv("a"), -55);
+        assert_eq!(*first.lock().unwrap(), vec![
+            (1, "a".to_owned(), -50),
+            (2, "b".to_owned(), -60),
+            (1, "a".to_owned(), -55),
+        ]);
+
+        let (f, second) = recorder();
+        on_discovered.set(f);
+        on_discovered.discovered(&3, &adv("c"), -70);
+        assert_eq!(first.lock().unwrap().len(), 3);
+        assert_eq!(*second.lock().unwrap(), vec![(3, "c".to_owned(), -70)]);
+    }
+
+    #[test]
+    fn value_received() {
+        let (sender, _receiver) = crate::sync::bounded_channel(1);
+        let mut state = State::new(sender, &mut CentralManagerBuilder::new(),
+            shared(ManagerState::PoweredOn, 0));
+        state.value_dedup.set_enabled(1, 10, true);
+        let now = Instant::now();
+        let at = |ms| now + Duration::from_millis(ms);
+
+        assert_eq!(state.value_received(1, 10, &Ok(vec![1]), at(0)),
+            Some(ValueStamp { sequence: 1, queue_timestamp: at(0) }));
+        assert_eq!(state.value_received(1, 10, &Ok(vec![1]), at(5)), None);
+        assert_eq!(state.value_received(1, 10, &Ok(vec![2]), at(10)),
+            Some(ValueStamp { sequence: 2, queue_timestamp: at(10) }));
+        let error = Err(Error::new(ErrorKind::Att(AttErrorKind::UnlikelyError), "error"));
+        assert_eq!(state.value_received(1, 10, &error, at(15)),
+            Some(ValueStamp { sequence: 3, queue_timestamp: at(15) }));
+        assert_eq!(state.value_received(1, 11, &Ok(vec![2]), at(20)),
+            Some(ValueStamp { sequence: 1, queue_timestamp: at(20) }));
+    }
+
+    #[test]
+    fn event_history() {
+        let mut history = EventHistory::new(3);
+        for i in 0..5 {
+            history.push(i.to_string());
+        }
+        assert_eq!(history.to_vec(), vec!["2", "3", "4"]);
+
+        let (sender, _receiver) = crate::sync::bounded_channel(8);
+        let mut config = CentralManagerBuilder::new()
+            .event_overflow(EventOverflow::DropNewest { capacity: 8 });
+        let shared = shared(ManagerState::Unknown, 2);
+        let state = State::new(sender, &mut config, shared.clone());
+        for &new_state in &[ManagerState::PoweredOff, ManagerState::PoweredOn, ManagerState::Resetting] {
+            state.send(CentralEvent::ManagerStateChanged { new_state });
+        }
+        assert_eq!(shared.history.lock().unwrap().to_vec(), vec![
+            "ManagerStateChanged new_state=PoweredOn",
+            "ManagerStateChanged new_state=Resetting",
+        ]);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let (sender, _receiver) = crate::sync::bounded_channel(1);
+        let mut state = State::new(sender, &mut CentralManagerBuilder::new(),
+            shared(ManagerState::PoweredOn, 0));
+        state.polls.start(1, 10);
+        state.polls.start(1, 11);
+        state.read_retries.start(2, 20, 3);
+        state.shared.metrics.record_dropped();
+
+        let d = state.diagnostics(true);
+        assert_eq!(d.manager_state(), ManagerState::PoweredOn);
+        assert!(d.is_scanning());
+        assert!(d.connected().is_empty());
+        assert_eq!(d.polls(), 2);
+        assert_eq!(d.read_retries(), 1);
+        assert_eq!(d.metrics().dropped(), 1);
+        assert_eq!(d.metrics().sent(), 0);
+
+        state.shared.manager_state.store(ManagerState::PoweredOff as u8, Ordering::SeqCst);
+        assert_eq!(state.diagnostics(false).manager_state(), ManagerState::PoweredOff);
+    }
+
+    #[test]
+    fn teardown_summary() {
+        let (sender, _receiver) = crate::sync::bounded_channel(1);
+        let mut state = State::new(sender, &mut CentralManagerBuilder::new(),
+            shared(ManagerState::PoweredOn, 0));
+        let mut connected = Connections::default();
+        connected.connected(1, "a");
+        connected.connected(2, "b");
+        state.polls.start(1, 10);
+        state.read_retries.start(1, 11, 3);
+        state.value_sequences.next(2, 20);
+
+        let summary = state.teardown_summary(&connected);
+        assert_eq!(summary, TeardownSummary {
+            connected: 2,
+            polls: 1,
+            read_retries: 1,
+            ..Default::default()
+        });
+        assert_eq!(summary.to_string(), "2 connected peripherals, 0 throttled connects, 1 polls, \
+            1 read retries, 0 read sets, 0 tree discoveries");
+
+        connected.disconnected(1);
+        assert_eq!(state.teardown_summary(&connected).connected, 1);
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use super::characteristic::CBCharacteristic;
 
 /// An object that provides further information about a remote peripheral’s characteristic.
 ///
@@ -22,6 +23,11 @@ impl Descriptor {
             descriptor,
         }
     }
+
+    /// The Bluetooth-specific UUID of the descriptor.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
 }
 
 object_ptr_wrapper!(CBDescriptor);
@@ -34,11 +40,30 @@ impl CBDescriptor {
         }
     }
 
+    pub fn characteristic(&self) -> Option<CBCharacteristic> {
+        unsafe {
+            let r: *mut Object = msg_send![self.as_ptr(), characteristic];
+            CBCharacteristic::wrap_nullable(r)
+        }
+    }
+
+    /// Returns the descriptor value as bytes. CoreBluetooth reports values of some descriptors
+    /// as `NSNumber` (Client Characteristic Configuration, for example) or `NSString`
+    /// (Characteristic User Description), these are converted to 16-bit little-endian integers
+    /// and UTF-8 respectively.
     pub fn value(&self) -> Option<Vec<u8>> {
         unsafe {
             let r: *mut Object = msg_send![self.as_ptr(), value];
-            let r = NSData::wrap_nullable(r)?;
-            Some(r.as_bytes().into())
+            let r = NonNull::new(r)?;
+            let is_number: bool = msg_send![r.as_ptr(), isKindOfClass:class!(NSNumber)];
+            let is_string: bool = msg_send![r.as_ptr(), isKindOfClass:class!(NSString)];
+            Some(if is_number {
+                (NSNumber::wrap(r).get_i32() as u16).to_le_bytes().to_vec()
+            } else if is_string {
+                NSString::wrap(r).as_str().as_bytes().into()
+            } else {
+                NSData::wrap(r).as_bytes().into()
+            })
         }
     }
 }
\ No newline at end of file
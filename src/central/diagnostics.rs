@@ -0,0 +1,54 @@
+//! Snapshot of a central manager state for bug reports.
+
+use crate::ManagerState;
+
+use super::metrics::Metrics;
+use super::peripheral::{PeripheralId, PeripheralState};
+
+/// Snapshot of the central manager state returned by
+/// [`CentralManager::diagnostics`](../struct.CentralManager.html#method.diagnostics).
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    pub(in super) manager_state: ManagerState,
+    pub(in super) is_scanning: bool,
+    pub(in super) connected: Vec<(PeripheralId, PeripheralState)>,
+    pub(in super) polls: usize,
+    pub(in super) read_retries: usize,
+    pub(in super) metrics: Metrics,
+}
+
+impl Diagnostics {
+    /// State of the central manager.
+    pub fn manager_state(&self) -> ManagerState {
+        self.manager_state
+    }
+
+    /// Whether the central manager is scanning for peripherals.
+    pub fn is_scanning(&self) -> bool {
+        self.is_scanning
+    }
+
+    /// Peripherals this manager connected to and hasn't disconnected from yet, with their current
+    /// states, ordered by identifier.
+    pub fn connected(&self) -> &[(PeripheralId, PeripheralState)] {
+        &self.connected
+    }
+
+    /// Number of characteristics being polled with
+    /// [`Peripheral::start_polling`](../peripheral/struct.Peripheral.html#method.start_polling).
+    pub fn polls(&self) -> usize {
+        self.polls
+    }
+
+    /// Number of reads issued with
+    /// [`Peripheral::read_characteristic_with_retry`](../peripheral/struct.Peripheral.html#method.read_characteristic_with_retry)
+    /// that may still be retried.
+    pub fn read_retries(&self) -> usize {
+        self.read_retries
+    }
+
+    /// Event delivery counters.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+}
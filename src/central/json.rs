@@ -0,0 +1,42 @@
+//! Newline-delimited JSON output of central events, e.g. to pass them to another process.
+
+use std::io;
+
+use super::CentralEvent;
+
+/// Writes the `event` to the `writer` as a single-line JSON object followed by a newline.
+///
+/// The object has the event variant name in the `event` field and the variant fields as
+/// the other fields. Byte values are written as hex strings, results as `{"ok": value}` or
+/// `{"error": error}` objects and errors as objects with `kind` and `message` fields. Peripherals,
+/// services, characteristics and descriptors are written as objects with their identifiers and
+/// other retained information. The `tag` fields are omitted.
+pub fn write_event(mut writer: impl io::Write, event: &CentralEvent) -> io::Result<()> {
+    serde_json::to_writer(&mut writer, event)?;
+    writer.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ManagerState, Tag};
+    use crate::central::peripheral::MaxWriteLen;
+
+    #[test]
+    fn json_lines() {
+        let mut buf = Vec::new();
+        write_event(&mut buf, &CentralEvent::ManagerStateChanged {
+            new_state: ManagerState::PoweredOn,
+        }).unwrap();
+        write_event(&mut buf, &CentralEvent::GetMaxWriteLenResult {
+            max_write_len: MaxWriteLen {
+                with_response: 512,
+                without_response: 20,
+            },
+            tag: Some(Tag::new(42)),
+        }).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+            "{\"event\":\"ManagerStateChanged\",\"new_state\":\"PoweredOn\"}\n\
+            {\"event\":\"GetMaxWriteLenResult\",\"max_write_len\":{\"with_response\":512,\"without_response\":20}}\n");
+    }
+}
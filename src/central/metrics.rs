@@ -0,0 +1,207 @@
+//! Event delivery metrics of a central manager.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::sync::{self, Receiver, Sender, TrySendError};
+
+/// Specifies what the central manager does when the event channel is full.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum EventOverflow {
+    /// Wait until the receiver takes events from the channel. This is the default.
+    ///
+    /// Note the central manager stops processing any other work while waiting, so a stalled
+    /// receiver stalls the manager.
+    #[default]
+    Block,
+
+    /// Like [`Block`](#variant.Block) but the channel buffers a single event and the manager waits
+    /// at most `timeout` for room for each event, then drops it.
+    /// Dropped events are counted in [`Metrics::dropped`](struct.Metrics.html#method.dropped).
+    ///
+    /// This bounds the time a stalled receiver can stall the manager.
+    BlockWithTimeout {
+        timeout: Duration,
+    },
+
+    /// Use channel that buffers up to `capacity` events and drop new events while it's full.
+    /// Dropped events are counted in [`Metrics::dropped`](struct.Metrics.html#method.dropped).
+    DropNewest {
+        capacity: usize,
+    },
+}
+
+/// Snapshot of event delivery counters returned by
+/// [`CentralManager::metrics`](../struct.CentralManager.html#method.metrics).
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Metrics {
+    sent: u64,
+    dropped: u64,
+    pending: u64,
+}
+
+impl Metrics {
+    /// Number of events sent to the channel.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// Number of events dropped because the channel or the buffer of paused events was full.
+    /// Always zero unless [`EventOverflow::DropNewest`](enum.EventOverflow.html#variant.DropNewest)
+    /// or [`EventOverflow::BlockWithTimeout`](enum.EventOverflow.html#variant.BlockWithTimeout)
+    /// policy is used.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Number of events the central manager is currently blocked on, waiting for the receiver to
+    /// take them or make room in the channel. A value that stays non-zero indicates a stalled
+    /// receiver.
+    ///
+    /// This is not the channel depth: events already buffered in the channel (see
+    /// [`EventOverflow::DropNewest`](enum.EventOverflow.html#variant.DropNewest)) aren't counted
+    /// since the channel doesn't report how many items it holds. The value is at most 1 since
+    /// the manager delivers events one at a time.
+    pub fn pending(&self) -> u64 {
+        self.pending
+    }
+}
+
+/// Creates the event channel for the `overflow` policy.
+pub(in crate) fn event_channel<T>(overflow: EventOverflow) -> (Sender<T>, Receiver<T>) {
+    match overflow {
+        EventOverflow::Block => sync::channel(),
+        // The sender waits for room in the buffer rather than for the receiver to be parked
+        // in `recv`, so receivers polling with a timeout don't miss events.
+        EventOverflow::BlockWithTimeout { .. } => sync::bounded_channel(1),
+        EventOverflow::DropNewest { capacity } => sync::bounded_channel(capacity),
+    }
+}
+
+/// Lock-free event delivery counters shared between the delegate and the central manager.
+#[derive(Default)]
+pub(in crate) struct Counters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    pending: AtomicU64,
+}
+
+impl Counters {
+    pub fn snapshot(&self) -> Metrics {
+        Metrics {
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            pending: self.pending.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Counts an event dropped without trying to send it.
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Sends `item` to the channel according to the `overflow` policy, updating the counters.
+    pub fn send<T>(&self, sender: &Sender<T>, overflow: EventOverflow, item: T) {
+        match overflow {
+            EventOverflow::Block => {
+                self.pending.fetch_add(1, Ordering::Relaxed);
+                let sent = sender.send_blocking(item);
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+                if sent {
+                    self.sent.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            EventOverflow::BlockWithTimeout { timeout } => {
+                self.pending.fetch_add(1, Ordering::Relaxed);
+                let r = sender.send_timeout(item, timeout);
+                self.pending.fetch_sub(1, Ordering::Relaxed);
+                match r {
+                    Ok(()) => {
+                        self.sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(TrySendError::Full) => self.record_dropped(),
+                    Err(TrySendError::Disconnected) => {}
+                }
+            }
+            EventOverflow::DropNewest { .. } => {
+                match sender.try_send(item) {
+                    Ok(()) => {
+                        self.sent.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(TrySendError::Full) => self.record_dropped(),
+                    Err(TrySendError::Disconnected) => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drop_newest_counts_dropped() {
+        let overflow = EventOverflow::DropNewest { capacity: 2 };
+        let (sender, receiver) = sync::bounded_channel(2);
+        let counters = Counters::default();
+        for i in 0..5 {
+            counters.send(&sender, overflow, i);
+        }
+
+        let m = counters.snapshot();
+        assert_eq!(m.sent(), 2);
+        assert_eq!(m.dropped(), 3);
+        assert_eq!(m.pending(), 0);
+        drop(receiver);
+    }
+
+    #[test]
+    fn block_with_timeout_drops_when_stalled() {
+        let timeout = Duration::from_millis(20);
+        let overflow = EventOverflow::BlockWithTimeout { timeout };
+        let (sender, receiver) = event_channel(overflow);
+        let counters = Counters::default();
+        counters.send(&sender, overflow, 1);
+
+        let start = std::time::Instant::now();
+        counters.send(&sender, overflow, 2);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= timeout);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+
+        let m = counters.snapshot();
+        assert_eq!(m.sent(), 1);
+        assert_eq!(m.dropped(), 1);
+        assert_eq!(m.pending(), 0);
+        drop(receiver);
+    }
+
+    #[test]
+    fn block_with_timeout_polling_receiver() {
+        let overflow = EventOverflow::BlockWithTimeout { timeout: Duration::from_millis(200) };
+        let (sender, receiver) = event_channel(overflow);
+        let consumer = std::thread::spawn(move || {
+            let deadline = std::time::Instant::now() + Duration::from_secs(3);
+            let mut received = Vec::new();
+            while received.len() < 10 && std::time::Instant::now() < deadline {
+                // Polls without waiting, so it's never parked in a blocking receive.
+                match sync::recv_timeout(&receiver, Duration::from_secs(0)) {
+                    Some(v) => received.push(v),
+                    None => std::thread::sleep(Duration::from_millis(1)),
+                }
+            }
+            received
+        });
+
+        let counters = Counters::default();
+        for i in 0..10 {
+            counters.send(&sender, overflow, i);
+        }
+        assert_eq!(consumer.join().unwrap(), (0..10).collect::<Vec<_>>());
+        let m = counters.snapshot();
+        assert_eq!(m.sent(), 10);
+        assert_eq!(m.dropped(), 0);
+    }
+}
@@ -1,18 +1,115 @@
+#[cfg(feature = "async_std_unstable")]
+use async_std::stream::Stream;
 use objc::*;
 use objc::runtime::Object;
 use static_assertions::assert_impl_all;
+#[cfg(feature = "async_std_unstable")]
+use std::collections::HashMap;
+use std::any::Any;
+use std::fmt;
+#[cfg(feature = "async_std_unstable")]
+use std::pin::Pin;
 use std::ptr::NonNull;
+use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(feature = "async_std_unstable")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "async_std_unstable")]
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use crate::*;
+use crate::error::{Error, ErrorKind};
 use crate::platform::*;
+use crate::sync;
 use crate::uuid::*;
 
+use super::{CentralEvent, CentralManager, ManagerState};
 use super::command;
-use super::delegate::Delegate;
+use super::delegate::{Delegate, ServiceFilter, UserDataMap};
 use super::characteristic::*;
 use super::descriptor::*;
 use super::service::*;
 
+/// Identifier of a [`Peripheral`](struct.Peripheral.html).
+///
+/// The identifier is generated by the system and is specific to the local device: the same
+/// peripheral has different identifiers on different devices. It can be persisted and used later
+/// to retrieve the peripheral with [`get_peripherals`](../struct.CentralManager.html#method.get_peripherals),
+/// after converting it to [`Uuid`](../../uuid/struct.Uuid.html). The identifier may change if the
+/// system forgets the peripheral, for example when the pairing information is reset.
+#[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PeripheralId(Uuid);
+
+assert_impl_all!(PeripheralId: Send, Sync);
+
+impl fmt::Display for PeripheralId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for PeripheralId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PeripheralId({})", self.0)
+    }
+}
+
+impl FromStr for PeripheralId {
+    type Err = UuidParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(Self)
+    }
+}
+
+impl From<Uuid> for PeripheralId {
+    fn from(v: Uuid) -> Self {
+        Self(v)
+    }
+}
+
+impl From<PeripheralId> for Uuid {
+    fn from(v: PeripheralId) -> Self {
+        v.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PeripheralId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PeripheralId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Uuid::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Connection state of a peripheral.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum PeripheralState {
+    Disconnected = 0,
+    Connecting = 1,
+    Connected = 2,
+    Disconnecting = 3,
+}
+
+impl PeripheralState {
+    fn from_ns_integer(v: NSInteger) -> Self {
+        match v {
+            1 => Self::Connecting,
+            2 => Self::Connected,
+            3 => Self::Disconnecting,
+            _ => Self::Disconnected,
+        }
+    }
+}
+
 /// Information about maximum write lengths obtained via
 /// [`get_max_write_len`](struct.Peripheral.html#method.get_max_write_len) method.
 #[derive(Clone, Copy, Debug)]
@@ -33,6 +130,189 @@ impl MaxWriteLen {
     pub fn without_response(&self) -> usize {
         self.without_response
     }
+
+    /// ATT MTU negotiated for the connection.
+    ///
+    /// The maximum length of writes without response is the ATT MTU minus 3 bytes of the ATT
+    /// header (1 byte opcode and 2 bytes attribute handle), so this is derived from it.
+    pub fn att_mtu(&self) -> usize {
+        self.without_response + ATT_HEADER_LEN
+    }
+
+    /// Maximum number of value bytes a single notification or indication from the peripheral can
+    /// carry. Longer values are truncated by the peripheral.
+    ///
+    /// Like writes without response, notifications have the 3 byte ATT header, so this is
+    /// [`att_mtu`](#method.att_mtu) minus 3, which is the same as
+    /// [`without_response`](#method.without_response).
+    pub fn max_notification_payload(&self) -> usize {
+        self.att_mtu() - ATT_HEADER_LEN
+    }
+}
+
+/// Receives items from the `receiver` until `f` returns `Some`. Returns `None` if the channel is
+/// disconnected before that.
+fn wait_for<T, U>(receiver: &sync::Receiver<T>, mut f: impl FnMut(T) -> Option<U>) -> Option<U> {
+    loop {
+        if let Some(v) = f(sync::recv_blocking(receiver)?) {
+            return Some(v);
+        }
+    }
+}
+
+/// Runs `f` with the `peripheral` on the manager queue after all previously issued commands and
+/// blocks until it returns. Returns `None` if the manager has been dropped.
+///
+/// Discovery mutates the attribute tree of the peripheral on the manager queue, so the tree must
+/// be read there too.
+pub(in crate) fn inspect<T: 'static + Send>(peripheral: &StrongPtr<CBPeripheral>,
+    f: impl 'static + Send + FnOnce(CBPeripheral) -> T) -> Option<T>
+{
+    let (done, receiver) = sync::bounded_channel(1);
+    objc::rc::autoreleasepool(|| {
+        command::Inspect {
+            peripheral: peripheral.clone(),
+            f: Box::new(move |peripheral| {
+                let _ = done.send_blocking(f(peripheral));
+            }),
+        }.dispatch();
+    });
+    sync::recv_blocking(&receiver)
+}
+
+/// Length of the header of ATT write command and handle value notification/indication PDUs.
+const ATT_HEADER_LEN: usize = 3;
+
+/// Number of values buffered by a stream returned from `Peripheral::notifications`.
+#[cfg(feature = "async_std_unstable")]
+const NOTIFICATIONS_CAPACITY: usize = 64;
+
+/// Stream returned by `Peripheral::notifications`.
+#[cfg(feature = "async_std_unstable")]
+struct Notifications {
+    peripheral: StrongPtr<CBPeripheral>,
+    characteristic: StrongPtr<CBCharacteristic>,
+    id: u64,
+
+    /// `None` until the stream is polled for the first time.
+    receiver: Option<sync::Receiver<Result<Vec<u8>, Error>>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Notifications {
+    fn new(peripheral: StrongPtr<CBPeripheral>, characteristic: StrongPtr<CBCharacteristic>) -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Self {
+            peripheral,
+            characteristic,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            receiver: None,
+        }
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Stream for Notifications {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.receiver.is_none() {
+            let (sender, receiver) = sync::bounded_channel(NOTIFICATIONS_CAPACITY);
+            objc::rc::autoreleasepool(|| {
+                command::AddNotificationStream {
+                    peripheral: this.peripheral.clone(),
+                    characteristic: this.characteristic.clone(),
+                    id: this.id,
+                    sender,
+                }.dispatch();
+            });
+            this.receiver = Some(receiver);
+        }
+        Pin::new(this.receiver.as_mut().unwrap()).poll_next(cx)
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl Drop for Notifications {
+    fn drop(&mut self) {
+        if self.receiver.is_some() {
+            objc::rc::autoreleasepool(|| {
+                command::RemoveNotificationStream {
+                    peripheral: self.peripheral.clone(),
+                    characteristic: self.characteristic.clone(),
+                    id: self.id,
+                }.dispatch();
+            });
+        }
+    }
+}
+
+/// Error returned by [`Peripheral::sample`](struct.Peripheral.html#method.sample) with
+/// the values collected before the error.
+#[cfg(feature = "async_std_unstable")]
+#[derive(Clone, Debug)]
+pub struct SampleError {
+    error: Error,
+    values: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl SampleError {
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// Values collected before the error, oldest first.
+    pub fn values(&self) -> &[Vec<u8>] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Vec<u8>> {
+        self.values
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (after {} values)", self.error, self.values.len())
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl std::error::Error for SampleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[cfg(feature = "async_std_unstable")]
+impl From<SampleError> for Error {
+    fn from(v: SampleError) -> Self {
+        v.error
+    }
+}
+
+/// Takes `count` values from the `values` stream, then drops the stream.
+#[cfg(feature = "async_std_unstable")]
+async fn sample<S>(mut values: S, count: usize) -> Result<Vec<Vec<u8>>, SampleError>
+    where S: Stream<Item=Result<Vec<u8>, Error>> + Unpin
+{
+    use async_std::stream::StreamExt;
+
+    let mut r = Vec::with_capacity(count);
+    while r.len() < count {
+        match values.next().await {
+            Some(Ok(v)) => r.push(v),
+            Some(Err(error)) => return Err(SampleError { error, values: r }),
+            None => return Err(SampleError {
+                error: Error::new(ErrorKind::OperationCancelled, "central manager dropped"),
+                values: r,
+            }),
+        }
+    }
+    Ok(r)
 }
 
 /// A remote peripheral device.
@@ -52,9 +332,14 @@ impl MaxWriteLen {
 /// and another characteristic could transmit the heart rate measurement data. Finally,
 /// characteristics contain any number of descriptors that provide more information about the
 /// characteristic’s value, such as a human-readable description and a way to format the value.
+///
+/// A `Peripheral` can outlive the [central manager](../struct.CentralManager.html) it was obtained
+/// from. Once the manager is dropped the peripheral methods do nothing except logging a warning,
+/// and the methods returning a result fail with
+/// [`OperationCancelled`](../../error/enum.ErrorKind.html#variant.OperationCancelled) error.
 #[derive(Clone, Debug)]
 pub struct Peripheral {
-    id: Uuid,
+    id: PeripheralId,
     pub(in crate) peripheral: StrongPtr<CBPeripheral>,
 }
 
@@ -64,16 +349,139 @@ impl Peripheral {
     pub(in crate) unsafe fn retain(o: impl ObjectPtr) -> Self {
         let peripheral = CBPeripheral::wrap(o).retain();
         Self {
-            id: peripheral.id(),
+            id: PeripheralId(peripheral.id()),
             peripheral,
         }
     }
 
     /// Peripheral identifier.
-    pub fn id(&self) -> Uuid {
+    pub fn id(&self) -> PeripheralId {
         self.id
     }
 
+    /// Returns reference to the `characteristic` that can be resolved after the peripheral is
+    /// reconnected, or `None` if the characteristic doesn't belong to this peripheral or its
+    /// service is no longer discovered.
+    ///
+    /// The discovered characteristics are looked up on the manager queue after all previously
+    /// issued commands. This method blocks until then and must not be called from
+    /// [`event_filter`](../struct.CentralManagerBuilder.html#method.event_filter) or
+    /// [`on_discovered`](../struct.CentralManager.html#method.on_discovered) callbacks, nor from
+    /// the thread receiving events when the manager may be blocked delivering one under
+    /// [`EventOverflow::Block`](../metrics/enum.EventOverflow.html#variant.Block) policy.
+    pub fn characteristic_ref(&self, characteristic: &Characteristic) -> Option<CharacteristicRef> {
+        objc::rc::autoreleasepool(|| {
+            let service_uuid = characteristic.service_id()?;
+            let characteristic_uuid = characteristic.id();
+            let instance_index = instances(self.discovered_characteristics(), service_uuid,
+                characteristic_uuid)
+                .position(|c| c.characteristic.as_ptr() == characteristic.characteristic.as_ptr())?;
+            Some(CharacteristicRef {
+                peripheral_id: self.id,
+                service_uuid,
+                characteristic_uuid,
+                instance_index,
+            })
+        })
+    }
+
+    /// Resolves the characteristic reference created by
+    /// [`characteristic_ref`](struct.Peripheral.html#method.characteristic_ref).
+    ///
+    /// The lookup is done among the services and characteristics discovered so far, so after a
+    /// reconnect the referenced characteristic must be rediscovered first. Returns `None` if the
+    /// reference is for another peripheral or the characteristic hasn't been discovered.
+    ///
+    /// Blocks like [`characteristic_ref`](struct.Peripheral.html#method.characteristic_ref).
+    pub fn resolve(&self, characteristic: &CharacteristicRef) -> Option<Characteristic> {
+        if characteristic.peripheral_id != self.id {
+            return None;
+        }
+        objc::rc::autoreleasepool(|| {
+            instances(self.discovered_characteristics(), characteristic.service_uuid,
+                characteristic.characteristic_uuid)
+                .nth(characteristic.instance_index)
+        })
+    }
+
+    /// Returns `true` if a characteristic with the `characteristic_uuid` has been discovered in
+    /// a service with the `service_uuid`.
+    ///
+    /// Only the services and characteristics discovered so far are considered, so this returns
+    /// `false` until they're discovered with
+    /// [`discover_services`](struct.Peripheral.html#method.discover_services) and
+    /// [`discover_characteristics`](struct.Peripheral.html#method.discover_characteristics).
+    /// Blocks like [`characteristic_ref`](struct.Peripheral.html#method.characteristic_ref).
+    pub fn supports_characteristic(&self, service_uuid: Uuid, characteristic_uuid: Uuid) -> bool {
+        objc::rc::autoreleasepool(|| {
+            supports(self.discovered_characteristics(), service_uuid, characteristic_uuid)
+        })
+    }
+
+    /// Returns the first discovered service with the `uuid`, or `None` if there's none.
+    ///
+    /// 16- and 32-bit UUIDs match their 128-bit forms. Only the services discovered so far are
+    /// considered, so this returns `None` until they're discovered with
+    /// [`discover_services`](struct.Peripheral.html#method.discover_services).
+    /// Blocks like [`characteristic_ref`](struct.Peripheral.html#method.characteristic_ref).
+    pub fn service(&self, uuid: Uuid) -> Option<Service> {
+        self.services_matching(&[uuid]).into_iter().next()
+    }
+
+    /// Returns the discovered services with any of the `uuids` in discovery order.
+    ///
+    /// 16- and 32-bit UUIDs match their 128-bit forms. Only the services discovered so far are
+    /// considered, so this returns empty vector until they're discovered with
+    /// [`discover_services`](struct.Peripheral.html#method.discover_services).
+    ///
+    /// The services are read on the manager queue, so this blocks like
+    /// [`characteristic_ref`](struct.Peripheral.html#method.characteristic_ref).
+    pub fn services_matching(&self, uuids: &[Uuid]) -> Vec<Service> {
+        let services = inspect(&self.peripheral, |peripheral| {
+            peripheral.services().unwrap_or_default().into_iter()
+                .map(|s| (s.id(), s))
+                .collect()
+        }).unwrap_or_default();
+        super::service::matching(services, uuids)
+    }
+
+    /// Renders the discovered services, characteristics with their properties and descriptors as
+    /// indented human-readable text for debugging, for example:
+    ///
+    /// ```text
+    /// Peripheral ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6
+    ///   Service 180d (primary)
+    ///     Characteristic 2a37 [notify]
+    ///       Descriptor 2902
+    /// ```
+    ///
+    /// Attributes that haven't been discovered yet are marked as such and the text ends with
+    /// a note that discovery is incomplete. The format isn't stable.
+    ///
+    /// The tree is collected on the manager queue, so this blocks like
+    /// [`characteristic_ref`](struct.Peripheral.html#method.characteristic_ref).
+    pub fn debug_tree(&self) -> String {
+        use super::tree::{DebugCharacteristic, DebugService};
+
+        let services = inspect(&self.peripheral, |peripheral| {
+            peripheral.services().map(|services| services.into_iter()
+                .map(|s| DebugService {
+                    id: s.id(),
+                    is_primary: s.is_primary(),
+                    characteristics: s.service.characteristics().map(|chars| chars.into_iter()
+                        .map(|c| DebugCharacteristic {
+                            id: c.id(),
+                            properties: *c.properties(),
+                            descriptors: c.characteristic.descriptors()
+                                .map(|v| v.iter().map(|d| d.id()).collect()),
+                        })
+                        .collect()),
+                })
+                .collect::<Vec<_>>())
+        }).flatten();
+        super::tree::render_debug_tree(self.id, services.as_deref())
+    }
+
     /// Discovers all available services of the peripheral.
     ///
     /// See [`discover_services_with_uuids`](struct.Peripheral.html#method.discover_services_with_uuids).
@@ -81,6 +489,163 @@ impl Peripheral {
         self.discover_services_with_uuids0(None);
     }
 
+    /// Discovers all services of the peripheral like
+    /// [`discover_services`](struct.Peripheral.html#method.discover_services) does, but reports
+    /// only the [primary](../service/struct.Service.html#method.is_primary) ones in the
+    /// following [`ServicesDiscovered`](../enum.CentralEvent.html#variant.ServicesDiscovered) event.
+    pub fn discover_primary_services(&self) {
+        self.discover_services0(None, Some(ServiceFilter::Primary));
+    }
+
+    /// Discovers all services of the peripheral like
+    /// [`discover_services`](struct.Peripheral.html#method.discover_services) does, but reports
+    /// only the secondary ones in the following
+    /// [`ServicesDiscovered`](../enum.CentralEvent.html#variant.ServicesDiscovered) event.
+    pub fn discover_secondary_services(&self) {
+        self.discover_services0(None, Some(ServiceFilter::Secondary));
+    }
+
+    /// Disconnects the peripheral, connects it again and discovers all its services.
+    ///
+    /// This is a workaround for peripherals that change their GATT database without indicating
+    /// the change, leaving stale attributes in the system cache. It doesn't guarantee the cache is
+    /// flushed since Core Bluetooth has no way to do it explicitly.
+    ///
+    /// The usual connection and discovery events are triggered along the way followed by
+    /// [`RediscoveryCompleted`](../enum.CentralEvent.html#variant.RediscoveryCompleted) event.
+    pub fn force_rediscover(&self, central: &CentralManager) {
+        objc::rc::autoreleasepool(|| {
+            command::ForceRediscover {
+                manager: central.0.manager.clone(),
+                peripheral: self.peripheral.clone(),
+            }.dispatch();
+        })
+    }
+
+    /// Discovers all services of the peripheral, their characteristics and descriptors and returns
+    /// them as a single tree.
+    ///
+    /// The usual discovery events are triggered along the way. The future resolves with error if
+    /// any of the discoveries fails, the peripheral disconnects or the central manager leaves
+    /// [`PoweredOn`](../../enum.ManagerState.html#variant.PoweredOn) state. Concurrent calls for
+    /// the same peripheral share a single discovery.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn discover_tree_async(&self)
+        -> impl std::future::Future<Output=Result<super::tree::DeviceTree, crate::error::Error>>
+    {
+        let (sender, receiver) = crate::sync::bounded_channel(1);
+        objc::rc::autoreleasepool(|| {
+            command::DiscoverTree {
+                peripheral: self.peripheral.clone(),
+                sender,
+            }.dispatch();
+        });
+        async move {
+            receiver.recv().await.unwrap_or_else(|| Err(crate::error::Error::new(
+                crate::error::ErrorKind::OperationCancelled, "central manager dropped")))
+        }
+    }
+
+    /// Returns future that resolves with the next value of the `characteristic` for which
+    /// the `predicate` returns `true`, for example a reply to a request written to another
+    /// characteristic.
+    ///
+    /// Values come from notifications, indications and reads issued by the app; this method
+    /// doesn't read or subscribe to the characteristic itself. Call it before issuing the request
+    /// to not miss the reply. The values are still delivered in
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) events.
+    /// The future resolves with error if the peripheral disconnects or the manager leaves the
+    /// [`PoweredOn`](../../enum.ManagerState.html#variant.PoweredOn) state. It doesn't time out.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn next_value_where(&self, characteristic: &Characteristic,
+        predicate: impl Fn(&[u8]) -> bool + Send + 'static)
+        -> impl std::future::Future<Output=Result<Vec<u8>, Error>>
+    {
+        let (sender, receiver) = crate::sync::bounded_channel(1);
+        objc::rc::autoreleasepool(|| {
+            command::WaitForValue {
+                peripheral: self.peripheral.clone(),
+                characteristic: characteristic.characteristic.clone(),
+                predicate: Box::new(predicate),
+                sender,
+            }.dispatch();
+        });
+        async move {
+            receiver.recv().await.unwrap_or_else(|| Err(Error::new(ErrorKind::OperationCancelled,
+                "central manager dropped")))
+        }
+    }
+
+    /// Returns stream of values of the `characteristic`.
+    ///
+    /// The stream subscribes to the characteristic when it's polled for the first time, unless
+    /// the characteristic is already notifying, and unsubscribes when the last stream of the
+    /// characteristic is dropped. Values come from notifications, indications and reads issued by
+    /// the app and are still delivered in
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) events.
+    /// Up to 64 values are buffered, further values are dropped with a warning logged until
+    /// the stream is polled.
+    ///
+    /// If the peripheral disconnects or the manager leaves the
+    /// [`PoweredOn`](../../enum.ManagerState.html#variant.PoweredOn) state, the stream yields
+    /// the corresponding error and ends.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn notifications(&self, characteristic: &Characteristic)
+        -> impl Stream<Item=Result<Vec<u8>, Error>>
+    {
+        Notifications::new(self.peripheral.clone(), characteristic.characteristic.clone())
+    }
+
+    /// Returns future that subscribes to the `characteristic`, collects the next `count` of its
+    /// values and unsubscribes, like taking `count` values from the
+    /// [`notifications`](#method.notifications) stream.
+    ///
+    /// If the peripheral disconnects or the manager leaves the
+    /// [`PoweredOn`](../../enum.ManagerState.html#variant.PoweredOn) state before `count` values
+    /// are received, the future resolves with error that holds the values received so far.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn sample(&self, characteristic: &Characteristic, count: usize)
+        -> impl std::future::Future<Output=Result<Vec<Vec<u8>>, SampleError>>
+    {
+        sample(Notifications::new(self.peripheral.clone(), characteristic.characteristic.clone()),
+            count)
+    }
+
+    /// Reads values of the `characteristics` and returns future that resolves with all of the
+    /// results once all reads complete or the `deadline` elapses, whichever comes first.
+    ///
+    /// The results are keyed by [references](../characteristic/struct.CharacteristicRef.html) to
+    /// the characteristics. Reads that haven't completed by the deadline have
+    /// [`Timeout`](../../error/enum.ErrorKind.html#variant.Timeout) error as the result.
+    /// Characteristics that don't belong to this peripheral are skipped. Each read also triggers
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) event as if
+    /// [`read_characteristic`](struct.Peripheral.html#method.read_characteristic) was called.
+    ///
+    /// If the peripheral disconnects or the manager leaves the
+    /// [`PoweredOn`](../../enum.ManagerState.html#variant.PoweredOn) state, the pending reads
+    /// fail with the corresponding error.
+    #[cfg(feature = "async_std_unstable")]
+    pub fn read_set(&self, characteristics: &[Characteristic], deadline: Duration)
+        -> impl std::future::Future<Output=Result<HashMap<CharacteristicRef, Result<Vec<u8>, Error>>, Error>>
+    {
+        let (sender, receiver) = crate::sync::bounded_channel(1);
+        objc::rc::autoreleasepool(|| {
+            let characteristics = characteristics.iter()
+                .filter_map(|c| Some((self.characteristic_ref(c)?, c.characteristic.clone())))
+                .collect();
+            command::ReadSet {
+                peripheral: self.peripheral.clone(),
+                characteristics,
+                deadline,
+                sender,
+            }.dispatch();
+        });
+        async move {
+            receiver.recv().await.ok_or_else(|| Error::new(ErrorKind::OperationCancelled,
+                "central manager dropped"))
+        }
+    }
+
     /// Discovers peripheral's services with the specified `uuids`.
     ///
     /// When the peripheral discovers one or more services, it triggers
@@ -123,6 +688,45 @@ impl Peripheral {
         self.discover_characteristics0(service, Some(uuids));
     }
 
+    /// Discovers all services of the peripheral, then discovers characteristics with the specified
+    /// UUIDs in each of them. This is useful to find characteristics regardless of the services
+    /// they belong to.
+    ///
+    /// The peripheral triggers the usual
+    /// [`ServicesDiscovered`](../enum.CentralEvent.html#variant.ServicesDiscovered) and
+    /// [`CharacteristicsDiscovered`](../enum.CentralEvent.html#variant.CharacteristicsDiscovered)
+    /// events followed by
+    /// [`CharacteristicsFound`](../enum.CentralEvent.html#variant.CharacteristicsFound) event
+    /// with the characteristics found in all services. Calling this again before the event restarts
+    /// the search.
+    pub fn find_characteristics(&self, uuids: &[Uuid]) {
+        objc::rc::autoreleasepool(|| {
+            command::FindCharacteristics {
+                peripheral: self.peripheral.clone(),
+                uuids: uuids.to_vec(),
+            }.dispatch();
+        })
+    }
+
+    /// Discovers the characteristic with the specified UUID of a service and subscribes to it.
+    ///
+    /// The peripheral triggers [`CharacteristicsDiscovered`](../enum.CentralEvent.html#variant.CharacteristicsDiscovered)
+    /// event and, if the discovery succeeded, then proceeds as with
+    /// [`subscribe`](struct.Peripheral.html#method.subscribe) method. If the characteristic isn't
+    /// found or doesn't support notifications or indications,
+    /// [`CommandRejected`](../enum.CentralEvent.html#variant.CommandRejected) event is triggered
+    /// instead.
+    pub fn discover_and_subscribe(&self, service: &Service, characteristic_uuid: Uuid) {
+        objc::rc::autoreleasepool(|| {
+            command::DiscoverAndSubscribe {
+                peripheral: self.peripheral.clone(),
+                service: service.service.clone(),
+                uuids: CBUUID::array_from_uuids(&[characteristic_uuid]).retain(),
+                uuid: characteristic_uuid,
+            }.dispatch();
+        })
+    }
+
     /// Discovers the descriptors of a characteristic.
     ///
     /// When the peripheral discovers one or more descriptors, it triggers
@@ -135,6 +739,34 @@ impl Peripheral {
         })
     }
 
+    /// Reads values of all descriptors of a characteristic, discovering the descriptors first if
+    /// they haven't been discovered yet.
+    ///
+    /// The peripheral triggers [`DescriptorValue`](../enum.CentralEvent.html#variant.DescriptorValue)
+    /// event for every descriptor followed by
+    /// [`AllDescriptorsRead`](../enum.CentralEvent.html#variant.AllDescriptorsRead) event.
+    pub fn read_all_descriptors(&self, characteristic: &Characteristic) {
+        objc::rc::autoreleasepool(|| {
+            self.characteristic_cmd(characteristic)
+                .read_all_descriptors();
+        })
+    }
+
+    /// Reads the Client Characteristic Configuration descriptor of a characteristic, discovering
+    /// the descriptors first if they haven't been discovered yet. This allows verifying that
+    /// notifications or indications are actually enabled on the peripheral.
+    ///
+    /// The peripheral triggers [`CccdRead`](../enum.CentralEvent.html#variant.CccdRead) event
+    /// with the state parsed from the descriptor value, or with `None` if the characteristic
+    /// has no such descriptor. [`DescriptorValue`](../enum.CentralEvent.html#variant.DescriptorValue)
+    /// event is triggered for the descriptor as usual.
+    pub fn read_cccd(&self, characteristic: &Characteristic) {
+        objc::rc::autoreleasepool(|| {
+            self.characteristic_cmd(characteristic)
+                .read_cccd();
+        })
+    }
+
     /// Subscribes to notifications or indications of the value of a specified characteristic.
     ///
     /// After calling this method the peripheral triggers
@@ -155,6 +787,39 @@ impl Peripheral {
         })
     }
 
+    /// Same as [`subscribe`](struct.Peripheral.html#method.subscribe) but re-issues the
+    /// subscription up to `max_retries` times if it fails with a
+    /// [transient](../../error/enum.ErrorKind.html#method.is_transient) error, for example
+    /// because the peripheral is busy right after connection.
+    ///
+    /// Retries are issued after a short delay. Only the final result triggers
+    /// [`SubscriptionChangeResult`](../enum.CentralEvent.html#variant.SubscriptionChangeResult)
+    /// event.
+    pub fn subscribe_with_retry(&self, characteristic: &Characteristic, max_retries: u32) {
+        objc::rc::autoreleasepool(|| {
+            command::SubscribeWithRetry {
+                peripheral: self.peripheral.clone(),
+                characteristic: characteristic.characteristic.clone(),
+                max_retries,
+            }.dispatch();
+        })
+    }
+
+    /// Subscribes to the characteristic unless it's already notifying.
+    ///
+    /// Core Bluetooth may restore the notification state of a characteristic, for example after
+    /// reconnection. This method checks whether the characteristic is notifying and only calls
+    /// [`subscribe`](struct.Peripheral.html#method.subscribe) if it's not. If the characteristic is
+    /// already notifying this method is a no-op and no
+    /// [`SubscriptionChangeResult`](../enum.CentralEvent.html#variant.SubscriptionChangeResult)
+    /// event is triggered.
+    pub fn ensure_subscribed(&self, characteristic: &Characteristic) {
+        objc::rc::autoreleasepool(|| {
+            self.characteristic_cmd(characteristic)
+                .ensure_subscribed();
+        })
+    }
+
     /// Cancel subscription for characteristic value created by
     /// [`subscribe`](struct.Peripheral.html#method.subscribe) method.
     pub fn unsubscribe(&self, characteristic: &Characteristic) {
@@ -164,6 +829,26 @@ impl Peripheral {
         })
     }
 
+    /// Subscribes to all the `characteristics` at once.
+    ///
+    /// This is equivalent to calling [`subscribe`](struct.Peripheral.html#method.subscribe) for
+    /// each characteristic but dispatches a single command. Each subscription triggers its own
+    /// [`SubscriptionChangeResult`](../enum.CentralEvent.html#variant.SubscriptionChangeResult) event.
+    /// Characteristics that support neither notifications nor indications are skipped and
+    /// [`CommandRejected`](../enum.CentralEvent.html#variant.CommandRejected) event with
+    /// [`NotSubscribable`](../enum.CommandRejectReason.html#variant.NotSubscribable) reason is
+    /// triggered for each of them.
+    pub fn subscribe_all(&self, characteristics: &[Characteristic]) {
+        self.set_notify_all(characteristics, true);
+    }
+
+    /// Unsubscribes from all the `characteristics` at once.
+    ///
+    /// See [`subscribe_all`](struct.Peripheral.html#method.subscribe_all).
+    pub fn unsubscribe_all(&self, characteristics: &[Characteristic]) {
+        self.set_notify_all(characteristics, false);
+    }
+
     /// Retrieves the value of a specified characteristic.
     ///
     /// After calling this method the peripheral triggers
@@ -179,6 +864,108 @@ impl Peripheral {
         })
     }
 
+    /// Same as [`read_characteristic`](struct.Peripheral.html#method.read_characteristic) but
+    /// checks the characteristic is [readable](../characteristic/struct.Properties.html#method.can_read)
+    /// first.
+    ///
+    /// If it isn't, nothing is sent to the peripheral and
+    /// [`CommandRejected`](../enum.CentralEvent.html#variant.CommandRejected) event with
+    /// [`NotReadable`](../enum.CommandRejectReason.html#variant.NotReadable) reason is triggered
+    /// instead of the ATT error the peripheral would respond with.
+    pub fn read_characteristic_checked(&self, characteristic: &Characteristic) {
+        match check_readable(characteristic.properties(), characteristic.id()) {
+            Ok(()) => self.read_characteristic(characteristic),
+            Err(reason) => objc::rc::autoreleasepool(|| {
+                command::Reject {
+                    peripheral: self.peripheral.clone(),
+                    reason,
+                }.dispatch();
+            }),
+        }
+    }
+
+    /// Reads the value of the characteristic and subscribes to it once the read completes, for
+    /// characteristics that notify only when the value changes.
+    ///
+    /// This triggers [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue)
+    /// event with the initial value, followed by
+    /// [`SubscriptionChangeResult`](../enum.CentralEvent.html#variant.SubscriptionChangeResult) event.
+    /// The subscription is requested even if the read fails.
+    pub fn read_then_subscribe(&self, characteristic: &Characteristic) {
+        objc::rc::autoreleasepool(|| {
+            self.characteristic_cmd(characteristic)
+                .read_then_subscribe();
+        })
+    }
+
+    /// Same as [`read_characteristic`](struct.Peripheral.html#method.read_characteristic) but
+    /// re-issues the read up to `max_retries` times if it fails with a
+    /// [transient](../../error/enum.AttErrorKind.html#method.is_transient) ATT error.
+    ///
+    /// Retries are issued after a short delay. Only the final result triggers
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) event.
+    pub fn read_characteristic_with_retry(&self, characteristic: &Characteristic, max_retries: u32) {
+        objc::rc::autoreleasepool(|| {
+            command::ReadWithRetry {
+                peripheral: self.peripheral.clone(),
+                characteristic: characteristic.characteristic.clone(),
+                max_retries,
+            }.dispatch();
+        })
+    }
+
+    /// Starts reading the value of the characteristic periodically, for characteristics that
+    /// don't support notifications.
+    ///
+    /// The first read is issued immediately and then every `interval`. Each read triggers
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) event as if
+    /// [`read_characteristic`](struct.Peripheral.html#method.read_characteristic) was called.
+    /// If the previous read hasn't completed by the time of the next one, that read is skipped so
+    /// a slow peripheral isn't flooded with requests.
+    ///
+    /// Calling this method for a characteristic that's already polled restarts polling with the
+    /// new `interval`. Polling stops when [`stop_polling`](struct.Peripheral.html#method.stop_polling)
+    /// is called or the peripheral disconnects.
+    pub fn start_polling(&self, characteristic: &Characteristic, interval: Duration) {
+        objc::rc::autoreleasepool(|| {
+            command::StartPolling {
+                peripheral: self.peripheral.clone(),
+                characteristic: characteristic.characteristic.clone(),
+                interval,
+            }.dispatch();
+        })
+    }
+
+    /// Stops polling started by [`start_polling`](struct.Peripheral.html#method.start_polling).
+    /// A read already in flight still triggers its
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) event.
+    pub fn stop_polling(&self, characteristic: &Characteristic) {
+        objc::rc::autoreleasepool(|| {
+            self.characteristic_cmd(characteristic)
+                .stop_polling();
+        })
+    }
+
+    /// Enables or disables suppression of identical consecutive values of the characteristic.
+    ///
+    /// When enabled, a successful
+    /// [`CharacteristicValue`](../enum.CentralEvent.html#variant.CharacteristicValue) event is
+    /// not triggered if its value is identical to the previous value received for the
+    /// characteristic. This is useful for peripherals that repeatedly notify the same value.
+    /// Suppressed values aren't counted in the event `sequence`, and errors are always delivered.
+    ///
+    /// Deduplication is disabled by default and gets disabled when the peripheral disconnects.
+    pub fn set_deduplicate_values(&self, characteristic: &Characteristic, enabled: bool) {
+        objc::rc::autoreleasepool(|| {
+            let cmd = self.characteristic_cmd(characteristic);
+            if enabled {
+                cmd.enable_value_dedup();
+            } else {
+                cmd.disable_value_dedup();
+            }
+        })
+    }
+
     /// Writes the value of a characteristic.
     ///
     /// When you call this method to write the value of a characteristic, the peripheral triggers
@@ -199,7 +986,187 @@ impl Peripheral {
             command::WriteCharacteristic {
                 peripheral: self.peripheral.clone(),
                 characteristic: characteristic.characteristic.clone(),
-                value: NSData::from_bytes(value).retain(),
+                value: command::WriteValue::new(value),
+                kind: Some(kind),
+                rejected: None,
+            }.dispatch();
+        })
+    }
+
+    /// Writes the value of a characteristic using the default write kind of the peripheral set by
+    /// [`set_default_write_kind`](struct.Peripheral.html#method.set_default_write_kind), or
+    /// [`WithResponse`](../characteristic/enum.WriteKind.html#variant.WithResponse) if none is set.
+    ///
+    /// See [`write_characteristic`](struct.Peripheral.html#method.write_characteristic) for
+    /// details.
+    pub fn write(&self, characteristic: &Characteristic, value: &[u8]) {
+        objc::rc::autoreleasepool(|| {
+            command::WriteCharacteristic {
+                peripheral: self.peripheral.clone(),
+                characteristic: characteristic.characteristic.clone(),
+                value: command::WriteValue::new(value),
+                kind: None,
+                rejected: None,
+            }.dispatch();
+        })
+    }
+
+    /// Sets the write kind used by [`write`](struct.Peripheral.html#method.write). This is useful
+    /// for peripherals that support only one kind of writes.
+    ///
+    /// The default write kind gets reset to
+    /// [`WithResponse`](../characteristic/enum.WriteKind.html#variant.WithResponse) when the
+    /// peripheral disconnects.
+    pub fn set_default_write_kind(&self, kind: WriteKind) {
+        objc::rc::autoreleasepool(|| {
+            command::SetDefaultWriteKind {
+                peripheral: self.peripheral.clone(),
+                kind,
+            }.dispatch();
+        })
+    }
+
+    /// Attaches `data` to this peripheral, replacing the previously attached data. The data can
+    /// be retrieved with [`user_data`](#method.user_data) from any handle of the peripheral, for
+    /// example the one received in an event.
+    ///
+    /// The data is removed when the peripheral disconnects, unless configured otherwise with
+    /// [`CentralManagerBuilder::keep_user_data_on_disconnect`](../struct.CentralManagerBuilder.html#method.keep_user_data_on_disconnect).
+    /// Does nothing if the central manager has been dropped.
+    ///
+    /// The data is stored right away without going through the manager queue, so this method
+    /// doesn't block and can be called from any thread.
+    pub fn set_user_data(&self, data: impl Any + Send + Sync) {
+        self.with_user_data(|m| m.set(self.id, Some(Arc::new(data))));
+    }
+
+    /// Removes data attached with [`set_user_data`](#method.set_user_data).
+    pub fn remove_user_data(&self) {
+        self.with_user_data(|m| m.set(self.id, None));
+    }
+
+    /// Returns data attached with [`set_user_data`](#method.set_user_data), or `None` if there's
+    /// no data, it's not of type `T` or the central manager has been dropped.
+    ///
+    /// Like [`set_user_data`](#method.set_user_data) this doesn't block and can be called from
+    /// any thread, including event callbacks.
+    pub fn user_data<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.with_user_data(|m| m.get(self.id))??.downcast().ok()
+    }
+
+    fn with_user_data<T>(&self, f: impl FnOnce(&mut UserDataMap) -> T) -> Option<T> {
+        let shared = self.peripheral.delegate().shared()?;
+        let mut user_data = shared.user_data.lock().unwrap();
+        Some(f(&mut user_data))
+    }
+
+    /// Writes the value of a characteristic with response and blocks until the result is
+    /// received.
+    ///
+    /// The `receiver` must be the one returned along with the central manager. Events received
+    /// while waiting, other than the
+    /// [`WriteCharacteristicResult`](../enum.CentralEvent.html#variant.WriteCharacteristicResult)
+    /// event of this write, are passed to `other_events`.
+    ///
+    /// Returns error if `kind` is [`WithoutResponse`](../characteristic/enum.WriteKind.html#variant.WithoutResponse)
+    /// since there's no result to wait for, if the write is rejected with
+    /// [`CommandRejected`](../enum.CentralEvent.html#variant.CommandRejected) event, or if the
+    /// peripheral disconnects or the manager leaves the
+    /// [`PoweredOn`](../../enum.ManagerState.html#variant.PoweredOn) state before the result is
+    /// received.
+    ///
+    /// This method blocks the current thread and is meant for simple command line tools.
+    pub fn write_characteristic_blocking(&self, receiver: &sync::Receiver<CentralEvent>,
+        characteristic: &Characteristic, value: &[u8], kind: WriteKind,
+        mut other_events: impl FnMut(CentralEvent)) -> Result<(), Error>
+    {
+        if kind != WriteKind::WithResponse {
+            return Err(Error::new(ErrorKind::OperationNotSupported,
+                "write without response has no result to wait for"));
+        }
+        // The reason of rejection arrives here before the `CommandRejected` event, which tells it
+        // from rejections of other commands for this peripheral.
+        let (rejected, rejection) = sync::bounded_channel(1);
+        objc::rc::autoreleasepool(|| {
+            command::WriteCharacteristic {
+                peripheral: self.peripheral.clone(),
+                characteristic: characteristic.characteristic.clone(),
+                value: command::WriteValue::new(value),
+                kind: Some(kind),
+                rejected: Some(rejected),
+            }.dispatch();
+        });
+        let target = characteristic.characteristic.as_ptr();
+        wait_for(receiver, |event| match event {
+            CentralEvent::WriteCharacteristicResult { ref peripheral, ref characteristic, ref result }
+                if peripheral == self && characteristic.characteristic.as_ptr() == target =>
+            {
+                Some(result.clone())
+            }
+            CentralEvent::CommandRejected { ref peripheral, .. } if peripheral == self => {
+                other_events(event);
+                sync::try_recv(&rejection).map(|reason| Err(Error::new(ErrorKind::InvalidParameters,
+                    format!("write rejected: {:?}", reason))))
+            }
+            CentralEvent::PeripheralDisconnected { ref peripheral, .. } if peripheral == self => {
+                other_events(event);
+                Some(Err(Error::new(ErrorKind::PeripheralDisconnected, "peripheral disconnected")))
+            }
+            CentralEvent::ManagerStateChanged { new_state } if new_state != ManagerState::PoweredOn => {
+                other_events(event);
+                Some(Err(Error::new(ErrorKind::ManagerNotPoweredOn,
+                    "central manager left PoweredOn state")))
+            }
+            _ => {
+                other_events(event);
+                None
+            }
+        }).unwrap_or_else(|| Err(Error::new(ErrorKind::OperationCancelled, "central manager dropped")))
+    }
+
+    /// Writes values of several characteristics in sequence.
+    ///
+    /// Writes of [`WithResponse`](../characteristic/enum.WriteKind.html#variant.WithResponse) kind
+    /// are serialized: the next write is issued only after the result of the previous one is
+    /// received. Writes of [`WithoutResponse`](../characteristic/enum.WriteKind.html#variant.WithoutResponse)
+    /// kind are paced so they're issued only when the peripheral is ready to send them, to avoid
+    /// the writes being dropped.
+    ///
+    /// The individual writes trigger the same events as with
+    /// [`write_characteristic`](struct.Peripheral.html#method.write_characteristic) method. After
+    /// all the writes, [`WriteSequenceComplete`](../enum.CentralEvent.html#variant.WriteSequenceComplete)
+    /// event is triggered. If this method is called while a previous write sequence is in
+    /// progress, the new sequence starts after the previous one completes.
+    pub fn write_sequence(&self, writes: Vec<(Characteristic, Vec<u8>, WriteKind)>) {
+        objc::rc::autoreleasepool(|| {
+            command::WriteSequence {
+                peripheral: self.peripheral.clone(),
+                writes: writes.into_iter()
+                    .map(|(c, value, kind)| (c.characteristic, command::WriteValue::new(&value), kind))
+                    .collect(),
+            }.dispatch();
+        })
+    }
+
+    /// Writes the value of a characteristic identified by its UUID and the UUID of its service.
+    ///
+    /// The characteristic is looked up among the currently discovered services and characteristics
+    /// of the peripheral, so it's not necessary to hold a
+    /// [`Characteristic`](../characteristic/struct.Characteristic.html) that may become invalid
+    /// after reconnection. If there's no such characteristic,
+    /// [`CommandRejected`](../enum.CentralEvent.html#variant.CommandRejected) event is triggered
+    /// with [`NotDiscovered`](../enum.CommandRejectReason.html#variant.NotDiscovered) reason.
+    /// Otherwise the value is written as with
+    /// [`write_characteristic`](struct.Peripheral.html#method.write_characteristic) method.
+    pub fn write_by_uuid(&self, service_uuid: Uuid, characteristic_uuid: Uuid, value: &[u8],
+        kind: WriteKind)
+    {
+        objc::rc::autoreleasepool(|| {
+            command::WriteByUuid {
+                peripheral: self.peripheral.clone(),
+                service_uuid,
+                characteristic_uuid,
+                value: command::WriteValue::new(value),
                 kind,
             }.dispatch();
         })
@@ -266,12 +1233,49 @@ impl Peripheral {
         })
     }
 
+    fn discovered_characteristics(&self) -> Vec<(Uuid, Vec<(Uuid, Characteristic)>)> {
+        inspect(&self.peripheral, |peripheral| {
+            peripheral.services().unwrap_or_default().into_iter()
+                .map(|s| {
+                    let chars = s.service.characteristics().unwrap_or_default().into_iter()
+                        .map(|c| (c.id(), c))
+                        .collect();
+                    (s.id(), chars)
+                })
+                .collect()
+        }).unwrap_or_default()
+    }
+
+    fn set_notify_all(&self, characteristics: &[Characteristic], enabled: bool) {
+        let mut valid = Vec::with_capacity(characteristics.len());
+        let mut rejected = Vec::new();
+        for c in characteristics {
+            match check_subscribable(c.properties(), c.id()) {
+                Ok(()) => valid.push(c.characteristic.clone()),
+                Err(reason) => rejected.push(reason),
+            }
+        }
+        objc::rc::autoreleasepool(|| {
+            command::SetNotifyAll {
+                peripheral: self.peripheral.clone(),
+                characteristics: valid,
+                rejected,
+                enabled,
+            }.dispatch();
+        })
+    }
+
     fn discover_services_with_uuids0(&self, uuids: Option<&[Uuid]>) {
+        self.discover_services0(uuids, None);
+    }
+
+    fn discover_services0(&self, uuids: Option<&[Uuid]>, filter: Option<ServiceFilter>) {
         objc::rc::autoreleasepool(|| {
             let uuids = uuids.map(CBUUID::array_from_uuids).map(|v| v.retain());
             command::DiscoverServices {
                 peripheral: self.peripheral.clone(),
                 uuids,
+                filter,
             }.dispatch();
         })
     }
@@ -316,7 +1320,7 @@ impl Eq for Peripheral {}
 
 impl std::hash::Hash for Peripheral {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        state.write(&self.id)
+        state.write(&self.id.0)
     }
 }
 
@@ -376,6 +1380,17 @@ impl CBPeripheral {
 
     }
 
+    pub fn state(&self) -> PeripheralState {
+        unsafe {
+            let r: NSInteger = msg_send![self.as_ptr(), state];
+            PeripheralState::from_ns_integer(r)
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.state() == PeripheralState::Disconnected
+    }
+
     pub fn discover_services(&self, uuids: Option<NSArray>) {
         unsafe {
             let _: () = msg_send![self.as_ptr(), discoverServices:uuids.as_ptr()];
@@ -437,6 +1452,13 @@ impl CBPeripheral {
         }
     }
 
+    pub fn can_send_write_without_response(&self) -> bool {
+        unsafe {
+            let r: bool = msg_send![self.as_ptr(), canSendWriteWithoutResponse];
+            r
+        }
+    }
+
     pub fn max_write_len(&self, kind: WriteKind) -> usize {
         unsafe {
             let ty = kind as NSUInteger;
@@ -444,4 +1466,126 @@ impl CBPeripheral {
             r
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::central::CommandRejectReason;
+    use static_assertions::assert_type_ne_all;
+
+    assert_type_ne_all!(PeripheralId, Uuid);
+
+    #[test]
+    fn peripheral_id_round_trip() {
+        let s = "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6";
+        let id: PeripheralId = s.parse().unwrap();
+        assert_eq!(id.to_string(), s);
+        assert_eq!(format!("{:?}", id), format!("PeripheralId({})", s));
+        assert!("foo".parse::<PeripheralId>().is_err());
+
+        let uuid: Uuid = id.into();
+        assert_eq!(uuid, s.parse().unwrap());
+        assert_eq!(PeripheralId::from(uuid), id);
+    }
+
+    #[test]
+    fn wait_for() {
+        let (sender, receiver) = sync::bounded_channel(8);
+        for v in 1..=4 {
+            assert!(sender.send_blocking(v));
+        }
+        let mut other = Vec::new();
+        let r = super::wait_for(&receiver, |v| if v == 3 {
+            Some("done")
+        } else {
+            other.push(v);
+            None
+        });
+        assert_eq!(r, Some("done"));
+        assert_eq!(other, vec![1, 2]);
+
+        drop(sender);
+        assert_eq!(super::wait_for(&receiver, |_| Some(())), Some(()));
+        assert_eq!(super::wait_for(&receiver, |_| Some(())), None);
+    }
+
+    #[test]
+    fn try_recv() {
+        let (sender, receiver) = sync::bounded_channel(1);
+        assert_eq!(sync::try_recv(&receiver), None);
+        assert!(sender.send_blocking(CommandRejectReason::StaleHandle));
+        assert_eq!(sync::try_recv(&receiver), Some(CommandRejectReason::StaleHandle));
+        assert_eq!(sync::try_recv(&receiver), None);
+    }
+
+    #[cfg(feature = "async_std_unstable")]
+    #[test]
+    fn sample() {
+        use async_std::task::block_on;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        /// Stands for `Notifications`, which unsubscribes on drop.
+        struct Values {
+            values: Vec<Result<Vec<u8>, Error>>,
+            dropped: Arc<AtomicBool>,
+        }
+
+        impl Stream for Values {
+            type Item = Result<Vec<u8>, Error>;
+
+            fn poll_next(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<Self::Item>> {
+                let values = &mut self.get_mut().values;
+                Poll::Ready(if values.is_empty() { None } else { Some(values.remove(0)) })
+            }
+        }
+
+        impl Drop for Values {
+            fn drop(&mut self) {
+                self.dropped.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let values = |values: Vec<Result<Vec<u8>, Error>>| {
+            let dropped = Arc::new(AtomicBool::new(false));
+            (Values { values, dropped: dropped.clone() }, dropped)
+        };
+
+        let (v, dropped) = values((1..=4).map(|i| Ok(vec![i])).collect());
+        assert_eq!(block_on(super::sample(v, 3)).unwrap(), vec![vec![1], vec![2], vec![3]]);
+        assert!(dropped.load(Ordering::SeqCst));
+
+        let (v, dropped) = values(vec![Ok(vec![1]), Ok(vec![2]),
+            Err(Error::new(ErrorKind::PeripheralDisconnected, "disconnected"))]);
+        let e = block_on(super::sample(v, 3)).unwrap_err();
+        assert_eq!(e.error().kind(), ErrorKind::PeripheralDisconnected);
+        assert_eq!(e.values(), &[vec![1], vec![2]]);
+        assert!(dropped.load(Ordering::SeqCst));
+
+        let (v, _) = values(vec![Ok(vec![1])]);
+        let e = block_on(super::sample(v, 3)).unwrap_err();
+        assert_eq!(e.error().kind(), ErrorKind::OperationCancelled);
+        assert_eq!(e.into_values(), vec![vec![1]]);
+    }
+
+    #[test]
+    fn max_notification_payload() {
+        let l = MaxWriteLen {
+            with_response: 512,
+            without_response: 182,
+        };
+        assert_eq!(l.att_mtu(), 185);
+        assert_eq!(l.max_notification_payload(), l.att_mtu() - 3);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn peripheral_id_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        let id: PeripheralId = "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap();
+        assert_tokens(&id, &[Token::Str("ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6")]);
+        assert_de_tokens_error::<PeripheralId>(&[Token::Str("foo")], "invalid UUID string");
+    }
+}
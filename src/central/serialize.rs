@@ -0,0 +1,443 @@
+//! `Serialize` implementations for central events and their parts, and `Serialize` and
+//! `Deserialize` implementations for the session descriptor.
+//!
+//! Byte values are serialized as hex strings, results as `{"ok": value}` or `{"error": error}`
+//! objects and errors as objects with `kind` and `message` fields. Tags and timestamps aren't
+//! serializable and are omitted.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, MapAccess};
+use serde::ser::{SerializeMap, SerializeSeq};
+use std::fmt;
+
+use super::*;
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Hex<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+struct Outcome<'a, T>(Result<T, &'a Error>);
+
+impl<T: Serialize> Serialize for Outcome<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(1))?;
+        match &self.0 {
+            Ok(v) => m.serialize_entry("ok", v)?,
+            Err(e) => m.serialize_entry("error", e)?,
+        }
+        m.end()
+    }
+}
+
+fn outcome<'a, T, U>(result: &'a Result<T, Error>, f: impl FnOnce(&'a T) -> U) -> Outcome<'a, U> {
+    Outcome(result.as_ref().map(f))
+}
+
+fn bytes(result: &Result<Vec<u8>, Error>) -> Outcome<'_, Hex<'_>> {
+    outcome(result, |v| Hex(v))
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(2))?;
+        m.serialize_entry("kind", &format!("{:?}", self.kind()))?;
+        m.serialize_entry("message", &self.to_string())?;
+        m.end()
+    }
+}
+
+impl Serialize for Peripheral {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(1))?;
+        m.serialize_entry("id", &self.id())?;
+        m.end()
+    }
+}
+
+impl Serialize for Service {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(3))?;
+        m.serialize_entry("id", &self.id())?;
+        m.serialize_entry("primary", &self.is_primary())?;
+        m.serialize_entry("peripheral_id", &self.peripheral_id())?;
+        m.end()
+    }
+}
+
+impl Serialize for Characteristic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(3))?;
+        m.serialize_entry("id", &self.id())?;
+        m.serialize_entry("service_id", &self.service_id())?;
+        m.serialize_entry("properties", self.properties())?;
+        m.end()
+    }
+}
+
+impl Serialize for characteristic::Properties {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let props = [
+            ("broadcast", self.can_broadcast()),
+            ("read", self.can_read()),
+            ("write_without_response", self.can_write_without_response()),
+            ("write", self.can_write()),
+            ("notify", self.can_notify()),
+            ("indicate", self.can_indicate()),
+            ("authenticated_signed_writes", self.supports_authenticated_signed_writes()),
+            ("extended_properties", self.has_extended_properties()),
+            ("notify_encryption_required", self.is_notify_encryption_required()),
+            ("indicate_encryption_required", self.is_indicate_encryption_required()),
+        ];
+        let mut seq = serializer.serialize_seq(None)?;
+        for (name, _) in props.iter().filter(|(_, set)| *set) {
+            seq.serialize_element(name)?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for CccdState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(2))?;
+        m.serialize_entry("notify", &self.notify)?;
+        m.serialize_entry("indicate", &self.indicate)?;
+        m.end()
+    }
+}
+
+impl Serialize for Descriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(1))?;
+        m.serialize_entry("id", &self.id())?;
+        m.end()
+    }
+}
+
+impl Serialize for AdvertisementData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(9))?;
+        m.serialize_entry("connectable", &self.connectable)?;
+        m.serialize_entry("local_name", &self.local_name)?;
+        m.serialize_entry("manufacturer_data", &self.manufacturer_data.as_ref().map(|v| Hex(v)))?;
+        m.serialize_entry("service_data", &self.service_data)?;
+        m.serialize_entry("service_uuids", &self.service_uuids)?;
+        m.serialize_entry("solicited_service_uuids", &self.solicited_service_uuids)?;
+        m.serialize_entry("overflow_service_uuids", &self.overflow_service_uuids)?;
+        m.serialize_entry("tx_power_level", &self.tx_power_level)?;
+        m.serialize_entry("unknown_keys", &self.unknown_keys.iter()
+            .map(|(k, v)| (k, Hex(v)))
+            .collect::<Vec<_>>())?;
+        m.end()
+    }
+}
+
+impl Serialize for ServiceData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter().map(|(k, v)| (k, Hex(v))))
+    }
+}
+
+impl Serialize for MaxWriteLen {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(2))?;
+        m.serialize_entry("with_response", &self.with_response())?;
+        m.serialize_entry("without_response", &self.without_response())?;
+        m.end()
+    }
+}
+
+impl Serialize for Metrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(3))?;
+        m.serialize_entry("sent", &self.sent())?;
+        m.serialize_entry("dropped", &self.dropped())?;
+        m.serialize_entry("pending", &self.pending())?;
+        m.end()
+    }
+}
+
+struct ConnectedPeripheral(PeripheralId, PeripheralState);
+
+impl Serialize for ConnectedPeripheral {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(2))?;
+        m.serialize_entry("id", &self.0)?;
+        m.serialize_entry("state", &format!("{:?}", self.1))?;
+        m.end()
+    }
+}
+
+impl Serialize for Diagnostics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(6))?;
+        m.serialize_entry("manager_state", &format!("{:?}", self.manager_state()))?;
+        m.serialize_entry("is_scanning", &self.is_scanning())?;
+        m.serialize_entry("connected", &self.connected().iter()
+            .map(|&(id, state)| ConnectedPeripheral(id, state))
+            .collect::<Vec<_>>())?;
+        m.serialize_entry("polls", &self.polls())?;
+        m.serialize_entry("read_retries", &self.read_retries())?;
+        m.serialize_entry("metrics", &self.metrics())?;
+        m.end()
+    }
+}
+
+impl Serialize for SessionDescriptor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(Some(3))?;
+        m.serialize_entry("scan_services", &self.scan_services)?;
+        m.serialize_entry("scan_allow_duplicates", &self.scan_allow_duplicates)?;
+        m.serialize_entry("reconnect_peripherals", &self.reconnect_peripherals)?;
+        m.end()
+    }
+}
+
+/// Deserializes the descriptor from an object with the fields of `Serialize` output. Unknown
+/// fields are ignored.
+impl<'de> Deserialize<'de> for SessionDescriptor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SessionDescriptor;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("session descriptor object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut scan_services = None;
+                let mut scan_allow_duplicates = None;
+                let mut reconnect_peripherals = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "scan_services" => scan_services = Some(map.next_value()?),
+                        "scan_allow_duplicates" => scan_allow_duplicates = Some(map.next_value()?),
+                        "reconnect_peripherals" => reconnect_peripherals = Some(map.next_value()?),
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(SessionDescriptor {
+                    scan_services: scan_services
+                        .ok_or_else(|| de::Error::missing_field("scan_services"))?,
+                    scan_allow_duplicates: scan_allow_duplicates
+                        .ok_or_else(|| de::Error::missing_field("scan_allow_duplicates"))?,
+                    reconnect_peripherals: reconnect_peripherals
+                        .ok_or_else(|| de::Error::missing_field("reconnect_peripherals"))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+impl Serialize for CommandRejectReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut m = serializer.serialize_map(None)?;
+        match self {
+            CommandRejectReason::StaleHandle => {
+                m.serialize_entry("reason", "StaleHandle")?;
+            }
+            CommandRejectReason::WriteTooLarge { len, max } => {
+                m.serialize_entry("reason", "WriteTooLarge")?;
+                m.serialize_entry("len", len)?;
+                m.serialize_entry("max", max)?;
+            }
+            CommandRejectReason::NotDiscovered { uuid } => {
+                m.serialize_entry("reason", "NotDiscovered")?;
+                m.serialize_entry("uuid", uuid)?;
+            }
+            CommandRejectReason::NotSubscribable { uuid } => {
+                m.serialize_entry("reason", "NotSubscribable")?;
+                m.serialize_entry("uuid", uuid)?;
+            }
+            CommandRejectReason::NotReadable { uuid } => {
+                m.serialize_entry("reason", "NotReadable")?;
+                m.serialize_entry("uuid", uuid)?;
+            }
+        }
+        m.end()
+    }
+}
+
+macro_rules! event {
+    ($serializer:expr, $name:ident $(, $k:ident => $v:expr)*) => {{
+        let mut m = $serializer.serialize_map(None)?;
+        m.serialize_entry("event", stringify!($name))?;
+        $(m.serialize_entry(stringify!($k), $v)?;)*
+        m.end()
+    }};
+}
+
+/// Serializes the event as an object with the variant name in the `event` field and the variant
+/// fields as the other fields. The `tag` and `queue_timestamp` fields are omitted.
+impl Serialize for CentralEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use CentralEvent::*;
+        match self {
+            AllDescriptorsRead { peripheral, characteristic, result } => event!(serializer,
+                AllDescriptorsRead,
+                peripheral => peripheral,
+                characteristic => characteristic,
+                result => &outcome(result, |_| ())),
+            CccdRead { peripheral, characteristic, result } => event!(serializer,
+                CccdRead,
+                peripheral => peripheral,
+                characteristic => characteristic,
+                result => &outcome(result, |v| v)),
+            CharacteristicsDiscovered { peripheral, service, characteristics } => event!(serializer,
+                CharacteristicsDiscovered,
+                peripheral => peripheral,
+                service => service,
+                characteristics => &outcome(characteristics, |v| v)),
+            CharacteristicsFound { peripheral, characteristics } => event!(serializer,
+                CharacteristicsFound,
+                peripheral => peripheral,
+                characteristics => &outcome(characteristics, |v| v)),
+            CharacteristicValue {
+                peripheral, characteristic, value, sequence, queue_timestamp: _,
+            } => event!(serializer,
+                CharacteristicValue,
+                peripheral => peripheral,
+                characteristic => characteristic,
+                value => &bytes(value),
+                sequence => sequence),
+            CommandRejected { peripheral, reason } => event!(serializer,
+                CommandRejected,
+                peripheral => peripheral,
+                reason => reason),
+            DescriptorsDiscovered { peripheral, characteristic, descriptors } => event!(serializer,
+                DescriptorsDiscovered,
+                peripheral => peripheral,
+                characteristic => characteristic,
+                descriptors => &outcome(descriptors, |v| v)),
+            DescriptorValue { peripheral, descriptor, value } => event!(serializer,
+                DescriptorValue,
+                peripheral => peripheral,
+                descriptor => descriptor,
+                value => &bytes(value)),
+            GetMaxWriteLenResult { max_write_len, tag: _ } => event!(serializer,
+                GetMaxWriteLenResult,
+                max_write_len => max_write_len),
+            GetPeripheralsResult { peripherals, tag: _ } => event!(serializer,
+                GetPeripheralsResult,
+                peripherals => peripherals),
+            GetPeripheralsWithServicesResult { peripherals, tag: _ } => event!(serializer,
+                GetPeripheralsWithServicesResult,
+                peripherals => peripherals),
+            IncludedServicesDiscovered { peripheral, service, included_services } => event!(serializer,
+                IncludedServicesDiscovered,
+                peripheral => peripheral,
+                service => service,
+                included_services => &outcome(included_services, |v| v)),
+            LinkQualityWarning { peripheral, rssi } => event!(serializer,
+                LinkQualityWarning,
+                peripheral => peripheral,
+                rssi => rssi),
+            ManagerStateChanged { new_state } => event!(serializer,
+                ManagerStateChanged,
+                new_state => &format!("{:?}", new_state)),
+            PeripheralConnected { peripheral, was_already_connected, connect_duration } => event!(serializer,
+                PeripheralConnected,
+                peripheral => peripheral,
+                was_already_connected => was_already_connected,
+                connect_duration => connect_duration),
+            PeripheralConnectFailed { peripheral, error } => event!(serializer,
+                PeripheralConnectFailed,
+                peripheral => peripheral,
+                error => error),
+            PeripheralDisconnected { peripheral, error } => event!(serializer,
+                PeripheralDisconnected,
+                peripheral => peripheral,
+                error => error),
+            PeripheralDiscovered { peripheral, advertisement_data, rssi, estimated_interval } => event!(serializer,
+                PeripheralDiscovered,
+                peripheral => peripheral,
+                advertisement_data => advertisement_data,
+                rssi => rssi,
+                estimated_interval => &estimated_interval.map(|v| v.as_secs_f64())),
+            PeripheralIsReadyToWriteWithoutResponse { peripheral } => event!(serializer,
+                PeripheralIsReadyToWriteWithoutResponse,
+                peripheral => peripheral),
+            PeripheralNameChanged { peripheral, new_name } => event!(serializer,
+                PeripheralNameChanged,
+                peripheral => peripheral,
+                new_name => new_name),
+            ReadRssiResult { peripheral, rssi } => event!(serializer,
+                ReadRssiResult,
+                peripheral => peripheral,
+                rssi => &outcome(rssi, |v| v)),
+            RediscoveryCompleted { peripheral, services } => event!(serializer,
+                RediscoveryCompleted,
+                peripheral => peripheral,
+                services => &outcome(services, |v| v)),
+            ServicesAutoRediscovered { peripheral, services } => event!(serializer,
+                ServicesAutoRediscovered,
+                peripheral => peripheral,
+                services => &outcome(services, |v| v)),
+            ServicesChanged { peripheral, services, invalidated_services } => event!(serializer,
+                ServicesChanged,
+                peripheral => peripheral,
+                services => services,
+                invalidated_services => invalidated_services),
+            ServicesDiscovered { peripheral, services } => event!(serializer,
+                ServicesDiscovered,
+                peripheral => peripheral,
+                services => &outcome(services, |v| v)),
+            SubscriptionChangeResult { peripheral, characteristic, result } => event!(serializer,
+                SubscriptionChangeResult,
+                peripheral => peripheral,
+                characteristic => characteristic,
+                result => &outcome(result, |_| ())),
+            WriteCharacteristicResult { peripheral, characteristic, result } => event!(serializer,
+                WriteCharacteristicResult,
+                peripheral => peripheral,
+                characteristic => characteristic,
+                result => &outcome(result, |_| ())),
+            WriteDescriptorResult { peripheral, descriptor, result } => event!(serializer,
+                WriteDescriptorResult,
+                peripheral => peripheral,
+                descriptor => descriptor,
+                result => &outcome(result, |_| ())),
+            WriteSequenceComplete { peripheral, results } => event!(serializer,
+                WriteSequenceComplete,
+                peripheral => peripheral,
+                results => &results.iter().map(|r| outcome(r, |_| ())).collect::<Vec<_>>()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn value_outcome() {
+        let ok = Ok(vec![0x0a, 0xff, 0]);
+        assert_eq!(serde_json::to_string(&bytes(&ok)).unwrap(), r#"{"ok":"0aff00"}"#);
+
+        let err = Err(Error::new(ErrorKind::Att(crate::error::AttErrorKind::ReadNotPermitted), "nope"));
+        assert_eq!(serde_json::to_string(&bytes(&err)).unwrap(),
+            r#"{"error":{"kind":"Att(ReadNotPermitted)","message":"nope"}}"#);
+
+        let unit: Result<(), Error> = Ok(());
+        assert_eq!(serde_json::to_string(&outcome(&unit, |_| ())).unwrap(), r#"{"ok":null}"#);
+    }
+}
@@ -1,5 +1,6 @@
 use super::*;
 use crate::central::characteristic::Characteristic;
+use crate::central::peripheral::{CBPeripheral, PeripheralId};
 
 /// A collection of data and associated behaviors that accomplish a function or feature of a device.
 ///
@@ -9,6 +10,7 @@ use crate::central::characteristic::Characteristic;
 pub struct Service {
     id: Uuid,
     primary: bool,
+    peripheral_id: Option<PeripheralId>,
     pub(in crate) service: StrongPtr<CBService>,
 }
 
@@ -20,6 +22,7 @@ impl Service {
         Self {
             id: service.id(),
             primary: service.is_primary(),
+            peripheral_id: service.peripheral().map(|p| p.id().into()),
             service,
         }
     }
@@ -28,6 +31,13 @@ impl Service {
         self.id
     }
 
+    /// Returns identifier of the peripheral to which this service belongs, as returned by
+    /// [`Peripheral::id`](../peripheral/struct.Peripheral.html#method.id), or `None` if
+    /// the service was no longer attached to a peripheral when this object was created.
+    pub fn peripheral_id(&self) -> Option<PeripheralId> {
+        self.peripheral_id
+    }
+
     /// Indicates whether the type of service is primary or secondary.
     ///
     /// A peripheral’s service is either primary or secondary. A primary service describes the
@@ -54,6 +64,15 @@ impl std::hash::Hash for Service {
     }
 }
 
+/// Returns services with any of the `uuids` in discovery order. `services` contains the discovered
+/// services paired with their UUIDs.
+pub(in crate) fn matching<S>(services: Vec<(Uuid, S)>, uuids: &[Uuid]) -> Vec<S> {
+    services.into_iter()
+        .filter(|(id, _)| uuids.contains(id))
+        .map(|(_, s)| s)
+        .collect()
+}
+
 object_ptr_wrapper!(CBService);
 
 impl CBService {
@@ -71,6 +90,13 @@ impl CBService {
         }
     }
 
+    pub fn peripheral(&self) -> Option<CBPeripheral> {
+        unsafe {
+            let r: *mut Object = msg_send![self.as_ptr(), peripheral];
+            CBPeripheral::wrap_nullable(r)
+        }
+    }
+
     pub fn characteristics(&self) -> Option<Vec<Characteristic>> {
         let arr = unsafe {
             let r: *mut Object = msg_send![self.as_ptr(), characteristics];
@@ -80,4 +106,41 @@ impl CBService {
             .map(|v| unsafe { Characteristic::retain(v) })
             .collect())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::mem::ManuallyDrop;
+    use std::ptr::NonNull;
+
+    #[test]
+    fn peripheral_id() {
+        // The object is never dereferenced nor released.
+        let service = |peripheral_id| ManuallyDrop::new(Service {
+            id: Uuid::from_u16(0x180d),
+            primary: true,
+            peripheral_id,
+            service: unsafe { StrongPtr::wrap(CBService(NonNull::dangling())) },
+        });
+        let id: PeripheralId = "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap();
+        assert_eq!(service(Some(id)).peripheral_id(), Some(id));
+        assert_eq!(service(None).peripheral_id(), None);
+    }
+
+    #[test]
+    fn matching() {
+        let heart_rate = Uuid::from_slice(&[0x18, 0x0d]);
+        let battery = Uuid::from_u16(0x180f);
+        let custom: Uuid = "6e400001-b5a3-f393-e0a9-e50e24dcca9e".parse().unwrap();
+        let services = || vec![(battery, "a"), (heart_rate, "b"), (custom, "c"), (battery, "d")];
+
+        let long: Uuid = "0000180D-0000-1000-8000-00805F9B34FB".parse().unwrap();
+        assert_eq!(super::matching(services(), &[long]), vec!["b"]);
+        assert_eq!(super::matching(services(), &[Uuid::from_u16(0x180d)]), vec!["b"]);
+        assert_eq!(super::matching(services(), &[custom, battery]), vec!["a", "c", "d"]);
+        assert!(super::matching(services(), &[Uuid::from_u16(0x1800)]).is_empty());
+        assert!(super::matching(services(), &[]).is_empty());
+        assert!(super::matching(Vec::<(Uuid, &str)>::new(), &[battery]).is_empty());
+    }
+}
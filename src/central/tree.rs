@@ -0,0 +1,150 @@
+//! Attribute tree of a peripheral.
+
+use std::fmt::Write;
+
+use crate::uuid::Uuid;
+
+use super::characteristic::{Characteristic, Properties};
+use super::descriptor::Descriptor;
+use super::peripheral::PeripheralId;
+use super::service::Service;
+
+/// Services of a peripheral along with their characteristics and descriptors.
+#[derive(Clone, Debug)]
+pub struct DeviceTree {
+    pub services: Vec<ServiceNode>,
+}
+
+/// A service and its characteristics.
+#[derive(Clone, Debug)]
+pub struct ServiceNode {
+    pub service: Service,
+    pub characteristics: Vec<CharacteristicNode>,
+}
+
+/// A characteristic and its descriptors.
+#[derive(Clone, Debug)]
+pub struct CharacteristicNode {
+    pub characteristic: Characteristic,
+    pub descriptors: Vec<Descriptor>,
+}
+
+/// Service rendered by `render_debug_tree`. `None` means the children haven't been discovered.
+pub(in crate) struct DebugService {
+    pub id: Uuid,
+    pub is_primary: bool,
+    pub characteristics: Option<Vec<DebugCharacteristic>>,
+}
+
+/// Characteristic rendered by `render_debug_tree`. `None` means the descriptors haven't been
+/// discovered.
+pub(in crate) struct DebugCharacteristic {
+    pub id: Uuid,
+    pub properties: Properties,
+    pub descriptors: Option<Vec<Uuid>>,
+}
+
+/// Renders the attribute tree as indented text, one attribute per line. `None` `services` means
+/// the services haven't been discovered.
+pub(in crate) fn render_debug_tree(peripheral: PeripheralId, services: Option<&[DebugService]>)
+    -> String
+{
+    let mut r = format!("Peripheral {}\n", peripheral);
+    let mut incomplete = false;
+    let mut not_discovered = |r: &mut String, indent: &str, what: &str| {
+        incomplete = true;
+        let _ = writeln!(r, "{}({} not discovered)", indent, what);
+    };
+    if let Some(services) = services {
+        for service in services {
+            let _ = writeln!(r, "  Service {} ({})", service.id.to_short_string(),
+                if service.is_primary { "primary" } else { "secondary" });
+            let characteristics = if let Some(v) = &service.characteristics {
+                v
+            } else {
+                not_discovered(&mut r, "    ", "characteristics");
+                continue;
+            };
+            for characteristic in characteristics {
+                let _ = writeln!(r, "    Characteristic {} [{}]", characteristic.id.to_short_string(),
+                    characteristic.properties.names().join(", "));
+                if let Some(descriptors) = &characteristic.descriptors {
+                    for descriptor in descriptors {
+                        let _ = writeln!(r, "      Descriptor {}", descriptor.to_short_string());
+                    }
+                } else {
+                    not_discovered(&mut r, "      ", "descriptors");
+                }
+            }
+        }
+    } else {
+        not_discovered(&mut r, "  ", "services");
+    }
+    if incomplete {
+        r += "Note: discovery is incomplete\n";
+    }
+    r
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_debug_tree() {
+        let id: PeripheralId = "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap();
+        let services = vec![
+            DebugService {
+                id: Uuid::from_u16(0x180d),
+                is_primary: true,
+                characteristics: Some(vec![
+                    DebugCharacteristic {
+                        id: Uuid::from_u16(0x2a37),
+                        properties: Properties::from_bits_truncate(0x10),
+                        descriptors: Some(vec![Uuid::from_u16(0x2902)]),
+                    },
+                    DebugCharacteristic {
+                        id: Uuid::from_u16(0x2a38),
+                        properties: Properties::from_bits_truncate(0x02),
+                        descriptors: Some(Vec::new()),
+                    },
+                ]),
+            },
+        ];
+        assert_eq!(super::render_debug_tree(id, Some(&services)), "\
+Peripheral ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6
+  Service 180d (primary)
+    Characteristic 2a37 [notify]
+      Descriptor 2902
+    Characteristic 2a38 [read]
+");
+
+        let services = vec![
+            DebugService {
+                id: Uuid::from_u16(0x180f),
+                is_primary: false,
+                characteristics: Some(vec![DebugCharacteristic {
+                    id: Uuid::from_u16(0x2a19),
+                    properties: Properties::from_bits_truncate(0x12),
+                    descriptors: None,
+                }]),
+            },
+            DebugService {
+                id: Uuid::from_u16(0x180a),
+                is_primary: true,
+                characteristics: None,
+            },
+        ];
+        let r = super::render_debug_tree(id, Some(&services));
+        assert!(r.contains("  Service 180f (secondary)\n"), "{}", r);
+        assert!(r.contains("    Characteristic 2a19 [read, notify]\n      (descriptors not discovered)\n"), "{}", r);
+        assert!(r.contains("  Service 180a (primary)\n    (characteristics not discovered)\n"), "{}", r);
+        assert!(r.ends_with("Note: discovery is incomplete\n"), "{}", r);
+
+        assert_eq!(super::render_debug_tree(id, None), "\
+Peripheral ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6
+  (services not discovered)
+Note: discovery is incomplete
+");
+    }
+}
@@ -34,6 +34,13 @@ impl Error {
         }
     }
 
+    pub(in crate) fn new(kind: ErrorKind, description: impl Into<String>) -> Self {
+        Self {
+            kind,
+            description: description.into(),
+        }
+    }
+
     pub fn kind(&self) -> ErrorKind {
         self.kind
     }
@@ -95,6 +102,13 @@ pub enum ErrorKind {
     /// The device is unknown.
     UnknownDevice,
 
+    /// The central manager isn't in the [`PoweredOn`](../enum.ManagerState.html#variant.PoweredOn)
+    /// state.
+    ManagerNotPoweredOn,
+
+    /// The operation didn't complete before its deadline.
+    Timeout,
+
     Att(AttErrorKind),
 }
 
@@ -118,6 +132,45 @@ impl ErrorKind {
             _ => Unknown,
         }
     }
+
+    /// Returns `true` if the error is likely transient so the request may succeed if retried.
+    ///
+    /// These are the [transient](enum.AttErrorKind.html#method.is_transient) ATT errors and
+    /// [`Unknown`](#variant.Unknown), which Core Bluetooth reports for example when the peripheral
+    /// is busy.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ErrorKind::Att(kind) => kind.is_transient(),
+            ErrorKind::Unknown => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ErrorKind::*;
+        f.write_str(match self {
+            Other => "Error from unknown domain",
+            Unknown => "Unknown error",
+            InvalidParameters => "The specified parameters are invalid",
+            InvalidHandle => "The specified attribute handle is invalid",
+            NotConnected => "The device isn't currently connected",
+            OutOfSpace => "The device has run out of space to complete the operation",
+            OperationCancelled => "The operation was canceled",
+            ConnectionTimeout => "The connection timed out",
+            PeripheralDisconnected => "The peripheral disconnected",
+            UuidNotAllowed => "The specified UUID isn't permitted",
+            AlreadyAdvertising => "The peripheral is already advertising",
+            ConnectionFailed => "The connection failed",
+            ConnectionLimitReached => "The device already has the maximum number of connections",
+            OperationNotSupported => "The operation isn't supported",
+            UnknownDevice => "The device is unknown",
+            ManagerNotPoweredOn => "The central manager isn't powered on",
+            Timeout => "The operation timed out",
+            Att(kind) => return fmt::Display::fmt(kind, f),
+        })
+    }
 }
 
 /// The possible errors returned by a GATT server (a remote peripheral) during
@@ -208,4 +261,125 @@ impl AttErrorKind {
             _ => Other,
         }
     }
+
+    /// Returns `true` if the error is likely transient so the request may succeed if retried.
+    ///
+    /// These are [`UnlikelyError`](#variant.UnlikelyError) and
+    /// [`InsufficientResources`](#variant.InsufficientResources).
+    pub fn is_transient(&self) -> bool {
+        matches!(self, AttErrorKind::UnlikelyError | AttErrorKind::InsufficientResources)
+    }
+
+    /// Returns the numeric ATT status code of this error kind, as seen by clients.
+    ///
+    /// [`Other`](#variant.Other) doesn't retain its original code and maps to the code of
+    /// [`UnlikelyError`](#variant.UnlikelyError).
+    pub fn to_code(&self) -> u8 {
+        use AttErrorKind::*;
+        match self {
+            Success => 0,
+            InvalidHandle => 1,
+            ReadNotPermitted => 2,
+            WriteNotPermitted => 3,
+            InvalidPdu => 4,
+            InsufficientAuthentication => 5,
+            RequestNotSupported => 6,
+            InvalidOffset => 7,
+            InsufficientAuthorization => 8,
+            PrepareQueueFull => 9,
+            AttributeNotFound => 10,
+            AttributeNotLong => 11,
+            InsufficientEncryptionKeySize => 12,
+            InvalidAttributeValueLength => 13,
+            UnlikelyError | Other => 14,
+            InsufficientEncryption => 15,
+            UnsupportedGroupType => 16,
+            InsufficientResources => 17,
+        }
+    }
+}
+
+impl fmt::Display for AttErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use AttErrorKind::*;
+        f.write_str(match self {
+            Other => "Unknown ATT error",
+            Success => "The ATT request completed successfully",
+            InvalidHandle => "The attribute handle is invalid",
+            ReadNotPermitted => "Reading the attribute's value isn't permitted",
+            WriteNotPermitted => "Writing the attribute's value isn't permitted",
+            InvalidPdu => "The attribute PDU is invalid",
+            InsufficientAuthentication => "Insufficient authentication to read/write the attribute",
+            RequestNotSupported => "The request isn't supported by the attribute server",
+            InvalidOffset => "The offset is past the end of the attribute's value",
+            InsufficientAuthorization => "Insufficient authorization to read/write the attribute",
+            PrepareQueueFull => "The prepare queue is full",
+            AttributeNotFound => "The attribute wasn't found within the handle range",
+            AttributeNotLong => "The attribute can't be read or written using the read blob request",
+            InsufficientEncryptionKeySize => "The encryption key size is insufficient",
+            InvalidAttributeValueLength => "The attribute's value length is invalid for the operation",
+            UnlikelyError => "The ATT request encountered an unlikely error",
+            InsufficientEncryption => "Insufficient encryption to read/write the attribute",
+            UnsupportedGroupType => "The attribute type isn't a supported grouping attribute",
+            InsufficientResources => "Insufficient resources to complete the ATT request",
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn att_error_kind_code_round_trip() {
+        use AttErrorKind::*;
+        let kinds = &[
+            Success,
+            InvalidHandle,
+            ReadNotPermitted,
+            WriteNotPermitted,
+            InvalidPdu,
+            InsufficientAuthentication,
+            RequestNotSupported,
+            InvalidOffset,
+            InsufficientAuthorization,
+            PrepareQueueFull,
+            AttributeNotFound,
+            AttributeNotLong,
+            InsufficientEncryptionKeySize,
+            InvalidAttributeValueLength,
+            UnlikelyError,
+            InsufficientEncryption,
+            UnsupportedGroupType,
+            InsufficientResources,
+        ];
+        for &kind in kinds {
+            assert_eq!(AttErrorKind::from_code(kind.to_code() as isize), kind);
+        }
+        assert_eq!(Other.to_code(), UnlikelyError.to_code());
+    }
+
+    #[test]
+    fn is_transient() {
+        assert!(AttErrorKind::UnlikelyError.is_transient());
+        assert!(AttErrorKind::InsufficientResources.is_transient());
+        assert!(!AttErrorKind::ReadNotPermitted.is_transient());
+        assert!(!AttErrorKind::PrepareQueueFull.is_transient());
+        assert!(!AttErrorKind::Other.is_transient());
+
+        assert!(ErrorKind::Att(AttErrorKind::UnlikelyError).is_transient());
+        assert!(ErrorKind::Unknown.is_transient());
+        assert!(!ErrorKind::Att(AttErrorKind::InsufficientAuthentication).is_transient());
+        assert!(!ErrorKind::PeripheralDisconnected.is_transient());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(AttErrorKind::InsufficientAuthentication.to_string(),
+            "Insufficient authentication to read/write the attribute");
+        assert_eq!(ErrorKind::ConnectionTimeout.to_string(), "The connection timed out");
+        assert_eq!(ErrorKind::Att(AttErrorKind::ReadNotPermitted).to_string(),
+            AttErrorKind::ReadNotPermitted.to_string());
+        assert_eq!(format!("{:?}", ErrorKind::ConnectionTimeout), "ConnectionTimeout");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,636 @@
+//! Typed representations of standard GATT attribute values.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::known::characteristic as known;
+use crate::uuid::Uuid;
+
+/// Bounds-checked view of a characteristic or descriptor value. Getters take the byte offset
+/// of the field and return `None` if the value is too short, so malformed values from a
+/// misbehaving device can be decoded without panicking.
+///
+/// ```
+/// use core_bluetooth::gatt::ValueView;
+///
+/// let v = ValueView::new(&[0x0c, 0xfe, 0x37]);
+/// assert_eq!(v.get_i16_le(0), Some(-500));
+/// assert_eq!(v.get_u8(2), Some(55));
+/// assert_eq!(v.get_u16_le(2), None);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ValueView<'a>(&'a [u8]);
+
+impl<'a> ValueView<'a> {
+    /// Creates a view of the value `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the whole value.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    /// Returns the length of the value in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the value has no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `len` bytes starting at offset `i`.
+    pub fn get(&self, i: usize, len: usize) -> Option<&'a [u8]> {
+        self.0.get(i..i.checked_add(len)?)
+    }
+
+    /// Returns the byte at offset `i`.
+    pub fn get_u8(&self, i: usize) -> Option<u8> {
+        self.0.get(i).copied()
+    }
+
+    /// Returns the signed byte at offset `i`.
+    pub fn get_i8(&self, i: usize) -> Option<i8> {
+        self.get_u8(i).map(|v| v as i8)
+    }
+
+    /// Returns the little-endian `u16` at offset `i`.
+    pub fn get_u16_le(&self, i: usize) -> Option<u16> {
+        self.array(i).map(u16::from_le_bytes)
+    }
+
+    /// Returns the big-endian `u16` at offset `i`.
+    pub fn get_u16_be(&self, i: usize) -> Option<u16> {
+        self.array(i).map(u16::from_be_bytes)
+    }
+
+    /// Returns the little-endian `i16` at offset `i`.
+    pub fn get_i16_le(&self, i: usize) -> Option<i16> {
+        self.array(i).map(i16::from_le_bytes)
+    }
+
+    /// Returns the big-endian `i16` at offset `i`.
+    pub fn get_i16_be(&self, i: usize) -> Option<i16> {
+        self.array(i).map(i16::from_be_bytes)
+    }
+
+    /// Returns the little-endian 24-bit unsigned integer at offset `i`, as used by some GATT
+    /// characteristics.
+    pub fn get_u24_le(&self, i: usize) -> Option<u32> {
+        let b = self.get(i, 3)?;
+        Some(u32::from_le_bytes([b[0], b[1], b[2], 0]))
+    }
+
+    /// Returns the little-endian `u32` at offset `i`.
+    pub fn get_u32_le(&self, i: usize) -> Option<u32> {
+        self.array(i).map(u32::from_le_bytes)
+    }
+
+    /// Returns the big-endian `u32` at offset `i`.
+    pub fn get_u32_be(&self, i: usize) -> Option<u32> {
+        self.array(i).map(u32::from_be_bytes)
+    }
+
+    /// Returns the little-endian `i32` at offset `i`.
+    pub fn get_i32_le(&self, i: usize) -> Option<i32> {
+        self.array(i).map(i32::from_le_bytes)
+    }
+
+    fn array<T: Default + AsMut<[u8]>>(&self, i: usize) -> Option<T> {
+        let mut r = T::default();
+        let len = r.as_mut().len();
+        r.as_mut().copy_from_slice(self.get(i, len)?);
+        Some(r)
+    }
+}
+
+impl<'a> From<&'a [u8]> for ValueView<'a> {
+    fn from(v: &'a [u8]) -> Self {
+        Self::new(v)
+    }
+}
+
+/// Value of the
+/// [Characteristic Presentation Format](../known/descriptor/constant.CHARACTERISTIC_PRESENTATION_FORMAT.html)
+/// descriptor, which defines how the characteristic value should be formatted.
+///
+/// ```
+/// use core_bluetooth::gatt::PresentationFormat;
+///
+/// // Signed 16-bit value of hundredths of degree Celsius.
+/// let f = PresentationFormat::parse(&[0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x00, 0x00]).unwrap();
+/// assert_eq!(f.format_name(), Some("sint16"));
+/// assert_eq!(f.exponent, -2);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct PresentationFormat {
+    /// Format of the value, see [`format_name`](#method.format_name).
+    pub format: u8,
+
+    /// Base 10 exponent of the value: the actual value is `value * 10^exponent`.
+    pub exponent: i8,
+
+    /// Assigned number of the unit of the value, see [`unit_uuid`](#method.unit_uuid).
+    pub unit: u16,
+
+    /// Organization that defines the `description`. `1` is the Bluetooth SIG.
+    pub namespace: u8,
+
+    /// Namespace-specific description of the value.
+    pub description: u16,
+}
+
+impl PresentationFormat {
+    /// Parses the descriptor value. Returns `None` if `bytes` is shorter than 7 bytes.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 7 {
+            return None;
+        }
+        Some(Self {
+            format: bytes[0],
+            exponent: bytes[1] as i8,
+            unit: u16::from_le_bytes([bytes[2], bytes[3]]),
+            namespace: bytes[4],
+            description: u16::from_le_bytes([bytes[5], bytes[6]]),
+        })
+    }
+
+    /// Returns the short name of the [`format`](#structfield.format) as defined in the Bluetooth
+    /// Core Specification, e.g. `uint8` or `utf8s`. Returns `None` for reserved values.
+    pub fn format_name(&self) -> Option<&'static str> {
+        Some(match self.format {
+            0x01 => "boolean",
+            0x02 => "2bit",
+            0x03 => "nibble",
+            0x04 => "uint8",
+            0x05 => "uint12",
+            0x06 => "uint16",
+            0x07 => "uint24",
+            0x08 => "uint32",
+            0x09 => "uint48",
+            0x0a => "uint64",
+            0x0b => "uint128",
+            0x0c => "sint8",
+            0x0d => "sint12",
+            0x0e => "sint16",
+            0x0f => "sint24",
+            0x10 => "sint32",
+            0x11 => "sint48",
+            0x12 => "sint64",
+            0x13 => "sint128",
+            0x14 => "float32",
+            0x15 => "float64",
+            0x16 => "SFLOAT",
+            0x17 => "FLOAT",
+            0x18 => "duint16",
+            0x19 => "utf8s",
+            0x1a => "utf16s",
+            0x1b => "struct",
+            _ => return None,
+        })
+    }
+
+    /// Returns the UUID of the [`unit`](#structfield.unit), e.g. `0x272F` for degree Celsius.
+    pub fn unit_uuid(&self) -> Uuid {
+        Uuid::from_u16(self.unit)
+    }
+}
+
+/// Value of the [System ID](../known/characteristic/constant.SYSTEM_ID.html) characteristic,
+/// returned by [`system_id`](fn.system_id.html).
+///
+/// The System ID is an EUI-64 identifier of the device. Devices usually derive it from their
+/// public MAC address by inserting two bytes between the OUI and the rest of the address, so
+/// the MAC address can often be recovered with [`to_mac`](#method.to_mac).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SystemId {
+    /// Manufacturer defined identifier, 40 bits.
+    pub manufacturer_id: u64,
+
+    /// Organizationally Unique Identifier of the manufacturer, 24 bits.
+    pub oui: u32,
+}
+
+impl SystemId {
+    /// Returns the MAC address made of the [`oui`](#structfield.oui) and the low 24 bits of the
+    /// [`manufacturer_id`](#structfield.manufacturer_id).
+    ///
+    /// This is the actual MAC address of the device only if the System ID was derived from it,
+    /// which is common but not required by the specification.
+    #[cfg(feature = "macaddr")]
+    pub fn to_mac(&self) -> macaddr::MacAddr6 {
+        let oui = self.oui.to_be_bytes();
+        let id = self.manufacturer_id.to_be_bytes();
+        macaddr::MacAddr6::new(oui[1], oui[2], oui[3], id[5], id[6], id[7])
+    }
+}
+
+/// Parses value of the [System ID](../known/characteristic/constant.SYSTEM_ID.html)
+/// characteristic. Returns `None` if `value` is not 8 bytes long.
+///
+/// ```
+/// use core_bluetooth::gatt::system_id;
+///
+/// let id = system_id(&[0x9e, 0x03, 0xc0, 0xfe, 0xff, 0x38, 0xc1, 0xa4]).unwrap();
+/// assert_eq!(id.oui, 0xa4c138);
+/// assert_eq!(id.manufacturer_id, 0xfffec0039e);
+/// ```
+pub fn system_id(value: &[u8]) -> Option<SystemId> {
+    if value.len() != 8 {
+        return None;
+    }
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(value);
+    let v = u64::from_le_bytes(bytes);
+    Some(SystemId {
+        manufacturer_id: v & 0xff_ffff_ffff,
+        oui: (v >> 40) as u32,
+    })
+}
+
+/// Value of the [Heart Rate Measurement](../known/characteristic/constant.HEART_RATE_MEASUREMENT.html)
+/// characteristic.
+///
+/// ```
+/// use core_bluetooth::gatt::HeartRateMeasurement;
+///
+/// let m = HeartRateMeasurement::parse(&[0x16, 0x48, 0x1b, 0x03]).unwrap();
+/// assert_eq!(m.heart_rate, 72);
+/// assert_eq!(m.sensor_contact, Some(true));
+/// assert_eq!(m.rr_intervals, vec![795]);
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HeartRateMeasurement {
+    /// Heart rate in beats per minute.
+    pub heart_rate: u16,
+
+    /// Whether the sensor is in contact with the skin, or `None` if the sensor doesn't support
+    /// contact detection.
+    pub sensor_contact: Option<bool>,
+
+    /// Energy expended since the last reset in kilojoules.
+    pub energy_expended: Option<u16>,
+
+    /// RR-intervals in 1/1024 second units, oldest first.
+    pub rr_intervals: Vec<u16>,
+}
+
+impl HeartRateMeasurement {
+    const HEART_RATE_U16: u8 = 0x01;
+    const SENSOR_CONTACT_DETECTED: u8 = 0x02;
+    const SENSOR_CONTACT_SUPPORTED: u8 = 0x04;
+    const ENERGY_EXPENDED: u8 = 0x08;
+    const RR_INTERVALS: u8 = 0x10;
+
+    /// Parses the characteristic value. Returns `None` if `bytes` is shorter than the fields
+    /// indicated by its flags or has a trailing partial RR-interval.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let v = ValueView::new(bytes);
+        let flags = v.get_u8(0)?;
+        let (heart_rate, mut i) = if flags & Self::HEART_RATE_U16 == 0 {
+            (v.get_u8(1)? as u16, 2)
+        } else {
+            (v.get_u16_le(1)?, 3)
+        };
+        let sensor_contact = if flags & Self::SENSOR_CONTACT_SUPPORTED != 0 {
+            Some(flags & Self::SENSOR_CONTACT_DETECTED != 0)
+        } else {
+            None
+        };
+        let energy_expended = if flags & Self::ENERGY_EXPENDED != 0 {
+            i += 2;
+            Some(v.get_u16_le(i - 2)?)
+        } else {
+            None
+        };
+        let mut rr_intervals = Vec::new();
+        if flags & Self::RR_INTERVALS != 0 {
+            let chunks = bytes[i..].chunks_exact(2);
+            if !chunks.remainder().is_empty() {
+                return None;
+            }
+            rr_intervals.extend(chunks.map(|c| u16::from_le_bytes([c[0], c[1]])));
+        }
+        Some(Self {
+            heart_rate,
+            sensor_contact,
+            energy_expended,
+            rr_intervals,
+        })
+    }
+}
+
+/// Device category encoded in the upper 10 bits of the
+/// [Appearance](../known/characteristic/constant.APPEARANCE.html) characteristic value.
+///
+/// Note CoreBluetooth doesn't report the appearance in advertisement data, so it's only available
+/// by reading the characteristic.
+///
+/// ```
+/// use core_bluetooth::gatt::DeviceCategory;
+///
+/// assert_eq!(DeviceCategory::from_appearance(0x00c1), DeviceCategory::Watch);
+/// assert_eq!(DeviceCategory::from_appearance(0x0341), DeviceCategory::HeartRateSensor);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum DeviceCategory {
+    Unknown,
+    Phone,
+    Computer,
+    Watch,
+    Clock,
+    Display,
+    RemoteControl,
+    EyeGlasses,
+    Tag,
+    Keyring,
+    MediaPlayer,
+    BarcodeScanner,
+    Thermometer,
+    HeartRateSensor,
+    BloodPressure,
+    HumanInterfaceDevice,
+    GlucoseMeter,
+    RunningWalkingSensor,
+    Cycling,
+    PulseOximeter,
+    WeightScale,
+    OutdoorSportsActivity,
+}
+
+impl DeviceCategory {
+    /// Returns the category of the `appearance` value. Returns `Unknown` for categories not listed
+    /// in this enum.
+    pub fn from_appearance(appearance: u16) -> Self {
+        use DeviceCategory::*;
+        match appearance >> 6 {
+            0x01 => Phone,
+            0x02 => Computer,
+            0x03 => Watch,
+            0x04 => Clock,
+            0x05 => Display,
+            0x06 => RemoteControl,
+            0x07 => EyeGlasses,
+            0x08 => Tag,
+            0x09 => Keyring,
+            0x0a => MediaPlayer,
+            0x0b => BarcodeScanner,
+            0x0c => Thermometer,
+            0x0d => HeartRateSensor,
+            0x0e => BloodPressure,
+            0x0f => HumanInterfaceDevice,
+            0x10 => GlucoseMeter,
+            0x11 => RunningWalkingSensor,
+            0x12 => Cycling,
+            0x31 => PulseOximeter,
+            0x32 => WeightScale,
+            0x51 => OutdoorSportsActivity,
+            _ => Unknown,
+        }
+    }
+}
+
+/// Characteristic value decoded by [`DecoderRegistry`](struct.DecoderRegistry.html).
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DecodedValue {
+    Bool(bool),
+    Unsigned(u64),
+    Signed(i64),
+
+    /// Value with fractional part, e.g. temperature in degrees Celsius.
+    Float(f64),
+
+    String(String),
+    SystemId(SystemId),
+    Bytes(Vec<u8>),
+}
+
+type Decoder = Box<dyn Fn(&[u8]) -> Option<DecodedValue> + Send + Sync>;
+
+/// Maps characteristic UUIDs to decoders of their values.
+///
+/// ```
+/// use core_bluetooth::gatt::{DecodedValue, DecoderRegistry};
+/// use core_bluetooth::known::characteristic::BATTERY_LEVEL;
+/// use core_bluetooth::uuid::Uuid;
+///
+/// let registry = DecoderRegistry::standard()
+///     .register(Uuid::from_u16(0xfff1), |v| Some(DecodedValue::Bool(v.first()? != &0)));
+/// assert_eq!(registry.decode(BATTERY_LEVEL, &[87]), Some(DecodedValue::Unsigned(87)));
+/// assert_eq!(registry.decode(Uuid::from_u16(0xfff1), &[1]), Some(DecodedValue::Bool(true)));
+/// ```
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: HashMap<Uuid, Decoder>,
+}
+
+impl DecoderRegistry {
+    /// Creates registry without any decoders.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates registry with decoders of the following standard characteristics:
+    ///
+    /// * Device Name and the Device Information strings: `String`.
+    /// * Appearance and Battery Level: `Unsigned`.
+    /// * Tx Power Level: `Signed`.
+    /// * System ID: `SystemId`.
+    /// * Heart Rate Measurement: `Unsigned` heart rate in beats per minute.
+    /// * Temperature (degrees Celsius), Humidity (percent) and Pressure (pascals): `Float`.
+    pub fn standard() -> Self {
+        fn string(v: &[u8]) -> Option<DecodedValue> {
+            Some(DecodedValue::String(String::from_utf8_lossy(v).into_owned()))
+        }
+
+        let mut r = Self::new();
+        for &uuid in &[known::DEVICE_NAME, known::MODEL_NUMBER_STRING, known::SERIAL_NUMBER_STRING,
+            known::FIRMWARE_REVISION_STRING, known::HARDWARE_REVISION_STRING,
+            known::SOFTWARE_REVISION_STRING, known::MANUFACTURER_NAME_STRING]
+        {
+            r = r.register(uuid, string);
+        }
+        r.register(known::APPEARANCE, |v| {
+            Some(DecodedValue::Unsigned(ValueView::new(v).get_u16_le(0)? as u64))
+        })
+        .register(known::BATTERY_LEVEL, |v| Some(DecodedValue::Unsigned(*v.first()? as u64)))
+        .register(known::TX_POWER_LEVEL, |v| Some(DecodedValue::Signed(*v.first()? as i8 as i64)))
+        .register(known::SYSTEM_ID, |v| system_id(v).map(DecodedValue::SystemId))
+        .register(known::HEART_RATE_MEASUREMENT, |v| {
+            HeartRateMeasurement::parse(v).map(|m| DecodedValue::Unsigned(m.heart_rate as u64))
+        })
+        .register(known::TEMPERATURE, |v| {
+            Some(DecodedValue::Float(ValueView::new(v).get_i16_le(0)? as f64 / 100.0))
+        })
+        .register(known::HUMIDITY, |v| {
+            Some(DecodedValue::Float(ValueView::new(v).get_u16_le(0)? as f64 / 100.0))
+        })
+        .register(known::PRESSURE, |v| {
+            Some(DecodedValue::Float(ValueView::new(v).get_u32_le(0)? as f64 / 10.0))
+        })
+    }
+
+    /// Registers decoder of values of the characteristic `uuid`, replacing the existing one.
+    /// The decoder returns `None` if the value is malformed.
+    pub fn register(mut self, uuid: Uuid,
+        decoder: impl Fn(&[u8]) -> Option<DecodedValue> + Send + Sync + 'static) -> Self
+    {
+        self.decoders.insert(uuid, Box::new(decoder));
+        self
+    }
+
+    /// Adds all decoders of the `other` registry, replacing the existing ones.
+    pub fn merge(mut self, other: Self) -> Self {
+        self.decoders.extend(other.decoders);
+        self
+    }
+
+    /// Decodes `value` of the characteristic `uuid`, for example received in
+    /// [`CharacteristicValue`](../central/enum.CentralEvent.html#variant.CharacteristicValue) event.
+    /// Returns `None` if there's no decoder for the characteristic or the value is malformed.
+    pub fn decode(&self, uuid: Uuid, value: &[u8]) -> Option<DecodedValue> {
+        self.decoders.get(&uuid)?(value)
+    }
+}
+
+impl fmt::Debug for DecoderRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn value_view() {
+        let bytes = &[0x01, 0xfe, 0xff, 0x12, 0x34];
+        let v = ValueView::new(bytes);
+        assert_eq!(v.get_u8(0), Some(1));
+        assert_eq!(v.get_i8(1), Some(-2));
+        assert_eq!(v.get_i16_le(1), Some(-2));
+        assert_eq!(v.get_u16_be(3), Some(0x1234));
+        assert_eq!(v.get_u24_le(2), Some(0x34_12ff));
+        assert_eq!(v.get_u32_le(1), Some(0x3412_fffe));
+        assert_eq!(v.get(3, 2), Some(&bytes[3..]));
+
+        for len in 0..bytes.len() {
+            let v = ValueView::new(&bytes[..len]);
+            assert_eq!(v.get_u8(len), None);
+            assert_eq!(v.get_u16_le(len.saturating_sub(1)), None);
+            assert_eq!(v.get_i32_le(1), None);
+        }
+        assert_eq!(v.get_u8(usize::MAX), None);
+        assert_eq!(v.get(usize::MAX, 2), None);
+        assert_eq!(ValueView::new(&[]).get_i16_le(0), None);
+    }
+
+    #[test]
+    fn parse() {
+        let bytes = &[0x0e, 0xfe, 0x2f, 0x27, 0x01, 0x00, 0x01];
+        let f = PresentationFormat::parse(bytes).unwrap();
+        assert_eq!(f, PresentationFormat {
+            format: 0x0e,
+            exponent: -2,
+            unit: 0x272f,
+            namespace: 1,
+            description: 0x0100,
+        });
+        assert_eq!(f.format_name(), Some("sint16"));
+        assert_eq!(f.unit_uuid(), Uuid::from_u16(0x272f));
+
+        for len in 0..bytes.len() {
+            assert_eq!(PresentationFormat::parse(&bytes[..len]), None);
+        }
+    }
+
+    #[test]
+    fn system_id() {
+        let bytes = &[0x9e, 0x03, 0xc0, 0xfe, 0xff, 0x38, 0xc1, 0xa4];
+        let id = super::system_id(bytes).unwrap();
+        assert_eq!(id, SystemId {
+            manufacturer_id: 0xff_fec0_039e,
+            oui: 0xa4_c138,
+        });
+        #[cfg(feature = "macaddr")]
+        assert_eq!(id.to_mac(), "a4:c1:38:c0:03:9e".parse().unwrap());
+
+        assert_eq!(super::system_id(&bytes[1..]), None);
+        assert_eq!(super::system_id(&[0; 9]), None);
+    }
+
+    #[test]
+    fn decoder_registry() {
+        let registry = DecoderRegistry::new()
+            .register(Uuid::from_u16(0xfff1), |v| {
+                Some(DecodedValue::Signed(i16::from_be_bytes([*v.first()?, *v.get(1)?]) as i64))
+            });
+        assert_eq!(registry.decode(Uuid::from_u16(0xfff1), &[0xff, 0xfe]), Some(DecodedValue::Signed(-2)));
+        assert_eq!(registry.decode(Uuid::from_u16(0xfff1), &[0xff]), None);
+        assert_eq!(registry.decode(known::BATTERY_LEVEL, &[50]), None);
+
+        let registry = DecoderRegistry::standard().merge(registry);
+        assert_eq!(registry.decode(Uuid::from_u16(0xfff1), &[0, 1]), Some(DecodedValue::Signed(1)));
+        assert_eq!(registry.decode(known::BATTERY_LEVEL, &[50]), Some(DecodedValue::Unsigned(50)));
+        assert_eq!(registry.decode(known::DEVICE_NAME, b"Sensor"),
+            Some(DecodedValue::String("Sensor".into())));
+        assert_eq!(registry.decode(known::HEART_RATE_MEASUREMENT, &[0, 72]), Some(DecodedValue::Unsigned(72)));
+        assert_eq!(registry.decode(known::HEART_RATE_MEASUREMENT, &[1, 0x2c, 0x01]),
+            Some(DecodedValue::Unsigned(300)));
+        assert_eq!(registry.decode(known::HEART_RATE_MEASUREMENT, &[1, 0x2c]), None);
+        assert_eq!(registry.decode(known::TEMPERATURE, &[0x0c, 0xfe]), Some(DecodedValue::Float(-5.0)));
+    }
+
+    #[test]
+    fn heart_rate_measurement() {
+        let m = HeartRateMeasurement::parse(&[0x00, 0x48]).unwrap();
+        assert_eq!(m, HeartRateMeasurement {
+            heart_rate: 72,
+            sensor_contact: None,
+            energy_expended: None,
+            rr_intervals: vec![],
+        });
+
+        let m = HeartRateMeasurement::parse(&[0x05, 0x2c, 0x01]).unwrap();
+        assert_eq!(m.heart_rate, 300);
+        assert_eq!(m.sensor_contact, Some(false));
+
+        let m = HeartRateMeasurement::parse(&[0x1e, 0x50, 0x10, 0x00, 0x1b, 0x03, 0xf0, 0x02]).unwrap();
+        assert_eq!(m, HeartRateMeasurement {
+            heart_rate: 80,
+            sensor_contact: Some(true),
+            energy_expended: Some(16),
+            rr_intervals: vec![795, 752],
+        });
+
+        assert_eq!(HeartRateMeasurement::parse(&[]), None);
+        assert_eq!(HeartRateMeasurement::parse(&[0x01, 0x2c]), None);
+        assert_eq!(HeartRateMeasurement::parse(&[0x08, 0x50, 0x10]), None);
+        assert_eq!(HeartRateMeasurement::parse(&[0x10, 0x50, 0x1b]), None);
+    }
+
+    #[test]
+    fn device_category() {
+        let data = &[
+            (0x0000, DeviceCategory::Unknown),
+            (0x0040, DeviceCategory::Phone),
+            (0x0080, DeviceCategory::Computer),
+            (0x00c2, DeviceCategory::Watch),
+            (0x0300, DeviceCategory::Thermometer),
+            (0x0341, DeviceCategory::HeartRateSensor),
+            (0x03c2, DeviceCategory::HumanInterfaceDevice),
+            (0x0c41, DeviceCategory::PulseOximeter),
+            (0x1440, DeviceCategory::OutdoorSportsActivity),
+            (0x0fc0, DeviceCategory::Unknown),
+        ];
+        for &(appearance, exp) in data {
+            assert_eq!(DeviceCategory::from_appearance(appearance), exp, "{:#06x}", appearance);
+        }
+    }
+}
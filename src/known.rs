@@ -0,0 +1,443 @@
+//! UUIDs of standard services, characteristics and descriptors.
+//!
+//! The values are taken from the Bluetooth SIG
+//! [Assigned Numbers](https://www.bluetooth.com/specifications/assigned-numbers/). Only the most
+//! common ones are included.
+//!
+//! ```
+//! use core_bluetooth::known;
+//!
+//! assert_eq!(known::service::BATTERY.to_string(), "0000180f-0000-1000-8000-00805f9b34fb");
+//! ```
+
+pub mod service {
+    //! GATT services.
+
+    use crate::uuid::Uuid;
+
+    /// Generic Access.
+    pub const GENERIC_ACCESS: Uuid = Uuid::from_u16(0x1800);
+
+    /// Generic Attribute.
+    pub const GENERIC_ATTRIBUTE: Uuid = Uuid::from_u16(0x1801);
+
+    /// Immediate Alert.
+    pub const IMMEDIATE_ALERT: Uuid = Uuid::from_u16(0x1802);
+
+    /// Link Loss.
+    pub const LINK_LOSS: Uuid = Uuid::from_u16(0x1803);
+
+    /// Tx Power.
+    pub const TX_POWER: Uuid = Uuid::from_u16(0x1804);
+
+    /// Current Time.
+    pub const CURRENT_TIME: Uuid = Uuid::from_u16(0x1805);
+
+    /// Reference Time Update.
+    pub const REFERENCE_TIME_UPDATE: Uuid = Uuid::from_u16(0x1806);
+
+    /// Next DST Change.
+    pub const NEXT_DST_CHANGE: Uuid = Uuid::from_u16(0x1807);
+
+    /// Glucose.
+    pub const GLUCOSE: Uuid = Uuid::from_u16(0x1808);
+
+    /// Health Thermometer.
+    pub const HEALTH_THERMOMETER: Uuid = Uuid::from_u16(0x1809);
+
+    /// Device Information.
+    pub const DEVICE_INFORMATION: Uuid = Uuid::from_u16(0x180A);
+
+    /// Heart Rate.
+    pub const HEART_RATE: Uuid = Uuid::from_u16(0x180D);
+
+    /// Phone Alert Status.
+    pub const PHONE_ALERT_STATUS: Uuid = Uuid::from_u16(0x180E);
+
+    /// Battery.
+    pub const BATTERY: Uuid = Uuid::from_u16(0x180F);
+
+    /// Blood Pressure.
+    pub const BLOOD_PRESSURE: Uuid = Uuid::from_u16(0x1810);
+
+    /// Alert Notification.
+    pub const ALERT_NOTIFICATION: Uuid = Uuid::from_u16(0x1811);
+
+    /// Human Interface Device.
+    pub const HUMAN_INTERFACE_DEVICE: Uuid = Uuid::from_u16(0x1812);
+
+    /// Scan Parameters.
+    pub const SCAN_PARAMETERS: Uuid = Uuid::from_u16(0x1813);
+
+    /// Running Speed and Cadence.
+    pub const RUNNING_SPEED_AND_CADENCE: Uuid = Uuid::from_u16(0x1814);
+
+    /// Automation IO.
+    pub const AUTOMATION_IO: Uuid = Uuid::from_u16(0x1815);
+
+    /// Cycling Speed and Cadence.
+    pub const CYCLING_SPEED_AND_CADENCE: Uuid = Uuid::from_u16(0x1816);
+
+    /// Cycling Power.
+    pub const CYCLING_POWER: Uuid = Uuid::from_u16(0x1818);
+
+    /// Location and Navigation.
+    pub const LOCATION_AND_NAVIGATION: Uuid = Uuid::from_u16(0x1819);
+
+    /// Environmental Sensing.
+    pub const ENVIRONMENTAL_SENSING: Uuid = Uuid::from_u16(0x181A);
+
+    /// Body Composition.
+    pub const BODY_COMPOSITION: Uuid = Uuid::from_u16(0x181B);
+
+    /// User Data.
+    pub const USER_DATA: Uuid = Uuid::from_u16(0x181C);
+
+    /// Weight Scale.
+    pub const WEIGHT_SCALE: Uuid = Uuid::from_u16(0x181D);
+
+    /// Bond Management.
+    pub const BOND_MANAGEMENT: Uuid = Uuid::from_u16(0x181E);
+
+    /// Continuous Glucose Monitoring.
+    pub const CONTINUOUS_GLUCOSE_MONITORING: Uuid = Uuid::from_u16(0x181F);
+
+    /// Internet Protocol Support.
+    pub const INTERNET_PROTOCOL_SUPPORT: Uuid = Uuid::from_u16(0x1820);
+
+    /// Indoor Positioning.
+    pub const INDOOR_POSITIONING: Uuid = Uuid::from_u16(0x1821);
+
+    /// Pulse Oximeter.
+    pub const PULSE_OXIMETER: Uuid = Uuid::from_u16(0x1822);
+
+    /// HTTP Proxy.
+    pub const HTTP_PROXY: Uuid = Uuid::from_u16(0x1823);
+
+    /// Transport Discovery.
+    pub const TRANSPORT_DISCOVERY: Uuid = Uuid::from_u16(0x1824);
+
+    /// Object Transfer.
+    pub const OBJECT_TRANSFER: Uuid = Uuid::from_u16(0x1825);
+
+    /// Fitness Machine.
+    pub const FITNESS_MACHINE: Uuid = Uuid::from_u16(0x1826);
+}
+
+pub mod characteristic {
+    //! GATT characteristics.
+
+    use crate::uuid::Uuid;
+
+    /// Device Name.
+    pub const DEVICE_NAME: Uuid = Uuid::from_u16(0x2A00);
+
+    /// Appearance.
+    pub const APPEARANCE: Uuid = Uuid::from_u16(0x2A01);
+
+    /// Peripheral Privacy Flag.
+    pub const PERIPHERAL_PRIVACY_FLAG: Uuid = Uuid::from_u16(0x2A02);
+
+    /// Reconnection Address.
+    pub const RECONNECTION_ADDRESS: Uuid = Uuid::from_u16(0x2A03);
+
+    /// Peripheral Preferred Connection Parameters.
+    pub const PERIPHERAL_PREFERRED_CONNECTION_PARAMETERS: Uuid = Uuid::from_u16(0x2A04);
+
+    /// Service Changed.
+    pub const SERVICE_CHANGED: Uuid = Uuid::from_u16(0x2A05);
+
+    /// Alert Level.
+    pub const ALERT_LEVEL: Uuid = Uuid::from_u16(0x2A06);
+
+    /// Tx Power Level.
+    pub const TX_POWER_LEVEL: Uuid = Uuid::from_u16(0x2A07);
+
+    /// Date Time.
+    pub const DATE_TIME: Uuid = Uuid::from_u16(0x2A08);
+
+    /// Day of Week.
+    pub const DAY_OF_WEEK: Uuid = Uuid::from_u16(0x2A09);
+
+    /// Day Date Time.
+    pub const DAY_DATE_TIME: Uuid = Uuid::from_u16(0x2A0A);
+
+    /// Exact Time 256.
+    pub const EXACT_TIME_256: Uuid = Uuid::from_u16(0x2A0C);
+
+    /// DST Offset.
+    pub const DST_OFFSET: Uuid = Uuid::from_u16(0x2A0D);
+
+    /// Time Zone.
+    pub const TIME_ZONE: Uuid = Uuid::from_u16(0x2A0E);
+
+    /// Local Time Information.
+    pub const LOCAL_TIME_INFORMATION: Uuid = Uuid::from_u16(0x2A0F);
+
+    /// Time with DST.
+    pub const TIME_WITH_DST: Uuid = Uuid::from_u16(0x2A11);
+
+    /// Time Accuracy.
+    pub const TIME_ACCURACY: Uuid = Uuid::from_u16(0x2A12);
+
+    /// Time Source.
+    pub const TIME_SOURCE: Uuid = Uuid::from_u16(0x2A13);
+
+    /// Reference Time Information.
+    pub const REFERENCE_TIME_INFORMATION: Uuid = Uuid::from_u16(0x2A14);
+
+    /// Time Update Control Point.
+    pub const TIME_UPDATE_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A16);
+
+    /// Time Update State.
+    pub const TIME_UPDATE_STATE: Uuid = Uuid::from_u16(0x2A17);
+
+    /// Glucose Measurement.
+    pub const GLUCOSE_MEASUREMENT: Uuid = Uuid::from_u16(0x2A18);
+
+    /// Battery Level.
+    pub const BATTERY_LEVEL: Uuid = Uuid::from_u16(0x2A19);
+
+    /// Temperature Measurement.
+    pub const TEMPERATURE_MEASUREMENT: Uuid = Uuid::from_u16(0x2A1C);
+
+    /// Temperature Type.
+    pub const TEMPERATURE_TYPE: Uuid = Uuid::from_u16(0x2A1D);
+
+    /// Intermediate Temperature.
+    pub const INTERMEDIATE_TEMPERATURE: Uuid = Uuid::from_u16(0x2A1E);
+
+    /// Measurement Interval.
+    pub const MEASUREMENT_INTERVAL: Uuid = Uuid::from_u16(0x2A21);
+
+    /// Boot Keyboard Input Report.
+    pub const BOOT_KEYBOARD_INPUT_REPORT: Uuid = Uuid::from_u16(0x2A22);
+
+    /// System ID.
+    pub const SYSTEM_ID: Uuid = Uuid::from_u16(0x2A23);
+
+    /// Model Number String.
+    pub const MODEL_NUMBER_STRING: Uuid = Uuid::from_u16(0x2A24);
+
+    /// Serial Number String.
+    pub const SERIAL_NUMBER_STRING: Uuid = Uuid::from_u16(0x2A25);
+
+    /// Firmware Revision String.
+    pub const FIRMWARE_REVISION_STRING: Uuid = Uuid::from_u16(0x2A26);
+
+    /// Hardware Revision String.
+    pub const HARDWARE_REVISION_STRING: Uuid = Uuid::from_u16(0x2A27);
+
+    /// Software Revision String.
+    pub const SOFTWARE_REVISION_STRING: Uuid = Uuid::from_u16(0x2A28);
+
+    /// Manufacturer Name String.
+    pub const MANUFACTURER_NAME_STRING: Uuid = Uuid::from_u16(0x2A29);
+
+    /// IEEE 11073-20601 Regulatory Certification Data List.
+    pub const IEEE_11073_20601_REGULATORY_CERTIFICATION_DATA_LIST: Uuid = Uuid::from_u16(0x2A2A);
+
+    /// Current Time.
+    pub const CURRENT_TIME: Uuid = Uuid::from_u16(0x2A2B);
+
+    /// Boot Keyboard Output Report.
+    pub const BOOT_KEYBOARD_OUTPUT_REPORT: Uuid = Uuid::from_u16(0x2A32);
+
+    /// Boot Mouse Input Report.
+    pub const BOOT_MOUSE_INPUT_REPORT: Uuid = Uuid::from_u16(0x2A33);
+
+    /// Glucose Measurement Context.
+    pub const GLUCOSE_MEASUREMENT_CONTEXT: Uuid = Uuid::from_u16(0x2A34);
+
+    /// Blood Pressure Measurement.
+    pub const BLOOD_PRESSURE_MEASUREMENT: Uuid = Uuid::from_u16(0x2A35);
+
+    /// Intermediate Cuff Pressure.
+    pub const INTERMEDIATE_CUFF_PRESSURE: Uuid = Uuid::from_u16(0x2A36);
+
+    /// Heart Rate Measurement.
+    pub const HEART_RATE_MEASUREMENT: Uuid = Uuid::from_u16(0x2A37);
+
+    /// Body Sensor Location.
+    pub const BODY_SENSOR_LOCATION: Uuid = Uuid::from_u16(0x2A38);
+
+    /// Heart Rate Control Point.
+    pub const HEART_RATE_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A39);
+
+    /// Alert Status.
+    pub const ALERT_STATUS: Uuid = Uuid::from_u16(0x2A3F);
+
+    /// Ringer Control Point.
+    pub const RINGER_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A40);
+
+    /// Ringer Setting.
+    pub const RINGER_SETTING: Uuid = Uuid::from_u16(0x2A41);
+
+    /// Alert Category ID Bit Mask.
+    pub const ALERT_CATEGORY_ID_BIT_MASK: Uuid = Uuid::from_u16(0x2A42);
+
+    /// Alert Category ID.
+    pub const ALERT_CATEGORY_ID: Uuid = Uuid::from_u16(0x2A43);
+
+    /// Alert Notification Control Point.
+    pub const ALERT_NOTIFICATION_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A44);
+
+    /// Unread Alert Status.
+    pub const UNREAD_ALERT_STATUS: Uuid = Uuid::from_u16(0x2A45);
+
+    /// New Alert.
+    pub const NEW_ALERT: Uuid = Uuid::from_u16(0x2A46);
+
+    /// Supported New Alert Category.
+    pub const SUPPORTED_NEW_ALERT_CATEGORY: Uuid = Uuid::from_u16(0x2A47);
+
+    /// Supported Unread Alert Category.
+    pub const SUPPORTED_UNREAD_ALERT_CATEGORY: Uuid = Uuid::from_u16(0x2A48);
+
+    /// Blood Pressure Feature.
+    pub const BLOOD_PRESSURE_FEATURE: Uuid = Uuid::from_u16(0x2A49);
+
+    /// HID Information.
+    pub const HID_INFORMATION: Uuid = Uuid::from_u16(0x2A4A);
+
+    /// Report Map.
+    pub const REPORT_MAP: Uuid = Uuid::from_u16(0x2A4B);
+
+    /// HID Control Point.
+    pub const HID_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A4C);
+
+    /// Report.
+    pub const REPORT: Uuid = Uuid::from_u16(0x2A4D);
+
+    /// Protocol Mode.
+    pub const PROTOCOL_MODE: Uuid = Uuid::from_u16(0x2A4E);
+
+    /// Scan Interval Window.
+    pub const SCAN_INTERVAL_WINDOW: Uuid = Uuid::from_u16(0x2A4F);
+
+    /// PnP ID.
+    pub const PNP_ID: Uuid = Uuid::from_u16(0x2A50);
+
+    /// Glucose Feature.
+    pub const GLUCOSE_FEATURE: Uuid = Uuid::from_u16(0x2A51);
+
+    /// Record Access Control Point.
+    pub const RECORD_ACCESS_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A52);
+
+    /// RSC Measurement.
+    pub const RSC_MEASUREMENT: Uuid = Uuid::from_u16(0x2A53);
+
+    /// RSC Feature.
+    pub const RSC_FEATURE: Uuid = Uuid::from_u16(0x2A54);
+
+    /// SC Control Point.
+    pub const SC_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A55);
+
+    /// CSC Measurement.
+    pub const CSC_MEASUREMENT: Uuid = Uuid::from_u16(0x2A5B);
+
+    /// CSC Feature.
+    pub const CSC_FEATURE: Uuid = Uuid::from_u16(0x2A5C);
+
+    /// Sensor Location.
+    pub const SENSOR_LOCATION: Uuid = Uuid::from_u16(0x2A5D);
+
+    /// PLX Spot-Check Measurement.
+    pub const PLX_SPOT_CHECK_MEASUREMENT: Uuid = Uuid::from_u16(0x2A5E);
+
+    /// PLX Continuous Measurement.
+    pub const PLX_CONTINUOUS_MEASUREMENT: Uuid = Uuid::from_u16(0x2A5F);
+
+    /// PLX Features.
+    pub const PLX_FEATURES: Uuid = Uuid::from_u16(0x2A60);
+
+    /// Cycling Power Measurement.
+    pub const CYCLING_POWER_MEASUREMENT: Uuid = Uuid::from_u16(0x2A63);
+
+    /// Cycling Power Vector.
+    pub const CYCLING_POWER_VECTOR: Uuid = Uuid::from_u16(0x2A64);
+
+    /// Cycling Power Feature.
+    pub const CYCLING_POWER_FEATURE: Uuid = Uuid::from_u16(0x2A65);
+
+    /// Cycling Power Control Point.
+    pub const CYCLING_POWER_CONTROL_POINT: Uuid = Uuid::from_u16(0x2A66);
+
+    /// Location and Speed.
+    pub const LOCATION_AND_SPEED: Uuid = Uuid::from_u16(0x2A67);
+
+    /// Navigation.
+    pub const NAVIGATION: Uuid = Uuid::from_u16(0x2A68);
+
+    /// Elevation.
+    pub const ELEVATION: Uuid = Uuid::from_u16(0x2A6C);
+
+    /// Pressure.
+    pub const PRESSURE: Uuid = Uuid::from_u16(0x2A6D);
+
+    /// Temperature.
+    pub const TEMPERATURE: Uuid = Uuid::from_u16(0x2A6E);
+
+    /// Humidity.
+    pub const HUMIDITY: Uuid = Uuid::from_u16(0x2A6F);
+
+    /// Weight Measurement.
+    pub const WEIGHT_MEASUREMENT: Uuid = Uuid::from_u16(0x2A9D);
+
+    /// Weight Scale Feature.
+    pub const WEIGHT_SCALE_FEATURE: Uuid = Uuid::from_u16(0x2A9E);
+
+    /// Central Address Resolution.
+    pub const CENTRAL_ADDRESS_RESOLUTION: Uuid = Uuid::from_u16(0x2AA6);
+}
+
+pub mod descriptor {
+    //! GATT descriptors.
+
+    use crate::uuid::Uuid;
+
+    /// Characteristic Extended Properties.
+    pub const CHARACTERISTIC_EXTENDED_PROPERTIES: Uuid = Uuid::from_u16(0x2900);
+
+    /// Characteristic User Description.
+    pub const CHARACTERISTIC_USER_DESCRIPTION: Uuid = Uuid::from_u16(0x2901);
+
+    /// Client Characteristic Configuration.
+    pub const CLIENT_CHARACTERISTIC_CONFIGURATION: Uuid = Uuid::from_u16(0x2902);
+
+    /// Server Characteristic Configuration.
+    pub const SERVER_CHARACTERISTIC_CONFIGURATION: Uuid = Uuid::from_u16(0x2903);
+
+    /// Characteristic Presentation Format.
+    pub const CHARACTERISTIC_PRESENTATION_FORMAT: Uuid = Uuid::from_u16(0x2904);
+
+    /// Characteristic Aggregate Format.
+    pub const CHARACTERISTIC_AGGREGATE_FORMAT: Uuid = Uuid::from_u16(0x2905);
+
+    /// Valid Range.
+    pub const VALID_RANGE: Uuid = Uuid::from_u16(0x2906);
+
+    /// External Report Reference.
+    pub const EXTERNAL_REPORT_REFERENCE: Uuid = Uuid::from_u16(0x2907);
+
+    /// Report Reference.
+    pub const REPORT_REFERENCE: Uuid = Uuid::from_u16(0x2908);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::uuid::Uuid;
+
+    #[test]
+    fn canonical() {
+        let data = &[
+            (service::HEART_RATE, "0000180d-0000-1000-8000-00805f9b34fb"),
+            (characteristic::BATTERY_LEVEL, "00002a19-0000-1000-8000-00805f9b34fb"),
+            (descriptor::CLIENT_CHARACTERISTIC_CONFIGURATION, "00002902-0000-1000-8000-00805f9b34fb"),
+        ];
+        for &(uuid, s) in data {
+            assert_eq!(uuid, s.parse::<Uuid>().unwrap());
+        }
+    }
+}
@@ -38,15 +38,15 @@
 //!                 central.connect(&peripheral);
 //!             }
 //!         }
-//!         CentralEvent::PeripheralConnected { peripheral } => {
+//!         CentralEvent::PeripheralConnected { peripheral, .. } => {
 //!             peripheral.discover_services_with_uuids(&[
-//!                 "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap()]);
+//!                 uuid!("ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6")]);
 //!         }
 //!         CentralEvent::ServicesDiscovered { peripheral, services } => {
 //!             if let Ok(services) = services {
 //!                 for service in services {
 //!                     peripheral.discover_characteristics_with_uuids(&service, &[
-//!                         "ebe0ccc1-7a0a-4b0c-8a1a-6ff2997da3a6".parse().unwrap()]);
+//!                         uuid!("ebe0ccc1-7a0a-4b0c-8a1a-6ff2997da3a6")]);
 //!                 }
 //!             }
 //!         }
@@ -92,21 +92,77 @@ mod macros;
 
 pub mod central;
 pub mod error;
+pub mod gatt;
+pub mod known;
 mod platform;
 mod sync;
 pub mod uuid;
 mod util;
 
 use static_assertions::*;
+use std::any::Any;
+use std::fmt;
 
 pub use sync::Receiver;
 
 /// Arbitrary data to associate with asynchronous API call.
-pub type Tag = Box<dyn std::any::Any + Send>;
+///
+/// ```
+/// use core_bluetooth::Tag;
+///
+/// let tag = Tag::new(42u64);
+/// assert_eq!(tag.downcast_ref::<u64>(), Some(&42));
+/// assert_eq!(tag.into::<u64>().ok(), Some(42));
+/// ```
+pub struct Tag(Box<dyn Any + Send>);
 
 assert_impl_all!(Tag: Send);
 assert_not_impl_any!(Tag: Sync);
 
+impl Tag {
+    /// Constructs tag holding the `value`.
+    pub fn new<T: Any + Send>(value: T) -> Self {
+        Self(Box::new(value))
+    }
+
+    /// Returns `true` if the tag holds value of type `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.0.is::<T>()
+    }
+
+    /// Returns reference to the tag value if it's of type `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Returns mutable reference to the tag value if it's of type `T`.
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.0.downcast_mut()
+    }
+
+    /// Consumes the tag and returns its value if it's of type `T`. Otherwise returns the tag back.
+    pub fn into<T: Any>(self) -> Result<T, Self> {
+        self.0.downcast().map(|v| *v).map_err(Self)
+    }
+
+    /// Consumes the tag and returns the boxed value.
+    pub fn into_inner(self) -> Box<dyn Any + Send> {
+        self.0
+    }
+}
+
+impl From<Box<dyn Any + Send>> for Tag {
+    fn from(v: Box<dyn Any + Send>) -> Self {
+        Self(v)
+    }
+}
+
+impl fmt::Debug for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Tag(..)")
+    }
+}
+
 /// The possible states of a Core Bluetooth manager.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[non_exhaustive]
@@ -142,4 +198,60 @@ impl ManagerState {
             _ => return None,
         })
     }
+
+    /// Returns whether the device supports the Bluetooth low energy role of the manager:
+    /// `Some(false)` in the [`Unsupported`](#variant.Unsupported) state and `Some(true)` in
+    /// the [`Unauthorized`](#variant.Unauthorized), [`PoweredOff`](#variant.PoweredOff) and
+    /// [`PoweredOn`](#variant.PoweredOn) states. Returns `None` in the
+    /// [`Unknown`](#variant.Unknown) and [`Resetting`](#variant.Resetting) states because
+    /// the support can't be determined yet.
+    pub fn is_le_supported(&self) -> Option<bool> {
+        use ManagerState::*;
+        match self {
+            Unknown | Resetting => None,
+            Unsupported => Some(false),
+            Unauthorized | PoweredOff | PoweredOn => Some(true),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use central::CentralEvent;
+    use central::peripheral::MaxWriteLen;
+
+    #[test]
+    fn tag_round_trip() {
+        let event = CentralEvent::GetMaxWriteLenResult {
+            max_write_len: MaxWriteLen {
+                with_response: 512,
+                without_response: 20,
+            },
+            tag: Some(Tag::new(42u64)),
+        };
+        let tag = match event {
+            CentralEvent::GetMaxWriteLenResult { tag, .. } => tag.unwrap(),
+            _ => unreachable!(),
+        };
+        assert!(tag.is::<u64>());
+        assert!(tag.downcast_ref::<u32>().is_none());
+        let tag = tag.into::<i64>().unwrap_err();
+        assert_eq!(tag.into::<u64>().unwrap(), 42);
+    }
+
+    #[test]
+    fn is_le_supported() {
+        use ManagerState::*;
+        for &(state, supported) in &[
+            (Unknown, None),
+            (Resetting, None),
+            (Unsupported, Some(false)),
+            (Unauthorized, Some(true)),
+            (PoweredOff, Some(true)),
+            (PoweredOn, Some(true)),
+        ] {
+            assert_eq!(state.is_le_supported(), supported, "{:?}", state);
+        }
+    }
 }
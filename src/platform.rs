@@ -27,6 +27,8 @@ extern {
     pub(in crate) static CBCentralManagerScanOptionAllowDuplicatesKey: NSString;
     pub(in crate) static CBCentralManagerScanOptionSolicitedServiceUUIDsKey: NSString;
     pub(in crate) static CBCentralManagerOptionShowPowerAlertKey: NSString;
+    #[cfg(target_os = "ios")]
+    pub(in crate) static CBConnectPeripheralOptionRequiresANCS: NSString;
     pub(in crate) static CBErrorDomain: NSString;
     pub(in crate) static CBATTErrorDomain: NSString;
 }
@@ -127,11 +129,18 @@ impl<T: ObjectPtr> ObjectPtr for StrongPtr<T> {
 #[allow(non_camel_case_types)]
 pub type dispatch_function_t = extern fn(*mut c_void);
 
+#[allow(non_camel_case_types)]
+pub type dispatch_time_t = u64;
+
 pub const DISPATCH_QUEUE_SERIAL: *mut Object = ptr::null_mut();
+pub const DISPATCH_TIME_NOW: dispatch_time_t = 0;
 
 extern "C" {
+    pub fn dispatch_after_f(when: dispatch_time_t, queue: *mut Object, context: *mut c_void,
+        work: dispatch_function_t);
     pub fn dispatch_async_f(queue: *mut Object, context: *mut c_void, work: dispatch_function_t);
     pub fn dispatch_queue_create(label: *const c_char, attr: *mut Object) -> *mut Object;
+    pub fn dispatch_time(when: dispatch_time_t, delta: i64) -> dispatch_time_t;
 }
 
 object_ptr_wrapper!(NSNumber);
@@ -162,6 +171,15 @@ impl NSNumber {
 object_ptr_wrapper!(NSString);
 
 impl NSString {
+    #[cfg(all(test, target_os = "macos"))]
+    pub fn from_str(s: &str) -> Self {
+        let s = std::ffi::CString::new(s).unwrap();
+        unsafe {
+            let r: *mut Object = msg_send![class!(NSString), stringWithUTF8String:s.as_ptr()];
+            Self::wrap(r)
+        }
+    }
+
     pub fn as_str(&self) -> &str {
         unsafe {
             let r: *const c_char = msg_send![self.as_ptr(), UTF8String];
@@ -1,6 +1,39 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+
+/// Error returned by `Sender::try_send`.
+pub enum TrySendError {
+    Full,
+    Disconnected,
+}
+
+/// Senders of `Routes`. They're reference counted so a sender can be told apart from the one that
+/// replaced it.
+type RouteSenders<K, S> = Mutex<HashMap<K, Arc<S>>>;
+
+/// Looks up the sender for the route `key`.
+fn route_sender<K: Eq + Hash, S>(routes: &RouteSenders<K, S>, key: K) -> Option<(K, Arc<S>)> {
+    let sender = routes.lock().unwrap().get(&key)?.clone();
+    Some((key, sender))
+}
+
+/// Removes the route `key` after its receiver was dropped, unless `sender` was replaced since.
+fn remove_route<K: Eq + Hash, S>(routes: &RouteSenders<K, S>, key: K, sender: &Arc<S>) {
+    let mut routes = routes.lock().unwrap();
+    if matches!(routes.get(&key), Some(s) if Arc::ptr_eq(s, sender)) {
+        routes.remove(&key);
+    }
+}
+
 #[cfg(not(feature = "async_std_unstable"))]
 mod imp {
-    use std::sync::mpsc;
+    use std::hash::Hash;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{remove_route, route_sender, RouteSenders, TrySendError};
 
     pub struct Sender<T>(mpsc::SyncSender<T>);
 
@@ -9,30 +42,193 @@ mod imp {
         pub fn send_blocking(&self, item: T) -> bool {
             self.0.send(item).is_ok()
         }
+
+        /// Blocks until the `item` is sent or `timeout` elapses. Returns `TrySendError::Full` on
+        /// timeout.
+        ///
+        /// The channel must be buffered: over a rendezvous channel the item can only be sent while
+        /// the receiver is parked in a blocking receive.
+        pub fn send_timeout(&self, mut item: T, timeout: Duration) -> Result<(), TrySendError> {
+            // mpsc::SyncSender has no send with timeout, so poll with a short sleep instead.
+            const POLL_INTERVAL: Duration = Duration::from_millis(1);
+            let deadline = Instant::now() + timeout;
+            loop {
+                match self.0.try_send(item) {
+                    Ok(()) => return Ok(()),
+                    Err(mpsc::TrySendError::Disconnected(_)) => return Err(TrySendError::Disconnected),
+                    Err(mpsc::TrySendError::Full(v)) => item = v,
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(TrySendError::Full);
+                }
+                thread::sleep(POLL_INTERVAL.min(deadline - now));
+            }
+        }
+
+        pub fn try_send(&self, item: T) -> Result<(), TrySendError> {
+            self.0.try_send(item).map_err(|e| match e {
+                mpsc::TrySendError::Full(_) => TrySendError::Full,
+                mpsc::TrySendError::Disconnected(_) => TrySendError::Disconnected,
+            })
+        }
     }
 
     /// Receiving end of channel.
     pub type Receiver<T> = mpsc::Receiver<T>;
 
     pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
-        let (s, r) = mpsc::sync_channel(0);
+        bounded_channel(0)
+    }
+
+    pub fn bounded_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        let (s, r) = mpsc::sync_channel(capacity);
         (Sender(s), r)
     }
+
+    /// Blocks until an item is received or `timeout` elapses. Returns `None` on timeout or if the
+    /// channel is disconnected.
+    pub(in crate) fn recv_timeout<T>(receiver: &Receiver<T>, timeout: Duration) -> Option<T> {
+        receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Blocks until an item is received. Returns `None` if the channel is disconnected.
+    pub(in crate) fn recv_blocking<T>(receiver: &Receiver<T>) -> Option<T> {
+        receiver.recv().ok()
+    }
+
+    /// Receives an item if one is available without blocking.
+    pub(in crate) fn try_recv<T>(receiver: &Receiver<T>) -> Option<T> {
+        receiver.try_recv().ok()
+    }
+
+    /// Forwards items from `receiver` on a background thread to one of the two returned receivers:
+    /// the first receives items for which `f` returns `Some` along with the returned value,
+    /// the second receives the rest. Forwarding stops when `receiver` is disconnected.
+    /// The returned receivers are rendezvous channels like the one returned by `channel`.
+    pub(in crate) fn split<T, U, F>(receiver: Receiver<T>, mut f: F) -> (Receiver<(T, U)>, Receiver<T>)
+        where T: Send + 'static,
+              U: Send + 'static,
+              F: FnMut(&T) -> Option<U> + Send + 'static,
+    {
+        let (matched_s, matched_r) = mpsc::sync_channel(0);
+        let (rest_s, rest_r) = mpsc::sync_channel(0);
+        thread::spawn(move || {
+            for item in receiver {
+                // A receiver that was dropped simply doesn't get the items.
+                let _ = match f(&item) {
+                    Some(v) => matched_s.send((item, v)).is_ok(),
+                    None => rest_s.send(item).is_ok(),
+                };
+            }
+        });
+        (matched_r, rest_r)
+    }
+
+    /// Forwards items from `receiver` for which `f` returns `true` on a background thread to
+    /// the returned receiver. Forwarding stops when `receiver` is disconnected or the returned
+    /// receiver is dropped.
+    /// The returned receiver is a rendezvous channel like the one returned by `channel`.
+    pub(in crate) fn filter<T, F>(receiver: Receiver<T>, mut f: F) -> Receiver<T>
+        where T: Send + 'static,
+              F: FnMut(&T) -> bool + Send + 'static,
+    {
+        let (s, r) = mpsc::sync_channel(0);
+        thread::spawn(move || {
+            for item in receiver {
+                if f(&item) && s.send(item).is_err() {
+                    break;
+                }
+            }
+        });
+        r
+    }
+
+    /// Receivers of items routed by `route`, keyed by route key.
+    pub(in crate) struct Routes<K, T>(Arc<RouteSenders<K, mpsc::SyncSender<T>>>);
+
+    impl<K: Eq + Hash, T> Routes<K, T> {
+        /// Returns receiver of items with the `key`, replacing the previously returned one.
+        pub fn add(&self, key: K) -> Receiver<T> {
+            let (s, r) = mpsc::sync_channel(0);
+            self.0.lock().unwrap().insert(key, Arc::new(s));
+            r
+        }
+    }
+
+    /// Forwards items from `receiver` on a background thread to receivers added to the returned
+    /// `Routes` under the key returned by `f`. Items without key or receiver for their key are
+    /// forwarded to the returned receiver. Forwarding stops when `receiver` is disconnected.
+    /// All receivers are rendezvous channels like the one returned by `channel`.
+    ///
+    /// A route whose receiver was dropped is removed when an item fails to be sent to it, so the
+    /// following items with its key are forwarded to the returned receiver.
+    pub(in crate) fn route<T, K, F>(receiver: Receiver<T>, mut f: F) -> (Routes<K, T>, Receiver<T>)
+        where T: Send + 'static,
+              K: Eq + Hash + Send + 'static,
+              F: FnMut(&T) -> Option<K> + Send + 'static,
+    {
+        let routes: Arc<RouteSenders<K, mpsc::SyncSender<T>>> = Default::default();
+        let (rest_s, rest_r) = mpsc::sync_channel(0);
+        let thread_routes = routes.clone();
+        thread::spawn(move || {
+            for item in receiver {
+                match f(&item).and_then(|k| route_sender(&thread_routes, k)) {
+                    Some((key, s)) => if s.send(item).is_err() {
+                        remove_route(&thread_routes, key, &s);
+                    }
+                    // A receiver that was dropped simply doesn't get the items.
+                    None => {
+                        let _ = rest_s.send(item);
+                    }
+                }
+            }
+        });
+        (Routes(routes), rest_r)
+    }
 }
 
 #[cfg(feature = "async_std_unstable")]
 mod imp {
     use async_std::sync;
+    use std::future::Future;
+    use std::hash::Hash;
+    use std::sync::Arc;
+    use std::sync::atomic::{self, AtomicBool, Ordering};
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::time::Duration;
+
+    use super::{remove_route, route_sender, RouteSenders, TrySendError};
 
     pub struct Sender<T>(sync::Sender<T>);
 
     impl<T> Sender<T> {
         #[must_use]
         pub fn send_blocking(&self, item: T) -> bool {
-            async_std::task::block_on(async {
-                self.0.send(item).await;
-                true
-            })
+            async_std::task::block_on(send(&self.0, item))
+        }
+
+        /// Blocks until the `item` is sent or `timeout` elapses. Returns `TrySendError::Full` on
+        /// timeout.
+        pub fn send_timeout(&self, item: T, timeout: Duration) -> Result<(), TrySendError> {
+            let sent = async_std::future::timeout(timeout, send(&self.0, item));
+            match async_std::task::block_on(sent) {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(TrySendError::Disconnected),
+                Err(_) => Err(TrySendError::Full),
+            }
+        }
+
+        /// Note the fullness check is only reliable with a single sender, which is always the case
+        /// for the central manager channel.
+        pub fn try_send(&self, item: T) -> Result<(), TrySendError> {
+            if self.0.is_full() {
+                Err(TrySendError::Full)
+            } else if self.send_blocking(item) {
+                Ok(())
+            } else {
+                Err(TrySendError::Disconnected)
+            }
         }
     }
 
@@ -40,9 +236,164 @@ mod imp {
     pub type Receiver<T> = sync::Receiver<T>;
 
     pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
-        let (s, r) = sync::channel(1);
+        bounded_channel(1)
+    }
+
+    pub fn bounded_channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+        let (s, r) = sync::channel(capacity.max(1));
         (Sender(s), r)
     }
+
+    /// Blocks until an item is received or `timeout` elapses. Returns `None` on timeout or if the
+    /// channel is disconnected.
+    pub(in crate) fn recv_timeout<T>(receiver: &Receiver<T>, timeout: Duration) -> Option<T> {
+        async_std::task::block_on(async_std::future::timeout(timeout, receiver.recv()))
+            .ok()
+            .flatten()
+    }
+
+    /// Blocks until an item is received. Returns `None` if the channel is disconnected.
+    pub(in crate) fn recv_blocking<T>(receiver: &Receiver<T>) -> Option<T> {
+        async_std::task::block_on(receiver.recv())
+    }
+
+    /// Receives an item if one is available without blocking. The `receiver` must not be shared
+    /// with other consumers.
+    pub(in crate) fn try_recv<T>(receiver: &Receiver<T>) -> Option<T> {
+        if receiver.is_empty() {
+            None
+        } else {
+            recv_blocking(receiver)
+        }
+    }
+
+    /// Sends `item` to the channel unless it's disconnected. Returns `false` if it is, in which
+    /// case the `item` is dropped.
+    ///
+    /// `sync::Sender` doesn't tell whether its receivers are dropped, and its `send` stays pending
+    /// forever when the channel is disconnected and full. Such a send doesn't hold on to the waker
+    /// though, which is what tells it apart from a send waiting for room in the channel.
+    async fn send<T>(sender: &sync::Sender<T>, item: T) -> bool {
+        struct Probe {
+            task: Waker,
+            woken: AtomicBool,
+        }
+
+        impl Wake for Probe {
+            fn wake(self: Arc<Self>) {
+                self.wake_by_ref();
+            }
+
+            fn wake_by_ref(self: &Arc<Self>) {
+                self.woken.store(true, Ordering::SeqCst);
+                self.task.wake_by_ref();
+            }
+        }
+
+        let mut send = Box::pin(sender.send(item));
+        async_std::future::poll_fn(|cx| {
+            let probe = Arc::new(Probe {
+                task: cx.waker().clone(),
+                woken: AtomicBool::new(false),
+            });
+            let waker = Waker::from(probe.clone());
+            if send.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+                return Poll::Ready(true);
+            }
+            drop(waker);
+            let kept = Arc::strong_count(&probe) > 1;
+            // Pairs with the release of the dropped waker clones.
+            atomic::fence(Ordering::Acquire);
+            if kept || probe.woken.load(Ordering::SeqCst) {
+                Poll::Pending
+            } else {
+                Poll::Ready(false)
+            }
+        }).await
+    }
+
+    /// Forwards items from `receiver` on a background task to one of the two returned receivers:
+    /// the first receives items for which `f` returns `Some` along with the returned value,
+    /// the second receives the rest. Forwarding stops when `receiver` is disconnected.
+    pub(in crate) fn split<T, U, F>(receiver: Receiver<T>, mut f: F) -> (Receiver<(T, U)>, Receiver<T>)
+        where T: Send + 'static,
+              U: Send + 'static,
+              F: FnMut(&T) -> Option<U> + Send + 'static,
+    {
+        let (matched_s, matched_r) = sync::channel(1);
+        let (rest_s, rest_r) = sync::channel(1);
+        async_std::task::spawn(async move {
+            while let Some(item) = receiver.recv().await {
+                // A receiver that was dropped simply doesn't get the items.
+                let _ = match f(&item) {
+                    Some(v) => send(&matched_s, (item, v)).await,
+                    None => send(&rest_s, item).await,
+                };
+            }
+        });
+        (matched_r, rest_r)
+    }
+
+    /// Forwards items from `receiver` for which `f` returns `true` on a background task to
+    /// the returned receiver. Forwarding stops when `receiver` is disconnected or the returned
+    /// receiver is dropped.
+    pub(in crate) fn filter<T, F>(receiver: Receiver<T>, mut f: F) -> Receiver<T>
+        where T: Send + 'static,
+              F: FnMut(&T) -> bool + Send + 'static,
+    {
+        let (s, r) = sync::channel(1);
+        async_std::task::spawn(async move {
+            while let Some(item) = receiver.recv().await {
+                if f(&item) && !send(&s, item).await {
+                    break;
+                }
+            }
+        });
+        r
+    }
+
+    /// Receivers of items routed by `route`, keyed by route key.
+    pub(in crate) struct Routes<K, T>(Arc<RouteSenders<K, sync::Sender<T>>>);
+
+    impl<K: Eq + Hash, T> Routes<K, T> {
+        /// Returns receiver of items with the `key`, replacing the previously returned one.
+        pub fn add(&self, key: K) -> Receiver<T> {
+            let (s, r) = sync::channel(1);
+            self.0.lock().unwrap().insert(key, Arc::new(s));
+            r
+        }
+    }
+
+    /// Forwards items from `receiver` on a background task to receivers added to the returned
+    /// `Routes` under the key returned by `f`. Items without key or receiver for their key are
+    /// forwarded to the returned receiver. Forwarding stops when `receiver` is disconnected.
+    ///
+    /// A route whose receiver was dropped is removed when an item fails to be sent to it, so the
+    /// following items with its key are forwarded to the returned receiver. The channel buffers
+    /// one item, which is lost along with the receiver.
+    pub(in crate) fn route<T, K, F>(receiver: Receiver<T>, mut f: F) -> (Routes<K, T>, Receiver<T>)
+        where T: Send + 'static,
+              K: Eq + Hash + Send + 'static,
+              F: FnMut(&T) -> Option<K> + Send + 'static,
+    {
+        let routes: Arc<RouteSenders<K, sync::Sender<T>>> = Default::default();
+        let (rest_s, rest_r) = sync::channel(1);
+        let task_routes = routes.clone();
+        async_std::task::spawn(async move {
+            while let Some(item) = receiver.recv().await {
+                match f(&item).and_then(|k| route_sender(&task_routes, k)) {
+                    Some((key, s)) => if !send(&s, item).await {
+                        remove_route(&task_routes, key, &s);
+                    }
+                    // A receiver that was dropped simply doesn't get the items.
+                    None => {
+                        send(&rest_s, item).await;
+                    }
+                }
+            }
+        });
+        (Routes(routes), rest_r)
+    }
 }
 
 pub use imp::*;
\ No newline at end of file
@@ -1,6 +1,7 @@
 use objc::*;
 use objc::runtime::*;
 use static_assertions::assert_impl_all;
+use std::convert::TryFrom;
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 
@@ -9,6 +10,49 @@ use std::str::FromStr;
 
 const BASE_UUID_BYTES: [u8; 16] = [0, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0, 0x80, 0x5F, 0x9B, 0x34, 0xFB];
 
+/// Constructs [`Uuid`](uuid/struct.Uuid.html) from a string literal at compile time.
+///
+/// Besides the standard hyphenated form the literal can contain just 4 or 8 hex digits of
+/// `uuid16` or `uuid32`, which are expanded using the
+/// [Base UUID](uuid/struct.Uuid.html#method.base).
+///
+/// ```
+/// use core_bluetooth::uuid;
+/// use core_bluetooth::uuid::Uuid;
+///
+/// const SERVICE: Uuid = uuid!("ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6");
+/// assert_eq!(uuid!("180d"), Uuid::from_u16(0x180d));
+/// assert_eq!(uuid!("0000180d"), uuid!("0000180d-0000-1000-8000-00805f9b34fb"));
+/// ```
+///
+/// A malformed literal fails the build:
+///
+/// ```compile_fail,E0080
+/// # use core_bluetooth::uuid;
+/// # use core_bluetooth::uuid::Uuid;
+/// const SERVICE: Uuid = uuid!("ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3az");
+/// ```
+///
+/// ```compile_fail,E0080
+/// # use core_bluetooth::uuid;
+/// let _ = uuid!("ebe0ccb0-7a0a-4b0c-8a1a_6ff2997da3a6");
+/// ```
+///
+/// ```compile_fail,E0080
+/// # use core_bluetooth::uuid;
+/// let _ = uuid!("180");
+/// ```
+#[macro_export]
+macro_rules! uuid {
+    ($s:literal) => {{
+        const UUID: $crate::uuid::Uuid = match $crate::uuid::Uuid::__parse_literal($s) {
+            Some(v) => v,
+            None => panic!(concat!("invalid UUID literal: ", $s)),
+        };
+        UUID
+    }};
+}
+
 /// Bluetooth-tailored UUID.
 #[derive(Clone, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Uuid([u8; 16]);
@@ -29,14 +73,94 @@ impl Uuid {
         Self(bytes)
     }
 
+    /// Constructs instance from the specified `uuid16` value, using the [Base UUID](#method.base).
+    pub const fn from_u16(v: u16) -> Self {
+        Self::from_u32(v as u32)
+    }
+
+    /// Constructs instance from the specified `uuid32` value, using the [Base UUID](#method.base).
+    pub const fn from_u32(v: u32) -> Self {
+        let b = BASE_UUID_BYTES;
+        Self([(v >> 24) as u8, (v >> 16) as u8, (v >> 8) as u8, v as u8,
+            b[4], b[5], b[6], b[7], b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15]])
+    }
+
+    #[doc(hidden)]
+    pub const fn __parse_literal(s: &str) -> Option<Self> {
+        Self::parse(s.as_bytes(), true)
+    }
+
+    /// Parses the string form returned by `-[CBUUID UUIDString]`: 4 or 8 hex digits for UUIDs
+    /// derived from the Base UUID, the standard hyphenated form otherwise.
+    #[cfg(test)]
+    pub(in crate) fn from_cbuuid_string(s: &str) -> Option<Self> {
+        Self::parse(s.as_bytes(), true)
+    }
+
+    /// Parses the standard hyphenated string form. If `short` is `true` also accepts 4 and 8 hex
+    /// digits of `uuid16` and `uuid32` respectively.
+    const fn parse(s: &[u8], short: bool) -> Option<Self> {
+        const fn dig(c: u8) -> Option<u8> {
+            Some(match c {
+                b'0'..=b'9' => c - b'0',
+                b'a'..=b'f' => c - b'a' + 10,
+                b'A'..=b'F' => c - b'A' + 10,
+                _ => return None,
+            })
+        }
+
+        let full = match s.len() {
+            4 | 8 if short => false,
+            36 => true,
+            _ => return None,
+        };
+        let mut buf = BASE_UUID_BYTES;
+        let mut i = 0;
+        let mut j = 0;
+        while i < s.len() {
+            if full && (i == 8 || i == 13 || i == 18 || i == 23) {
+                if s[i] != b'-' {
+                    return None;
+                }
+                i += 1;
+                continue;
+            }
+            let (hi, lo) = match (dig(s[i]), dig(s[i + 1])) {
+                (Some(hi), Some(lo)) => (hi, lo),
+                _ => return None,
+            };
+            buf[j] = (hi << 4) | lo;
+            i += 2;
+            j += 1;
+        }
+        if j == 2 {
+            buf[2] = buf[0];
+            buf[3] = buf[1];
+            buf[0] = 0;
+            buf[1] = 0;
+        }
+        Some(Self(buf))
+    }
+
     /// Constructs instance from the specified slice of variable length.
     /// The supported lengths are 2 for `uuid16`, 4 for `uuid32` and 16 for a standard UUID.
     ///
     /// # Panics
     ///
     /// Panics if `bytes` length is not 2, 4 or 16.
+    /// See [`try_from_slice`](#method.try_from_slice) for a non-panicking version.
     pub fn from_slice(bytes: &[u8]) -> Self {
-        Self(match bytes.len() {
+        match Self::try_from_slice(bytes) {
+            Ok(v) => v,
+            Err(_) => panic!("invalid slice len {}, expected 2, 4 or 16 bytes", bytes.len()),
+        }
+    }
+
+    /// Constructs instance from the specified slice of variable length.
+    /// The supported lengths are 2 for `uuid16`, 4 for `uuid32` and 16 for a standard UUID.
+    /// Returns error if `bytes` length is not 2, 4 or 16.
+    pub fn try_from_slice(bytes: &[u8]) -> Result<Self, UuidSliceError> {
+        Ok(Self(match bytes.len() {
             2 => {
                 let mut r = BASE_UUID_BYTES;
                 r[2] = bytes[0];
@@ -56,8 +180,8 @@ impl Uuid {
                 r.copy_from_slice(bytes);
                 r
             }
-            _ => panic!("invalid slice len {}, expected 2, 4 or 16 bytes", bytes.len()),
-        })
+            len => return Err(UuidSliceError(len)),
+        }))
     }
 
     /// Returns inner bytes array.
@@ -77,6 +201,23 @@ impl Uuid {
             &self.0
         }
     }
+
+    /// Returns the [shortest form](#method.shorten) of this UUID as string: 4 or 8 hex digits for
+    /// UUIDs based on the Bluetooth base UUID, the full form otherwise.
+    ///
+    /// ```
+    /// use core_bluetooth::uuid::Uuid;
+    ///
+    /// assert_eq!(Uuid::from_u16(0x180d).to_short_string(), "180d");
+    /// ```
+    pub fn to_short_string(&self) -> String {
+        let short = self.shorten();
+        if short.len() == self.0.len() {
+            self.to_string()
+        } else {
+            short.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+    }
 }
 
 assert_impl_all!(Uuid: Send, Sync);
@@ -112,15 +253,45 @@ impl fmt::Debug for Uuid {
     }
 }
 
+#[cfg(feature = "serde")]
+impl serde::Serialize for Uuid {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Uuid {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Uuid;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("UUID string")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
 impl From<[u8; 16]> for Uuid {
     fn from(v: [u8; 16]) -> Self {
         Self::from_bytes(v)
     }
 }
 
-impl From<&[u8]> for Uuid {
-    fn from(v: &[u8]) -> Self {
-        Self::from_slice(v)
+impl TryFrom<&[u8]> for Uuid {
+    type Error = UuidSliceError;
+
+    fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+        Self::try_from_slice(v)
     }
 }
 
@@ -128,46 +299,7 @@ impl FromStr for Uuid {
     type Err = UuidParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.as_bytes();
-        if s.len() != 36 {
-            return Err(UuidParseError(()));
-        }
-        const PARTS: [(usize, usize); 4] = [(8, 4), (13, 6), (18, 8), (23, 10)];
-        if s[PARTS[0].0] != b'-'
-            || s[PARTS[1].0] != b'-'
-            || s[PARTS[2].0] != b'-'
-            || s[PARTS[3].0] != b'-'
-        {
-            return Err(UuidParseError(()));
-        }
-
-        fn decode(src: &[u8], dst: &mut [u8]) -> Result<(), UuidParseError> {
-            debug_assert_eq!(src.len() % 2, 0);
-            debug_assert_eq!(dst.len(), src.len() / 2);
-
-            fn dig(c: u8) -> Result<u8, UuidParseError> {
-                Ok(match c {
-                    b'0'..=b'9' => c - b'0',
-                    b'a'..=b'f' => c - b'a' + 10,
-                    b'A'..=b'F' => c - b'A' + 10,
-                    _ => return Err(UuidParseError(())),
-                })
-            }
-
-            for (s, d) in src.chunks(2).zip(dst.iter_mut()) {
-                *d = (dig(s[0])? << 4) | dig(s[1])?;
-            }
-
-            Ok(())
-        }
-
-        let mut buf = [0; 16];
-        decode(&s[..PARTS[0].0], &mut buf[..PARTS[0].1])?;
-        decode(&s[PARTS[0].0 + 1..PARTS[1].0], &mut buf[PARTS[0].1..PARTS[1].1])?;
-        decode(&s[PARTS[1].0 + 1..PARTS[2].0], &mut buf[PARTS[1].1..PARTS[2].1])?;
-        decode(&s[PARTS[2].0 + 1..PARTS[3].0], &mut buf[PARTS[2].1..PARTS[3].1])?;
-        decode(&s[PARTS[3].0 + 1..], &mut buf[PARTS[3].1..])?;
-        Ok(buf.into())
+        Self::parse(s.as_bytes(), false).ok_or(UuidParseError(()))
     }
 }
 
@@ -182,6 +314,25 @@ impl fmt::Display for UuidParseError {
 
 impl std::error::Error for UuidParseError {}
 
+/// Error returned when constructing [`Uuid`](struct.Uuid.html) from a slice of unsupported length.
+#[derive(Debug)]
+pub struct UuidSliceError(usize);
+
+impl UuidSliceError {
+    /// Returns length of the rejected slice.
+    pub fn slice_len(&self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for UuidSliceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid slice len {}, expected 2, 4 or 16 bytes", self.0)
+    }
+}
+
+impl std::error::Error for UuidSliceError {}
+
 object_ptr_wrapper!(NSUUID);
 
 impl NSUUID {
@@ -251,6 +402,21 @@ mod test {
         }
     }
 
+    #[test]
+    fn to_short_string() {
+        assert_eq!(Uuid::from_u16(0x2a37).to_short_string(), "2a37");
+        assert_eq!(Uuid::from_u32(0x1234_5678).to_short_string(), "12345678");
+        let s = "ebe0ccb0-7a0a-4b0c-8a1a-6ff2997da3a6";
+        assert_eq!(s.parse::<Uuid>().unwrap().to_short_string(), s);
+    }
+
+    #[test]
+    fn from_u16_u32() {
+        assert_eq!(Uuid::from_u16(0x180d), Uuid::from_slice(&[0x18, 0x0d]));
+        assert_eq!(Uuid::from_u32(0x1234_5678), Uuid::from_slice(&[0x12, 0x34, 0x56, 0x78]));
+        assert_eq!(Uuid::from_u16(0), Uuid::base());
+    }
+
     #[test]
     fn parse_ok() {
         let data = &[
@@ -267,6 +433,44 @@ mod test {
         }
     }
 
+    #[test]
+    fn parse_literal() {
+        const FULL: Uuid = uuid!("12345678-9AbC-Def0-1234-56789aBCDEF0");
+        assert_eq!(FULL, "12345678-9abc-def0-1234-56789abcdef0".parse().unwrap());
+        assert_eq!(uuid!("180D"), Uuid::from_slice(&[0x18, 0x0d]));
+        assert_eq!(uuid!("1234abcd"), Uuid::from_slice(&[0x12, 0x34, 0xab, 0xcd]));
+
+        assert!("180d".parse::<Uuid>().is_err());
+        assert!("1234abcd".parse::<Uuid>().is_err());
+        for &inp in &["", "18", "180", "180z", "1234abc", "0000180d-", "1234abcd1234abcd"] {
+            assert!(Uuid::__parse_literal(inp).is_none());
+        }
+    }
+
+    /// Checks that UUIDs parsed from `CBUUID` strings match the ones converted from `CBUUID` data,
+    /// as `CBUUID::to_uuid` does, for each length CoreBluetooth reports.
+    #[test]
+    fn cbuuid_round_trip() {
+        let data: &[(&str, &[u8])] = &[
+            ("180D", &[0x18, 0x0d]),
+            ("12345678", &[0x12, 0x34, 0x56, 0x78]),
+            ("0000FEAA", &[0x00, 0x00, 0xfe, 0xaa]),
+            ("6E400001-B5A3-F393-E0A9-E50E24DCCA9E", &[0x6e, 0x40, 0x00, 0x01, 0xb5, 0xa3, 0xf3,
+                0x93, 0xe0, 0xa9, 0xe5, 0x0e, 0x24, 0xdc, 0xca, 0x9e]),
+        ];
+        for &(string, bytes) in data {
+            let uuid = Uuid::from_cbuuid_string(string).unwrap();
+            assert_eq!(uuid, Uuid::from_slice(bytes), "{}", string);
+        }
+
+        let heart_rate = Uuid::from_cbuuid_string("180D").unwrap();
+        assert_eq!(heart_rate, Uuid::from_u16(0x180d));
+        assert_eq!(heart_rate.shorten(), &[0x18, 0x0d]);
+        assert_eq!(Uuid::from_cbuuid_string("0000180D-0000-1000-8000-00805F9B34FB"), Some(heart_rate));
+        assert_eq!(Uuid::from_cbuuid_string("0000FEAA").unwrap().shorten(), &[0xfe, 0xaa]);
+        assert_eq!(Uuid::from_cbuuid_string("180"), None);
+    }
+
     #[test]
     fn parse_fail() {
         let data = &[
@@ -283,4 +487,26 @@ mod test {
             assert!(inp.parse::<Uuid>().is_err());
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn try_from_slice() {
+        assert_eq!(Uuid::try_from_slice(&[0x12, 0x34]).unwrap(), Uuid::from_slice(&[0x12, 0x34]));
+        assert_eq!(Uuid::try_from_slice(&[0, 0, 0x12, 0x34]).unwrap(), Uuid::from_slice(&[0x12, 0x34]));
+        assert_eq!(Uuid::try_from_slice(&BASE_UUID_BYTES).unwrap(), Uuid::base());
+        for &len in &[0, 1, 3, 5, 15, 17] {
+            let err = Uuid::try_from_slice(&vec![0; len]).unwrap_err();
+            assert_eq!(err.slice_len(), len);
+        }
+    }
+
+    #[test]
+    fn try_from() {
+        assert_eq!(Uuid::try_from(&[0x12, 0x34][..]).unwrap(), Uuid::from_u16(0x1234));
+        assert_eq!(Uuid::try_from(&[0, 0, 0x12, 0x34][..]).unwrap(), Uuid::from_u16(0x1234));
+        assert_eq!(Uuid::try_from(&BASE_UUID_BYTES[..]).unwrap(), Uuid::base());
+        for &len in &[0, 1, 3, 5, 15, 17] {
+            let err = Uuid::try_from(&vec![0; len][..]).unwrap_err();
+            assert_eq!(err.slice_len(), len);
+        }
+    }
+}